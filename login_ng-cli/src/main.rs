@@ -18,26 +18,54 @@
 */
 
 use std::env;
+use std::io::Write;
+use std::os::fd::FromRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Duration;
 
 use login_ng::command::SessionCommand;
+use login_ng::keycache::KeyCacheConfig;
+use login_ng::nextboot::{current_boot_id, NextBootAutologin};
+use login_ng::storage::{load_user_auth_data, StorageSource};
 
+use login_ng_user_interactions::askpass::{pending_password_requests, ASK_PASSWORD_DIR};
 use login_ng_user_interactions::cli::CommandLineLoginUserInteractionHandler;
+use login_ng_user_interactions::generate::generate_from_env;
+use login_ng_user_interactions::greeter::{serve_greeter, Greeter, GREETER_SOCKET_FD};
+use login_ng_user_interactions::i18n::{tr, tr_args};
+use login_ng_user_interactions::interrupt::{
+    install_interrupt_handlers, interrupt_signal, interrupted, TerminalState,
+};
+use login_ng_user_interactions::kiosk::KioskConfig;
 use login_ng_user_interactions::login::*;
+use login_ng_user_interactions::plymouth::plymouth_handoff;
+use login_ng_user_interactions::theme::{colors_supported, Theme};
+use login_ng_user_interactions::vt::{Vt, VtSelection};
+use login_ng_user_interactions::{
+    prompt_masked_with_timeout, prompt_password_with_timeout, prompt_plain_with_timeout,
+    CLEAR_SCREEN,
+};
 
 #[cfg(feature = "pam")]
-use login_ng_user_interactions::pam::PamLoginExecutor;
+use login_ng_user_interactions::pam::{PamLoginError, PamLoginExecutor};
 
-use argh::FromArgs;
+use argh::{ArgsInfo, FromArgs};
+use serde::Serialize;
 
-#[derive(FromArgs, PartialEq, Debug)]
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
 /// Command line tool for managing login-ng authentication methods
 struct Args {
     #[argh(option, short = 'b')]
     /// display the copyright banner
     banner: Option<bool>,
 
+    #[argh(switch, short = 'q')]
+    /// do not print the banner nor informational messages of the PAM stack (passes PAM_SILENT): only prompts and errors are shown
+    quiet: bool,
+
     #[argh(option, short = 'u')]
     /// username to authenticate
     user: Option<String>,
@@ -57,6 +85,63 @@ struct Args {
     #[argh(option, short = 'f')]
     /// maximum number of accepted failures before the login gets aborted (defaults to 5)
     failures: Option<usize>,
+
+    #[argh(option, short = 't')]
+    /// seconds a prompt can be left unanswered before the login attempt gets aborted: 0 disables the timeout (defaults to 120)
+    prompt_timeout: Option<u64>,
+
+    #[argh(option)]
+    /// seconds after which an unanswered prompt continues with its default answer (see --default-user and --default-autologin)
+    default_timeout: Option<u64>,
+
+    #[argh(option)]
+    /// username used when the username prompt is left unanswered for --default-timeout seconds
+    default_user: Option<String>,
+
+    #[argh(switch)]
+    /// continue with the empty (autologin) secret when the secret prompt is left unanswered for --default-timeout seconds
+    default_autologin: bool,
+
+    #[argh(option)]
+    /// virtual terminal (a number or auto for the first free one) to prompt on: stdio is redirected to it and it is activated before prompting, released to the session and reclaimed once it exits
+    vt: Option<VtSelection>,
+
+    #[argh(switch)]
+    /// log in the account of kiosk.conf without prompting and keep its application running forever
+    kiosk: bool,
+
+    #[argh(switch)]
+    /// prompt the user from a process running as the unprivileged login-ng-greeter user: only PAM and service calls are made as root
+    greeter: bool,
+
+    #[argh(switch, hidden_help)]
+    /// serve the prompts of the privileged process over the inherited socket (see --greeter)
+    serve_greeter: bool,
+
+    #[argh(switch)]
+    /// lock the terminal until the current user authenticates again: exits successfully only once unlocked
+    lock: bool,
+
+    #[argh(switch)]
+    /// act as a systemd password agent: password requests (such as disk passphrases) are answered before each login prompt
+    ask_password: bool,
+
+    #[argh(switch)]
+    /// authenticate and validate the account without opening a session nor running any command: the exit code tells the outcome (0 authenticated, 10 authentication failed, 11 account rejected, 12 error)
+    test_auth: bool,
+
+    #[argh(switch)]
+    /// with --test-auth, print the outcome as JSON on stdout (informational messages of the PAM stack are suppressed)
+    json: bool,
+
+    #[argh(switch)]
+    /// print prompts and messages without the colors of theme.conf (also disabled by the NO_COLOR environment variable)
+    no_color: bool,
+
+    #[cfg(feature = "approval")]
+    #[argh(switch)]
+    /// display a QR code to approve the login from a companion device instead of typing the password (requires the username)
+    qr: bool,
 }
 
 #[cfg(feature = "greetd")]
@@ -76,6 +161,7 @@ fn login_greetd(
 #[cfg(feature = "pam")]
 fn login_pam(
     allow_autologin: bool,
+    quiet: bool,
     prompter: Arc<Mutex<dyn LoginUserInteractionHandler>>,
     maybe_username: &Option<String>,
     retrival_strategy: &SessionCommandRetrival,
@@ -85,17 +171,520 @@ fn login_pam(
             prompter,
         );
 
-    let mut login_executer = PamLoginExecutor::new(conversation, allow_autologin);
+    let mut login_executer = PamLoginExecutor::new(conversation, allow_autologin).with_quiet(quiet);
 
     login_executer.execute(maybe_username, retrival_strategy)
 }
 
+#[cfg_attr(not(all(feature = "greetd", feature = "pam")), allow(unused_variables))]
+fn login_attempt(
+    allow_autologin: bool,
+    quiet: bool,
+    prompter: Arc<Mutex<dyn LoginUserInteractionHandler>>,
+    maybe_username: &Option<String>,
+    retrival_strategy: &SessionCommandRetrival,
+) -> Result<LoginResult, LoginError> {
+    match env::var("GREETD_SOCK") {
+        Ok(greetd_sock) => {
+            #[cfg(feature = "greetd")]
+            {
+                login_greetd(greetd_sock, prompter, maybe_username, retrival_strategy)
+            }
+
+            #[cfg(not(feature = "greetd"))]
+            {
+                eprintln!("greetd support has been removed.");
+                Err(LoginError::NoLoginSupport)
+            }
+        }
+        _ => {
+            #[cfg(feature = "pam")]
+            {
+                login_pam(
+                    allow_autologin,
+                    quiet,
+                    prompter,
+                    maybe_username,
+                    retrival_strategy,
+                )
+            }
+            #[cfg(not(feature = "pam"))]
+            {
+                eprintln!("greetd support has either been removed or the service is unavailable, while pam support is compile-time disabled.");
+                Err(LoginError::NoLoginSupport)
+            }
+        }
+    }
+}
+
+/// Log in the kiosk account over and over: the session manager keeps the
+/// application running, this only covers the whole session going away.
+fn run_kiosk(kiosk: &KioskConfig, vt: Option<Vt>, quiet: bool) {
+    let username = Some(kiosk.user().clone());
+
+    let mut handler =
+        CommandLineLoginUserInteractionHandler::new(true, username.clone(), Some(String::new()));
+
+    if let Some(vt) = vt {
+        handler = handler.with_vt(vt);
+    }
+
+    let prompter = Arc::new(Mutex::new(handler));
+
+    let command_retrieval = SessionCommandRetrival::Defined(kiosk.session_command());
+
+    while !interrupted() {
+        match login_attempt(true, quiet, prompter.clone(), &username, &command_retrieval) {
+            Ok(LoginResult::Success { .. } | LoginResult::Authenticated { .. }) => {}
+            Ok(LoginResult::Failure) => eprintln!(
+                "{}",
+                tr_args(
+                    "kiosk-login-failed",
+                    "Kiosk login as { $username } failed.",
+                    &[("username", kiosk.user().clone())],
+                )
+            ),
+            Err(err) => eprintln!(
+                "{}",
+                tr_args(
+                    "kiosk-login-errored",
+                    "Kiosk login as { $username } errored: { $error }",
+                    &[
+                        ("username", kiosk.user().clone()),
+                        ("error", err.user_message()),
+                    ],
+                )
+            ),
+        }
+
+        std::thread::sleep(kiosk.restart_delay());
+    }
+}
+
+/// Exit codes of --test-auth
+const TEST_AUTH_OK: i32 = 0;
+const TEST_AUTH_FAILED: i32 = 10;
+const TEST_AUTH_REJECTED: i32 = 11;
+const TEST_AUTH_ERROR: i32 = 12;
+
+/// Outcome of --test-auth, printed for the administrator or as JSON for scripts
+#[derive(Serialize)]
+struct TestAuthReport {
+    #[serde(rename = "result")]
+    outcome: &'static str,
+    exit_code: i32,
+    username: Option<String>,
+    uid: Option<u32>,
+    password_expired: bool,
+    error: Option<String>,
+}
+
+impl TestAuthReport {
+    fn new(result: Result<LoginResult, LoginError>) -> Self {
+        let (outcome, exit_code) = match &result {
+            Ok(LoginResult::Authenticated { .. } | LoginResult::Success { .. }) => {
+                ("authenticated", TEST_AUTH_OK)
+            }
+            Ok(LoginResult::Failure)
+            | Err(LoginError::AuthenticationFailed | LoginError::UserDiscoveryError) => {
+                ("failed", TEST_AUTH_FAILED)
+            }
+            Err(
+                LoginError::AccountExpired
+                | LoginError::PermissionDenied
+                | LoginError::MaxTries
+                | LoginError::CredentialsUnavailable,
+            ) => ("rejected", TEST_AUTH_REJECTED),
+            #[cfg(feature = "pam")]
+            Err(LoginError::PamError(PamLoginError::Locked(_) | PamLoginError::Validation(_))) => {
+                ("rejected", TEST_AUTH_REJECTED)
+            }
+            Err(_) => ("error", TEST_AUTH_ERROR),
+        };
+
+        let mut report = Self {
+            outcome,
+            exit_code,
+            username: None,
+            uid: None,
+            password_expired: false,
+            error: None,
+        };
+
+        match result {
+            Ok(LoginResult::Authenticated {
+                username,
+                uid,
+                password_expired,
+            }) => {
+                report.username = Some(username);
+                report.uid = Some(uid);
+                report.password_expired = password_expired;
+            }
+            Ok(LoginResult::Success { username, uid, .. }) => {
+                report.username = Some(username);
+                report.uid = Some(uid);
+            }
+            Ok(LoginResult::Failure) => {}
+            Err(err) => report.error = Some(err.user_message()),
+        }
+
+        report
+    }
+}
+
+/// Authenticate and validate the account as a login would, stopping before the session
+/// is opened: meant for checking the PAM stack and the secondary authentication from scripts.
+fn run_test_auth(args: &Args, theme: &Theme) -> i32 {
+    let allow_autologin = args.autologin.unwrap_or(false);
+
+    #[cfg(feature = "pam")]
+    let result = {
+        let handler = CommandLineLoginUserInteractionHandler::new(
+            allow_autologin,
+            args.user.clone(),
+            args.password.clone(),
+        )
+        .with_prompt_timeout(prompt_timeout(args))
+        .with_theme(theme.clone());
+
+        let conversation =
+            login_ng_user_interactions::conversation::ProxyLoginUserInteractionHandlerConversation::new(
+                Arc::new(Mutex::new(handler)),
+            );
+
+        PamLoginExecutor::new(conversation, allow_autologin)
+            .with_quiet(args.quiet || args.json)
+            .with_dry_run(true)
+            .execute(&args.user, &SessionCommandRetrival::FromUserConfig)
+    };
+
+    // only the PAM stack can be tested: greetd would start the session
+    #[cfg(not(feature = "pam"))]
+    let result = {
+        let _ = allow_autologin;
+        Err(LoginError::NoLoginSupport)
+    };
+
+    let report = TestAuthReport::new(result);
+
+    if args.json {
+        return match serde_json::to_string(&report) {
+            Ok(json) => {
+                println!("{json}");
+                report.exit_code
+            }
+            Err(err) => {
+                eprintln!("{err}");
+                TEST_AUTH_ERROR
+            }
+        };
+    }
+
+    match (&report.username, report.uid, &report.error) {
+        (Some(username), Some(uid), _) => {
+            println!(
+                "{}",
+                theme.info(&tr_args(
+                    "test-auth-ok",
+                    "{ $username } (uid { $uid }) authenticated: the account is allowed to log in.",
+                    &[("username", username.clone()), ("uid", uid.to_string())],
+                ))
+            );
+
+            if report.password_expired {
+                println!(
+                    "{}",
+                    theme.info(&tr_args(
+                        "test-auth-password-expired",
+                        "The password of { $username } has expired and must be changed at the next login.",
+                        &[("username", username.clone())],
+                    ))
+                );
+            }
+        }
+        (_, _, error) => eprintln!(
+            "{}",
+            theme.error(&tr_args(
+                "test-auth-failed",
+                "Authentication test failed: { $error }",
+                &[(
+                    "error",
+                    error
+                        .clone()
+                        .unwrap_or_else(|| LoginError::AuthenticationFailed.user_message()),
+                )],
+            ))
+        ),
+    }
+
+    report.exit_code
+}
+
+/// Arguments of the unprivileged greeter: the prompt timeouts are enforced there
+fn greeter_args(args: &Args) -> Vec<String> {
+    let mut greeter_args = vec![String::from("--serve-greeter")];
+
+    if let Some(prompt_timeout) = args.prompt_timeout {
+        greeter_args.push(String::from("--prompt-timeout"));
+        greeter_args.push(prompt_timeout.to_string());
+    }
+
+    if let Some(default_timeout) = args.default_timeout {
+        greeter_args.push(String::from("--default-timeout"));
+        greeter_args.push(default_timeout.to_string());
+    }
+
+    if let Some(default_user) = &args.default_user {
+        greeter_args.push(String::from("--default-user"));
+        greeter_args.push(default_user.clone());
+    }
+
+    if args.default_autologin {
+        greeter_args.push(String::from("--default-autologin"));
+    }
+
+    if args.no_color {
+        greeter_args.push(String::from("--no-color"));
+    }
+
+    greeter_args
+}
+
+/// The theme of theme.conf, without colors if disabled there, by --no-color or by the environment
+fn theme(args: &Args) -> Theme {
+    let theme = Theme::load();
+    let colored = theme.colored() && !args.no_color && colors_supported();
+
+    theme.with_colors(colored)
+}
+
+fn prompt_timeout(args: &Args) -> Option<Duration> {
+    match args.prompt_timeout.unwrap_or(120) {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    }
+}
+
+/// Answer the password requests of systemd pending at the time of the call, as a password agent would
+fn answer_password_requests(timeout: Option<Duration>) {
+    for request in pending_password_requests(Path::new(ASK_PASSWORD_DIR)) {
+        let prompt = format!("{}: ", request.message());
+
+        let password = match request.echo() {
+            true => prompt_plain_with_timeout(prompt.as_str(), timeout),
+            false => prompt_password_with_timeout(prompt.as_str(), timeout),
+        };
+
+        // an unanswered prompt tells the asking process no password is coming
+        if let Err(err) = request.answer(password.ok().as_deref()) {
+            eprintln!(
+                "{}",
+                tr_args(
+                    "ask-password-failed",
+                    "Could not answer the password request: { $error }",
+                    &[("error", err.to_string())],
+                )
+            );
+        }
+    }
+}
+
+fn with_default_answers(
+    handler: CommandLineLoginUserInteractionHandler,
+    args: &Args,
+) -> CommandLineLoginUserInteractionHandler {
+    match args.default_timeout {
+        Some(default_timeout) => handler.with_default_answers(
+            Duration::from_secs(default_timeout),
+            args.default_user.clone(),
+            match args.default_autologin {
+                true => Some(String::new()),
+                false => None,
+            },
+        ),
+        None => handler,
+    }
+}
+
+/// The unprivileged greeter spawned by --greeter: read what the user types on behalf
+/// of the privileged process, that checks it and talks to PAM and the service.
+fn run_greeter(args: &Args) -> i32 {
+    let stream = unsafe { UnixStream::from_raw_fd(GREETER_SOCKET_FD) };
+
+    let mut handler = with_default_answers(
+        CommandLineLoginUserInteractionHandler::new(false, None, None)
+            .with_prompt_timeout(prompt_timeout(args))
+            .with_theme(theme(args)),
+        args,
+    );
+
+    match serve_greeter(stream, &mut handler) {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("{err}");
+            1
+        }
+    }
+}
+
+/// Keep prompting the current user until the main password or one of the
+/// secondary ones is entered: only an interruption ends it without unlocking.
+fn run_lock(theme: &Theme) -> i32 {
+    let Some(username) = login_ng::users::get_current_username() else {
+        eprintln!(
+            "{}",
+            tr("lock-unknown-user", "Could not identify the current user.")
+        );
+        return 2;
+    };
+    let username = username.to_string_lossy().to_string();
+
+    let user_cfg = match load_user_auth_data(&StorageSource::Username(username.clone())) {
+        Ok(Some(user_cfg)) => user_cfg,
+        _ => {
+            eprintln!(
+                "{}",
+                tr_args(
+                    "lock-not-configured",
+                    "{ $username } has no login-ng authentication data: the session cannot be unlocked.",
+                    &[("username", username.clone())],
+                )
+            );
+            return 2;
+        }
+    };
+
+    // an empty line would unlock an account whose secret is empty (as for autologin)
+    if user_cfg.main_by_secret(Some(b"")).is_ok() {
+        eprintln!(
+            "{}",
+            tr_args(
+                "lock-empty-secret",
+                "{ $username } can log in without a password: the session cannot be locked.",
+                &[("username", username.clone())],
+            )
+        );
+        return 2;
+    }
+
+    let key_cache = KeyCacheConfig::load(login_ng::config::config_dir());
+
+    while !interrupted() {
+        print!("{CLEAR_SCREEN}");
+        println!(
+            "{}",
+            tr_args(
+                "lock-locked",
+                "Session of { $username } locked.",
+                &[("username", username.clone())],
+            )
+        );
+        let _ = std::io::stdout().flush();
+
+        let prompt = tr("lock-unlock-prompt", "Password or PIN: ");
+        let secret = match theme.mask() {
+            Some(mask) => prompt_masked_with_timeout(prompt.as_str(), mask, None),
+            None => prompt_password_with_timeout(prompt.as_str(), None),
+        };
+
+        // without a terminal to read from there is no way to unlock: give up instead of spinning
+        let secret = match secret {
+            Ok(secret) => secret,
+            Err(_) if interrupted() => break,
+            Err(err) => {
+                eprintln!(
+                    "{}",
+                    tr_args(
+                        "lock-prompt-error",
+                        "Cannot read the password: { $error }",
+                        &[("error", err.to_string())],
+                    )
+                );
+                return 1;
+            }
+        };
+
+        // unlocking again shortly after skips the password hashes if keycache.conf allows it and this runs as root
+        let result = match key_cache.lookup(&username, secret.as_bytes(), &user_cfg) {
+            Some(main) => Ok(main),
+            None => user_cfg
+                .main_by_secret(Some(secret.as_bytes()))
+                .inspect(|main| {
+                    if let Err(err) = key_cache.store(&username, secret.as_bytes(), &user_cfg, main)
+                    {
+                        eprintln!("Could not cache the unlock: {err}");
+                    }
+                }),
+        };
+        login_ng::secure::wipe(secret.into_bytes().as_mut_slice());
+
+        match result {
+            Ok(_) => {
+                print!("{CLEAR_SCREEN}");
+                let _ = std::io::stdout().flush();
+
+                return 0;
+            }
+            Err(_) => {
+                eprintln!("{}", tr("lock-wrong-secret", "Wrong password or PIN."));
+                std::thread::sleep(Duration::from_secs(2));
+            }
+        }
+    }
+
+    128 + interrupt_signal().unwrap_or(libc::SIGINT)
+}
+
 fn main() {
     let version = login_ng::LIBRARY_VERSION;
 
-    let args: Args = argh::from_env();
+    // packagers generate the shell completions and the manual page with the hidden generate subcommand
+    if let Some(exit_code) = generate_from_env::<Args>("login_ng-cli") {
+        std::process::exit(exit_code);
+    }
+
+    let mut args: Args = argh::from_env();
+
+    // an interrupted login must not leave the terminal without echo
+    let mut terminal_state = TerminalState::save();
+    if let Err(err) = install_interrupt_handlers() {
+        eprintln!("Could not install the interrupt handlers: {err}");
+    }
+
+    if args.serve_greeter {
+        std::process::exit(run_greeter(&args));
+    }
+
+    // take over the console from the boot splash before anything gets printed
+    let _vt_takeover = match plymouth_handoff() {
+        Ok(vt_takeover) => vt_takeover,
+        Err(err) => {
+            eprintln!("Could not take over the console from plymouth: {err}");
+            None
+        }
+    };
+
+    // the VT must be active before anything gets printed on it
+    let vt = match args.vt {
+        Some(selection) => match selection.open().and_then(|vt| {
+            vt.attach_stdio()?;
+            vt.activate().map(|_| vt)
+        }) {
+            Ok(vt) => {
+                // the terminal to restore on interruption is now the VT
+                terminal_state = TerminalState::save();
+                Some(vt)
+            }
+            Err(err) => {
+                eprintln!("Could not activate VT {selection}: {err}");
+                None
+            }
+        },
+        None => None,
+    };
 
-    if args.banner.unwrap_or_default() {
+    if args.banner.unwrap_or_default() && !args.quiet {
         println!("login-ng version {version}, Copyright (C) 2024 Denis Benato");
         println!("login-ng comes with ABSOLUTELY NO WARRANTY;");
         println!("This is free software, and you are welcome to redistribute it");
@@ -103,10 +692,68 @@ fn main() {
         println!("\n");
     }
 
-    let allow_autologin = args.autologin.unwrap_or(false);
+    if args.lock {
+        let exit_code = run_lock(&theme(&args));
+
+        if let Some(terminal_state) = &terminal_state {
+            terminal_state.restore();
+        }
+
+        std::process::exit(exit_code);
+    }
+
+    if args.test_auth {
+        let exit_code = run_test_auth(&args, &theme(&args));
+
+        if let Some(terminal_state) = &terminal_state {
+            terminal_state.restore();
+        }
+
+        std::process::exit(exit_code);
+    }
+
+    let kiosk = match KioskConfig::load() {
+        Some(kiosk) if args.kiosk || kiosk.enabled() => Some(kiosk),
+        None if args.kiosk => {
+            eprintln!(
+                "{}",
+                tr(
+                    "kiosk-not-configured",
+                    "Kiosk mode requires the user and cmd keys in the Kiosk section of kiosk.conf",
+                )
+            );
+            std::process::exit(-1)
+        }
+        _ => None,
+    };
+
+    if let Some(kiosk) = kiosk {
+        // only an interruption ends the kiosk mode
+        run_kiosk(&kiosk, vt, args.quiet);
+
+        if let Some(terminal_state) = &terminal_state {
+            terminal_state.restore();
+        }
+
+        print!("{CLEAR_SCREEN}");
+        let _ = std::io::stdout().flush();
+
+        std::process::exit(128 + interrupt_signal().unwrap_or(libc::SIGINT));
+    }
+
+    // a user armed for autologin at this boot is picked without prompting: PAM consumes the token
+    let next_boot_user = current_boot_id()
+        .ok()
+        .and_then(|boot_id| NextBootAutologin::default().pending(&boot_id));
+
+    let allow_autologin = args.autologin.unwrap_or(false) || next_boot_user.is_some();
 
     let max_failures = args.failures.unwrap_or(5);
 
+    let prompt_timeout = prompt_timeout(&args);
+
+    let theme = theme(&args);
+
     let autoselect_user = match &allow_autologin {
         true => match &args.user {
             Some(_) => args.user.clone(),
@@ -121,69 +768,185 @@ fn main() {
         false => args.user.clone(),
     };
 
-    let prompter = Arc::new(Mutex::new(CommandLineLoginUserInteractionHandler::new(
-        allow_autologin,
-        autoselect_user,
-        args.password.clone(),
-    )));
-
-    let command_retrieval = match args.cmd {
-        Some(command) => SessionCommandRetrival::Defined(SessionCommand::new(command)),
-        _ => SessionCommandRetrival::AutodetectFromUserHome,
+    let autoselect_user = match &args.user {
+        Some(_) => autoselect_user,
+        None => next_boot_user.or(autoselect_user),
     };
 
-    'login_attempt: for attempt in 0..max_failures {
-        let login_result: Result<LoginResult, LoginError> = match env::var("GREETD_SOCK") {
-            Ok(greetd_sock) => {
-                #[cfg(feature = "greetd")]
-                {
-                    login_greetd(
-                        greetd_sock,
-                        prompter.clone(),
-                        &args.user,
-                        &command_retrieval,
+    #[cfg(feature = "approval")]
+    let password = match (args.qr, &autoselect_user) {
+        (true, Some(username)) => {
+            use login_ng_user_interactions::approval::{phone_approved_login, ApprovalError};
+
+            match phone_approved_login(username, Duration::from_secs(120), |qr| {
+                println!("{qr}");
+                println!(
+                    "{}",
+                    tr_args(
+                        "qr-scan",
+                        "Scan the code with your companion device to log in as { $username }.",
+                        &[("username", username.clone())],
                     )
-                }
-
-                #[cfg(not(feature = "greetd"))]
-                {
-                    eprintln!("greetd support has been removed.");
-                    Err(LoginError::NoLoginSupport)
+                );
+            })
+            .and_then(|main_password| {
+                login_ng::secret_to_string(&main_password)
+                    .map_err(|err| ApprovalError::Refused(err.to_string()))
+            }) {
+                Ok(main_password) => Some(main_password),
+                Err(err) => {
+                    eprintln!(
+                        "{}",
+                        tr_args(
+                            "qr-failed",
+                            "Phone-approved login failed: { $error }",
+                            &[("error", err.to_string())],
+                        )
+                    );
+                    args.password.clone()
                 }
             }
-            _ => {
-                #[cfg(feature = "pam")]
-                {
-                    login_pam(
-                        allow_autologin,
-                        prompter.clone(),
-                        &args.user,
-                        &command_retrieval,
+        }
+        (true, None) => {
+            eprintln!(
+                "{}",
+                tr(
+                    "qr-username-required",
+                    "Phone-approved login requires the username to be specified",
+                )
+            );
+            args.password.clone()
+        }
+        (false, _) => args.password.clone(),
+    };
+
+    #[cfg(not(feature = "approval"))]
+    let password = args.password.clone();
+
+    // the guest account is picked by typing its name at the username prompt
+    let guest = guest_config();
+    if guest.enabled() && autoselect_user.is_none() {
+        println!(
+            "{}",
+            tr_args(
+                "guest-hint",
+                "Log in as { $username } for a guest session: nothing is kept after logout.",
+                &[("username", guest.user().clone())],
+            )
+        );
+    }
+
+    let mut handler =
+        CommandLineLoginUserInteractionHandler::new(allow_autologin, autoselect_user, password)
+            .with_prompt_timeout(prompt_timeout)
+            .with_theme(theme.clone());
+
+    if let Some(vt) = vt {
+        handler = handler.with_vt(vt);
+    }
+
+    handler = with_default_answers(handler, &args);
+
+    // keyboard input is then only ever parsed by the unprivileged greeter
+    if args.greeter {
+        match Greeter::spawn(&greeter_args(&args)) {
+            Ok(greeter) => handler = handler.with_greeter(greeter),
+            Err(err) => {
+                eprintln!(
+                    "{}",
+                    tr_args(
+                        "greeter-failed",
+                        "Could not start the unprivileged greeter: { $error }",
+                        &[("error", err.to_string())],
                     )
-                }
-                #[cfg(not(feature = "pam"))]
-                {
-                    eprintln!("greetd support has either been removed or the service is unavailable, while pam support is compile-time disabled.");
-                    Err(LoginError::NoLoginSupport)
-                }
+                );
+                std::process::exit(-1)
             }
-        };
+        }
+    }
+
+    let prompter = Arc::new(Mutex::new(handler));
+
+    let command_retrieval = match args.cmd {
+        Some(command) => SessionCommandRetrival::Defined(SessionCommand::new(command)),
+        _ => SessionCommandRetrival::FromUserConfig,
+    };
+
+    'login_attempt: for attempt in 0..max_failures {
+        if interrupted() {
+            break 'login_attempt;
+        }
+
+        if let Some(header) = theme.styled_header().filter(|_| !args.quiet) {
+            println!("{header}\n");
+        }
+
+        if args.ask_password {
+            answer_password_requests(prompt_timeout);
+        }
+
+        let login_result = login_attempt(
+            allow_autologin,
+            args.quiet,
+            prompter.clone(),
+            &args.user,
+            &command_retrieval,
+        );
 
         match login_result {
             Ok(succeeded) => match succeeded {
-                LoginResult::Success => break 'login_attempt,
+                LoginResult::Success { .. } | LoginResult::Authenticated { .. } => {
+                    break 'login_attempt
+                }
                 LoginResult::Failure => {
-                    eprintln!("Login attempt {}/{max_failures} failed.", attempt + 1)
+                    eprintln!(
+                        "{}",
+                        theme.error(&tr_args(
+                            "login-attempt-failed",
+                            "Login attempt { $attempt }/{ $max } failed.",
+                            &[
+                                ("attempt", (attempt + 1).to_string()),
+                                ("max", max_failures.to_string()),
+                            ],
+                        ))
+                    )
                 }
             },
             Err(err) => eprintln!(
-                "Login attempt {}/{max_failures} errored: {}",
-                attempt + 1,
-                err
+                "{}",
+                theme.error(&tr_args(
+                    "login-attempt-errored",
+                    "Login attempt { $attempt }/{ $max } errored: { $error }",
+                    &[
+                        ("attempt", (attempt + 1).to_string()),
+                        ("max", max_failures.to_string()),
+                        ("error", err.user_message()),
+                    ],
+                ))
             ),
         };
 
         // Clear out the screen to avoid disclosing past  user activities
         print!("{esc}[2J{esc}[1;1H", esc = 27 as char);
     }
+
+    if interrupted() {
+        if let Ok(mut guard) = prompter.lock() {
+            guard.wipe_secrets();
+        }
+
+        if let Some(password) = args.password.take() {
+            let mut bytes = password.into_bytes();
+            login_ng::secure::wipe(bytes.as_mut_slice());
+        }
+
+        if let Some(terminal_state) = &terminal_state {
+            terminal_state.restore();
+        }
+
+        print!("{CLEAR_SCREEN}");
+        let _ = std::io::stdout().flush();
+
+        std::process::exit(128 + interrupt_signal().unwrap_or(libc::SIGINT));
+    }
 }
@@ -22,24 +22,36 @@ use std::path::PathBuf;
 
 use chrono::Local;
 use chrono::TimeZone;
-use login_ng::command::SessionCommand;
-use login_ng::mount::MountParams;
+use login_ng::command::{PreferredSession, SessionCommand};
+use login_ng::config::{config_dir, init_config_dir, unsafe_config_files};
+use login_ng::desktop::find_session;
+use login_ng::environment::{
+    parse_nice, parse_umask, IoPriority, SessionEnvironment, SessionEnvironmentError,
+};
+use login_ng::faillock::{read_tally, reset_tally, user_lock_status, FaillockConfig, LockStatus};
+use login_ng::mount::{parse_size, MountParams};
+use login_ng::nextboot::{current_boot_id, NextBootAutologin, NextBootAutologinError};
+use login_ng::recovery::RecoveryTokens;
+use login_ng::storage::load_user_login_history;
 use login_ng::storage::load_user_mountpoints;
 use login_ng::storage::load_user_session_command;
+use login_ng::storage::load_user_session_environment;
 use login_ng::storage::store_user_mountpoints;
 use login_ng::storage::store_user_session_command;
+use login_ng::storage::store_user_session_environment;
 use login_ng::storage::StorageSource;
 use login_ng::storage::{load_user_auth_data, remove_user_data, store_user_auth_data};
 use login_ng::user::UserAuthData;
 
+use login_ng_user_interactions::generate::generate_from_env;
 use login_ng_user_interactions::prompt_password;
 
 #[cfg(feature = "pam")]
 use login_ng_user_interactions::pam_client2::{Context, Flag};
 
-use argh::FromArgs;
+use argh::{ArgsInfo, FromArgs};
 
-#[derive(FromArgs, PartialEq, Debug)]
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
 /// Command line tool for managing login-ng authentication methods
 struct Args {
     #[cfg(feature = "pam")]
@@ -63,7 +75,7 @@ struct Args {
     command: Command,
 }
 
-#[derive(FromArgs, PartialEq, Debug)]
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
 #[argh(subcommand)]
 /// Subcommands for managing authentication methods
 enum Command {
@@ -72,17 +84,23 @@ enum Command {
     Reset(ResetCommand),
     Inspect(InspectCommand),
     Add(AddAuthCommand),
+    Intermediate(IntermediateKeyCommand),
     SetSession(SetSessionCommand),
+    Session(SessionManagementCommand),
+    Environment(EnvironmentCommand),
     ChangeMainMount(ChangeMainMountCommand),
     ChangeSecondaryMount(ChangeSecondaryMountCommand),
+    Recovery(RecoveryCommand),
+    NextBootAutologin(NextBootAutologinCommand),
+    Init(InitCommand),
 }
 
-#[derive(FromArgs, PartialEq, Debug)]
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
 /// Print information about the software
 #[argh(subcommand, name = "info")]
 struct InfoCommand {}
 
-#[derive(FromArgs, PartialEq, Debug)]
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
 /// Set the mount command that has to be used to mount the user home directory
 #[argh(subcommand, name = "set-pre-mount")]
 struct ChangeSecondaryMountCommand {
@@ -91,7 +109,7 @@ struct ChangeSecondaryMountCommand {
     dir: String,
 
     #[argh(option)]
-    /// device to mount
+    /// device to mount: a path or UUID=, LABEL=, PARTUUID= or PARTLABEL=
     device: String,
 
     #[argh(option)]
@@ -101,14 +119,18 @@ struct ChangeSecondaryMountCommand {
     #[argh(option)]
     /// mount options relative to the filesystem type (corresponds to -o flag in mount)
     flags: Vec<String>,
+
+    #[argh(option)]
+    /// space the user can take on the mounted filesystem, as in 10G: applied by the service after mounting (xfs and ext4 mounted with prjquota, or btrfs)
+    quota: Option<String>,
 }
 
-#[derive(FromArgs, PartialEq, Debug)]
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
 /// Set the mount command that has to be used to mount the user home directory
 #[argh(subcommand, name = "set-home-mount")]
 struct ChangeMainMountCommand {
     #[argh(option)]
-    /// device to mount
+    /// device to mount: a path or UUID=, LABEL=, PARTUUID= or PARTLABEL=
     device: String,
 
     #[argh(option)]
@@ -118,9 +140,13 @@ struct ChangeMainMountCommand {
     #[argh(option)]
     /// mount options relative to the filesystem type (corresponds to -o flag in mount)
     flags: Vec<String>,
+
+    #[argh(option)]
+    /// space the user can take on the mounted filesystem, as in 10G: applied by the service after mounting (xfs and ext4 mounted with prjquota, or btrfs)
+    quota: Option<String>,
 }
 
-#[derive(FromArgs, PartialEq, Debug)]
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
 /// Setup initial authentication data also creating a new intermediate key
 #[argh(subcommand, name = "setup")]
 struct SetupCommand {
@@ -129,7 +155,7 @@ struct SetupCommand {
     intermediate: Option<String>,
 }
 
-#[derive(FromArgs, PartialEq, Debug)]
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
 /// Set the default session command to be executed when a user login if nothing else is being specified
 #[argh(subcommand, name = "set-session")]
 struct SetSessionCommand {
@@ -140,19 +166,144 @@ struct SetSessionCommand {
     #[argh(option)]
     /// additional arguments for the command
     args: Vec<String>,
+
+    #[argh(option)]
+    /// environment variable for the session in the KEY=VALUE form
+    env: Vec<String>,
+
+    #[argh(option)]
+    /// working directory the session is started in
+    cwd: Option<PathBuf>,
+}
+
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
+/// Manage the umask, locale and priorities the session is started with
+#[argh(subcommand, name = "environment")]
+struct EnvironmentCommand {
+    #[argh(subcommand)]
+    command: EnvironmentSubcommand,
+}
+
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
+#[argh(subcommand)]
+enum EnvironmentSubcommand {
+    Set(SetEnvironmentCommand),
+    Clear(ClearEnvironmentCommand),
+}
+
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
+/// Set the session environment, replacing the previous one
+#[argh(subcommand, name = "set")]
+struct SetEnvironmentCommand {
+    #[argh(option)]
+    /// octal file mode creation mask (for example 077)
+    umask: Option<String>,
+
+    #[argh(option)]
+    /// LANG, LANGUAGE or LC_* variable in the KEY=VALUE form
+    locale: Vec<String>,
+
+    #[argh(option)]
+    /// niceness of the session, from -20 to 19
+    nice: Option<String>,
+
+    #[argh(option)]
+    /// I/O scheduling: idle, best-effort[:0-7] or realtime[:0-7]
+    ionice: Option<String>,
+}
+
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
+/// Remove the session environment
+#[argh(subcommand, name = "clear")]
+struct ClearEnvironmentCommand {}
+
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
+/// Manage the preferred session stored in the user configuration
+#[argh(subcommand, name = "session")]
+struct SessionManagementCommand {
+    #[argh(subcommand)]
+    command: SessionManagementSubcommand,
+}
+
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
+#[argh(subcommand)]
+enum SessionManagementSubcommand {
+    Set(SetPreferredSessionCommand),
+    Clear(ClearPreferredSessionCommand),
+}
+
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
+/// Set the preferred session: either an installed desktop session or a command
+#[argh(subcommand, name = "set")]
+struct SetPreferredSessionCommand {
+    #[argh(option)]
+    /// desktop file name (without .desktop) or desktop name of an installed session
+    desktop: Option<String>,
+
+    #[argh(option)]
+    /// command to execute
+    cmd: Option<String>,
+
+    #[argh(option)]
+    /// additional arguments for the command
+    args: Vec<String>,
+
+    #[argh(option)]
+    /// environment variable for the session in the KEY=VALUE form
+    env: Vec<String>,
+
+    #[argh(option)]
+    /// working directory the session is started in
+    cwd: Option<PathBuf>,
 }
 
-#[derive(FromArgs, PartialEq, Debug)]
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
+/// Remove the preferred session
+#[argh(subcommand, name = "clear")]
+struct ClearPreferredSessionCommand {}
+
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
 /// Reset additional authentication data also destroying the intermediate key
 #[argh(subcommand, name = "reset")]
 struct ResetCommand {}
 
-#[derive(FromArgs, PartialEq, Debug)]
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
 /// Inspects user login settings
 #[argh(subcommand, name = "inspect")]
 struct InspectCommand {}
 
-#[derive(FromArgs, PartialEq, Debug)]
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
+/// Issue (as root) a single-use recovery token for a locked-out user: the user will have to change the password after using it
+#[argh(subcommand, name = "recovery")]
+struct RecoveryCommand {
+    #[argh(option)]
+    /// user the token is issued to
+    user: String,
+
+    #[argh(switch)]
+    /// revoke the token that has not been used yet instead of issuing a new one
+    revoke: bool,
+}
+
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
+/// Arm (as root) a single-use autologin for the next boot only, consumed by the greeter
+#[argh(subcommand, name = "next-boot-autologin")]
+struct NextBootAutologinCommand {
+    #[argh(option)]
+    /// user to log in automatically at the next boot
+    user: Option<String>,
+
+    #[argh(switch)]
+    /// remove the autologin that has not been consumed yet instead of arming a new one
+    cancel: bool,
+}
+
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
+/// Create (as root) the configuration directory with safe permissions and report unsafe configuration files
+#[argh(subcommand, name = "init")]
+struct InitCommand {}
+
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
 /// Add a new authentication method
 #[argh(subcommand, name = "add")]
 struct AddAuthCommand {
@@ -168,14 +319,14 @@ struct AddAuthCommand {
     method: AddAuthMethod,
 }
 
-#[derive(FromArgs, PartialEq, Debug)]
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
 #[argh(subcommand)]
 /// Subcommands for adding an authentication method
 enum AddAuthMethod {
     Password(AddAuthPasswordCommand),
 }
 
-#[derive(FromArgs, PartialEq, Debug)]
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
 /// Command to add a new authentication method
 #[argh(subcommand, name = "password")]
 struct AddAuthPasswordCommand {
@@ -184,9 +335,249 @@ struct AddAuthPasswordCommand {
     secondary_pw: Option<String>,
 }
 
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
+/// Manage additional intermediate keys, each one able to unlock the main password
+#[argh(subcommand, name = "intermediate")]
+struct IntermediateKeyCommand {
+    #[argh(subcommand)]
+    command: IntermediateKeyOperation,
+}
+
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
+#[argh(subcommand)]
+/// Subcommands for managing intermediate keys
+enum IntermediateKeyOperation {
+    Add(AddIntermediateKeyCommand),
+    Remove(RemoveIntermediateKeyCommand),
+}
+
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
+/// Add a new intermediate key
+#[argh(subcommand, name = "add")]
+struct AddIntermediateKeyCommand {
+    #[argh(option)]
+    /// name of the new intermediate key
+    name: String,
+
+    #[argh(option)]
+    /// an already existing intermediate key
+    intermediate: Option<String>,
+
+    #[argh(option)]
+    /// the new intermediate key
+    key: Option<String>,
+}
+
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
+/// Revoke an intermediate key: authentication methods unlocking it will stop working
+#[argh(subcommand, name = "remove")]
+struct RemoveIntermediateKeyCommand {
+    #[argh(option)]
+    /// name of the intermediate key to be removed
+    name: String,
+}
+
+fn init() {
+    if login_ng::users::get_current_uid() != 0 {
+        eprintln!("Only root can initialize the configuration directory");
+        std::process::exit(-1)
+    }
+
+    let dir = config_dir();
+
+    if let Err(err) = init_config_dir(dir) {
+        eprintln!(
+            "Error initializing the configuration directory {}: {err}",
+            dir.display()
+        );
+        std::process::exit(-1)
+    }
+
+    match unsafe_config_files(dir) {
+        Ok(unsafe_files) if unsafe_files.is_empty() => {
+            println!("Configuration directory {} is ready.", dir.display())
+        }
+        Ok(unsafe_files) => {
+            for err in unsafe_files.iter() {
+                eprintln!("{err}: the file will be ignored");
+            }
+            std::process::exit(-1)
+        }
+        Err(err) => {
+            eprintln!("Error checking the configuration files: {err}");
+            std::process::exit(-1)
+        }
+    }
+}
+
+fn recovery(recovery_cmd: &RecoveryCommand) {
+    if login_ng::users::get_current_uid() != 0 {
+        eprintln!("Only root can manage recovery tokens");
+        std::process::exit(-1)
+    }
+
+    if login_ng::users::get_user_by_name(&recovery_cmd.user).is_none() {
+        eprintln!("User {} does not exist", recovery_cmd.user);
+        std::process::exit(-1)
+    }
+
+    let recovery_tokens = RecoveryTokens::default();
+
+    match recovery_cmd.revoke {
+        true => match recovery_tokens.revoke(&recovery_cmd.user) {
+            Ok(_) => println!("Recovery token revoked."),
+            Err(err) => {
+                eprintln!("Error revoking the recovery token: {err}");
+                std::process::exit(-1)
+            }
+        },
+        false => match recovery_tokens.issue(&recovery_cmd.user) {
+            Ok(token) => {
+                // the token must be usable by a locked-out user: lift the lockout
+                if let Err(err) = reset_tally(&FaillockConfig::load(), &recovery_cmd.user) {
+                    eprintln!("Error resetting the failed login attempts: {err}");
+                }
+
+                println!("Recovery token for {}: {token}", recovery_cmd.user);
+                println!("The token can be used only once, in place of the password.");
+            }
+            Err(err) => {
+                eprintln!("Error issuing the recovery token: {err}");
+                std::process::exit(-1)
+            }
+        },
+    }
+}
+
+fn next_boot_autologin(autologin_cmd: &NextBootAutologinCommand) {
+    if login_ng::users::get_current_uid() != 0 {
+        eprintln!("Only root can manage the next boot autologin");
+        std::process::exit(-1)
+    }
+
+    let autologin = NextBootAutologin::default();
+
+    if autologin_cmd.cancel {
+        match autologin.disarm() {
+            Ok(_) => println!("Next boot autologin removed."),
+            Err(err) => {
+                eprintln!("Error removing the next boot autologin: {err}");
+                std::process::exit(-1)
+            }
+        }
+
+        return;
+    }
+
+    let Some(username) = &autologin_cmd.user else {
+        eprintln!("The user to log in at the next boot must be specified");
+        std::process::exit(-1)
+    };
+
+    if login_ng::users::get_user_by_name(username).is_none() {
+        eprintln!("User {username} does not exist");
+        std::process::exit(-1)
+    }
+
+    let armed = current_boot_id()
+        .map_err(NextBootAutologinError::from)
+        .and_then(|boot_id| autologin.arm(username, &boot_id));
+
+    match armed {
+        Ok(_) => {
+            println!("{username} will be logged in automatically at the next boot.");
+            println!("The autologin is used only once and only by greeters enabling it.");
+        }
+        Err(err) => {
+            eprintln!("Error arming the next boot autologin: {err}");
+            std::process::exit(-1)
+        }
+    }
+}
+
+fn session_environment(
+    set_cmd: SetEnvironmentCommand,
+) -> Result<SessionEnvironment, SessionEnvironmentError> {
+    let mut environment = SessionEnvironment::new()
+        .with_umask(set_cmd.umask.as_deref().map(parse_umask).transpose()?)?
+        .with_nice(set_cmd.nice.as_deref().map(parse_nice).transpose()?)?
+        .with_io_priority(
+            set_cmd
+                .ionice
+                .as_deref()
+                .map(|ionice| ionice.parse::<IoPriority>())
+                .transpose()?,
+        );
+
+    for pair in set_cmd.locale.iter() {
+        match pair.split_once('=') {
+            Some((key, value)) => {
+                environment = environment.with_locale(key.to_string(), value.to_string())?
+            }
+            None => return Err(SessionEnvironmentError::InvalidLocaleVariable(pair.clone())),
+        }
+    }
+
+    Ok(environment)
+}
+
+fn session_command(
+    cmd: String,
+    args: Vec<String>,
+    env: &[String],
+    cwd: Option<PathBuf>,
+) -> SessionCommand {
+    let mut command = SessionCommand::new(cmd).with_args(args).with_cwd(cwd);
+
+    for pair in env.iter() {
+        match pair.split_once('=') {
+            Some((key, value)) => command = command.with_env(key.to_string(), value.to_string()),
+            None => {
+                eprintln!("Invalid environment variable {pair}: expected KEY=VALUE");
+                std::process::exit(-1)
+            }
+        }
+    }
+
+    command
+}
+
+fn mount_quota(quota: &Option<String>) -> Option<u64> {
+    let quota = quota.as_ref()?;
+
+    match parse_size(quota) {
+        Some(quota) => Some(quota),
+        None => {
+            eprintln!("Invalid quota {quota}: expected a size such as 512M or 10G");
+            std::process::exit(-1)
+        }
+    }
+}
+
 fn main() {
+    // packagers generate the shell completions and the manual page with the hidden generate subcommand
+    if let Some(exit_code) = generate_from_env::<Args>("login_ng-ctl") {
+        std::process::exit(exit_code);
+    }
+
     let args: Args = argh::from_env();
 
+    // recovery tokens are managed by root on behalf of users that cannot authenticate
+    if let Command::Recovery(recovery_cmd) = &args.command {
+        recovery(recovery_cmd);
+        return;
+    }
+
+    if let Command::NextBootAutologin(autologin_cmd) = &args.command {
+        next_boot_autologin(autologin_cmd);
+        return;
+    }
+
+    if let Command::Init(_) = &args.command {
+        init();
+        return;
+    }
+
     #[cfg(not(feature = "pam"))]
     let (storage_source, maybe_main_password) = match args.directory {
         Some(path) => (StorageSource::Path(path), args.password),
@@ -297,28 +688,37 @@ fn main() {
                 std::process::exit(-1)
             };
 
-            user_mounts = Some(new_data.with_premount(
-                &mount_data.dir,
-                &MountParams::new(mount_data.device, mount_data.fstype, mount_data.flags),
-            ));
+            let quota = mount_quota(&mount_data.quota);
+
+            user_mounts = Some(
+                new_data.with_premount(
+                    &mount_data.dir,
+                    &MountParams::new(mount_data.device, mount_data.fstype, mount_data.flags)
+                        .with_quota(quota),
+                ),
+            );
 
             write_file = Some(true)
         }
         Command::ChangeMainMount(mount_data) => {
+            let quota = mount_quota(&mount_data.quota);
+
             user_mounts = Some(
-                user_mounts
-                    .unwrap_or_default()
-                    .with_mount(&MountParams::new(
-                        mount_data.device,
-                        mount_data.fstype,
-                        mount_data.flags,
-                    )),
+                user_mounts.unwrap_or_default().with_mount(
+                    &MountParams::new(mount_data.device, mount_data.fstype, mount_data.flags)
+                        .with_quota(quota),
+                ),
             );
 
             write_file = Some(true)
         }
         Command::SetSession(session_data) => {
-            let command = SessionCommand::new(session_data.cmd);
+            let command = session_command(
+                session_data.cmd,
+                session_data.args,
+                &session_data.env,
+                session_data.cwd,
+            );
 
             match store_user_session_command(&command, &storage_source) {
                 Ok(_) => {}
@@ -328,6 +728,53 @@ fn main() {
                 }
             }
         }
+        Command::Session(session_cmd) => {
+            match session_cmd.command {
+                SessionManagementSubcommand::Set(set_cmd) => {
+                    let preferred = match (set_cmd.desktop, set_cmd.cmd) {
+                        (Some(desktop), None) => {
+                            if find_session(desktop.as_str()).is_none() {
+                                eprintln!("Warning: no installed session matches {desktop}");
+                            }
+
+                            PreferredSession::Desktop(desktop)
+                        }
+                        (None, Some(cmd)) => PreferredSession::Command(session_command(
+                            cmd,
+                            set_cmd.args,
+                            &set_cmd.env,
+                            set_cmd.cwd,
+                        )),
+                        _ => {
+                            eprintln!("Exactly one of --desktop or --cmd must be specified");
+                            std::process::exit(-1)
+                        }
+                    };
+
+                    user_cfg.set_preferred_session(Some(preferred));
+                }
+                SessionManagementSubcommand::Clear(_) => user_cfg.set_preferred_session(None),
+            }
+
+            write_file = Some(true)
+        }
+        Command::Environment(environment_cmd) => {
+            let environment = match environment_cmd.command {
+                EnvironmentSubcommand::Set(set_cmd) => match session_environment(set_cmd) {
+                    Ok(environment) => environment,
+                    Err(err) => {
+                        eprintln!("{err}.\nAborting.");
+                        std::process::exit(-1)
+                    }
+                },
+                EnvironmentSubcommand::Clear(_) => SessionEnvironment::new(),
+            };
+
+            if let Err(err) = store_user_session_environment(&environment, &storage_source) {
+                eprintln!("Error saving the session environment: {err}");
+                std::process::exit(-1)
+            }
+        }
         Command::Setup(s) => {
             if user_cfg.has_main() {
                 eprintln!("User already has an intermediate key present: use reset if you want to delete the old one");
@@ -390,6 +837,11 @@ fn main() {
                     println!("Path: {}", path.to_string_lossy());
                     println!("-----------------------------------------------------------");
                 }
+                StorageSource::Chain(backends) => {
+                    println!("-----------------------------------------------------------");
+                    println!("Backends: {backends:?}");
+                    println!("-----------------------------------------------------------");
+                }
             }
 
             match user_mounts {
@@ -404,13 +856,19 @@ fn main() {
                     }
 
                     println!("args: {}", primary_mount.flags().join(","));
+                    if let Some(quota) = primary_mount.quota() {
+                        println!("quota: {quota} bytes");
+                    }
 
                     mount_info.foreach(|a, b| {
                         println!("***********************************************************");
                         println!("    directory: {}", a.clone());
                         println!("    device: {}", b.device().clone());
                         println!("    filesystem: {}", b.fstype().clone());
-                        println!("    args: {}", b.flags().join(","))
+                        println!("    args: {}", b.flags().join(","));
+                        if let Some(quota) = b.quota() {
+                            println!("    quota: {quota} bytes");
+                        }
                     });
                 }
                 None => println!("No user-defined mounts"),
@@ -422,7 +880,16 @@ fn main() {
                 Ok(maybe_data) => match maybe_data {
                     Some(data) => {
                         let cmd = data.command();
-                        println!("Default session command: {cmd}")
+                        println!("Default session command: {cmd}");
+                        if !data.args().is_empty() {
+                            println!("    args: {}", data.args().join(" "));
+                        }
+                        for pair in data.env_pairs().iter() {
+                            println!("    env: {pair}");
+                        }
+                        if let Some(cwd) = data.cwd() {
+                            println!("    cwd: {}", cwd.display());
+                        }
                     }
                     None => println!("No default session set."),
                 },
@@ -432,8 +899,87 @@ fn main() {
                 }
             };
 
+            match load_user_session_environment(&storage_source) {
+                Ok(Some(environment)) => {
+                    if let Some(umask) = environment.umask() {
+                        println!("Session umask: {umask:03o}");
+                    }
+                    for (key, value) in environment.locale().iter() {
+                        println!("Session locale: {key}={value}");
+                    }
+                    if let Some(nice) = environment.nice() {
+                        println!("Session niceness: {nice}");
+                    }
+                    if let Some(io_priority) = environment.io_priority() {
+                        println!("Session I/O scheduling: {io_priority}");
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => eprintln!("Error in reading the session environment: {err}"),
+            };
+
+            match user_cfg.preferred_session() {
+                Some(PreferredSession::Desktop(desktop)) => {
+                    println!("Preferred session: desktop {desktop}")
+                }
+                Some(PreferredSession::Command(command)) => {
+                    println!("Preferred session: {}", command.argv().join(" "))
+                }
+                None => println!("No preferred session set."),
+            }
+
             println!("-----------------------------------------------------------");
 
+            match load_user_login_history(&storage_source) {
+                Ok(history) => match history.last() {
+                    Some(last_login) => println!(
+                        "Last login: {} on {} {}",
+                        Local
+                            .timestamp_opt(last_login.timestamp() as i64, 0)
+                            .unwrap(),
+                        last_login.host(),
+                        last_login.line()
+                    ),
+                    None => println!("No logins recorded."),
+                },
+                Err(err) => eprintln!("Error in reading the user login history: {err}"),
+            };
+
+            if let StorageSource::Username(username) = &storage_source {
+                let faillock_config = FaillockConfig::load();
+                match read_tally(&faillock_config, username) {
+                    Ok(tally) => println!("Failed login attempts: {}", tally.len()),
+                    Err(err) => eprintln!("Error in reading the user failed logins: {err}"),
+                };
+
+                match user_lock_status(username) {
+                    Ok(LockStatus::Unlocked) => {}
+                    Ok(LockStatus::Locked { until: Some(until) }) => println!(
+                        "Account locked until {}",
+                        chrono::DateTime::<Local>::from(until)
+                    ),
+                    Ok(LockStatus::Locked { until: None }) => {
+                        println!("Account locked until reset by the administrator")
+                    }
+                    Err(err) => eprintln!("Error in reading the user lock status: {err}"),
+                };
+            }
+
+            println!("-----------------------------------------------------------");
+
+            if user_cfg.has_main() {
+                println!("Intermediate keys: primary");
+                for key in user_cfg.intermediate_keys() {
+                    println!(
+                        "    {} (created at: {})",
+                        key.name(),
+                        Local.timestamp_opt(key.creation_date() as i64, 0).unwrap()
+                    );
+                }
+
+                println!("-----------------------------------------------------------");
+            }
+
             let methods_count = user_cfg.secondary().len();
             match methods_count {
                 0 => {
@@ -465,6 +1011,67 @@ fn main() {
                 println!("-----------------------------------------------------------");
             }
         }
+        Command::Recovery(_) => unreachable!("recovery tokens are handled before authentication"),
+        Command::NextBootAutologin(_) => {
+            unreachable!("the next boot autologin is handled before authentication")
+        }
+        Command::Init(_) => unreachable!("initialization is handled before authentication"),
+        Command::Intermediate(intermediate_cmd) => match intermediate_cmd.command {
+            IntermediateKeyOperation::Add(add_cmd) => {
+                if !user_cfg.has_main() {
+                    eprintln!("Cannot add an intermediate key for an account with no main password: use setup first.\nAborting.");
+                    std::process::exit(-1);
+                }
+
+                let intermediate_key = add_cmd.intermediate.unwrap_or_else(|| {
+                    prompt_password("Intermediate key:").expect("Failed to read intermediate key")
+                });
+
+                let new_key = match add_cmd.key {
+                    Some(key) => key,
+                    None => {
+                        let key = prompt_password("New intermediate key:")
+                            .expect("Failed to read the new intermediate key");
+
+                        let repeat = prompt_password("New intermediate key (repeat):")
+                            .expect("Failed to read the new intermediate key (repeat)");
+                        if key != repeat {
+                            eprintln!("Intermediate keys do not match.\nAborting.");
+                            std::process::exit(-1)
+                        }
+
+                        key
+                    }
+                };
+
+                match user_cfg.add_intermediate_key(
+                    &add_cmd.name,
+                    intermediate_key.as_bytes(),
+                    new_key.as_bytes(),
+                ) {
+                    Ok(_) => {
+                        write_file = Some(true);
+                        println!("Intermediate key added.");
+                    }
+                    Err(err) => {
+                        eprintln!("Error adding an intermediate key: {}.\nAborting.", err);
+                        std::process::exit(-1);
+                    }
+                }
+            }
+            IntermediateKeyOperation::Remove(remove_cmd) => {
+                if !user_cfg.remove_intermediate_key(&remove_cmd.name) {
+                    eprintln!(
+                        "No intermediate key named {} found.\nAborting.",
+                        remove_cmd.name
+                    );
+                    std::process::exit(-1);
+                }
+
+                write_file = Some(true);
+                println!("Intermediate key removed.");
+            }
+        },
         Command::Add(add_cmd) => {
             let intermediate_password = match user_cfg.has_main() {
                 false => add_cmd.intermediate.clone().unwrap_or_else(|| {
@@ -500,9 +1107,10 @@ fn main() {
 
             // if the main password is accepted update the stored one
             if let Some(main_password) = maybe_main_password {
-                user_cfg
-                    .set_main(&main_password, &intermediate_password)
-                    .expect("Error handling main password");
+                if let Err(err) = user_cfg.set_main(&main_password, &intermediate_password) {
+                    eprintln!("Error updating the main password: {err}");
+                    std::process::exit(-1)
+                }
             }
 
             match add_cmd.method {
@@ -0,0 +1,174 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    os::{fd::AsRawFd, unix::net::UnixStream},
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use nix::{
+    sys::signal::{self, Signal},
+    unistd::Pid,
+};
+use tokio::{
+    process::{Child, Command},
+    time::{sleep, Duration, Instant},
+};
+
+use crate::errors::SessionBusError;
+
+/// Time the bus daemon is given to start listening
+const SESSION_BUS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Returns the path of the socket of the first unix:path= address of the given bus addresses
+pub fn bus_socket_path(address: &str) -> Option<PathBuf> {
+    address.split(';').find_map(|address| {
+        address
+            .strip_prefix("unix:")?
+            .split(',')
+            .find_map(|param| param.strip_prefix("path="))
+            .map(PathBuf::from)
+    })
+}
+
+/// Returns true if a bus is listening on the socket of the given address
+pub fn is_bus_running(address: &str) -> bool {
+    match bus_socket_path(address) {
+        Some(socket) => UnixStream::connect(socket).is_ok(),
+        None => false,
+    }
+}
+
+/// Look for the given program in the directories of PATH
+pub fn find_program(name: &str) -> Option<PathBuf> {
+    std::env::var_os("PATH").and_then(|path| {
+        std::env::split_paths(&path)
+            .map(|dir| dir.join(name))
+            .find(|program| program.is_file())
+    })
+}
+
+/// A session bus started for the session, when there is no user bus to join
+#[derive(Debug)]
+pub struct SessionBus {
+    child: Child,
+    socket: PathBuf,
+}
+
+impl SessionBus {
+    /// Start dbus-broker or, if it is not installed, dbus-daemon listening on the given socket:
+    /// a bus already answering there is never replaced
+    pub async fn start(socket: &Path) -> Result<Self, SessionBusError> {
+        // a socket left behind by a bus that is gone would make bind fail
+        if socket.exists() {
+            if UnixStream::connect(socket).is_ok() {
+                return Err(SessionBusError::AlreadyRunning(socket.to_path_buf()));
+            }
+
+            std::fs::remove_file(socket)?;
+        }
+
+        let mut child = match (
+            find_program("dbus-broker-launch"),
+            find_program("dbus-daemon"),
+        ) {
+            (Some(launcher), _) => Self::spawn_broker(&launcher, socket)?,
+            (None, Some(daemon)) => Command::new(daemon)
+                .args(["--session", "--nofork", "--nopidfile", "--nosyslog"])
+                .arg(format!("--address=unix:path={}", socket.display()))
+                .stdin(Stdio::null())
+                .kill_on_drop(true)
+                .spawn()?,
+            (None, None) => return Err(SessionBusError::NotInstalled),
+        };
+
+        let deadline = Instant::now() + SESSION_BUS_TIMEOUT;
+        while UnixStream::connect(socket).is_err() {
+            if let Some(status) = child.try_wait()? {
+                return Err(SessionBusError::Exited(status));
+            }
+
+            if Instant::now() >= deadline {
+                let _ = child.kill().await;
+                return Err(SessionBusError::Timeout(SESSION_BUS_TIMEOUT.as_secs()));
+            }
+
+            sleep(Duration::from_millis(100)).await;
+        }
+
+        Ok(Self {
+            child,
+            socket: socket.to_path_buf(),
+        })
+    }
+
+    /// dbus-broker only takes its listening socket as systemd would pass it:
+    /// the socket is bound here and handed over as fd 3.
+    fn spawn_broker(launcher: &Path, socket: &Path) -> Result<Child, SessionBusError> {
+        let listener = std::os::unix::net::UnixListener::bind(socket)?;
+        let fd = listener.as_raw_fd();
+
+        let mut command = Command::new("/bin/sh");
+        command
+            // LISTEN_PID must be the pid of the launcher: the one of the shell it replaces
+            .args(["-c", "LISTEN_PID=$$ exec \"$0\" \"$@\""])
+            .arg(launcher)
+            .args(["--scope", "user"])
+            .env("LISTEN_FDS", "1")
+            .stdin(Stdio::null())
+            .kill_on_drop(true);
+
+        unsafe {
+            command.pre_exec(move || {
+                // dup2 clears close-on-exec, that has to be done by hand if the fd is already 3
+                let result = match fd == 3 {
+                    true => libc::fcntl(fd, libc::F_SETFD, 0),
+                    false => libc::dup2(fd, 3),
+                };
+
+                match result < 0 {
+                    true => Err(std::io::Error::last_os_error()),
+                    false => Ok(()),
+                }
+            });
+        }
+
+        // the child holds its own copy of the listening socket
+        Ok(command.spawn()?)
+    }
+
+    /// Value of DBUS_SESSION_BUS_ADDRESS for clients of this bus
+    pub fn address(&self) -> String {
+        format!("unix:path={}", self.socket.display())
+    }
+
+    /// Ask the bus daemon to terminate, waiting for it to do so
+    pub async fn stop(mut self) {
+        if let Some(pid) = self.child.id() {
+            if let Err(err) = signal::kill(Pid::from_raw(pid as i32), Signal::SIGTERM) {
+                eprintln!("Error terminating the session bus: {err}");
+                let _ = self.child.kill().await;
+            }
+        }
+
+        let _ = self.child.wait().await;
+        let _ = std::fs::remove_file(&self.socket);
+    }
+}
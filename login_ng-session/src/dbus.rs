@@ -22,7 +22,7 @@ use std::sync::Arc;
 use serde::{Deserialize, Serialize};
 use zbus::interface;
 
-use crate::manager::SessionManager;
+use crate::{errors::SessionManagerError, manager::SessionManager};
 
 #[derive(Debug, Clone)]
 pub struct SessionManagerDBus {
@@ -49,9 +49,14 @@ pub struct TargetStatus {
 )]
 impl SessionManagerDBus {
     pub async fn start(&self, target: String) -> u32 {
-        self.manager.start(&target).await;
+        match self.manager.start(&target).await {
+            Ok(_) => 0u32,
+            Err(err) => {
+                eprintln!("Error starting {target}: {err}");
 
-        todo!()
+                error_code(&err)
+            }
+        }
     }
 
     pub async fn stop(&self, target: String) -> u32 {
@@ -95,11 +100,48 @@ impl SessionManagerDBus {
                     crate::errors::SessionManagerError::ManualActionError(error) => {
                         (3, format!("{error}"))
                     }
+                    crate::errors::SessionManagerError::InvalidEnvironment(error) => {
+                        (5, error.to_string())
+                    }
                 }
             }
         }
     }
 
+    /// Set KEY=VALUE variables in the environment of services started from now on
+    pub async fn set_environment(&self, assignments: Vec<String>) -> u32 {
+        match self.manager.set_environment(assignments.as_slice()).await {
+            Ok(_) => 0u32,
+            Err(err) => {
+                eprintln!("Error setting the environment: {err}");
+
+                error_code(&err)
+            }
+        }
+    }
+
+    /// Remove variables from the environment of services started from now on
+    pub async fn unset_environment(&self, names: Vec<String>) -> u32 {
+        match self.manager.unset_environment(names.as_slice()).await {
+            Ok(_) => 0u32,
+            Err(err) => {
+                eprintln!("Error unsetting the environment: {err}");
+
+                error_code(&err)
+            }
+        }
+    }
+
+    /// The environment services are started with, as KEY=VALUE assignments
+    pub async fn environment(&self) -> Vec<String> {
+        self.manager
+            .environment()
+            .await
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect()
+    }
+
     pub async fn change(&self, target: String, cmd: String, args: Vec<String>) -> u32 {
         todo!()
     }
@@ -108,3 +150,12 @@ impl SessionManagerDBus {
         todo!()
     }
 }
+
+fn error_code(err: &SessionManagerError) -> u32 {
+    match err {
+        SessionManagerError::ZbusError(_) => 1,
+        SessionManagerError::NotFound(_) => 2,
+        SessionManagerError::ManualActionError(_) => 3,
+        SessionManagerError::InvalidEnvironment(_) => 5,
+    }
+}
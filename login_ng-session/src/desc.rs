@@ -18,7 +18,7 @@
 */
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet},
     fs::File,
     io::Read,
     path::PathBuf,
@@ -43,7 +43,11 @@ pub struct NodeServiceDescriptor {
     args: Vec<String>,
     max_restarts: u64,
     restart_delay_secs: u64,
+    /// restart the node also when it exits successfully
+    restart_on_success: Option<bool>,
     dependencies: Vec<String>,
+    /// seconds to wait for the network to be online before starting the node
+    network_online_timeout_secs: Option<u64>,
 }
 
 impl NodeServiceDescriptor {
@@ -57,6 +61,32 @@ impl NodeServiceDescriptor {
         Self::find_and_load(hashmap, filename, directories, &mut currently_loading).await
     }
 
+    /// Load every service definition found in the given directories, together with its dependencies
+    pub async fn load_all(
+        hashmap: &mut HashMap<String, Arc<SessionNode>>,
+        directories: &[PathBuf],
+    ) -> NodeLoadingResult<()> {
+        let mut filenames = BTreeSet::new();
+        for dir in directories.iter() {
+            let Ok(entries) = std::fs::read_dir(dir) else {
+                continue;
+            };
+
+            for entry in entries.flatten() {
+                let filename = entry.file_name().to_string_lossy().into_owned();
+                if filename.ends_with(".service") {
+                    filenames.insert(filename);
+                }
+            }
+        }
+
+        for filename in filenames.iter() {
+            Self::load_tree(hashmap, filename, directories).await?;
+        }
+
+        Ok(())
+    }
+
     /// Attempts to find and load a session node from a specified file, checking for cyclic dependencies.
     ///
     /// This function searches for a file with the given `filename` in the provided `directories`.
@@ -102,11 +132,11 @@ impl NodeServiceDescriptor {
         // Add the current filename to the loading set
         currently_loading.insert(filename.clone());
 
-        // Check if the file is already loaded
+        // Check if the file is already loaded: nodes shared between trees are reused
         if hashmap.contains_key(filename) {
             // Remove from loading set before returning
             currently_loading.remove(filename);
-            return Err(NodeLoadingError::CyclicDependency(filename.clone()));
+            return Ok(());
         }
 
         let mut chosen = None;
@@ -195,9 +225,11 @@ impl NodeServiceDescriptor {
             main.cmd(),
             main.args(),
             stop_signal,
-            SessionNodeRestart::new(main.max_restarts(), main.delay()),
+            SessionNodeRestart::new(main.max_restarts(), main.delay())
+                .with_restart_on_success(main.restart_on_success()),
             dependencies,
-        );
+        )
+        .with_network_online_timeout(main.network_online_timeout());
 
         hashmap.insert(filename.clone(), Arc::new(node));
 
@@ -227,7 +259,15 @@ impl NodeServiceDescriptor {
         Duration::from_secs(self.restart_delay_secs)
     }
 
+    pub fn restart_on_success(&self) -> bool {
+        self.restart_on_success.unwrap_or(false)
+    }
+
     pub fn dependencies(&self) -> &[String] {
         self.dependencies.as_slice()
     }
+
+    pub fn network_online_timeout(&self) -> Option<Duration> {
+        self.network_online_timeout_secs.map(Duration::from_secs)
+    }
 }
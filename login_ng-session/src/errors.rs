@@ -18,7 +18,7 @@
 */
 
 use serde_json::error::Error as JSONError;
-use std::io::Error as IOError;
+use std::{io::Error as IOError, path::PathBuf};
 use thiserror::Error;
 use zbus::Error as ZError;
 
@@ -34,6 +34,9 @@ pub enum SessionManagerError {
 
     #[error("Error issuing manual action: {0}")]
     ManualActionError(#[from] ManualActionIssueError),
+
+    #[error("Invalid environment assignment: {0}")]
+    InvalidEnvironment(String),
 }
 
 #[derive(Debug, Error)]
@@ -56,6 +59,39 @@ pub enum NodeLoadingError {
 
 pub type NodeLoadingResult<T> = Result<T, NodeLoadingError>;
 
+#[derive(Debug, Error)]
+pub enum XServerError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] IOError),
+
+    #[error("No free X display")]
+    NoFreeDisplay,
+
+    #[error("The X server did not start within {0} seconds")]
+    Timeout(u64),
+
+    #[error("The X server exited: {0}")]
+    Exited(std::process::ExitStatus),
+}
+
+#[derive(Debug, Error)]
+pub enum SessionBusError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] IOError),
+
+    #[error("Neither dbus-broker nor dbus-daemon is installed")]
+    NotInstalled,
+
+    #[error("The session bus did not start within {0} seconds")]
+    Timeout(u64),
+
+    #[error("The session bus exited: {0}")]
+    Exited(std::process::ExitStatus),
+
+    #[error("A bus is already listening on {0}")]
+    AlreadyRunning(PathBuf),
+}
+
 #[derive(Debug, Error)]
 pub(crate) enum NodeDependencyError {
     #[error("I/O error: {0}")]
@@ -17,11 +17,16 @@
     51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 */
 
+pub mod bus;
 pub mod dbus;
 pub mod desc;
 pub mod errors;
+#[cfg(feature = "logind")]
+pub mod lock;
 pub mod manager;
 pub mod node;
+pub mod systemd;
+pub mod xserver;
 
 #[cfg(test)]
 pub(crate) mod tests;
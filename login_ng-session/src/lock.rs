@@ -0,0 +1,231 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{path::PathBuf, process::ExitStatus, time::Duration};
+
+use futures_util::StreamExt;
+use pam_login_ng_common::{
+    inhibit::INHIBIT_WHO,
+    logind::{logind_manager, logind_session, LogindManagerProxy, LogindSessionProxy},
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    process::{Child, Command},
+    time::sleep,
+};
+use zbus::{zvariant::OwnedFd, Connection};
+
+use crate::errors::{NodeLoadingError, NodeLoadingResult};
+
+/// Descriptor of the locker, searched in the same directories as services
+pub const LOCKER_FILE_NAME: &str = "locker.json";
+
+/// Time to wait before starting again a locker that exited without unlocking the session
+const LOCKER_RESPAWN_DELAY: Duration = Duration::from_secs(1);
+
+/// The program started when logind asks the session to lock: the session is
+/// unlocked only when it exits successfully, otherwise it is started again.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LockerDescriptor {
+    cmd: String,
+    args: Vec<String>,
+
+    /// lock the session before the system goes to sleep, so that it resumes locked
+    #[serde(default)]
+    lock_on_resume: Option<bool>,
+}
+
+impl Default for LockerDescriptor {
+    /// login_ng-cli re-authenticates the user with the main password or any of the secondary ones
+    fn default() -> Self {
+        Self {
+            cmd: String::from("login_ng-cli"),
+            args: vec![String::from("--lock")],
+            lock_on_resume: None,
+        }
+    }
+}
+
+impl LockerDescriptor {
+    /// Load the first locker.json found in the given directories, in order of preference:
+    /// None if there is none, as locking is opt-in.
+    pub fn load(directories: &[PathBuf]) -> NodeLoadingResult<Option<Self>> {
+        let Some(file) = directories
+            .iter()
+            .map(|dir| dir.join(LOCKER_FILE_NAME))
+            .find(|file| file.exists())
+        else {
+            return Ok(None);
+        };
+
+        let content = std::fs::read_to_string(file).map_err(NodeLoadingError::IOError)?;
+
+        serde_json::from_str::<Self>(content.as_str())
+            .map(Some)
+            .map_err(NodeLoadingError::JSONError)
+    }
+
+    pub fn cmd(&self) -> &String {
+        &self.cmd
+    }
+
+    pub fn args(&self) -> &[String] {
+        self.args.as_slice()
+    }
+
+    pub fn lock_on_resume(&self) -> bool {
+        self.lock_on_resume.unwrap_or_default()
+    }
+
+    fn spawn(&self) -> std::io::Result<Child> {
+        Command::new(self.cmd.as_str())
+            .args(self.args.as_slice())
+            .kill_on_drop(true)
+            .spawn()
+    }
+}
+
+/// Start the locker unless it is already running and tell logind the session is locked
+async fn start_locker(
+    locker: &LockerDescriptor,
+    running: &mut Option<Child>,
+    session: &LogindSessionProxy<'static>,
+) {
+    if running.is_none() {
+        match locker.spawn() {
+            Ok(child) => *running = Some(child),
+            Err(err) => eprintln!("Error starting the locker {}: {err}", locker.cmd()),
+        }
+    }
+
+    if let Err(err) = session.set_locked_hint(true).await {
+        eprintln!("Error setting the locked hint: {err}");
+    }
+}
+
+/// Take a delay inhibitor lock on sleep: logind waits for it to be released (at most InhibitDelayMaxSec)
+/// before suspending, leaving the time to lock the session
+async fn delay_sleep(manager: &LogindManagerProxy<'static>) -> Option<OwnedFd> {
+    match manager
+        .inhibit("sleep", INHIBIT_WHO, "Locking the session", "delay")
+        .await
+    {
+        Ok(fd) => Some(fd),
+        Err(err) => {
+            eprintln!("Error taking the sleep delay inhibitor lock: {err}");
+            None
+        }
+    }
+}
+
+async fn wait_locker(locker: &mut Option<Child>) -> std::io::Result<ExitStatus> {
+    match locker {
+        Some(child) => child.wait().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Follow the Lock and Unlock signals logind emits for the session this process belongs to:
+/// the locker is started on Lock and the session is unlocked once it exits successfully.
+/// If the locker asks so the session is also locked when the system is about to sleep.
+pub async fn watch_lock_signals(locker: LockerDescriptor) -> zbus::Result<()> {
+    let connection = Connection::system().await?;
+
+    let manager = logind_manager(&connection).await?;
+    let session_path = manager.get_session_by_pid(std::process::id()).await?;
+    let session = logind_session(&connection, session_path).await?;
+    let session_id = session.id().await?;
+
+    let mut lock_signals = session.receive_lock().await?;
+    let mut unlock_signals = session.receive_unlock().await?;
+    let mut sleep_signals = manager.receive_prepare_for_sleep().await?;
+
+    println!("Watching lock requests for session {session_id}");
+
+    let mut running: Option<Child> = None;
+
+    let mut sleep_delay = match locker.lock_on_resume() {
+        true => delay_sleep(&manager).await,
+        false => None,
+    };
+
+    loop {
+        tokio::select! {
+            Some(_) = lock_signals.next() => {
+                println!("Locking session {session_id}");
+
+                start_locker(&locker, &mut running, &session).await;
+            }
+            Some(signal) = sleep_signals.next(), if locker.lock_on_resume() => {
+                let going_to_sleep = match signal.args() {
+                    Ok(args) => args.start,
+                    Err(err) => {
+                        eprintln!("Error reading the PrepareForSleep signal: {err}");
+                        continue;
+                    }
+                };
+
+                // lock before sleeping so that nothing of the session is shown on resume,
+                // and request it to logind as well so that graphical lockers blank the outputs
+                if going_to_sleep {
+                    println!("Locking session {session_id} before sleeping");
+
+                    if let Err(err) = manager.lock_session(session_id.as_str()).await {
+                        eprintln!("Error requesting the lock of session {session_id}: {err}");
+                    }
+                }
+
+                // a resume without the session locked (as the locker failed to start) is retried
+                start_locker(&locker, &mut running, &session).await;
+
+                // the system is held until the session is locked, then the delay is taken again for the next time
+                match going_to_sleep {
+                    true => drop(sleep_delay.take()),
+                    false => sleep_delay = delay_sleep(&manager).await,
+                }
+            }
+            Some(_) = unlock_signals.next() => {
+                // unlocked from elsewhere (loginctl unlock-session or the locker itself)
+                if let Some(mut child) = running.take() {
+                    let _ = child.kill().await;
+                }
+
+                if let Err(err) = session.set_locked_hint(false).await {
+                    eprintln!("Error clearing the locked hint: {err}");
+                }
+            }
+            status = wait_locker(&mut running) => {
+                running = None;
+
+                match status {
+                    Ok(status) if status.success() => {
+                        println!("Unlocking session {session_id}");
+                        manager.unlock_session(session_id.as_str()).await?;
+                    }
+                    _ => {
+                        eprintln!("The locker exited without unlocking the session: starting it again");
+                        sleep(LOCKER_RESPAWN_DELAY).await;
+
+                        start_locker(&locker, &mut running, &session).await;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -20,18 +20,61 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
+use argh::FromArgs;
+
+use login_ng::environment::{load_environment_d, USER_ENVIRONMENT_D_DIR};
 use login_ng::users::{get_user_by_name, os::unix::UserExt};
+use login_ng::xorg::XServerConfig;
+use login_ng_session::bus::{is_bus_running, SessionBus};
 use login_ng_session::dbus::SessionManagerDBus;
 use login_ng_session::desc::NodeServiceDescriptor;
 use login_ng_session::errors::SessionManagerError;
 use login_ng_session::manager::SessionManager;
 use login_ng_session::node::{SessionNode, SessionNodeRestart, SessionNodeType};
+use login_ng_session::systemd::{SystemdManagerCompat, SYSTEMD_BUS_NAME, SYSTEMD_OBJECT_PATH};
+use login_ng_session::xserver::{session_vt, XServer};
 use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::signal::unix::{signal, SignalKind};
 use zbus::connection;
 
+#[derive(FromArgs, PartialEq, Debug)]
+/// Manager of the services making up a user session
+struct Args {
+    #[argh(option)]
+    /// run only the given command, restarting it forever even when it exits successfully (kiosk mode)
+    kiosk: Option<String>,
+
+    #[argh(option)]
+    /// argument of the kiosk command (can be repeated)
+    kiosk_arg: Vec<String>,
+
+    #[argh(option)]
+    /// seconds to wait before restarting the kiosk command (defaults to 2)
+    kiosk_restart_delay: Option<u64>,
+
+    #[argh(switch)]
+    /// run as the user service manager: start and stop services on demand until terminated
+    user: bool,
+
+    #[argh(option)]
+    /// run only the given command as the session, without restarting it
+    command: Option<String>,
+
+    #[argh(option)]
+    /// argument of the session command (can be repeated)
+    command_arg: Vec<String>,
+
+    #[argh(switch)]
+    /// start an X server on the VT of the session and run the session on it (X11 sessions)
+    x11: bool,
+}
+
 #[tokio::main]
 async fn main() -> Result<(), SessionManagerError> {
+    let args: Args = argh::from_env();
+
     let username = login_ng::users::get_current_username().unwrap();
 
     let user = get_user_by_name(username.as_os_str()).expect("Failed to get user information");
@@ -47,60 +90,102 @@ async fn main() -> Result<(), SessionManagerError> {
     let default_service_name = String::from("default.service");
 
     let mut nodes = HashMap::new();
-    match NodeServiceDescriptor::load_tree(
-        &mut nodes,
-        &default_service_name,
-        load_directories.as_slice(),
-    )
-    .await
-    {
-        Ok(_) => {}
-        Err(err) => match err {
-            login_ng_session::errors::NodeLoadingError::IOError(err) => {
-                eprintln!("File error: {err}");
-                std::process::exit(-1)
-            }
-            login_ng_session::errors::NodeLoadingError::FileNotFound(filename) => {
-                // if the default target is missing use the default user shell
-                if filename == default_service_name {
-                    let shell = user.shell().to_string_lossy().into_owned();
-
-                    eprintln!(
-                        "Definition for {default_service_name} not found: using shell {shell}"
-                    );
-
-                    nodes = HashMap::from([(
-                        default_service_name.clone(),
-                        Arc::new(SessionNode::new(
+    if let Some(kiosk_cmd) = args.kiosk {
+        // the kiosk application is the whole session: ignore user-defined services
+        nodes.insert(
+            default_service_name.clone(),
+            Arc::new(SessionNode::new(
+                default_service_name.clone(),
+                SessionNodeType::Service,
+                None,
+                kiosk_cmd,
+                args.kiosk_arg,
+                nix::sys::signal::Signal::SIGTERM,
+                SessionNodeRestart::always(Duration::from_secs(
+                    args.kiosk_restart_delay.unwrap_or(2),
+                )),
+                vec![],
+            )),
+        );
+    } else if let Some(session_cmd) = args.command {
+        // the session is the given command (a desktop session): ignore user-defined services
+        nodes.insert(
+            default_service_name.clone(),
+            Arc::new(SessionNode::new(
+                default_service_name.clone(),
+                SessionNodeType::Service,
+                None,
+                session_cmd,
+                args.command_arg,
+                nix::sys::signal::Signal::SIGTERM,
+                SessionNodeRestart::no_restart(),
+                vec![],
+            )),
+        );
+    } else if args.user {
+        // every service can be started on demand: default.service is just started first
+        if let Err(err) =
+            NodeServiceDescriptor::load_all(&mut nodes, load_directories.as_slice()).await
+        {
+            eprintln!("Error loading services: {err}");
+            std::process::exit(-1)
+        }
+    } else {
+        match NodeServiceDescriptor::load_tree(
+            &mut nodes,
+            &default_service_name,
+            load_directories.as_slice(),
+        )
+        .await
+        {
+            Ok(_) => {}
+            Err(err) => match err {
+                login_ng_session::errors::NodeLoadingError::IOError(err) => {
+                    eprintln!("File error: {err}");
+                    std::process::exit(-1)
+                }
+                login_ng_session::errors::NodeLoadingError::FileNotFound(filename) => {
+                    // if the default target is missing use the default user shell
+                    if filename == default_service_name {
+                        let shell = user.shell().to_string_lossy().into_owned();
+
+                        eprintln!(
+                            "Definition for {default_service_name} not found: using shell {shell}"
+                        );
+
+                        nodes = HashMap::from([(
                             default_service_name.clone(),
-                            SessionNodeType::Service,
-                            None,
-                            shell.clone(),
-                            vec![],
-                            nix::sys::signal::Signal::SIGTERM,
-                            SessionNodeRestart::no_restart(),
-                            vec![],
-                        )),
-                    )])
-                } else {
-                    eprintln!("Dependency not found: {filename}");
+                            Arc::new(SessionNode::new(
+                                default_service_name.clone(),
+                                SessionNodeType::Service,
+                                None,
+                                shell.clone(),
+                                vec![],
+                                nix::sys::signal::Signal::SIGTERM,
+                                SessionNodeRestart::no_restart(),
+                                vec![],
+                            )),
+                        )])
+                    } else {
+                        eprintln!("Dependency not found: {filename}");
+                        std::process::exit(-1)
+                    }
+                }
+                login_ng_session::errors::NodeLoadingError::CyclicDependency(filename) => {
+                    eprintln!("Cycle for target: {filename}");
                     std::process::exit(-1)
                 }
-            }
-            login_ng_session::errors::NodeLoadingError::CyclicDependency(filename) => {
-                eprintln!("Cycle for target: {filename}");
-                std::process::exit(-1)
-            }
-            login_ng_session::errors::NodeLoadingError::JSONError(err) => {
-                eprintln!("JSON deserialization error: {err}");
-                std::process::exit(-1)
-            }
-            login_ng_session::errors::NodeLoadingError::InvalidKind(err) => {
-                eprintln!("JSON syntax error: unrecognised kind value {err}");
-                std::process::exit(-1)
-            }
-        },
-    };
+                login_ng_session::errors::NodeLoadingError::JSONError(err) => {
+                    eprintln!("JSON deserialization error: {err}");
+                    std::process::exit(-1)
+                }
+                login_ng_session::errors::NodeLoadingError::InvalidKind(err) => {
+                    eprintln!("JSON syntax error: unrecognised kind value {err}");
+                    std::process::exit(-1)
+                }
+            },
+        };
+    }
 
     // the XDG_RUNTIME_DIR is required for generating the default dbus socket path
     // and also the runtime directory (hopefully /tmp mounted) to keep track of services
@@ -116,7 +201,25 @@ async fn main() -> Result<(), SessionManagerError> {
 
     std::fs::create_dir(manager_runtime_path.clone()).unwrap();
 
-    let manager = Arc::new(SessionManager::new(nodes));
+    // X clients started by the session find the server through the inherited environment
+    let mut x_server = None;
+    if args.x11 {
+        let config = XServerConfig::load(login_ng::config::config_dir());
+
+        match XServer::start(&config, session_vt(), &manager_runtime_path).await {
+            Ok(server) => {
+                println!("X server running on display {}", server.display_name());
+
+                std::env::set_var("DISPLAY", server.display_name());
+                std::env::set_var("XAUTHORITY", server.authority());
+                x_server = Some(server);
+            }
+            Err(err) => {
+                eprintln!("Error starting the X server: {err}");
+                std::process::exit(-1)
+            }
+        }
+    }
 
     // This is the default user dbus address
     // DBUS_SESSION_BUS_ADDRESS=unix:path=/run/user/1000/bus
@@ -136,7 +239,40 @@ async fn main() -> Result<(), SessionManagerError> {
         }
     }
 
-    let dbus_manager = connection::Builder::session()
+    // without a user bus the session gets its own one, so that commands don't need dbus-run-session:
+    // a bus already answering on the default socket (i.e. of another session of the user) is joined instead
+    let mut session_bus = None;
+    let default_bus = format!("unix:path={}", xdg_runtime_dir.join("bus").display());
+    if !is_bus_running(std::env::var("DBUS_SESSION_BUS_ADDRESS").unwrap().as_str()) {
+        if is_bus_running(&default_bus) {
+            println!("Joining the session bus on {default_bus}");
+
+            std::env::set_var("DBUS_SESSION_BUS_ADDRESS", default_bus.as_str());
+        } else {
+            match SessionBus::start(&xdg_runtime_dir.join("bus")).await {
+                Ok(bus) => {
+                    println!("Started the session bus on {}", bus.address());
+
+                    std::env::set_var("DBUS_SESSION_BUS_ADDRESS", bus.address());
+                    session_bus = Some(bus);
+                }
+                Err(err) => eprintln!("Error starting the session bus: {err}"),
+            }
+        }
+    }
+
+    // nodes inherit the environment of the manager, dbus address included
+    let manager = match args.user {
+        // the PAM-provided environment is inherited: environment.d only fills the gaps
+        true => SessionManager::new(nodes).with_imported_environment(load_environment_d(
+            &user.home_dir().join(USER_ENVIRONMENT_D_DIR),
+            user.uid(),
+        )),
+        false => SessionManager::new(nodes),
+    };
+    let manager = Arc::new(manager);
+
+    let mut dbus_builder = connection::Builder::session()
         .map_err(SessionManagerError::ZbusError)?
         .name("org.neroreflex.login_ng_service")
         .map_err(SessionManagerError::ZbusError)?
@@ -144,16 +280,114 @@ async fn main() -> Result<(), SessionManagerError> {
             "/org/zbus/login_ng_service",
             SessionManagerDBus::new(manager.clone()),
         )
-        .map_err(SessionManagerError::ZbusError)?
+        .map_err(SessionManagerError::ZbusError)?;
+
+    if args.user {
+        dbus_builder = dbus_builder
+            .name(SYSTEMD_BUS_NAME)
+            .map_err(SessionManagerError::ZbusError)?
+            .serve_at(
+                SYSTEMD_OBJECT_PATH,
+                SystemdManagerCompat::new(manager.clone()),
+            )
+            .map_err(SessionManagerError::ZbusError)?;
+    }
+
+    let dbus_manager = dbus_builder
         .build()
         .await
         .map_err(SessionManagerError::ZbusError)?;
 
+    if args.user {
+        // services activated by the bus daemon should see the same environment as ours
+        let environment = manager.environment().await;
+        let activation_environment = environment
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect::<HashMap<_, _>>();
+
+        match zbus::fdo::DBusProxy::new(&dbus_manager).await {
+            Ok(proxy) => {
+                if let Err(err) = proxy
+                    .update_activation_environment(activation_environment)
+                    .await
+                {
+                    eprintln!("Error updating the dbus activation environment: {err}");
+                }
+            }
+            Err(err) => eprintln!("Error connecting to the dbus daemon: {err}"),
+        }
+    }
+
+    #[cfg(feature = "logind")]
+    match login_ng_session::lock::LockerDescriptor::load(load_directories.as_slice()) {
+        Ok(Some(locker)) => {
+            tokio::spawn(async move {
+                if let Err(err) = login_ng_session::lock::watch_lock_signals(locker).await {
+                    eprintln!("Session lock disabled: {err}");
+                }
+            });
+        }
+        Ok(None) => {}
+        Err(err) => eprintln!("Error loading the locker: {err}"),
+    }
+
+    if args.user {
+        let mut sigterm = signal(SignalKind::terminate()).expect("Failed to handle SIGTERM");
+
+        println!("Running the user service manager");
+
+        if manager.contains(&default_service_name) {
+            manager.start(&default_service_name).await?;
+        }
+
+        // like systemd --user this outlives the default service: stop everything once terminated
+        sigterm.recv().await;
+
+        manager.stop_all().await;
+
+        drop(dbus_manager);
+
+        if let Some(session_bus) = session_bus {
+            session_bus.stop().await;
+        }
+
+        return Ok(());
+    }
+
     println!("Running the session manager");
 
-    manager.run(&default_service_name).await?;
+    match x_server {
+        Some(mut x_server) => {
+            // the session lasts as long as both the X server and the default service do
+            let session = manager.run(&default_service_name);
+            tokio::pin!(session);
+
+            tokio::select! {
+                res = &mut session => {
+                    x_server.stop().await;
+                    res?;
+                }
+                status = x_server.wait() => {
+                    match status {
+                        Ok(status) => eprintln!("The X server exited: {status}"),
+                        Err(err) => eprintln!("Error waiting for the X server: {err}"),
+                    }
+
+                    manager.stop_all().await;
+                    session.await?;
+                }
+            }
+        }
+        None => manager.run(&default_service_name).await?,
+    }
 
     drop(dbus_manager);
 
+    // every service might be using the bus: it goes away last
+    if let Some(session_bus) = session_bus {
+        session_bus.stop().await;
+    }
+
     Ok(())
 }
@@ -17,9 +17,15 @@
     51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 */
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
 
-use tokio::task::{self, JoinSet};
+use tokio::{
+    sync::RwLock,
+    task::{self, JoinSet},
+};
 
 use crate::{
     errors::SessionManagerError,
@@ -36,9 +42,31 @@ impl ManagerStatus {
     }
 }
 
+/// Split a KEY=VALUE environment assignment, refusing empty or malformed names
+pub fn parse_environment_assignment(assignment: &str) -> Option<(String, String)> {
+    let (key, value) = assignment.split_once('=')?;
+
+    match is_valid_environment_name(key) && !value.contains('\0') {
+        true => Some((key.to_string(), value.to_string())),
+        false => None,
+    }
+}
+
+pub fn is_valid_environment_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct SessionManager {
     services: HashMap<String, Arc<SessionNode>>,
+    environment: RwLock<BTreeMap<String, String>>,
 }
 
 impl SessionManager {
@@ -48,7 +76,84 @@ impl SessionManager {
             .map(|(name, node)| (name.clone(), node.clone()))
             .collect::<HashMap<String, Arc<SessionNode>>>();
 
-        Self { services }
+        let environment = RwLock::new(std::env::vars().collect());
+
+        Self {
+            services,
+            environment,
+        }
+    }
+
+    /// Add the given variables to the environment of nodes, without overriding the inherited ones
+    pub fn with_imported_environment(self, imported: BTreeMap<String, String>) -> Self {
+        let mut environment = self.environment.into_inner();
+        for (key, value) in imported.into_iter() {
+            environment.entry(key).or_insert(value);
+        }
+
+        Self {
+            services: self.services,
+            environment: RwLock::new(environment),
+        }
+    }
+
+    /// The environment nodes started from now on are spawned with
+    pub async fn environment(&self) -> BTreeMap<String, String> {
+        self.environment.read().await.clone()
+    }
+
+    /// Set the given KEY=VALUE variables: nothing is changed if any of those is malformed
+    pub async fn set_environment(&self, assignments: &[String]) -> Result<(), SessionManagerError> {
+        let parsed = assignments
+            .iter()
+            .map(|assignment| {
+                parse_environment_assignment(assignment)
+                    .ok_or(SessionManagerError::InvalidEnvironment(assignment.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.environment.write().await.extend(parsed);
+
+        Ok(())
+    }
+
+    /// Remove the given variables (names or KEY=VALUE assignments, removed only on a value match)
+    pub async fn unset_environment(&self, names: &[String]) -> Result<(), SessionManagerError> {
+        if let Some(invalid) = names.iter().find(|name| match name.split_once('=') {
+            Some(_) => parse_environment_assignment(name).is_none(),
+            None => !is_valid_environment_name(name),
+        }) {
+            return Err(SessionManagerError::InvalidEnvironment(invalid.clone()));
+        }
+
+        let mut environment = self.environment.write().await;
+        for name in names.iter() {
+            match name.split_once('=') {
+                Some((key, value)) => {
+                    if environment.get(key).is_some_and(|current| current == value) {
+                        environment.remove(key);
+                    }
+                }
+                None => {
+                    environment.remove(name);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn environment_vars(&self) -> Vec<(String, String)> {
+        self.environment
+            .read()
+            .await
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    pub fn contains(&self, target: &String) -> bool {
+        self.services.contains_key(target)
     }
 
     pub async fn is_running(&self, target: &String) -> Result<bool, SessionManagerError> {
@@ -58,8 +163,36 @@ impl SessionManager {
         }
     }
 
+    /// Start a node together with its dependencies that are not running yet,
+    /// returning false if the node was running already
     pub async fn start(&self, target: &String) -> Result<bool, SessionManagerError> {
-        todo!()
+        let Some(node) = self.services.get(target).cloned() else {
+            return Err(SessionManagerError::NotFound(target.clone()));
+        };
+
+        let environment = self.environment_vars().await;
+
+        let mut started = false;
+        let mut visited = HashSet::new();
+        let mut pending = vec![node];
+        while let Some(node) = pending.pop() {
+            if !visited.insert(node.name().to_string()) {
+                continue;
+            }
+
+            pending.extend(node.dependencies().iter().cloned());
+
+            if node.is_running().await {
+                continue;
+            }
+
+            started |= node.name() == target.as_str();
+
+            let env = environment.clone();
+            task::spawn(async move { SessionNode::run_with_environment(node, false, env).await });
+        }
+
+        Ok(started)
     }
 
     pub async fn stop(&self, target: &String) -> Result<(), SessionManagerError> {
@@ -70,6 +203,21 @@ impl SessionManager {
         self.manual_action(target, ManualAction::Restart).await
     }
 
+    /// Stop every running node
+    pub async fn stop_all(&self) {
+        for (node_name, node_value) in self.services.iter() {
+            if !node_value.is_running().await {
+                continue;
+            }
+
+            if let Err(err) =
+                SessionNode::issue_manual_action(node_value.clone(), ManualAction::Stop).await
+            {
+                eprintln!("Error stopping {node_name}: {err}");
+            }
+        }
+    }
+
     async fn manual_action(
         &self,
         target: &String,
@@ -109,18 +257,23 @@ impl SessionManager {
             return Err(SessionManagerError::NotFound(target.clone()));
         };
 
+        let environment = self.environment_vars().await;
+
         // start all services and let those sync themselves
         let node_run_tasks = other_nodes
             .iter()
             .map(|node| {
                 let n = node.clone();
-                async move { SessionNode::run(n, false).await }
+                let env = environment.clone();
+                async move { SessionNode::run_with_environment(n, false, env).await }
             })
             .collect::<JoinSet<_>>();
 
         // wait for the target run to exit
         let (main_node_res, other_nodes_res) = tokio::join!(
-            task::spawn(async move { SessionNode::run(main_node, true).await }),
+            task::spawn(async move {
+                SessionNode::run_with_environment(main_node, true, environment).await
+            }),
             node_run_tasks.join_all()
         );
 
@@ -36,26 +36,49 @@ use tokio::{
     time::{self, sleep, Instant},
 };
 
+use login_ng::network::wait_network_online;
+
 use crate::errors::{NodeDependencyError, NodeDependencyResult};
 
 #[derive(Debug)]
 pub struct SessionNodeRestart {
     max_times: u64,
     delay: Duration,
+    on_success: bool,
 }
 
 impl SessionNodeRestart {
     pub fn new(max_times: u64, delay: Duration) -> Self {
-        Self { max_times, delay }
+        Self {
+            max_times,
+            delay,
+            on_success: false,
+        }
     }
 
     pub fn no_restart() -> Self {
         Self {
             max_times: u64::MIN,
             delay: Duration::from_secs(5),
+            on_success: false,
+        }
+    }
+
+    /// Restart the node forever, even when it exits successfully (kiosk applications)
+    pub fn always(delay: Duration) -> Self {
+        Self {
+            max_times: u64::MAX,
+            delay,
+            on_success: true,
         }
     }
 
+    /// Also restart the node when it exits successfully
+    pub fn with_restart_on_success(mut self, on_success: bool) -> Self {
+        self.on_success = on_success;
+        self
+    }
+
     pub fn max_times(&self) -> u64 {
         self.max_times
     }
@@ -63,6 +86,10 @@ impl SessionNodeRestart {
     pub fn delay(&self) -> Duration {
         self.delay
     }
+
+    pub fn on_success(&self) -> bool {
+        self.on_success
+    }
 }
 
 impl Default for SessionNodeRestart {
@@ -70,6 +97,7 @@ impl Default for SessionNodeRestart {
         Self {
             max_times: u64::MAX,
             delay: Duration::from_secs(5),
+            on_success: false,
         }
     }
 }
@@ -140,6 +168,7 @@ pub struct SessionNode {
     cmd: String,
     args: Vec<String>,
     dependencies: Vec<Arc<SessionNode>>,
+    network_online_timeout: Option<Duration>,
     status: Arc<RwLock<SessionNodeStatus>>,
     status_notify: Arc<Notify>,
 }
@@ -169,21 +198,52 @@ impl SessionNode {
             restart,
             stop_signal,
             dependencies,
+            network_online_timeout: None,
             status,
             status_notify,
         }
     }
 
-    pub async fn run(node: Arc<SessionNode>, main: bool) -> RunResult {
-        assert_send_sync::<Arc<SessionNode>>();
+    /// Delays the first start of the node until the network is online (or the timeout expires)
+    pub fn with_network_online_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.network_online_timeout = timeout;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
 
+    pub fn dependencies(&self) -> &[Arc<SessionNode>] {
+        self.dependencies.as_slice()
+    }
+
+    pub async fn run(node: Arc<SessionNode>, main: bool) -> RunResult {
         // Store environments at the beginning and reuse them later to ensure no bad env is carried over
         let environment = std::env::vars().collect::<Vec<_>>();
 
+        Self::run_with_environment(node, main, environment).await
+    }
+
+    /// Run the node spawning its process (and every restart of it) with the given environment
+    pub async fn run_with_environment(
+        node: Arc<SessionNode>,
+        main: bool,
+        environment: Vec<(String, String)>,
+    ) -> RunResult {
+        assert_send_sync::<Arc<SessionNode>>();
+
         let name = node.name.clone();
 
         let mut restarted: u64 = 0;
 
+        // network-dependent services would only fail and burn restarts on fast boots
+        if let Some(timeout) = node.network_online_timeout {
+            if let Err(err) = wait_network_online(timeout).await {
+                eprintln!("Network not online for {name}, starting it anyway: {err}");
+            }
+        }
+
         loop {
             restarted += 1;
             let will_restart_if_failed = restarted <= node.restart.max_times();
@@ -306,7 +366,7 @@ impl SessionNode {
                             None => match &last_exec_result {
                                 RunResult::Exited(result) => {
                                     success = result.success();
-                                    SessionNodeStatus::Stopped { time: Instant::now(), restart: !result.success() && will_restart_if_failed, reason: SessionNodeStopReason::Completed(*result) }
+                                    SessionNodeStatus::Stopped { time: Instant::now(), restart: (!result.success() || node.restart.on_success()) && will_restart_if_failed, reason: SessionNodeStopReason::Completed(*result) }
                                 },
                                 RunResult::Error => {
                                     SessionNodeStatus::Stopped { time: Instant::now(), restart: will_restart_if_failed, reason: SessionNodeStopReason::Errored /*(err)*/ }
@@ -353,7 +413,7 @@ impl SessionNode {
                 None => {
                     // node exited (either successfully or with an error)
                     // attempt to sleep before restarting it
-                    if will_restart_if_failed && !success {
+                    if will_restart_if_failed && (!success || node.restart.on_success()) {
                         sleep(node.restart.delay()).await;
                         continue;
                     }
@@ -21,6 +21,7 @@ use std::path::PathBuf;
 
 use argh::FromArgs;
 use login_ng_session::dbus::SessionManagerDBusProxy;
+use pam_login_ng_common::session::SessionsProxy;
 use zbus::Connection;
 
 #[derive(FromArgs, PartialEq, Debug)]
@@ -42,6 +43,7 @@ enum Command {
     Start(StartCommand),
     Stop(StopCommand),
     Restart(RestartCommand),
+    SwitchUser(SwitchUserCommand),
 }
 
 #[derive(FromArgs, PartialEq, Debug)]
@@ -64,6 +66,15 @@ struct StopCommand {}
 #[argh(subcommand, name = "restart")]
 struct RestartCommand {}
 
+#[derive(FromArgs, PartialEq, Debug)]
+/// Switch to the session of another user or start a greeter on a free VT (bind it to a key)
+#[argh(subcommand, name = "switch-user")]
+struct SwitchUserCommand {
+    #[argh(option, short = 'u')]
+    /// user to switch to: a greeter is started if omitted or if the user has no open session
+    user: Option<String>,
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // the XDG_RUNTIME_DIR is required for generating the default dbus socket path
@@ -85,11 +96,29 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         )
     }
 
+    let args: Args = argh::from_env();
+
+    // fast user switching is handled by pam_login_ng-service on the system bus
+    if let Command::SwitchUser(switch_command) = &args.command {
+        let connection = Connection::system().await?;
+        let proxy = SessionsProxy::new(&connection).await?;
+
+        let username = switch_command.user.clone().unwrap_or_default();
+        let (report, vt) = proxy.switch_user(username.as_str()).await?;
+        match report.is_ok() {
+            true => println!("Switched to VT {vt}"),
+            false => {
+                eprintln!("Cannot switch user: {report}");
+                std::process::exit(1)
+            }
+        }
+
+        return Ok(());
+    }
+
     let connection = Connection::session().await?;
     let proxy = SessionManagerDBusProxy::new(&connection).await?;
 
-    let args: Args = argh::from_env();
-
     let target = match &args.target {
         Some(t) => t.clone(),
         None => String::from("default.service"),
@@ -105,6 +134,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         Command::Start(_start_command) => {
             proxy.start(target).await.unwrap();
         }
+        Command::SwitchUser(_switch_command) => unreachable!(),
         Command::Inspect(_inspect_command) => {
             let (status, result) = proxy.inspect(target).await.unwrap();
             if status == 0 {
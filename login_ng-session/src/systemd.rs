@@ -0,0 +1,134 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
+
+use zbus::{fdo, interface, zvariant::OwnedObjectPath};
+
+use crate::{errors::SessionManagerError, manager::SessionManager};
+
+/// Well-known name taken on the user bus when running as the user service manager
+pub const SYSTEMD_BUS_NAME: &str = "org.freedesktop.systemd1";
+
+pub const SYSTEMD_OBJECT_PATH: &str = "/org/freedesktop/systemd1";
+
+/// The subset of org.freedesktop.systemd1.Manager desktops and
+/// dbus-update-activation-environment --systemd rely upon for the user instance
+#[derive(Debug)]
+pub struct SystemdManagerCompat {
+    manager: Arc<SessionManager>,
+    last_job: AtomicU32,
+}
+
+impl SystemdManagerCompat {
+    pub fn new(manager: Arc<SessionManager>) -> Self {
+        Self {
+            manager,
+            last_job: AtomicU32::new(0),
+        }
+    }
+
+    /// Jobs complete immediately: the returned path only satisfies callers expecting one
+    fn job(&self) -> fdo::Result<OwnedObjectPath> {
+        let id = self.last_job.fetch_add(1, Ordering::Relaxed) + 1;
+
+        OwnedObjectPath::try_from(format!("{SYSTEMD_OBJECT_PATH}/job/{id}"))
+            .map_err(|err| fdo::Error::Failed(format!("{err}")))
+    }
+}
+
+fn to_fdo(err: SessionManagerError) -> fdo::Error {
+    match err {
+        SessionManagerError::InvalidEnvironment(_) => fdo::Error::InvalidArgs(format!("{err}")),
+        SessionManagerError::NotFound(_) => fdo::Error::FileNotFound(format!("{err}")),
+        _ => fdo::Error::Failed(format!("{err}")),
+    }
+}
+
+#[interface(name = "org.freedesktop.systemd1.Manager")]
+impl SystemdManagerCompat {
+    async fn start_unit(&self, name: String, _mode: String) -> fdo::Result<OwnedObjectPath> {
+        self.manager.start(&name).await.map_err(to_fdo)?;
+
+        self.job()
+    }
+
+    async fn stop_unit(&self, name: String, _mode: String) -> fdo::Result<OwnedObjectPath> {
+        // stopping a unit that is not running is not an error
+        if self.manager.is_running(&name).await.map_err(to_fdo)? {
+            self.manager.stop(&name).await.map_err(to_fdo)?;
+        }
+
+        self.job()
+    }
+
+    async fn restart_unit(&self, name: String, _mode: String) -> fdo::Result<OwnedObjectPath> {
+        match self.manager.is_running(&name).await.map_err(to_fdo)? {
+            true => self.manager.restart(&name).await.map_err(to_fdo)?,
+            false => {
+                self.manager.start(&name).await.map_err(to_fdo)?;
+            }
+        }
+
+        self.job()
+    }
+
+    async fn set_environment(&self, assignments: Vec<String>) -> fdo::Result<()> {
+        self.manager
+            .set_environment(assignments.as_slice())
+            .await
+            .map_err(to_fdo)
+    }
+
+    async fn unset_environment(&self, names: Vec<String>) -> fdo::Result<()> {
+        self.manager
+            .unset_environment(names.as_slice())
+            .await
+            .map_err(to_fdo)
+    }
+
+    async fn unset_and_set_environment(
+        &self,
+        names: Vec<String>,
+        assignments: Vec<String>,
+    ) -> fdo::Result<()> {
+        self.manager
+            .unset_environment(names.as_slice())
+            .await
+            .map_err(to_fdo)?;
+
+        self.manager
+            .set_environment(assignments.as_slice())
+            .await
+            .map_err(to_fdo)
+    }
+
+    #[zbus(property)]
+    async fn environment(&self) -> Vec<String> {
+        self.manager
+            .environment()
+            .await
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect()
+    }
+}
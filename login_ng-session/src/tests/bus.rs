@@ -0,0 +1,80 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::path::PathBuf;
+
+use crate::{
+    bus::{bus_socket_path, find_program, is_bus_running, SessionBus},
+    errors::SessionBusError,
+};
+
+#[test]
+fn bus_address_parsing() {
+    assert_eq!(
+        bus_socket_path("unix:path=/run/user/1000/bus"),
+        Some(PathBuf::from("/run/user/1000/bus"))
+    );
+    assert_eq!(
+        bus_socket_path("tcp:host=localhost,port=4000;unix:guid=0123,path=/tmp/bus"),
+        Some(PathBuf::from("/tmp/bus"))
+    );
+    assert_eq!(bus_socket_path("unix:abstract=/tmp/dbus-abcdef"), None);
+    assert_eq!(bus_socket_path(""), None);
+}
+
+#[test]
+fn running_bus_detection() {
+    let socket = PathBuf::from("./running_bus_detection_test");
+    let _ = std::fs::remove_file(&socket);
+
+    let address = format!("unix:path={}", socket.display());
+    assert!(!is_bus_running(address.as_str()));
+
+    let listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+    assert!(is_bus_running(address.as_str()));
+
+    // a socket nobody listens on anymore is not a bus
+    drop(listener);
+    assert!(!is_bus_running(address.as_str()));
+
+    std::fs::remove_file(&socket).unwrap();
+}
+
+#[tokio::test]
+async fn running_bus_kept() {
+    let socket = PathBuf::from("./running_bus_kept_test");
+    let _ = std::fs::remove_file(&socket);
+
+    // a bus answering on the socket is never unlinked to start another one
+    let listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+    assert!(matches!(
+        SessionBus::start(&socket).await,
+        Err(SessionBusError::AlreadyRunning(_))
+    ));
+    assert!(socket.exists());
+
+    drop(listener);
+    std::fs::remove_file(&socket).unwrap();
+}
+
+#[test]
+fn program_lookup() {
+    assert!(find_program("sh").is_some());
+    assert_eq!(find_program("surely-not-an-installed-program"), None);
+}
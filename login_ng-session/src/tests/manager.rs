@@ -0,0 +1,91 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::manager::{parse_environment_assignment, SessionManager};
+
+#[test]
+fn test_environment_assignment() {
+    assert_eq!(
+        parse_environment_assignment("XDG_CURRENT_DESKTOP=sway:wlroots"),
+        Some((
+            String::from("XDG_CURRENT_DESKTOP"),
+            String::from("sway:wlroots")
+        ))
+    );
+    assert_eq!(
+        parse_environment_assignment("EMPTY="),
+        Some((String::from("EMPTY"), String::new()))
+    );
+    assert_eq!(parse_environment_assignment("NO_VALUE"), None);
+    assert_eq!(parse_environment_assignment("=value"), None);
+    assert_eq!(parse_environment_assignment("1ST=value"), None);
+    assert_eq!(parse_environment_assignment("WITH SPACE=value"), None);
+}
+
+#[tokio::test]
+async fn test_environment_import() {
+    let manager = SessionManager::new(HashMap::new()).with_imported_environment(BTreeMap::from([
+        (String::from("PATH"), String::from("/nowhere")),
+        (
+            String::from("LOGIN_NG_TEST_IMPORTED"),
+            String::from("imported"),
+        ),
+    ]));
+
+    let environment = manager.environment().await;
+    assert_eq!(environment.get("PATH").cloned(), std::env::var("PATH").ok());
+    assert_eq!(
+        environment
+            .get("LOGIN_NG_TEST_IMPORTED")
+            .map(String::as_str),
+        Some("imported")
+    );
+
+    manager
+        .set_environment(&[
+            String::from("LOGIN_NG_TEST_A=a"),
+            String::from("LOGIN_NG_TEST_B=b"),
+        ])
+        .await
+        .unwrap();
+
+    // a malformed assignment leaves the environment untouched
+    assert!(manager
+        .set_environment(&[String::from("LOGIN_NG_TEST_C=c"), String::from("=broken")])
+        .await
+        .is_err());
+
+    manager
+        .unset_environment(&[
+            String::from("LOGIN_NG_TEST_A"),
+            String::from("LOGIN_NG_TEST_B=other"),
+        ])
+        .await
+        .unwrap();
+
+    let environment = manager.environment().await;
+    assert!(!environment.contains_key("LOGIN_NG_TEST_A"));
+    assert!(!environment.contains_key("LOGIN_NG_TEST_C"));
+    assert_eq!(
+        environment.get("LOGIN_NG_TEST_B").map(String::as_str),
+        Some("b")
+    );
+}
@@ -17,4 +17,6 @@
     51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 */
 
+pub mod bus;
 pub mod desc;
+pub mod manager;
@@ -0,0 +1,130 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    path::{Path, PathBuf},
+    process::ExitStatus,
+};
+
+use login_ng::{
+    vt::vt_of_tty,
+    xorg::{free_display, generate_cookie, is_display_ready, write_xauthority, XServerConfig},
+};
+use nix::{
+    sys::signal::{self, Signal},
+    unistd::Pid,
+};
+use tokio::{
+    process::{Child, Command},
+    time::{sleep, Duration, Instant},
+};
+
+use crate::errors::XServerError;
+
+/// The X server of an X11 session, started on the VT of the session
+#[derive(Debug)]
+pub struct XServer {
+    child: Child,
+    display: u32,
+    authority: PathBuf,
+}
+
+/// The VT of the session: the one logind assigned or else the one stdin is attached to
+pub fn session_vt() -> Option<u32> {
+    match std::env::var("XDG_VTNR")
+        .ok()
+        .and_then(|vt| vt.parse().ok())
+    {
+        Some(vt) => Some(vt),
+        None => vt_of_tty(std::fs::read_link("/proc/self/fd/0").ok()?.to_str()?),
+    }
+}
+
+impl XServer {
+    /// Start the X server on the first free display, protected by a fresh cookie
+    /// written to an Xauthority file inside the given runtime directory.
+    pub async fn start(
+        config: &XServerConfig,
+        vt: Option<u32>,
+        runtime_dir: &Path,
+    ) -> Result<Self, XServerError> {
+        let display = free_display().ok_or(XServerError::NoFreeDisplay)?;
+
+        // the same file is the server auth file and the client XAUTHORITY
+        let authority = runtime_dir.join("Xauthority");
+        write_xauthority(&authority, display, &generate_cookie())?;
+
+        let argv = config.server_argv(display, vt, &authority);
+        let mut child = Command::new(argv[0].as_str())
+            .args(&argv[1..])
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let deadline = Instant::now() + config.timeout();
+        while !is_display_ready(display) {
+            if let Some(status) = child.try_wait()? {
+                return Err(XServerError::Exited(status));
+            }
+
+            if Instant::now() >= deadline {
+                let _ = child.kill().await;
+                return Err(XServerError::Timeout(config.timeout().as_secs()));
+            }
+
+            sleep(Duration::from_millis(100)).await;
+        }
+
+        Ok(Self {
+            child,
+            display,
+            authority,
+        })
+    }
+
+    pub fn display(&self) -> u32 {
+        self.display
+    }
+
+    /// Value of DISPLAY for clients of this server
+    pub fn display_name(&self) -> String {
+        format!(":{}", self.display)
+    }
+
+    pub fn authority(&self) -> &PathBuf {
+        &self.authority
+    }
+
+    /// Wait for the X server to exit
+    pub async fn wait(&mut self) -> std::io::Result<ExitStatus> {
+        self.child.wait().await
+    }
+
+    /// Ask the X server to terminate, waiting for it to do so
+    pub async fn stop(mut self) {
+        if let Some(pid) = self.child.id() {
+            if let Err(err) = signal::kill(Pid::from_raw(pid as i32), Signal::SIGTERM) {
+                eprintln!("Error terminating the X server: {err}");
+                let _ = self.child.kill().await;
+            }
+        }
+
+        let _ = self.child.wait().await;
+        let _ = std::fs::remove_file(&self.authority);
+    }
+}
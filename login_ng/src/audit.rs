@@ -0,0 +1,152 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    mem::size_of,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+};
+
+use thiserror::Error;
+
+// from linux/netlink.h and linux/audit.h
+const NETLINK_AUDIT: libc::c_int = 9;
+const NLMSG_ALIGNTO: usize = 4;
+
+#[derive(Debug, Error)]
+pub enum AuditError {
+    #[error("Error opening the audit socket: {0}")]
+    Socket(std::io::Error),
+
+    #[error("Error sending the audit record: {0}")]
+    Send(std::io::Error),
+}
+
+/// Type of the user-space audit record
+#[repr(u16)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AuditRecordType {
+    /// User-space authentication attempt
+    UserAuth = 1100,
+
+    /// User-space session start
+    UserStart = 1105,
+
+    /// User-space session end
+    UserEnd = 1106,
+}
+
+#[repr(C)]
+struct NlMsgHdr {
+    nlmsg_len: u32,
+    nlmsg_type: u16,
+    nlmsg_flags: u16,
+    nlmsg_seq: u32,
+    nlmsg_pid: u32,
+}
+
+/// Escapes a value as auditd expects it: quoted if printable, hex-encoded otherwise
+fn audit_value(value: &str) -> String {
+    match value
+        .bytes()
+        .all(|b| b.is_ascii_graphic() && b != b'"' && b != b'\'')
+    {
+        true => format!("\"{value}\""),
+        false => value.bytes().map(|b| format!("{b:02X}")).collect(),
+    }
+}
+
+/// Builds the text of an audit record in the format used by PAM-aware programs
+pub fn audit_message(op: &str, username: &str, terminal: &str, success: bool) -> String {
+    let exe = std::env::current_exe()
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or(String::from("?"));
+
+    format!(
+        "op={op} acct={} exe={} hostname=? addr=? terminal={} res={}",
+        audit_value(username),
+        audit_value(&exe),
+        match terminal.is_empty() {
+            true => "?",
+            false => terminal,
+        },
+        match success {
+            true => "success",
+            false => "failed",
+        }
+    )
+}
+
+/// Sends a user-space record to the kernel audit subsystem:
+/// requires CAP_AUDIT_WRITE, so this is meant to be used by privileged processes.
+pub fn audit_log(record_type: AuditRecordType, message: &str) -> Result<(), AuditError> {
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+            NETLINK_AUDIT,
+        )
+    };
+
+    if fd < 0 {
+        return Err(AuditError::Socket(std::io::Error::last_os_error()));
+    }
+
+    let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let payload_len = message.len() + 1;
+    let len = size_of::<NlMsgHdr>() + payload_len;
+    let aligned_len = (len + NLMSG_ALIGNTO - 1) & !(NLMSG_ALIGNTO - 1);
+
+    let header = NlMsgHdr {
+        nlmsg_len: len as u32,
+        nlmsg_type: record_type as u16,
+        nlmsg_flags: libc::NLM_F_REQUEST as u16,
+        nlmsg_seq: 1,
+        nlmsg_pid: 0,
+    };
+
+    let mut buffer = vec![0u8; aligned_len];
+    buffer[..size_of::<NlMsgHdr>()].copy_from_slice(unsafe {
+        std::slice::from_raw_parts(
+            &header as *const NlMsgHdr as *const u8,
+            size_of::<NlMsgHdr>(),
+        )
+    });
+    buffer[size_of::<NlMsgHdr>()..size_of::<NlMsgHdr>() + message.len()]
+        .copy_from_slice(message.as_bytes());
+
+    let mut address: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+    address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+
+    let sent = unsafe {
+        libc::sendto(
+            socket.as_raw_fd(),
+            buffer.as_ptr() as *const libc::c_void,
+            buffer.len(),
+            0,
+            &address as *const libc::sockaddr_nl as *const libc::sockaddr,
+            size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        )
+    };
+
+    match sent < 0 {
+        true => Err(AuditError::Send(std::io::Error::last_os_error())),
+        false => Ok(()),
+    }
+}
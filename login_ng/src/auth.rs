@@ -17,9 +17,13 @@
     51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 */
 
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::{
+    collections::HashMap,
+    sync::{OnceLock, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use bytevec2::*;
+use bytevec2::{errors::ByteVecError, *};
 
 use aes_gcm::{
     aead::{Aead, AeadCore, KeyInit, OsRng},
@@ -31,6 +35,7 @@ use bcrypt::{hash, verify, DEFAULT_COST};
 
 use crate::{
     error::*,
+    secure::LockedBytes,
     user::{AuthDataNonce, AuthDataSalt, UserAuthDataError},
 };
 
@@ -49,14 +54,14 @@ bytevec_decl! {
 impl SecondaryPassword {
     // WARNING: it is the user responsibility to check that the intermediate value matches the MainPassword field,
     // therefore the user MUST verify() it beforehand
-    pub fn new(intermediate: &String, password: &String) -> Result<Self, UserOperationError> {
+    pub fn new(intermediate: &[u8], password: &[u8]) -> Result<Self, UserOperationError> {
         let password_salt_arr =
             <[u8; 32]>::try_from(Aes256Gcm::generate_key(&mut OsRng).to_vec().as_slice()).unwrap();
 
         let password_hash =
-            hash(password.as_str(), DEFAULT_COST).map_err(UserOperationError::HashingError)?;
+            hash(password, DEFAULT_COST).map_err(UserOperationError::HashingError)?;
 
-        let password_derived_key = crate::derive_key(password.as_str(), &password_salt_arr);
+        let password_derived_key = crate::derive_key(password, &password_salt_arr);
 
         let key = Key::<Aes256Gcm>::from_slice(&password_derived_key);
 
@@ -65,7 +70,7 @@ impl SecondaryPassword {
         let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
 
         let enc_intermediate = cipher
-            .encrypt(&nonce, crate::password_to_vec(intermediate).as_ref())
+            .encrypt(&nonce, intermediate)
             .map_err(UserOperationError::EncryptionError)?;
 
         let temp: [u8; 32] = password_salt_arr;
@@ -81,8 +86,8 @@ impl SecondaryPassword {
     }
 
     // get the intermediate if the password is correct
-    pub fn intermediate(&self, password: &String) -> Result<String, UserOperationError> {
-        if !verify(password.as_str(), self.password_hash.as_str())
+    pub fn intermediate(&self, password: &[u8]) -> Result<LockedBytes, UserOperationError> {
+        if !verify(password, self.password_hash.as_str())
             .map_err(UserOperationError::HashingError)?
         {
             return Err(UserOperationError::User(
@@ -91,7 +96,7 @@ impl SecondaryPassword {
         }
 
         let temp: [u8; 32] = self.password_salt.into();
-        let password_derived_key = crate::derive_key(password.as_str(), temp.as_slice());
+        let password_derived_key = crate::derive_key(password, temp.as_slice());
 
         let key = Key::<Aes256Gcm>::from_slice(&password_derived_key);
         let cipher = Aes256Gcm::new(key);
@@ -99,32 +104,133 @@ impl SecondaryPassword {
         let temp: [u8; 12] = self.enc_intermediate_nonce.into();
         let nonce = Nonce::from_slice(temp.as_slice());
 
-        let dec_result = cipher
-            .decrypt(nonce, self.enc_intermediate.as_ref())
-            .map_err(UserOperationError::EncryptionError)?;
+        Ok(LockedBytes::from_vec(
+            cipher
+                .decrypt(nonce, self.enc_intermediate.as_ref())
+                .map_err(UserOperationError::EncryptionError)?,
+        ))
+    }
+}
+
+impl AuthMethod for SecondaryPassword {
+    fn method_id(&self) -> u32 {
+        PASSWORD_AUTH_METHOD_ID
+    }
+
+    fn type_name(&self) -> String {
+        String::from("password")
+    }
+
+    fn unlock(&self, secret: Option<&[u8]>) -> Result<LockedBytes, UserOperationError> {
+        match secret {
+            Some(provided_secondary) => self.intermediate(provided_secondary),
+            None => Err(UserOperationError::User(
+                UserAuthDataError::MatchingAuthNotProvided,
+            )),
+        }
+    }
+
+    fn serialize(&self) -> Result<Vec<u8>, ByteVecError> {
+        self.encode::<u16>()
+    }
+
+    fn clone_method(&self) -> Box<dyn AuthMethod> {
+        Box::new(self.clone())
+    }
+}
+
+/// Identifier of secondary passwords in the stored data
+pub const PASSWORD_AUTH_METHOD_ID: u32 = 0;
+
+/// A secondary authentication method: it holds the intermediate key encrypted in a way
+/// that only the matching factor (a password, a token, ...) can unlock.
+pub trait AuthMethod: std::fmt::Debug + Send + Sync {
+    /// Identifier of the method in the stored data: it must be unique and never change
+    fn method_id(&self) -> u32;
+
+    /// Name of the method as shown to the user
+    fn type_name(&self) -> String;
+
+    /// Get the intermediate key if the provided secret matches this method
+    fn unlock(&self, secret: Option<&[u8]>) -> Result<LockedBytes, UserOperationError>;
+
+    /// Serialize the state of the method: it will be given back to the decoder registered
+    /// for the method_id when loading the user data
+    fn serialize(&self) -> Result<Vec<u8>, ByteVecError>;
+
+    fn clone_method(&self) -> Box<dyn AuthMethod>;
+}
+
+/// Function that recreates an authentication method from its serialized state
+pub type AuthMethodDecoder = fn(&[u8]) -> Result<Box<dyn AuthMethod>, ByteVecError>;
+
+static AUTH_METHODS: OnceLock<RwLock<HashMap<u32, AuthMethodDecoder>>> = OnceLock::new();
+
+fn decode_password(data: &[u8]) -> Result<Box<dyn AuthMethod>, ByteVecError> {
+    Ok(Box::new(SecondaryPassword::decode::<u16>(data)?))
+}
+
+fn auth_methods() -> &'static RwLock<HashMap<u32, AuthMethodDecoder>> {
+    AUTH_METHODS.get_or_init(|| {
+        RwLock::new(HashMap::from([(
+            PASSWORD_AUTH_METHOD_ID,
+            decode_password as AuthMethodDecoder,
+        )]))
+    })
+}
+
+/// Register a new kind of authentication method so that it can be loaded from the stored data:
+/// returns false if the method_id is already taken.
+pub fn register_auth_method(method_id: u32, decoder: AuthMethodDecoder) -> bool {
+    let mut methods = auth_methods().write().unwrap();
 
-        Ok(crate::vec_to_password(&dec_result))
+    if methods.contains_key(&method_id) {
+        return false;
     }
+
+    methods.insert(method_id, decoder);
+
+    true
 }
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+/// Get the decoder registered for the given method_id
+pub fn auth_method_decoder(method_id: u32) -> Option<AuthMethodDecoder> {
+    auth_methods().read().unwrap().get(&method_id).copied()
+}
+
+#[derive(Debug)]
 pub struct SecondaryAuth {
     name: String,
     creation_date: u64,
-    method: SecondaryAuthMethod,
+    method: Box<dyn AuthMethod>,
+}
+
+impl Clone for SecondaryAuth {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            creation_date: self.creation_date,
+            method: self.method.clone_method(),
+        }
+    }
 }
 
-#[derive(Debug, Eq, PartialEq, Clone)]
-pub enum SecondaryAuthMethod {
-    Password(SecondaryPassword),
+impl PartialEq for SecondaryAuth {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.creation_date == other.creation_date
+            && self.method.method_id() == other.method.method_id()
+            && match (self.method.serialize(), other.method.serialize()) {
+                (Ok(a), Ok(b)) => a == b,
+                _ => false,
+            }
+    }
 }
 
+impl Eq for SecondaryAuth {}
+
 impl SecondaryAuth {
-    pub fn new_password(
-        name: &str,
-        creation_date: Option<u64>,
-        password: SecondaryPassword,
-    ) -> Self {
+    pub fn new(name: &str, creation_date: Option<u64>, method: Box<dyn AuthMethod>) -> Self {
         Self {
             name: String::from(name),
             creation_date: match creation_date {
@@ -134,12 +240,20 @@ impl SecondaryAuth {
                     Err(_err) => 0u64,
                 },
             },
-            method: SecondaryAuthMethod::Password(password),
+            method,
         }
     }
 
-    pub(crate) fn data(&self) -> &SecondaryAuthMethod {
-        &self.method
+    pub fn new_password(
+        name: &str,
+        creation_date: Option<u64>,
+        password: SecondaryPassword,
+    ) -> Self {
+        Self::new(name, creation_date, Box::new(password))
+    }
+
+    pub fn method(&self) -> &dyn AuthMethod {
+        self.method.as_ref()
     }
 
     pub fn name(&self) -> String {
@@ -151,22 +265,13 @@ impl SecondaryAuth {
     }
 
     pub fn type_name(&self) -> String {
-        match self.method {
-            SecondaryAuthMethod::Password(_) => String::from("password"),
-        }
+        self.method.type_name()
     }
 
     pub fn intermediate(
         &self,
-        secondary_password: &Option<String>,
-    ) -> Result<String, UserOperationError> {
-        match &self.method {
-            SecondaryAuthMethod::Password(pwd) => match &secondary_password {
-                Some(provided_secondary) => pwd.intermediate(provided_secondary),
-                None => Err(UserOperationError::User(
-                    UserAuthDataError::MatchingAuthNotProvided,
-                )),
-            },
-        }
+        secondary_password: Option<&[u8]>,
+    ) -> Result<LockedBytes, UserOperationError> {
+        self.method.unlock(secondary_password)
     }
 }
@@ -0,0 +1,499 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    ffi::OsString,
+    io::Write,
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+};
+
+use users::{get_user_by_name, os::unix::UserExt};
+
+use crate::storage::StorageError;
+
+/// Configuration file (in the login_ng configuration directory) of the storage chain
+pub const STORAGE_CONFIG_FILE: &str = "storage.conf";
+
+/// Root-only directory holding the data of users whose home directory cannot hold it
+pub const USER_STORAGE_DIR: &str = "/etc/login_ng/users";
+
+/// Directory where pam_login_ng-service keeps the data users store through it
+pub const SERVICE_STORAGE_DIR: &str = "/var/lib/login_ng/storage";
+
+/// A place where the data of a user is kept, as a set of named attributes
+#[derive(Debug, Clone, PartialEq)]
+pub enum StorageBackend {
+    /// Extended attributes of the home directory of the user (the systemd-homed one when present)
+    Home(String),
+
+    /// Extended attributes of the given path
+    XAttr(PathBuf),
+
+    /// One file per attribute in the given directory, only accessible by root
+    Directory(PathBuf),
+
+    /// pam_login_ng-service, for users that can write neither their home directory nor the
+    /// configuration directory: root reads the directory of the service directly instead.
+    Service(String),
+}
+
+/// Kinds of backends, as written in storage.conf
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageBackendKind {
+    Home,
+    Directory,
+    Service,
+}
+
+/// Usernames that can safely be used as a path component
+fn is_safe_username(username: &str) -> bool {
+    !username.is_empty() && !username.starts_with('.') && !username.contains('/')
+}
+
+/// The directory of the given user in the storage of pam_login_ng-service
+pub fn service_storage_dir(username: &str) -> Option<PathBuf> {
+    is_safe_username(username).then(|| Path::new(SERVICE_STORAGE_DIR).join(username))
+}
+
+pub(crate) fn homedir_by_username(username: &String) -> Result<OsString, StorageError> {
+    let user = get_user_by_name(&username).ok_or(StorageError::UserDiscoveryError)?;
+
+    let systemd_homed_str: OsString = format!("/home/{}.homedir", username).into();
+    let systemd_homed_path = Path::new(systemd_homed_str.as_os_str());
+
+    let home_dir_path = match systemd_homed_path.exists() {
+        true => systemd_homed_str,
+        false => user.home_dir().as_os_str().into(),
+    };
+
+    match Path::new(home_dir_path.as_os_str()).exists() {
+        true => Ok(home_dir_path),
+        false => Err(StorageError::HomeDirNotFound(home_dir_path)),
+    }
+}
+
+/// The file holding the attribute in a directory backend
+fn attribute_path(dir: &Path, name: &str) -> Result<PathBuf, StorageError> {
+    match name.is_empty() || name.starts_with('.') || name.contains('/') || name.ends_with(".tmp") {
+        true => Err(StorageError::InvalidAttributeName(String::from(name))),
+        false => Ok(dir.join(name)),
+    }
+}
+
+fn directory_get(dir: &Path, name: &str) -> Result<Option<Vec<u8>>, StorageError> {
+    match std::fs::read(attribute_path(dir, name)?) {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(StorageError::XAttrError(err)),
+    }
+}
+
+fn directory_set(dir: &Path, name: &str, value: &[u8]) -> Result<(), StorageError> {
+    let path = attribute_path(dir, name)?;
+
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)?;
+
+    // written aside and renamed, so that a reader never sees half an attribute
+    let temp_path = dir.join(format!("{name}.tmp"));
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&temp_path)?;
+    file.write_all(value)?;
+    file.sync_all()?;
+
+    Ok(std::fs::rename(temp_path, path)?)
+}
+
+fn directory_remove(dir: &Path, name: &str) -> Result<(), StorageError> {
+    match std::fs::remove_file(attribute_path(dir, name)?) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+            Err(StorageError::XAttrError(err))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn directory_list(dir: &Path) -> Result<Vec<String>, StorageError> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(StorageError::XAttrError(err)),
+    };
+
+    let mut names = vec![];
+    for entry in entries {
+        if let Some(name) = entry?.file_name().to_str() {
+            if attribute_path(dir, name).is_ok() {
+                names.push(String::from(name));
+            }
+        }
+    }
+
+    Ok(names)
+}
+
+fn xattr_remove(path: &Path, name: &str) -> Result<(), StorageError> {
+    match xattr::remove_deref(path, name) {
+        Err(err) if err.raw_os_error() != Some(libc::ENODATA) => Err(StorageError::XAttrError(err)),
+        _ => Ok(()),
+    }
+}
+
+fn xattr_list(path: &Path) -> Result<Vec<String>, StorageError> {
+    Ok(xattr::list_deref(path)?
+        .filter_map(|attr| attr.to_str().map(String::from))
+        .collect())
+}
+
+#[cfg(feature = "dbus-storage")]
+#[zbus::proxy(
+    interface = "org.neroreflex.login_ng_storage1",
+    default_service = "org.neroreflex.login_ng_session",
+    default_path = "/org/zbus/login_ng_storage"
+)]
+pub trait StorageService {
+    /// The value of the attribute of the user, if it is set
+    fn get_attribute(&self, username: &str, name: &str) -> zbus::Result<(bool, Vec<u8>)>;
+
+    fn set_attribute(&self, username: &str, name: &str, value: &[u8]) -> zbus::Result<()>;
+
+    fn remove_attribute(&self, username: &str, name: &str) -> zbus::Result<()>;
+
+    fn list_attributes(&self, username: &str) -> zbus::Result<Vec<String>>;
+}
+
+/// The part of the sessions interface of pam_login_ng-service needed for the version handshake
+#[cfg(feature = "dbus-storage")]
+#[zbus::proxy(
+    interface = "org.neroreflex.login_ng_session1",
+    default_service = "org.neroreflex.login_ng_session",
+    default_path = "/org/zbus/login_ng_session"
+)]
+trait ServiceInfo {
+    /// Protocol version, login_ng version and features of the service
+    fn get_info(&self) -> zbus::Result<(u32, String, Vec<String>)>;
+}
+
+/// Error name of a call to a method the service does not have
+#[cfg(feature = "dbus-storage")]
+const UNKNOWN_METHOD: &str = "org.freedesktop.DBus.Error.UnknownMethod";
+
+/// Refuse to talk to a service speaking another protocol, as pam_login_ng.so does:
+/// a service predating the handshake is reported as speaking protocol version 0.
+#[cfg(feature = "dbus-storage")]
+fn verify_service(connection: &zbus::blocking::Connection) -> Result<(), StorageError> {
+    let proxy = ServiceInfoProxyBlocking::new(connection).map_err(service_error)?;
+
+    let protocol_version = match proxy.get_info() {
+        Ok((protocol_version, _, _)) => protocol_version,
+        Err(zbus::Error::MethodError(name, _, _)) if name.as_str() == UNKNOWN_METHOD => 0,
+        Err(err) => return Err(service_error(err)),
+    };
+
+    match protocol_version == crate::SERVICE_PROTOCOL_VERSION {
+        true => Ok(()),
+        false => Err(StorageError::IncompatibleService {
+            service: protocol_version,
+            client: crate::SERVICE_PROTOCOL_VERSION,
+        }),
+    }
+}
+
+/// Bus pam_login_ng-service and its clients fall back to when no session bus is set
+#[cfg(feature = "dbus-storage")]
+const DEFAULT_SERVICE_BUS_ADDRESS: &str = "unix:path=/run/dbus/system_bus_socket";
+
+/// Connect to the bus pam_login_ng-service is on, the way pam_login_ng.so and pam_login_ng-mount do
+#[cfg(feature = "dbus-storage")]
+fn service_connection() -> zbus::Result<zbus::blocking::Connection> {
+    match std::env::var("DBUS_SESSION_BUS_ADDRESS") {
+        Ok(_) => zbus::blocking::Connection::session(),
+        Err(_) => {
+            zbus::blocking::connection::Builder::address(DEFAULT_SERVICE_BUS_ADDRESS)?.build()
+        }
+    }
+}
+
+#[cfg(feature = "dbus-storage")]
+fn service_proxy() -> Result<StorageServiceProxyBlocking<'static>, StorageError> {
+    let connection =
+        service_connection().map_err(|err| StorageError::ServiceError(err.to_string()))?;
+
+    verify_service(&connection)?;
+
+    StorageServiceProxyBlocking::new(&connection)
+        .map_err(|err| StorageError::ServiceError(err.to_string()))
+}
+
+#[cfg(feature = "dbus-storage")]
+fn service_error(err: zbus::Error) -> StorageError {
+    StorageError::ServiceError(err.to_string())
+}
+
+impl StorageBackend {
+    /// Where the extended attributes are, for the backends keeping them there
+    fn xattr_path(&self) -> Result<Option<PathBuf>, StorageError> {
+        match self {
+            Self::Home(username) => Ok(Some(PathBuf::from(homedir_by_username(username)?))),
+            Self::XAttr(path) => Ok(Some(path.clone())),
+            _ => Ok(None),
+        }
+    }
+
+    /// The directory holding the attributes, for the backends keeping them in files
+    fn directory(&self) -> Result<Option<PathBuf>, StorageError> {
+        match self {
+            Self::Directory(dir) => Ok(Some(dir.clone())),
+            Self::Service(username) if users::get_effective_uid() == 0 => {
+                match service_storage_dir(username) {
+                    Some(dir) => Ok(Some(dir)),
+                    None => Err(StorageError::UserDiscoveryError),
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        if let Some(path) = self.xattr_path()? {
+            return Ok(xattr::get_deref(path, name)?);
+        }
+
+        if let Some(dir) = self.directory()? {
+            return directory_get(&dir, name);
+        }
+
+        self.service_get(name)
+    }
+
+    pub fn set(&self, name: &str, value: &[u8]) -> Result<(), StorageError> {
+        if let Some(path) = self.xattr_path()? {
+            return Ok(xattr::set(path, name, value)?);
+        }
+
+        if let Some(dir) = self.directory()? {
+            return directory_set(&dir, name, value);
+        }
+
+        self.service_set(name, value)
+    }
+
+    /// Remove the attribute: removing an attribute that is not set is not an error
+    pub fn remove(&self, name: &str) -> Result<(), StorageError> {
+        if let Some(path) = self.xattr_path()? {
+            return xattr_remove(&path, name);
+        }
+
+        if let Some(dir) = self.directory()? {
+            return directory_remove(&dir, name);
+        }
+
+        self.service_remove(name)
+    }
+
+    /// Names of the attributes set
+    pub fn list(&self) -> Result<Vec<String>, StorageError> {
+        if let Some(path) = self.xattr_path()? {
+            return xattr_list(&path);
+        }
+
+        if let Some(dir) = self.directory()? {
+            return directory_list(&dir);
+        }
+
+        self.service_list()
+    }
+
+    #[cfg(feature = "dbus-storage")]
+    fn service_get(&self, name: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        let Self::Service(username) = self else {
+            return Err(StorageError::ServiceUnavailable);
+        };
+
+        match service_proxy()?
+            .get_attribute(username, name)
+            .map_err(service_error)?
+        {
+            (true, value) => Ok(Some(value)),
+            (false, _) => Ok(None),
+        }
+    }
+
+    #[cfg(feature = "dbus-storage")]
+    fn service_set(&self, name: &str, value: &[u8]) -> Result<(), StorageError> {
+        let Self::Service(username) = self else {
+            return Err(StorageError::ServiceUnavailable);
+        };
+
+        service_proxy()?
+            .set_attribute(username, name, value)
+            .map_err(service_error)
+    }
+
+    #[cfg(feature = "dbus-storage")]
+    fn service_remove(&self, name: &str) -> Result<(), StorageError> {
+        let Self::Service(username) = self else {
+            return Err(StorageError::ServiceUnavailable);
+        };
+
+        service_proxy()?
+            .remove_attribute(username, name)
+            .map_err(service_error)
+    }
+
+    #[cfg(feature = "dbus-storage")]
+    fn service_list(&self) -> Result<Vec<String>, StorageError> {
+        let Self::Service(username) = self else {
+            return Err(StorageError::ServiceUnavailable);
+        };
+
+        service_proxy()?
+            .list_attributes(username)
+            .map_err(service_error)
+    }
+
+    #[cfg(not(feature = "dbus-storage"))]
+    fn service_get(&self, _name: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        Err(StorageError::ServiceUnavailable)
+    }
+
+    #[cfg(not(feature = "dbus-storage"))]
+    fn service_set(&self, _name: &str, _value: &[u8]) -> Result<(), StorageError> {
+        Err(StorageError::ServiceUnavailable)
+    }
+
+    #[cfg(not(feature = "dbus-storage"))]
+    fn service_remove(&self, _name: &str) -> Result<(), StorageError> {
+        Err(StorageError::ServiceUnavailable)
+    }
+
+    #[cfg(not(feature = "dbus-storage"))]
+    fn service_list(&self) -> Result<Vec<String>, StorageError> {
+        Err(StorageError::ServiceUnavailable)
+    }
+}
+
+/// The backends the data of a user is looked for in, in order: the first one holding it is
+/// used for loads, and it is written to the first one that accepts writes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StorageChain {
+    kinds: Vec<StorageBackendKind>,
+}
+
+impl Default for StorageChain {
+    fn default() -> Self {
+        Self {
+            kinds: vec![
+                StorageBackendKind::Home,
+                StorageBackendKind::Directory,
+                StorageBackendKind::Service,
+            ],
+        }
+    }
+}
+
+impl StorageChain {
+    pub fn new(kinds: Vec<StorageBackendKind>) -> Self {
+        Self { kinds }
+    }
+
+    /// Parses the [Storage] section of a storage.conf file: chain lists home, directory and
+    /// service separated by commas or spaces, unknown backends are ignored and an empty chain
+    /// keeps the default one.
+    pub fn parse(content: &str) -> Self {
+        let mut in_section = false;
+
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                in_section = line.eq_ignore_ascii_case("[storage]");
+                continue;
+            }
+
+            if !in_section {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+
+            if key.trim() != "chain" {
+                continue;
+            }
+
+            let mut kinds = vec![];
+            for kind in value.split([',', ' ']).map(str::trim) {
+                let kind = match kind {
+                    "home" => StorageBackendKind::Home,
+                    "directory" => StorageBackendKind::Directory,
+                    "service" => StorageBackendKind::Service,
+                    _ => continue,
+                };
+
+                if !kinds.contains(&kind) {
+                    kinds.push(kind);
+                }
+            }
+
+            if !kinds.is_empty() {
+                return Self::new(kinds);
+            }
+        }
+
+        Self::default()
+    }
+
+    pub fn load(config_dir: &Path) -> Self {
+        match crate::config::read_config_file(&config_dir.join(STORAGE_CONFIG_FILE)) {
+            Ok(content) => Self::parse(&content),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn kinds(&self) -> &Vec<StorageBackendKind> {
+        &self.kinds
+    }
+
+    /// The backends of the given user: those keeping files are left out for usernames
+    /// that cannot be used as a file name.
+    pub fn backends(&self, username: &str) -> Vec<StorageBackend> {
+        self.kinds
+            .iter()
+            .filter_map(|kind| match kind {
+                StorageBackendKind::Home => Some(StorageBackend::Home(String::from(username))),
+                StorageBackendKind::Directory => is_safe_username(username)
+                    .then(|| StorageBackend::Directory(Path::new(USER_STORAGE_DIR).join(username))),
+                StorageBackendKind::Service => is_safe_username(username)
+                    .then(|| StorageBackend::Service(String::from(username))),
+            })
+            .collect()
+    }
+}
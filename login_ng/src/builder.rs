@@ -0,0 +1,153 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use thiserror::Error;
+
+use crate::{
+    command::PreferredSession,
+    error::UserOperationError,
+    mount::MountPoints,
+    secure::LockedBytes,
+    storage::{store_user_auth_data, store_user_mountpoints, StorageError, StorageSource},
+    user::UserAuthData,
+};
+
+#[derive(Debug, Error)]
+pub enum UserBuilderError {
+    #[error("Secondary authentication methods require a main password")]
+    SecondaryWithoutMain,
+
+    #[error("The main password requires an intermediate key")]
+    MainWithoutIntermediate,
+
+    #[error("The intermediate key requires a main password")]
+    IntermediateWithoutMain,
+
+    #[error("Duplicated secondary authentication method: {0}")]
+    DuplicatedSecondary(String),
+
+    #[error("login-ng error: {0}")]
+    Operation(#[from] UserOperationError),
+}
+
+/// Everything login-ng stores for a user: authentication data and mounts
+#[derive(Debug, Clone, Default)]
+pub struct User {
+    auth: UserAuthData,
+    mounts: Option<MountPoints>,
+}
+
+impl User {
+    pub fn new(auth: UserAuthData, mounts: Option<MountPoints>) -> Self {
+        Self { auth, mounts }
+    }
+
+    pub fn builder() -> UserBuilder {
+        UserBuilder::default()
+    }
+
+    pub fn auth(&self) -> &UserAuthData {
+        &self.auth
+    }
+
+    pub fn mounts(&self) -> &Option<MountPoints> {
+        &self.mounts
+    }
+
+    pub fn into_parts(self) -> (UserAuthData, Option<MountPoints>) {
+        (self.auth, self.mounts)
+    }
+
+    /// Store both the authentication data and the mounts to the given source
+    pub fn store(self, source: &StorageSource) -> Result<(), StorageError> {
+        store_user_auth_data(self.auth, source)?;
+        store_user_mountpoints(self.mounts, source)
+    }
+}
+
+/// Collects the parts of a user configuration, checking at build() that they make sense together
+#[derive(Default)]
+pub struct UserBuilder {
+    main: Option<LockedBytes>,
+    intermediate: Option<LockedBytes>,
+    secondaries: Vec<(String, LockedBytes)>,
+    mounts: Option<MountPoints>,
+    session: Option<PreferredSession>,
+}
+
+impl UserBuilder {
+    pub fn main_password<S: AsRef<[u8]>>(mut self, main: S) -> Self {
+        self.main = Some(LockedBytes::from_slice(main.as_ref()));
+        self
+    }
+
+    pub fn intermediate_key<S: AsRef<[u8]>>(mut self, intermediate: S) -> Self {
+        self.intermediate = Some(LockedBytes::from_slice(intermediate.as_ref()));
+        self
+    }
+
+    /// Add a secondary password: the empty one allows autologin
+    pub fn secondary_password<S: AsRef<[u8]>>(mut self, name: &str, secret: S) -> Self {
+        self.secondaries
+            .push((String::from(name), LockedBytes::from_slice(secret.as_ref())));
+        self
+    }
+
+    pub fn mounts(mut self, mounts: MountPoints) -> Self {
+        self.mounts = Some(mounts);
+        self
+    }
+
+    pub fn default_session(mut self, session: PreferredSession) -> Self {
+        self.session = Some(session);
+        self
+    }
+
+    pub fn build(self) -> Result<User, UserBuilderError> {
+        let mut auth = UserAuthData::new();
+
+        match (&self.main, &self.intermediate) {
+            (Some(main), Some(intermediate)) => auth.set_main_secret(main, intermediate)?,
+            (Some(_), None) => return Err(UserBuilderError::MainWithoutIntermediate),
+            (None, Some(_)) => return Err(UserBuilderError::IntermediateWithoutMain),
+            (None, None) => {
+                if !self.secondaries.is_empty() {
+                    return Err(UserBuilderError::SecondaryWithoutMain);
+                }
+            }
+        }
+
+        for (idx, (name, secret)) in self.secondaries.iter().enumerate() {
+            if self.secondaries[..idx]
+                .iter()
+                .any(|(other, _)| other == name)
+            {
+                return Err(UserBuilderError::DuplicatedSecondary(name.clone()));
+            }
+
+            if let Some(intermediate) = &self.intermediate {
+                auth.add_secondary_secret(name, intermediate, secret)?;
+            }
+        }
+
+        auth.set_preferred_session(self.session);
+
+        Ok(User::new(auth, self.mounts))
+    }
+}
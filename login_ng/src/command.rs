@@ -17,17 +17,88 @@
     51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 */
 
+use std::{collections::BTreeMap, path::PathBuf};
+
+/// The command used to start a user session, along with the
+/// arguments, additional environment variables and working directory
+/// it has to be started with.
 #[derive(Debug, Clone, PartialEq)]
 pub struct SessionCommand {
     command: String,
+    args: Vec<String>,
+    env: BTreeMap<String, String>,
+    cwd: Option<PathBuf>,
 }
 
 impl SessionCommand {
     pub fn new(command: String) -> Self {
-        Self { command }
+        Self {
+            command,
+            args: vec![],
+            env: BTreeMap::new(),
+            cwd: None,
+        }
+    }
+
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    pub fn with_env(mut self, key: String, value: String) -> Self {
+        self.env.insert(key, value);
+        self
+    }
+
+    pub fn with_envs(mut self, env: BTreeMap<String, String>) -> Self {
+        self.env = env;
+        self
+    }
+
+    pub fn with_cwd(mut self, cwd: Option<PathBuf>) -> Self {
+        self.cwd = cwd;
+        self
     }
 
     pub fn command(&self) -> String {
         self.command.clone()
     }
+
+    pub fn args(&self) -> &Vec<String> {
+        &self.args
+    }
+
+    pub fn env(&self) -> &BTreeMap<String, String> {
+        &self.env
+    }
+
+    pub fn cwd(&self) -> &Option<PathBuf> {
+        &self.cwd
+    }
+
+    /// The full command line: the command followed by its arguments
+    pub fn argv(&self) -> Vec<String> {
+        let mut argv = vec![self.command.clone()];
+        argv.extend(self.args.iter().cloned());
+        argv
+    }
+
+    /// Environment variables in the KEY=VALUE form
+    pub fn env_pairs(&self) -> Vec<String> {
+        self.env
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect()
+    }
+}
+
+/// The session a user prefers to be started on login
+#[derive(Debug, Clone, PartialEq)]
+pub enum PreferredSession {
+    /// An explicit session command
+    Command(SessionCommand),
+
+    /// An installed wayland or X11 session, identified by its desktop file
+    /// name or one of its DesktopNames
+    Desktop(String),
 }
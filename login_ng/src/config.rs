@@ -0,0 +1,157 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    fs::{DirBuilder, OpenOptions, Permissions},
+    io::Write,
+    os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+/// Configuration shipped by the distribution: takes precedence when present
+pub const SYSTEM_CONFIG_DIR: &str = "/usr/lib/login_ng/";
+
+/// Configuration written by the administrator and by the service
+pub const CONFIG_DIR: &str = "/etc/login_ng/";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] std::io::Error),
+
+    #[error("{0} is owned by an untrusted user")]
+    UntrustedOwner(PathBuf),
+
+    #[error("{0} has unsafe permissions {1:o}")]
+    UnsafePermissions(PathBuf, u32),
+}
+
+/// The configuration directory in use
+pub fn config_dir() -> &'static Path {
+    match std::fs::exists(SYSTEM_CONFIG_DIR).unwrap_or(false) {
+        true => Path::new(SYSTEM_CONFIG_DIR),
+        false => Path::new(CONFIG_DIR),
+    }
+}
+
+/// Fails unless the path is owned by root (or by the user running the program,
+/// that can modify it anyway) and none of the forbidden mode bits are set
+fn check_permissions(path: &Path, forbidden: u32) -> Result<(), ConfigError> {
+    let metadata = std::fs::metadata(path)?;
+
+    if metadata.uid() != 0 && metadata.uid() != users::get_effective_uid() {
+        return Err(ConfigError::UntrustedOwner(path.to_path_buf()));
+    }
+
+    let mode = metadata.mode() & 0o7777;
+    if mode & forbidden != 0 {
+        return Err(ConfigError::UnsafePermissions(path.to_path_buf(), mode));
+    }
+
+    Ok(())
+}
+
+/// Fails if anyone but root can replace files in the directory containing the path
+fn check_parent_permissions(path: &Path) -> Result<(), ConfigError> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => check_permissions(parent, 0o022),
+        _ => Ok(()),
+    }
+}
+
+/// Fails if the path (or the directory containing it) can be modified by anyone but root
+pub fn check_config_permissions(path: &Path) -> Result<(), ConfigError> {
+    check_parent_permissions(path)?;
+
+    check_permissions(path, 0o022)
+}
+
+/// Read a configuration file, refusing it if anyone but root can modify it
+pub fn read_config_file(path: &Path) -> Result<String, ConfigError> {
+    check_config_permissions(path)?;
+
+    Ok(std::fs::read_to_string(path)?)
+}
+
+/// Read a file holding secrets, refusing it if anyone but root can access it
+pub fn read_private_file(path: &Path) -> Result<String, ConfigError> {
+    check_parent_permissions(path)?;
+
+    check_permissions(path, 0o077)?;
+
+    Ok(std::fs::read_to_string(path)?)
+}
+
+/// Write a file only root can access, fixing the ownership and mode of an existing one:
+/// a symlink in its place is refused rather than followed
+pub fn write_private_file(path: &Path, content: &[u8]) -> Result<(), ConfigError> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)?;
+
+    if users::get_effective_uid() == 0 {
+        std::os::unix::fs::fchown(&file, Some(0), Some(0))?;
+    }
+    file.set_permissions(Permissions::from_mode(0o600))?;
+
+    file.write_all(content)?;
+
+    Ok(file.sync_all()?)
+}
+
+/// Create the configuration directory owned by root and accessible only by root:
+/// an already existing directory is checked instead, as other programs may need to read it.
+pub fn init_config_dir(dir: &Path) -> Result<(), ConfigError> {
+    if !std::fs::exists(dir)? {
+        DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+
+        if users::get_effective_uid() == 0 {
+            std::os::unix::fs::chown(dir, Some(0), Some(0))?;
+        }
+        std::fs::set_permissions(dir, Permissions::from_mode(0o700))?;
+    }
+
+    check_permissions(dir, 0o022)
+}
+
+/// Files in the configuration directory that will be refused for their permissions:
+/// the directory must not be used until they are fixed
+pub fn unsafe_config_files(dir: &Path) -> Result<Vec<ConfigError>, ConfigError> {
+    let mut found = vec![];
+
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if !path.is_file() {
+            continue;
+        }
+
+        if let Err(err) = check_permissions(path.as_path(), 0o022) {
+            found.push(err);
+        }
+    }
+
+    Ok(found)
+}
@@ -0,0 +1,229 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    ffi::CString,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::secure::LockedBytes;
+
+/// Environment variable systemd uses to tell services where their (decrypted) credentials are
+pub const CREDENTIALS_DIRECTORY_ENV: &str = "CREDENTIALS_DIRECTORY";
+
+/// Prefix of both the systemd credential name and the kernel keyring key description:
+/// the secret of user "alice" is read from credential "login_ng.alice" or key "login_ng:alice".
+pub const CREDENTIAL_PREFIX: &str = "login_ng";
+
+/// Argument of pam_login_ng.so enabling machine-provisioned secrets in a PAM service
+pub const PROVISIONED_SECRET_PAM_ARG: &str = "provisioned_secret";
+
+// from linux/keyctl.h
+const KEY_SPEC_SESSION_KEYRING: libc::c_long = -3;
+pub(crate) const KEY_SPEC_USER_KEYRING: libc::c_long = -4;
+const KEYCTL_SEARCH: libc::c_long = 10;
+const KEYCTL_READ: libc::c_long = 11;
+
+#[derive(Debug, Error)]
+pub enum CredentialError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] std::io::Error),
+
+    #[error("Invalid username")]
+    InvalidUsername,
+}
+
+/// Where a machine-provisioned secret has been found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CredentialSource {
+    /// A (possibly TPM-bound) credential made available by systemd, see systemd-creds(1)
+    Systemd,
+
+    /// A "user" key in the kernel keyring, as provisioned by the initrd
+    Keyring,
+}
+
+/// Name of the systemd credential holding the secret of the given user
+pub fn credential_name(username: &str) -> Result<String, CredentialError> {
+    if username.is_empty() || username.contains('/') || username.starts_with('.') {
+        return Err(CredentialError::InvalidUsername);
+    }
+
+    Ok(format!("{CREDENTIAL_PREFIX}.{username}"))
+}
+
+/// Description of the kernel keyring key holding the secret of the given user
+pub fn keyring_description(username: &str) -> String {
+    format!("{CREDENTIAL_PREFIX}:{username}")
+}
+
+/// Removes the trailing newline editors and `echo` add to secrets stored in files
+fn trim_secret(mut secret: Vec<u8>) -> LockedBytes {
+    while secret.last().is_some_and(|b| *b == b'\n' || *b == b'\r') {
+        secret.pop();
+    }
+
+    LockedBytes::from_vec(secret)
+}
+
+/// Reads the secret of the given user from a credentials directory
+pub fn read_credential(dir: &Path, username: &str) -> Result<Option<LockedBytes>, CredentialError> {
+    match std::fs::read(dir.join(credential_name(username)?)) {
+        Ok(secret) => Ok(Some(trim_secret(secret))),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(CredentialError::IOError(err)),
+    }
+}
+
+/// Reads the secret of the given user from the credentials systemd passed to the running service
+pub fn systemd_credential(username: &str) -> Result<Option<LockedBytes>, CredentialError> {
+    match std::env::var_os(CREDENTIALS_DIRECTORY_ENV) {
+        Some(dir) => read_credential(PathBuf::from(dir).as_path(), username),
+        None => Ok(None),
+    }
+}
+
+/// Looks for a "user" key with the given description in a keyring, returning its serial
+pub(crate) fn keyring_search(
+    keyring: libc::c_long,
+    description: &CString,
+) -> std::io::Result<Option<libc::c_long>> {
+    let key_type = CString::new("user").unwrap();
+
+    let serial = unsafe {
+        libc::syscall(
+            libc::SYS_keyctl,
+            KEYCTL_SEARCH,
+            keyring,
+            key_type.as_ptr(),
+            description.as_ptr(),
+            0 as libc::c_long,
+        )
+    };
+
+    if serial < 0 {
+        return match std::io::Error::last_os_error().raw_os_error() {
+            Some(libc::ENOKEY) | Some(libc::EKEYEXPIRED) | Some(libc::EKEYREVOKED) => Ok(None),
+            _ => Err(std::io::Error::last_os_error()),
+        };
+    }
+
+    Ok(Some(serial))
+}
+
+/// Reads the payload of the key with the given serial
+pub(crate) fn keyring_read(serial: libc::c_long) -> std::io::Result<LockedBytes> {
+    // the first call tells the size of the payload
+    let len = unsafe {
+        libc::syscall(
+            libc::SYS_keyctl,
+            KEYCTL_READ,
+            serial,
+            std::ptr::null_mut::<libc::c_char>(),
+            0 as libc::size_t,
+        )
+    };
+
+    if len < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let mut payload = LockedBytes::new(len as usize);
+    let read = unsafe {
+        libc::syscall(
+            libc::SYS_keyctl,
+            KEYCTL_READ,
+            serial,
+            payload.as_mut_ptr() as *mut libc::c_char,
+            payload.len() as libc::size_t,
+        )
+    };
+
+    if read < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(LockedBytes::from_slice(
+        &payload[..(read as usize).min(payload.len())],
+    ))
+}
+
+/// Reads the secret of the given user from the first of the given kernel keyrings holding it
+fn keyring_lookup(
+    username: &str,
+    keyrings: &[libc::c_long],
+) -> Result<Option<LockedBytes>, CredentialError> {
+    let description = CString::new(keyring_description(username))
+        .map_err(|_| CredentialError::InvalidUsername)?;
+
+    for keyring in keyrings {
+        let Some(serial) = keyring_search(*keyring, &description)? else {
+            continue;
+        };
+
+        return Ok(Some(trim_secret(keyring_read(serial)?.to_vec())));
+    }
+
+    Ok(None)
+}
+
+/// Reads the secret of the given user from the user or session kernel keyring
+pub fn keyring_credential(username: &str) -> Result<Option<LockedBytes>, CredentialError> {
+    keyring_lookup(username, &[KEY_SPEC_USER_KEYRING, KEY_SPEC_SESSION_KEYRING])
+}
+
+/// Whether the process runs with privileges it has not been started with (setuid or setgid)
+fn privileges_elevated() -> bool {
+    unsafe { libc::getuid() != libc::geteuid() || libc::getgid() != libc::getegid() }
+}
+
+/// Looks for a secret provisioned for the given user, preferring systemd credentials over the kernel keyring.
+///
+/// The environment and the session keyring are inherited from whoever started the process and are therefore
+/// ignored when running as a PAM module (`from_pam`), where only the user keyring is searched,
+/// and nothing at all is trusted in a setuid or setgid process.
+pub fn provisioned_secret(
+    username: &str,
+    from_pam: bool,
+) -> Option<(CredentialSource, LockedBytes)> {
+    let elevated = privileges_elevated();
+
+    if !from_pam && !elevated {
+        if let Ok(Some(secret)) = systemd_credential(username) {
+            return Some((CredentialSource::Systemd, secret));
+        }
+
+        if let Ok(Some(secret)) = keyring_credential(username) {
+            return Some((CredentialSource::Keyring, secret));
+        }
+
+        return None;
+    }
+
+    if elevated {
+        return None;
+    }
+
+    match keyring_lookup(username, &[KEY_SPEC_USER_KEYRING]) {
+        Ok(Some(secret)) => Some((CredentialSource::Keyring, secret)),
+        _ => None,
+    }
+}
@@ -0,0 +1,313 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::{
+    command::SessionCommand,
+    xorg::{x11_launcher_args, X11_SESSION_LAUNCHER},
+};
+
+/// Directories holding wayland session desktop entries, in order of preference
+pub const WAYLAND_SESSIONS_DIRS: [&str; 2] = [
+    "/usr/local/share/wayland-sessions",
+    "/usr/share/wayland-sessions",
+];
+
+/// Directories holding X11 session desktop entries, in order of preference
+pub const X_SESSIONS_DIRS: [&str; 2] = ["/usr/local/share/xsessions", "/usr/share/xsessions"];
+
+#[derive(Debug, Error)]
+pub enum DesktopEntryError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] std::io::Error),
+
+    #[error("Missing [Desktop Entry] group")]
+    MissingGroup,
+
+    #[error("Missing Exec key")]
+    MissingExec,
+
+    #[error("Invalid Exec key: {0}")]
+    InvalidExec(String),
+
+    #[error("Entry is hidden")]
+    Hidden,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SessionType {
+    Wayland,
+    X11,
+}
+
+impl SessionType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionType::Wayland => "wayland",
+            SessionType::X11 => "x11",
+        }
+    }
+}
+
+/// A session as described by a wayland-sessions or xsessions desktop entry
+#[derive(Debug, Clone, PartialEq)]
+pub struct DesktopSession {
+    id: String,
+    name: String,
+    exec: Vec<String>,
+    desktop_names: Vec<String>,
+    session_type: SessionType,
+}
+
+impl DesktopSession {
+    /// Parse the content of a desktop entry: id is the file name without the .desktop extension
+    pub fn parse(
+        id: &str,
+        content: &str,
+        session_type: SessionType,
+    ) -> Result<Self, DesktopEntryError> {
+        let mut in_entry = false;
+        let mut found_entry = false;
+        let mut name = None;
+        let mut exec = None;
+        let mut desktop_names = vec![];
+
+        for line in content.lines().map(|line| line.trim()) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                in_entry = line == "[Desktop Entry]";
+                found_entry |= in_entry;
+                continue;
+            }
+
+            if !in_entry {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+
+            match key.trim() {
+                "Name" => name = Some(value.trim().to_string()),
+                "Exec" => exec = Some(value.trim().to_string()),
+                "DesktopNames" => {
+                    desktop_names = value
+                        .split(';')
+                        .map(|name| name.trim())
+                        .filter(|name| !name.is_empty())
+                        .map(String::from)
+                        .collect()
+                }
+                "Hidden" | "NoDisplay" if value.trim() == "true" => {
+                    return Err(DesktopEntryError::Hidden)
+                }
+                _ => {}
+            }
+        }
+
+        if !found_entry {
+            return Err(DesktopEntryError::MissingGroup);
+        }
+
+        let exec = split_exec(exec.ok_or(DesktopEntryError::MissingExec)?.as_str())?;
+
+        Ok(Self {
+            id: id.to_string(),
+            name: name.unwrap_or(id.to_string()),
+            exec,
+            desktop_names,
+            session_type,
+        })
+    }
+
+    pub fn load(path: &Path, session_type: SessionType) -> Result<Self, DesktopEntryError> {
+        let id = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        Self::parse(
+            id.as_str(),
+            std::fs::read_to_string(path)?.as_str(),
+            session_type,
+        )
+    }
+
+    pub fn id(&self) -> &String {
+        &self.id
+    }
+
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn exec(&self) -> &Vec<String> {
+        &self.exec
+    }
+
+    pub fn desktop_names(&self) -> &Vec<String> {
+        &self.desktop_names
+    }
+
+    pub fn session_type(&self) -> SessionType {
+        self.session_type
+    }
+
+    /// Check if this session is the one identified by the given name:
+    /// either its desktop file id or one of its DesktopNames.
+    pub fn matches(&self, name: &str) -> bool {
+        self.id == name
+            || self
+                .desktop_names
+                .iter()
+                .any(|desktop| desktop.eq_ignore_ascii_case(name))
+    }
+
+    /// Build the command used to start this session, including the
+    /// XDG variables a display manager is expected to set.
+    /// X11 sessions are wrapped into the launcher starting the X server they need.
+    pub fn session_command(&self) -> SessionCommand {
+        let command = match self.session_type {
+            SessionType::Wayland => {
+                SessionCommand::new(self.exec[0].clone()).with_args(self.exec[1..].to_vec())
+            }
+            SessionType::X11 => SessionCommand::new(String::from(X11_SESSION_LAUNCHER))
+                .with_args(x11_launcher_args(&self.exec)),
+        };
+
+        let mut command = command
+            .with_env(
+                String::from("XDG_SESSION_TYPE"),
+                String::from(self.session_type.as_str()),
+            )
+            .with_env(String::from("XDG_SESSION_DESKTOP"), self.id.clone());
+
+        if !self.desktop_names.is_empty() {
+            command = command.with_env(
+                String::from("XDG_CURRENT_DESKTOP"),
+                self.desktop_names.join(":"),
+            );
+        }
+
+        command
+    }
+}
+
+/// Split an Exec value into its arguments, handling quoting and
+/// dropping the field codes (%f, %U, ...) that make no sense for a session.
+pub(crate) fn split_exec(exec: &str) -> Result<Vec<String>, DesktopEntryError> {
+    let mut args = vec![];
+    let mut current = String::new();
+    let mut has_current = false;
+    let mut quoted = false;
+    let mut chars = exec.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                has_current = true;
+            }
+            '\\' if quoted => match chars.next() {
+                Some(escaped) => current.push(escaped),
+                None => return Err(DesktopEntryError::InvalidExec(exec.to_string())),
+            },
+            '%' if !quoted => match chars.next() {
+                Some('%') => {
+                    current.push('%');
+                    has_current = true;
+                }
+                Some(_) => {}
+                None => return Err(DesktopEntryError::InvalidExec(exec.to_string())),
+            },
+            c if c.is_whitespace() && !quoted => {
+                if has_current {
+                    args.push(std::mem::take(&mut current));
+                    has_current = false;
+                }
+            }
+            c => {
+                current.push(c);
+                has_current = true;
+            }
+        }
+    }
+
+    if quoted {
+        return Err(DesktopEntryError::InvalidExec(exec.to_string()));
+    }
+
+    if has_current {
+        args.push(current);
+    }
+
+    match args.is_empty() {
+        true => Err(DesktopEntryError::InvalidExec(exec.to_string())),
+        false => Ok(args),
+    }
+}
+
+fn sessions_in(dirs: &[&str], session_type: SessionType) -> Vec<DesktopSession> {
+    let mut sessions: Vec<DesktopSession> = vec![];
+
+    for dir in dirs.iter() {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+
+        let mut paths = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "desktop"))
+            .collect::<Vec<PathBuf>>();
+        paths.sort();
+
+        for path in paths.iter() {
+            if let Ok(session) = DesktopSession::load(path, session_type) {
+                // entries in earlier directories shadow the ones with the same id
+                if !sessions.iter().any(|s| s.id() == session.id()) {
+                    sessions.push(session);
+                }
+            }
+        }
+    }
+
+    sessions
+}
+
+/// List installed sessions: wayland ones first, then X11 ones
+pub fn installed_sessions() -> Vec<DesktopSession> {
+    let mut sessions = sessions_in(&WAYLAND_SESSIONS_DIRS, SessionType::Wayland);
+    sessions.extend(sessions_in(&X_SESSIONS_DIRS, SessionType::X11));
+    sessions
+}
+
+/// Find an installed session by desktop file id or DesktopNames entry
+pub fn find_session(name: &str) -> Option<DesktopSession> {
+    installed_sessions()
+        .into_iter()
+        .find(|session| session.matches(name))
+}
@@ -1 +1,437 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
 
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs::OpenOptions,
+    io::Read,
+    os::unix::fs::{MetadataExt, OpenOptionsExt},
+    path::Path,
+    str::FromStr,
+};
+
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SessionEnvironmentError {
+    #[error("Invalid umask {0}: an octal value up to 777 is expected")]
+    InvalidUmask(String),
+
+    #[error("Invalid niceness {0}: a value between -20 and 19 is expected")]
+    InvalidNice(String),
+
+    #[error("Invalid I/O scheduling {0}: idle, best-effort[:0-7] or realtime[:0-7] is expected")]
+    InvalidIoPriority(String),
+
+    #[error("{0} is not a locale variable")]
+    InvalidLocaleVariable(String),
+}
+
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+const IOPRIO_DEFAULT_LEVEL: u8 = 4;
+
+/// I/O scheduling class and level of the session, as set by ionice(1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoPriority {
+    RealTime(u8),
+    BestEffort(u8),
+    Idle,
+}
+
+impl IoPriority {
+    fn ioprio(&self) -> libc::c_int {
+        match self {
+            IoPriority::RealTime(level) => (1 << IOPRIO_CLASS_SHIFT) | *level as libc::c_int,
+            IoPriority::BestEffort(level) => (2 << IOPRIO_CLASS_SHIFT) | *level as libc::c_int,
+            IoPriority::Idle => 3 << IOPRIO_CLASS_SHIFT,
+        }
+    }
+}
+
+impl FromStr for IoPriority {
+    type Err = SessionEnvironmentError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || SessionEnvironmentError::InvalidIoPriority(String::from(s));
+
+        let (class, level) = match s.split_once(':') {
+            Some((class, level)) => (class, Some(level)),
+            None => (s, None),
+        };
+
+        let level = match level {
+            Some(level) => match level.parse::<u8>() {
+                Ok(level) if level <= 7 => level,
+                _ => return Err(invalid()),
+            },
+            None => IOPRIO_DEFAULT_LEVEL,
+        };
+
+        match class {
+            "realtime" => Ok(IoPriority::RealTime(level)),
+            "best-effort" => Ok(IoPriority::BestEffort(level)),
+            "idle" if !s.contains(':') => Ok(IoPriority::Idle),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Display for IoPriority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IoPriority::RealTime(level) => write!(f, "realtime:{level}"),
+            IoPriority::BestEffort(level) => write!(f, "best-effort:{level}"),
+            IoPriority::Idle => write!(f, "idle"),
+        }
+    }
+}
+
+/// Whether the given environment variable defines the locale
+pub fn is_locale_variable(key: &str) -> bool {
+    key == "LANG" || key == "LANGUAGE" || (key.len() > 3 && key.starts_with("LC_"))
+}
+
+/// Parse an octal umask such as 022 or 0077
+pub fn parse_umask(value: &str) -> Result<u32, SessionEnvironmentError> {
+    match u32::from_str_radix(value, 8) {
+        Ok(umask) if umask <= 0o777 => Ok(umask),
+        _ => Err(SessionEnvironmentError::InvalidUmask(String::from(value))),
+    }
+}
+
+/// Parse a niceness value as accepted by nice(1)
+pub fn parse_nice(value: &str) -> Result<i32, SessionEnvironmentError> {
+    match value.parse::<i32>() {
+        Ok(nice) if (-20..=19).contains(&nice) => Ok(nice),
+        _ => Err(SessionEnvironmentError::InvalidNice(String::from(value))),
+    }
+}
+
+/// Directory, relative to the home directory, holding the environment.d(5) files of the user
+pub const USER_ENVIRONMENT_D_DIR: &str = ".config/environment.d";
+
+fn is_variable_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Expands $KEY, ${KEY}, ${KEY:-default} and ${KEY:+alternate} against the variables defined so far
+fn expand_environment_value(value: &str, env: &BTreeMap<String, String>) -> String {
+    let mut result = String::new();
+    let mut chars = value.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    result.push(escaped);
+                }
+            }
+            '$' if chars.peek() == Some(&'{') => {
+                chars.next();
+
+                let mut expression = String::new();
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        break;
+                    }
+                    expression.push(c);
+                }
+
+                let expanded = match expression.split_once(":-") {
+                    Some((key, default)) => match env.get(key).filter(|value| !value.is_empty()) {
+                        Some(value) => value.clone(),
+                        None => String::from(default),
+                    },
+                    None => match expression.split_once(":+") {
+                        Some((key, alternate)) => {
+                            match env.get(key).filter(|value| !value.is_empty()) {
+                                Some(_) => String::from(alternate),
+                                None => String::new(),
+                            }
+                        }
+                        None => env.get(&expression).cloned().unwrap_or_default(),
+                    },
+                };
+
+                result.push_str(&expanded);
+            }
+            '$' if chars.peek().is_some_and(|c| is_variable_char(*c)) => {
+                let mut key = String::new();
+                while let Some(c) = chars.next_if(|c| is_variable_char(*c)) {
+                    key.push(c);
+                }
+
+                result.push_str(
+                    env.get(&key)
+                        .map(|value| value.as_str())
+                        .unwrap_or_default(),
+                );
+            }
+            c => result.push(c),
+        }
+    }
+
+    result
+}
+
+/// Adds the KEY=VALUE assignments of an environment.d(5) file to the given environment
+pub fn parse_environment_d(content: &str, env: &mut BTreeMap<String, String>) {
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(is_variable_char) {
+            continue;
+        }
+
+        let value = expand_environment_value(value.trim(), env);
+        env.insert(String::from(key), value);
+    }
+}
+
+/// Reads the *.conf files of an environment.d(5) directory in lexicographic order:
+/// files that are not regular files owned by the given user are skipped, as they
+/// might be read on behalf of the user by a privileged process
+pub fn load_environment_d(dir: &Path, owner: u32) -> BTreeMap<String, String> {
+    let mut env = BTreeMap::new();
+
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return env;
+    };
+
+    let mut files = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "conf"))
+        .collect::<Vec<_>>();
+    files.sort();
+
+    for file in files {
+        // opening a FIFO or a device could block or have side effects: look before opening
+        if !std::fs::symlink_metadata(&file).is_ok_and(|metadata| metadata.is_file()) {
+            continue;
+        }
+
+        // the checks are repeated on the opened file, in case it has been replaced in between
+        let Ok(mut file) = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+            .open(&file)
+        else {
+            continue;
+        };
+
+        if !file
+            .metadata()
+            .is_ok_and(|metadata| metadata.is_file() && metadata.uid() == owner)
+        {
+            continue;
+        }
+
+        let mut content = String::new();
+        if file.read_to_string(&mut content).is_ok() {
+            parse_environment_d(&content, &mut env);
+        }
+    }
+
+    env
+}
+
+/// Process settings the user session is started with, taking the place of
+/// pam_umask and pam_env on minimal systems.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionEnvironment {
+    umask: Option<u32>,
+    locale: BTreeMap<String, String>,
+    nice: Option<i32>,
+    io_priority: Option<IoPriority>,
+}
+
+impl SessionEnvironment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_umask(mut self, umask: Option<u32>) -> Result<Self, SessionEnvironmentError> {
+        if let Some(value) = umask {
+            if value > 0o777 {
+                return Err(SessionEnvironmentError::InvalidUmask(format!("{value:o}")));
+            }
+        }
+
+        self.umask = umask;
+        Ok(self)
+    }
+
+    pub fn with_locale(
+        mut self,
+        key: String,
+        value: String,
+    ) -> Result<Self, SessionEnvironmentError> {
+        if !is_locale_variable(&key) {
+            return Err(SessionEnvironmentError::InvalidLocaleVariable(key));
+        }
+
+        self.locale.insert(key, value);
+        Ok(self)
+    }
+
+    pub fn with_nice(mut self, nice: Option<i32>) -> Result<Self, SessionEnvironmentError> {
+        if let Some(value) = nice {
+            if !(-20..=19).contains(&value) {
+                return Err(SessionEnvironmentError::InvalidNice(value.to_string()));
+            }
+        }
+
+        self.nice = nice;
+        Ok(self)
+    }
+
+    pub fn with_io_priority(mut self, io_priority: Option<IoPriority>) -> Self {
+        self.io_priority = io_priority;
+        self
+    }
+
+    pub fn umask(&self) -> Option<u32> {
+        self.umask
+    }
+
+    /// LANG, LANGUAGE and LC_* variables to be set in the session environment
+    pub fn locale(&self) -> &BTreeMap<String, String> {
+        &self.locale
+    }
+
+    pub fn nice(&self) -> Option<i32> {
+        self.nice
+    }
+
+    pub fn io_priority(&self) -> Option<IoPriority> {
+        self.io_priority
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Apply the umask and priorities to the current process so that the session spawned
+    /// next inherits them: the previous settings are returned to be restored afterwards.
+    /// NOTE: raising priorities requires the process to be privileged
+    pub fn apply(&self) -> std::io::Result<ProcessSettings> {
+        let mut previous = ProcessSettings::default();
+
+        match self.apply_recording(&mut previous) {
+            Ok(()) => Ok(previous),
+            Err(err) => {
+                // do not leave the process half-configured
+                let _ = previous.restore();
+
+                Err(err)
+            }
+        }
+    }
+
+    fn apply_recording(&self, previous: &mut ProcessSettings) -> std::io::Result<()> {
+        if let Some(umask) = self.umask {
+            previous.umask = Some(unsafe { libc::umask(umask as libc::mode_t) } as u32);
+        }
+
+        if let Some(nice) = self.nice {
+            let current = current_nice()?;
+
+            set_nice(nice)?;
+            previous.nice = Some(current);
+        }
+
+        if let Some(io_priority) = &self.io_priority {
+            let ioprio = unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, 0) };
+            if ioprio < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            set_ioprio(io_priority.ioprio())?;
+            previous.ioprio = Some(ioprio as libc::c_int);
+        }
+
+        Ok(())
+    }
+}
+
+/// Settings of the current process replaced by SessionEnvironment::apply
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProcessSettings {
+    umask: Option<u32>,
+    nice: Option<i32>,
+    ioprio: Option<libc::c_int>,
+}
+
+impl ProcessSettings {
+    pub fn restore(&self) -> std::io::Result<()> {
+        if let Some(umask) = self.umask {
+            unsafe { libc::umask(umask as libc::mode_t) };
+        }
+
+        if let Some(nice) = self.nice {
+            set_nice(nice)?;
+        }
+
+        if let Some(ioprio) = self.ioprio {
+            set_ioprio(ioprio)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn current_nice() -> std::io::Result<i32> {
+    // -1 is a valid niceness: errors can only be told apart by errno
+    unsafe { *libc::__errno_location() = 0 };
+
+    let nice = unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) };
+    if nice == -1 {
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error().unwrap_or(0) != 0 {
+            return Err(err);
+        }
+    }
+
+    Ok(nice)
+}
+
+fn set_nice(nice: i32) -> std::io::Result<()> {
+    match unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+fn set_ioprio(ioprio: libc::c_int) -> std::io::Result<()> {
+    match unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
@@ -0,0 +1,349 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use thiserror::Error;
+
+/// Configuration file shared with pam_faillock
+pub const FAILLOCK_CONF: &str = "/etc/security/faillock.conf";
+
+const DEFAULT_TALLY_DIR: &str = "/var/run/faillock";
+
+// from pam_faillock's faillock.h
+const TALLY_SOURCE_SIZE: usize = 52;
+const TALLY_RECORD_SIZE: usize = 64;
+const TALLY_STATUS_VALID: u16 = 0x1;
+const TALLY_STATUS_TTY: u16 = 0x4;
+
+#[derive(Debug, Error)]
+pub enum FaillockError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] std::io::Error),
+
+    #[error("Invalid username for a tally: {0}")]
+    InvalidUsername(String),
+}
+
+/// The subset of faillock.conf settings that determines lockouts
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaillockConfig {
+    dir: PathBuf,
+    deny: usize,
+    fail_interval: Duration,
+    /// None means the account stays locked until an administrator unlocks it
+    unlock_time: Option<Duration>,
+    even_deny_root: bool,
+    root_unlock_time: Option<Duration>,
+}
+
+impl Default for FaillockConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from(DEFAULT_TALLY_DIR),
+            deny: 3,
+            fail_interval: Duration::from_secs(900),
+            unlock_time: Some(Duration::from_secs(600)),
+            even_deny_root: false,
+            root_unlock_time: Some(Duration::from_secs(600)),
+        }
+    }
+}
+
+fn parse_unlock_time(value: &str) -> Option<Option<Duration>> {
+    match value {
+        "never" => Some(None),
+        value => match value.parse::<u64>() {
+            Ok(0) => Some(None),
+            Ok(secs) => Some(Some(Duration::from_secs(secs))),
+            Err(_) => None,
+        },
+    }
+}
+
+impl FaillockConfig {
+    /// Parses the content of a faillock.conf file: unknown or invalid settings are ignored
+    pub fn parse(content: &str) -> Self {
+        let mut config = Self::default();
+        let mut root_unlock_time = None;
+
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => (line, ""),
+            };
+
+            match key {
+                "dir" if !value.is_empty() => config.dir = PathBuf::from(value),
+                "deny" => {
+                    if let Ok(deny) = value.parse() {
+                        config.deny = deny
+                    }
+                }
+                "fail_interval" => {
+                    if let Ok(secs) = value.parse() {
+                        config.fail_interval = Duration::from_secs(secs)
+                    }
+                }
+                "unlock_time" => {
+                    if let Some(unlock_time) = parse_unlock_time(value) {
+                        config.unlock_time = unlock_time
+                    }
+                }
+                "even_deny_root" => config.even_deny_root = true,
+                "root_unlock_time" => root_unlock_time = parse_unlock_time(value),
+                _ => {}
+            }
+        }
+
+        // as in pam_faillock root_unlock_time defaults to unlock_time
+        config.root_unlock_time = root_unlock_time.unwrap_or(config.unlock_time);
+
+        config
+    }
+
+    /// Loads the system configuration, falling back to pam_faillock defaults
+    pub fn load() -> Self {
+        match std::fs::read_to_string(FAILLOCK_CONF) {
+            Ok(content) => Self::parse(&content),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        self.dir.as_path()
+    }
+
+    pub fn deny(&self) -> usize {
+        self.deny
+    }
+
+    pub fn fail_interval(&self) -> Duration {
+        self.fail_interval
+    }
+
+    pub fn unlock_time(&self) -> Option<Duration> {
+        self.unlock_time
+    }
+
+    /// The tally file of the given user: usernames that would not name a file
+    /// inside the tally directory are refused
+    fn tally_path(&self, username: &str) -> Result<PathBuf, FaillockError> {
+        match username.is_empty() || username.starts_with('.') || username.contains('/') {
+            true => Err(FaillockError::InvalidUsername(String::from(username))),
+            false => Ok(self.dir.join(username)),
+        }
+    }
+}
+
+/// A single failed authentication as recorded by pam_faillock
+#[derive(Debug, Clone, PartialEq)]
+pub struct TallyRecord {
+    source: String,
+    status: u16,
+    time: u64,
+}
+
+impl TallyRecord {
+    pub fn new(source: &str, time: u64) -> Self {
+        Self {
+            source: source.to_string(),
+            status: TALLY_STATUS_VALID | TALLY_STATUS_TTY,
+            time,
+        }
+    }
+
+    /// The tty (or remote host or service) the failed attempt came from
+    pub fn source(&self) -> &String {
+        &self.source
+    }
+
+    /// Seconds since the UNIX epoch of the failed attempt
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.status & TALLY_STATUS_VALID != 0
+    }
+
+    fn from_bytes(bytes: &[u8; TALLY_RECORD_SIZE]) -> Self {
+        let source_len = bytes[..TALLY_SOURCE_SIZE]
+            .iter()
+            .position(|b| *b == 0)
+            .unwrap_or(TALLY_SOURCE_SIZE);
+
+        Self {
+            source: String::from_utf8_lossy(&bytes[..source_len]).into_owned(),
+            status: u16::from_ne_bytes([bytes[54], bytes[55]]),
+            time: u64::from_ne_bytes(bytes[56..64].try_into().unwrap()),
+        }
+    }
+
+    fn to_bytes(&self) -> [u8; TALLY_RECORD_SIZE] {
+        let mut bytes = [0u8; TALLY_RECORD_SIZE];
+
+        // keep the terminating NUL
+        let source = self.source.as_bytes();
+        let source_len = source.len().min(TALLY_SOURCE_SIZE - 1);
+        bytes[..source_len].copy_from_slice(&source[..source_len]);
+
+        bytes[54..56].copy_from_slice(&self.status.to_ne_bytes());
+        bytes[56..64].copy_from_slice(&self.time.to_ne_bytes());
+
+        bytes
+    }
+}
+
+/// Lockout state of an account
+#[derive(Debug, Clone, PartialEq)]
+pub enum LockStatus {
+    Unlocked,
+
+    /// The account is locked: None means it won't be unlocked automatically
+    Locked {
+        until: Option<SystemTime>,
+    },
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Reads the failure records of the given user: a missing tally means no failures
+pub fn read_tally(
+    config: &FaillockConfig,
+    username: &str,
+) -> Result<Vec<TallyRecord>, FaillockError> {
+    let mut content = vec![];
+
+    match File::open(config.tally_path(username)?) {
+        Ok(mut file) => file.read_to_end(&mut content)?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(FaillockError::IOError(err)),
+    };
+
+    Ok(content
+        .chunks_exact(TALLY_RECORD_SIZE)
+        .map(|chunk| TallyRecord::from_bytes(chunk.try_into().unwrap()))
+        .collect())
+}
+
+/// Appends a failure record to the tally of the given user
+pub fn record_failure(
+    config: &FaillockConfig,
+    username: &str,
+    source: &str,
+) -> Result<(), FaillockError> {
+    let tally_path = config.tally_path(username)?;
+
+    std::fs::create_dir_all(config.dir())?;
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o600)
+        .open(tally_path)?;
+
+    file.write_all(&TallyRecord::new(source, now()).to_bytes())?;
+
+    Ok(())
+}
+
+/// Clears the tally of the given user, as `faillock --reset` does
+pub fn reset_tally(config: &FaillockConfig, username: &str) -> Result<(), FaillockError> {
+    match OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .open(config.tally_path(username)?)
+    {
+        Ok(_) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(FaillockError::IOError(err)),
+    }
+}
+
+/// Computes the lockout state from the given records the same way pam_faillock does
+pub fn lock_status(
+    config: &FaillockConfig,
+    records: &[TallyRecord],
+    is_root: bool,
+    now: u64,
+) -> LockStatus {
+    if config.deny == 0 || (is_root && !config.even_deny_root) {
+        return LockStatus::Unlocked;
+    }
+
+    let interval = config.fail_interval.as_secs();
+    let recent = records
+        .iter()
+        .filter(|r| r.is_valid() && r.time.saturating_add(interval) >= now)
+        .collect::<Vec<_>>();
+
+    if recent.len() < config.deny {
+        return LockStatus::Unlocked;
+    }
+
+    let latest = recent.iter().map(|r| r.time).max().unwrap_or(now);
+
+    let unlock_time = match is_root {
+        true => config.root_unlock_time,
+        false => config.unlock_time,
+    };
+
+    match unlock_time {
+        None => LockStatus::Locked { until: None },
+        Some(unlock_time) => {
+            let until = latest.saturating_add(unlock_time.as_secs());
+            match until > now {
+                true => LockStatus::Locked {
+                    until: Some(UNIX_EPOCH + Duration::from_secs(until)),
+                },
+                false => LockStatus::Unlocked,
+            }
+        }
+    }
+}
+
+/// Returns the current lockout state of the given user according to the system configuration
+pub fn user_lock_status(username: &str) -> Result<LockStatus, FaillockError> {
+    let config = FaillockConfig::load();
+
+    let records = read_tally(&config, username)?;
+
+    let is_root = users::get_user_by_name(username)
+        .map(|user| user.uid() == 0)
+        .unwrap_or(false);
+
+    Ok(lock_status(&config, &records, is_root, now()))
+}
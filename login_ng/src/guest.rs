@@ -0,0 +1,150 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::path::{Path, PathBuf};
+
+use crate::config::{init_config_dir, read_private_file, write_private_file, ConfigError};
+
+/// Configuration file of guest sessions, in the login_ng configuration directory
+pub const GUEST_CONFIG_FILE: &str = "guest.conf";
+
+/// Name of the guest account, also typed at the username prompt to start a guest session
+pub const DEFAULT_GUEST_USER: &str = "guest";
+
+/// Size of the tmpfs backing the guest home directory (tmpfs size= syntax)
+pub const DEFAULT_GUEST_TMPFS_SIZE: &str = "25%";
+
+/// Directory (only accessible by root) recording the guest accounts created by login-ng
+pub const GUEST_ACCOUNTS_DIR: &str = "/var/lib/login_ng/guest";
+
+/// Directories shared between users that are cleaned of guest files at logout
+pub const GUEST_TEMP_DIRS: [&str; 3] = ["/tmp", "/var/tmp", "/dev/shm"];
+
+/// Guest sessions: a locked-down account without a password whose home directory
+/// lives in memory and is wiped, along with every other trace of it, at logout.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GuestConfig {
+    enabled: bool,
+    user: String,
+    tmpfs_size: String,
+}
+
+impl Default for GuestConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            user: String::from(DEFAULT_GUEST_USER),
+            tmpfs_size: String::from(DEFAULT_GUEST_TMPFS_SIZE),
+        }
+    }
+}
+
+impl GuestConfig {
+    /// Parses the [Guest] section of a guest.conf file: unknown or invalid settings are ignored
+    pub fn parse(content: &str) -> Self {
+        let mut config = Self::default();
+        let mut in_section = false;
+
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                in_section = line.eq_ignore_ascii_case("[guest]");
+                continue;
+            }
+
+            if !in_section {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+
+            let value = value.trim();
+            match key.trim() {
+                "enabled" => {
+                    config.enabled = matches!(value, "true" | "yes" | "1");
+                }
+                "user" if !value.is_empty() && value != "root" => {
+                    config.user = String::from(value);
+                }
+                "tmpfs_size" if !value.is_empty() && !value.contains(',') => {
+                    config.tmpfs_size = String::from(value);
+                }
+                _ => {}
+            }
+        }
+
+        config
+    }
+
+    pub fn load(config_dir: &Path) -> Self {
+        match crate::config::read_config_file(&config_dir.join(GUEST_CONFIG_FILE)) {
+            Ok(content) => Self::parse(&content),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn user(&self) -> &String {
+        &self.user
+    }
+
+    pub fn tmpfs_size(&self) -> &String {
+        &self.tmpfs_size
+    }
+
+    /// Whether logging in as the given user starts a guest session
+    pub fn is_guest(&self, username: &str) -> bool {
+        self.enabled && self.user == username
+    }
+}
+
+fn guest_marker_path(dir: &Path, username: &str) -> Option<PathBuf> {
+    match username.is_empty() || username.contains('/') || username.starts_with('.') {
+        true => None,
+        false => Some(dir.join(username)),
+    }
+}
+
+/// Record that login-ng has created the guest account with the given uid
+pub fn mark_guest_account(dir: &Path, username: &str, uid: u32) -> Result<(), ConfigError> {
+    let path = guest_marker_path(dir, username).ok_or_else(|| {
+        ConfigError::IOError(std::io::Error::from(std::io::ErrorKind::InvalidInput))
+    })?;
+
+    init_config_dir(dir)?;
+    write_private_file(path.as_path(), uid.to_string().as_bytes())
+}
+
+/// Whether the account has been created by login-ng as a guest account (see [`mark_guest_account`]):
+/// accounts that existed before are never logged in (and wiped) as guests.
+pub fn is_guest_account(dir: &Path, username: &str, uid: u32) -> bool {
+    guest_marker_path(dir, username)
+        .and_then(|path| read_private_file(path.as_path()).ok())
+        .and_then(|content| content.trim().parse::<u32>().ok())
+        == Some(uid)
+}
@@ -0,0 +1,65 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Maximum number of login records kept for each user
+pub const MAX_LOGIN_HISTORY: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoginRecord {
+    timestamp: u64,
+    host: String,
+    line: String,
+}
+
+impl LoginRecord {
+    pub fn new(timestamp: u64, host: String, line: String) -> Self {
+        Self {
+            timestamp,
+            host,
+            line,
+        }
+    }
+
+    /// Creates a record for a login happening right now
+    pub fn now(host: String, line: String) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        Self::new(timestamp, host, line)
+    }
+
+    /// Seconds since the UNIX epoch of the login
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Host the login has been performed on
+    pub fn host(&self) -> &String {
+        &self.host
+    }
+
+    /// Terminal line (tty) the login has been performed on, empty if unknown
+    pub fn line(&self) -> &String {
+        &self.line
+    }
+}
@@ -0,0 +1,208 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    os::unix::fs::{lchown, symlink, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+/// Configuration file of home directory creation, in the login_ng configuration directory
+pub const HOME_CONFIG_FILE: &str = "home.conf";
+
+/// Directory whose content is copied in newly created home directories
+pub const DEFAULT_SKEL_DIR: &str = "/etc/skel";
+
+/// Permissions of newly created home directories
+pub const DEFAULT_HOME_MODE: u32 = 0o700;
+
+#[derive(Debug, Error)]
+pub enum HomeError {
+    #[error("I/O error creating {0}: {1}")]
+    IOError(PathBuf, std::io::Error),
+}
+
+/// Creation of missing home directories at login (as pam_mkhomedir does),
+/// so that accounts coming from LDAP and similar sources work on their first login:
+/// disabled unless enabled in home.conf.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HomeConfig {
+    create: bool,
+    skel: Option<PathBuf>,
+    mode: u32,
+}
+
+impl Default for HomeConfig {
+    fn default() -> Self {
+        Self {
+            create: false,
+            skel: Some(PathBuf::from(DEFAULT_SKEL_DIR)),
+            mode: DEFAULT_HOME_MODE,
+        }
+    }
+}
+
+impl HomeConfig {
+    /// Parses the [Home] section of a home.conf file: unknown or invalid settings are ignored
+    pub fn parse(content: &str) -> Self {
+        let mut config = Self::default();
+        let mut in_section = false;
+
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                in_section = line.eq_ignore_ascii_case("[home]");
+                continue;
+            }
+
+            if !in_section {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+
+            let value = value.trim();
+            match key.trim() {
+                "create" => {
+                    config.create = matches!(value, "true" | "yes" | "1");
+                }
+                // an empty skel creates empty home directories
+                "skel" if value.is_empty() => {
+                    config.skel = None;
+                }
+                "skel" if value.starts_with('/') => {
+                    config.skel = Some(PathBuf::from(value));
+                }
+                "mode" => {
+                    if let Ok(mode) = u32::from_str_radix(value, 8) {
+                        if mode <= 0o777 {
+                            config.mode = mode;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        config
+    }
+
+    pub fn load(config_dir: &Path) -> Self {
+        match crate::config::read_config_file(&config_dir.join(HOME_CONFIG_FILE)) {
+            Ok(content) => Self::parse(&content),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn create(&self) -> bool {
+        self.create
+    }
+
+    pub fn skel(&self) -> Option<&PathBuf> {
+        self.skel.as_ref()
+    }
+
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    /// Create the given home directory, owned by the given user, if it does not exist yet:
+    /// returns whether it has been created.
+    pub fn create_home(
+        &self,
+        home: &Path,
+        uid: libc::uid_t,
+        gid: libc::gid_t,
+    ) -> Result<bool, HomeError> {
+        if !self.create || home.symlink_metadata().is_ok() {
+            return Ok(false);
+        }
+
+        let io_error = |path: &Path| {
+            let path = path.to_path_buf();
+            move |err| HomeError::IOError(path, err)
+        };
+
+        if let Some(parent) = home.parent() {
+            std::fs::create_dir_all(parent).map_err(io_error(parent))?;
+        }
+
+        // nobody but root has access until the skeleton has been copied
+        std::fs::create_dir(home).map_err(io_error(home))?;
+        std::fs::set_permissions(home, std::fs::Permissions::from_mode(0o700))
+            .map_err(io_error(home))?;
+
+        if let Some(skel) = &self.skel {
+            if skel.is_dir() {
+                copy_skel(skel, home, uid, gid)?;
+            }
+        }
+
+        lchown(home, Some(uid), Some(gid)).map_err(io_error(home))?;
+        std::fs::set_permissions(home, std::fs::Permissions::from_mode(self.mode))
+            .map_err(io_error(home))?;
+
+        Ok(true)
+    }
+}
+
+/// Copy the content of the skeleton directory, giving it to the user:
+/// symlinks are copied as they are, special files are skipped.
+fn copy_skel(
+    source: &Path,
+    destination: &Path,
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+) -> Result<(), HomeError> {
+    let entries =
+        std::fs::read_dir(source).map_err(|err| HomeError::IOError(source.to_path_buf(), err))?;
+
+    for entry in entries {
+        let entry = entry.map_err(|err| HomeError::IOError(source.to_path_buf(), err))?;
+        let from = entry.path();
+        let to = destination.join(entry.file_name());
+
+        let io_error = |err| HomeError::IOError(to.clone(), err);
+
+        let file_type = entry.file_type().map_err(io_error)?;
+        if file_type.is_symlink() {
+            symlink(std::fs::read_link(&from).map_err(io_error)?, &to).map_err(io_error)?;
+        } else if file_type.is_dir() {
+            std::fs::create_dir(&to).map_err(io_error)?;
+            copy_skel(&from, &to, uid, gid)?;
+            std::fs::set_permissions(&to, entry.metadata().map_err(io_error)?.permissions())
+                .map_err(io_error)?;
+        } else if file_type.is_file() {
+            std::fs::copy(&from, &to).map_err(io_error)?;
+        } else {
+            continue;
+        }
+
+        lchown(&to, Some(uid), Some(gid)).map_err(io_error)?;
+    }
+
+    Ok(())
+}
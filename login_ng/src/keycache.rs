@@ -0,0 +1,396 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{ffi::CString, path::Path, time::Duration};
+
+use aes_gcm::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use thiserror::Error;
+
+use crate::{
+    config::{init_config_dir, read_private_file, write_private_file, ConfigError},
+    credentials::{keyring_read, keyring_search, KEY_SPEC_USER_KEYRING},
+    secure::LockedBytes,
+    user::UserAuthData,
+};
+
+/// Configuration file (in the login_ng configuration directory) enabling the cache
+pub const KEY_CACHE_CONFIG_FILE: &str = "keycache.conf";
+
+/// Prefix of the kernel keyring key description: the unlock of user "alice" is cached as "login_ng-cache:alice"
+pub const KEY_CACHE_PREFIX: &str = "login_ng-cache";
+
+/// Time a cached unlock stays valid when the configuration does not say otherwise
+pub const DEFAULT_KEY_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// Directory (only accessible by root) holding the random key every cached unlock is sealed with
+pub const KEY_CACHE_DIR: &str = "/var/lib/login_ng/keycache";
+
+const KEY_CACHE_KEY_FILE: &str = "cache.key";
+const KEY_CACHE_KEY_LEN: usize = 32;
+
+// from linux/keyctl.h
+const KEYCTL_SET_TIMEOUT: libc::c_long = 15;
+const KEYCTL_INVALIDATE: libc::c_long = 21;
+
+const SALT_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, Error)]
+pub enum KeyCacheError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] std::io::Error),
+
+    #[error("Invalid username")]
+    InvalidUsername,
+
+    #[error("Invalid cache key")]
+    InvalidCacheKey,
+
+    #[error("Configuration error: {0}")]
+    ConfigError(#[from] ConfigError),
+
+    #[error("Encryption error: {0}")]
+    EncryptionError(aes_gcm::Error),
+}
+
+/// Opt-in cache of successful unlocks in the kernel keyring of root,
+/// so that unlocking again with the same secret within the TTL skips the expensive
+/// password hashes: the main password is kept encrypted with a key derived from the secret
+/// and from a random key only root can read, authenticated along with the stored hash of the main password
+/// so that it stops opening as soon as the main password changes.
+///
+/// Only a process running as root can use the cache: anywhere else it never finds nor stores anything.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyCacheConfig {
+    enabled: bool,
+    ttl: Duration,
+}
+
+impl Default for KeyCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl: DEFAULT_KEY_CACHE_TTL,
+        }
+    }
+}
+
+/// Description of the kernel keyring key caching the unlock of the given user
+pub fn key_cache_description(username: &str) -> String {
+    format!("{KEY_CACHE_PREFIX}:{username}")
+}
+
+/// Key material of a sealed unlock: without the cache key the secret alone is worthless
+fn sealing_input(cache_key: &[u8], secret: &[u8]) -> LockedBytes {
+    let mut input = LockedBytes::new(cache_key.len() + secret.len());
+    input[..cache_key.len()].copy_from_slice(cache_key);
+    input[cache_key.len()..].copy_from_slice(secret);
+    input
+}
+
+/// Encrypts the main password with a key derived from the cache key and the secret that unlocked it,
+/// bound to the given data (the stored hash of the main password) that must be the same to open it
+pub fn seal_unlock(
+    cache_key: &[u8],
+    secret: &[u8],
+    binding: &[u8],
+    main: &[u8],
+) -> Result<Vec<u8>, KeyCacheError> {
+    let salt = Aes256Gcm::generate_key(&mut OsRng);
+    let derived_key = crate::derive_key(&sealing_input(cache_key, secret), salt.as_slice());
+
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&derived_key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+    let enc_main = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: main,
+                aad: binding,
+            },
+        )
+        .map_err(KeyCacheError::EncryptionError)?;
+
+    let mut payload = Vec::with_capacity(SALT_LEN + NONCE_LEN + enc_main.len());
+    payload.extend_from_slice(salt.as_slice());
+    payload.extend_from_slice(nonce.as_slice());
+    payload.extend_from_slice(enc_main.as_slice());
+
+    Ok(payload)
+}
+
+/// Recovers the main password from a sealed unlock: None unless the cache key, the secret
+/// and the bound data are the ones it was sealed with
+pub fn open_unlock(
+    payload: &[u8],
+    cache_key: &[u8],
+    secret: &[u8],
+    binding: &[u8],
+) -> Option<LockedBytes> {
+    if payload.len() <= SALT_LEN + NONCE_LEN {
+        return None;
+    }
+
+    let (salt, rest) = payload.split_at(SALT_LEN);
+    let (nonce, enc_main) = rest.split_at(NONCE_LEN);
+
+    let derived_key = crate::derive_key(&sealing_input(cache_key, secret), salt);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&derived_key));
+
+    cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: enc_main,
+                aad: binding,
+            },
+        )
+        .ok()
+        .map(LockedBytes::from_vec)
+}
+
+/// The user keyring is only out of reach of the users when it is the one of root,
+/// and the cache key can only be read by root anyway
+fn running_as_root() -> bool {
+    unsafe { libc::getuid() == 0 && libc::geteuid() == 0 }
+}
+
+/// Reads the random key cached unlocks are sealed with, generating it on first use if asked to
+fn cache_key(dir: &Path, create: bool) -> Result<Option<LockedBytes>, KeyCacheError> {
+    let path = dir.join(KEY_CACHE_KEY_FILE);
+
+    if !std::fs::exists(&path)? {
+        if !create {
+            return Ok(None);
+        }
+
+        init_config_dir(dir)?;
+
+        let mut key = LockedBytes::new(KEY_CACHE_KEY_LEN);
+        OsRng.fill_bytes(&mut key);
+
+        let mut encoded = key
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>()
+            .into_bytes();
+        let written = write_private_file(&path, &encoded);
+        crate::secure::wipe(encoded.as_mut_slice());
+        written?;
+
+        return Ok(Some(key));
+    }
+
+    let mut encoded = read_private_file(&path)?.into_bytes();
+    let hex = encoded.trim_ascii();
+
+    let mut key = LockedBytes::new(KEY_CACHE_KEY_LEN);
+    let valid = hex.len() == KEY_CACHE_KEY_LEN * 2
+        && hex.chunks(2).zip(key.iter_mut()).all(|(digits, byte)| {
+            std::str::from_utf8(digits)
+                .ok()
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .map(|value| *byte = value)
+                .is_some()
+        });
+    crate::secure::wipe(encoded.as_mut_slice());
+
+    match valid {
+        true => Ok(Some(key)),
+        false => Err(KeyCacheError::InvalidCacheKey),
+    }
+}
+
+fn description(username: &str) -> Result<CString, KeyCacheError> {
+    if username.is_empty() || username.contains(':') {
+        return Err(KeyCacheError::InvalidUsername);
+    }
+
+    CString::new(key_cache_description(username)).map_err(|_| KeyCacheError::InvalidUsername)
+}
+
+/// Drops the cached unlock of the given user, as done on logout
+pub fn forget_cached_unlock(username: &str) -> Result<(), KeyCacheError> {
+    let description = description(username)?;
+
+    if !running_as_root() {
+        return Ok(());
+    }
+
+    let Some(serial) = keyring_search(KEY_SPEC_USER_KEYRING, &description)? else {
+        return Ok(());
+    };
+
+    if unsafe { libc::syscall(libc::SYS_keyctl, KEYCTL_INVALIDATE, serial) } < 0 {
+        return Err(KeyCacheError::IOError(std::io::Error::last_os_error()));
+    }
+
+    Ok(())
+}
+
+impl KeyCacheConfig {
+    /// Parses the [KeyCache] section of a keycache.conf file: unknown or invalid settings are ignored
+    pub fn parse(content: &str) -> Self {
+        let mut config = Self::default();
+        let mut in_section = false;
+
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                in_section = line.eq_ignore_ascii_case("[keycache]");
+                continue;
+            }
+
+            if !in_section {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+
+            let value = value.trim();
+            match key.trim() {
+                "enabled" => {
+                    config.enabled = matches!(value, "true" | "yes" | "1");
+                }
+                "ttl" => {
+                    if let Ok(ttl) = value.parse::<u64>() {
+                        if ttl > 0 {
+                            config.ttl = Duration::from_secs(ttl);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        config
+    }
+
+    pub fn load(config_dir: &Path) -> Self {
+        match crate::config::read_config_file(&config_dir.join(KEY_CACHE_CONFIG_FILE)) {
+            Ok(content) => Self::parse(&content),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Looks for a cached unlock of the given user made with the same secret: an unlock cached
+    /// before the main password changed no longer opens, so no password hash is needed to check it
+    pub fn lookup(
+        &self,
+        username: &str,
+        secret: &[u8],
+        user_cfg: &UserAuthData,
+    ) -> Option<LockedBytes> {
+        if !self.enabled || !running_as_root() {
+            return None;
+        }
+
+        let serial = keyring_search(KEY_SPEC_USER_KEYRING, &description(username).ok()?)
+            .ok()
+            .flatten()?;
+
+        let binding = user_cfg.main_hash()?;
+        let cache_key = cache_key(Path::new(KEY_CACHE_DIR), false).ok().flatten()?;
+
+        open_unlock(
+            &keyring_read(serial).ok()?,
+            &cache_key,
+            secret,
+            binding.as_bytes(),
+        )
+    }
+
+    /// Caches a successful unlock of the given user until the TTL expires
+    pub fn store(
+        &self,
+        username: &str,
+        secret: &[u8],
+        user_cfg: &UserAuthData,
+        main: &[u8],
+    ) -> Result<(), KeyCacheError> {
+        if !self.enabled || !running_as_root() {
+            return Ok(());
+        }
+
+        let Some(binding) = user_cfg.main_hash() else {
+            return Ok(());
+        };
+
+        let key_type = CString::new("user").unwrap();
+        let description = description(username)?;
+        let Some(cache_key) = cache_key(Path::new(KEY_CACHE_DIR), true)? else {
+            return Err(KeyCacheError::InvalidCacheKey);
+        };
+        let mut payload = seal_unlock(&cache_key, secret, binding.as_bytes(), main)?;
+
+        let serial = unsafe {
+            libc::syscall(
+                libc::SYS_add_key,
+                key_type.as_ptr(),
+                description.as_ptr(),
+                payload.as_ptr() as *const libc::c_void,
+                payload.len() as libc::size_t,
+                KEY_SPEC_USER_KEYRING,
+            )
+        };
+
+        crate::secure::wipe(payload.as_mut_slice());
+
+        if serial < 0 {
+            return Err(KeyCacheError::IOError(std::io::Error::last_os_error()));
+        }
+
+        if unsafe {
+            libc::syscall(
+                libc::SYS_keyctl,
+                KEYCTL_SET_TIMEOUT,
+                serial,
+                self.ttl.as_secs() as libc::c_uint,
+            )
+        } < 0
+        {
+            let err = std::io::Error::last_os_error();
+
+            // a cached unlock that never expires is worse than no cache at all
+            unsafe { libc::syscall(libc::SYS_keyctl, KEYCTL_INVALIDATE, serial) };
+
+            return Err(KeyCacheError::IOError(err));
+        }
+
+        Ok(())
+    }
+}
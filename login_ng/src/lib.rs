@@ -17,13 +17,35 @@
     51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 */
 
+#[cfg(feature = "audit")]
+pub mod audit;
 pub mod auth;
+pub mod backend;
+pub mod builder;
 pub mod command;
+pub mod config;
+pub mod credentials;
+pub mod desktop;
 pub mod environment;
 pub mod error;
+pub mod faillock;
+pub mod guest;
+pub mod history;
+pub mod home;
+pub mod keycache;
+pub mod motd;
 pub mod mount;
+#[cfg(feature = "network")]
+pub mod network;
+pub mod nextboot;
+pub mod privileges;
+pub mod recovery;
+pub mod secure;
+pub mod shadow;
 pub mod storage;
 pub mod user;
+pub mod vt;
+pub mod xorg;
 
 pub extern crate aes_gcm;
 pub extern crate rs_sha512;
@@ -44,12 +66,16 @@ use users::{os::unix::UserExt, User};
 
 pub const LIBRARY_VERSION: &str = env!("CARGO_PKG_VERSION");
 
-pub(crate) fn derive_key(input: &str, salt: &[u8]) -> [u8; 32] {
+/// Version of the D-Bus protocol spoken between pam_login_ng-service and its clients:
+/// bumped every time a method or a reply changes in a way older peers cannot handle
+pub const SERVICE_PROTOCOL_VERSION: u32 = 2;
+
+pub(crate) fn derive_key(input: &[u8], salt: &[u8]) -> secure::LockedBytes {
     // Create an HKDF instance with SHA-256 as the hash function
-    let hkdf = Hkdf::<Sha256>::new(Some(salt), input.as_bytes());
+    let hkdf = Hkdf::<Sha256>::new(Some(salt), input);
 
-    // Prepare a buffer for the derived key
-    let mut okm = [0u8; 32]; // Output key material (32 bytes)
+    // Prepare a buffer (kept out of swap) for the derived key
+    let mut okm = secure::LockedBytes::new(32); // Output key material (32 bytes)
 
     // Extract the key material
     hkdf.expand(&[], &mut okm).expect("Failed to expand key");
@@ -57,17 +83,12 @@ pub(crate) fn derive_key(input: &str, salt: &[u8]) -> [u8; 32] {
     okm
 }
 
-pub(crate) fn password_to_vec(password: &String) -> Vec<u8> {
-    password.as_str().into()
-}
-
-pub(crate) fn vec_to_password(vec: &Vec<u8>) -> String {
-    String::from_utf8_lossy(vec.as_slice()).to_string()
-}
-
-// this MUST be implemented and used because entering invalid strings can be a security hole (see lossy_utf8)
-pub(crate) fn is_valid_password(password: &String) -> bool {
-    vec_to_password(password_to_vec(password).as_ref()) == password.clone()
+/// Secrets are byte strings: this converts one for the APIs that need text,
+/// refusing non-UTF-8 input instead of silently replacing invalid sequences.
+pub fn secret_to_string(secret: &[u8]) -> Result<String, user::UserAuthDataError> {
+    std::str::from_utf8(secret)
+        .map(String::from)
+        .map_err(|_| user::UserAuthDataError::NonUtf8Secret)
 }
 
 pub fn valid_users() -> Vec<User> {
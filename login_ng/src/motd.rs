@@ -0,0 +1,164 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    io::Read,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::mpsc::channel,
+    time::{Duration, Instant},
+};
+
+/// Static message of the day
+pub const MOTD_FILE: &str = "/etc/motd";
+
+/// Directory of the executables generating the dynamic part of the message of the day
+pub const MOTD_SCRIPTS_DIR: &str = "/etc/update-motd.d";
+
+/// Time the update-motd.d scripts are given, altogether, before the login goes on without them
+pub const MOTD_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// File in the user home that disables the message of the day, as for login(1)
+pub const HUSHLOGIN_FILE: &str = ".hushlogin";
+
+/// The message of the day displayed after a successful login: the output
+/// of the update-motd.d scripts followed by the content of /etc/motd.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Motd {
+    file: PathBuf,
+    scripts_dir: PathBuf,
+    timeout: Duration,
+}
+
+impl Default for Motd {
+    fn default() -> Self {
+        Self::new(PathBuf::from(MOTD_FILE), PathBuf::from(MOTD_SCRIPTS_DIR))
+    }
+}
+
+impl Motd {
+    pub fn new(file: PathBuf, scripts_dir: PathBuf) -> Self {
+        Self {
+            file,
+            scripts_dir,
+            timeout: MOTD_TIMEOUT,
+        }
+    }
+
+    /// Change the time the scripts are given: those still running afterwards are killed
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Whether the user has asked not to be shown the message of the day
+    pub fn hushed(home_dir: &Path) -> bool {
+        home_dir.join(HUSHLOGIN_FILE).exists()
+    }
+
+    /// Executable scripts in lexicographic order, as run-parts would run them
+    pub fn scripts(&self) -> Vec<PathBuf> {
+        let Ok(entries) = std::fs::read_dir(&self.scripts_dir) else {
+            return vec![];
+        };
+
+        let mut scripts = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                std::fs::metadata(path)
+                    .map(|metadata| {
+                        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+                    })
+                    .unwrap_or(false)
+            })
+            .collect::<Vec<PathBuf>>();
+
+        scripts.sort();
+
+        scripts
+    }
+
+    /// Run the scripts and read the static file: failing scripts, and those
+    /// not done before the timeout, are skipped
+    pub fn render(&self) -> String {
+        let mut motd = String::new();
+        let deadline = Instant::now() + self.timeout;
+
+        for script in self.scripts() {
+            if let Some(output) = run_script(&script, deadline) {
+                motd.push_str(String::from_utf8_lossy(&output).as_ref());
+            }
+        }
+
+        if let Ok(content) = std::fs::read_to_string(&self.file) {
+            motd.push_str(content.as_str());
+        }
+
+        motd
+    }
+}
+
+/// Run a script, returning its output if it succeeds before the deadline: it is killed otherwise
+fn run_script(script: &Path, deadline: Instant) -> Option<Vec<u8>> {
+    let mut child = Command::new(script)
+        .env_clear()
+        .env(
+            "PATH",
+            "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin",
+        )
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    // the output is read aside: a background process keeping it open must not block the login
+    let mut stdout = child.stdout.take()?;
+    let (output_tx, output_rx) = channel();
+    std::thread::spawn(move || {
+        let mut output = vec![];
+        let _ = stdout.read_to_end(&mut output);
+        let _ = output_tx.send(output);
+    });
+
+    let output = output_rx
+        .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        .ok();
+
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Some(status),
+            Ok(None) if output.is_some() && Instant::now() < deadline => {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                break None;
+            }
+        }
+    };
+
+    match status {
+        Some(status) if status.success() => output,
+        _ => None,
+    }
+}
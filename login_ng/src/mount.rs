@@ -22,11 +22,53 @@ use std::hash::{BuildHasher, Hasher};
 
 use std::collections::HashMap;
 
+/// Filesystem types that need the network to be online before they can be mounted
+const NETWORK_FSTYPES: &[&str] = &[
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "sshfs",
+    "fuse.sshfs",
+    "glusterfs",
+    "ceph",
+    "9p",
+    "davfs",
+];
+
+/// How a quota is enforced on a mounted filesystem
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum QuotaKind {
+    /// project quota (xfs and ext4, mounted with the prjquota option)
+    Project,
+
+    /// btrfs quota group of the mounted subvolume
+    Qgroup,
+}
+
+/// Parses a size in bytes, optionally followed by a K, M, G or T (binary) multiplier
+pub fn parse_size(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let (digits, multiplier) = match value.chars().last()?.to_ascii_uppercase() {
+        'K' => (&value[..value.len() - 1], 1u64 << 10),
+        'M' => (&value[..value.len() - 1], 1u64 << 20),
+        'G' => (&value[..value.len() - 1], 1u64 << 30),
+        'T' => (&value[..value.len() - 1], 1u64 << 40),
+        _ => (value, 1u64),
+    };
+
+    match digits.parse::<u64>() {
+        Ok(0) | Err(_) => None,
+        Ok(size) => size.checked_mul(multiplier),
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct MountParams {
     fstype: String,
     device: String,
     flags: Vec<String>,
+    quota: Option<u64>,
 }
 
 impl MountParams {
@@ -35,9 +77,16 @@ impl MountParams {
             device,
             fstype,
             flags,
+            quota: None,
         }
     }
 
+    /// Caps the space that can be used on the mounted filesystem to the given bytes
+    pub fn with_quota(mut self, quota: Option<u64>) -> Self {
+        self.quota = quota;
+        self
+    }
+
     pub fn device(&self) -> &String {
         &self.device
     }
@@ -61,6 +110,28 @@ impl MountParams {
     pub fn set_flags(&mut self, flags: Vec<String>) {
         self.flags = flags;
     }
+
+    pub fn quota(&self) -> Option<u64> {
+        self.quota
+    }
+
+    pub fn set_quota(&mut self, quota: Option<u64>) {
+        self.quota = quota;
+    }
+
+    /// How the quota can be enforced on this filesystem: None if it does not support quotas
+    pub fn quota_kind(&self) -> Option<QuotaKind> {
+        match self.fstype.as_str() {
+            "xfs" | "ext4" => Some(QuotaKind::Project),
+            "btrfs" => Some(QuotaKind::Qgroup),
+            _ => None,
+        }
+    }
+
+    /// Returns true if mounting this filesystem requires the network to be online
+    pub fn is_network(&self) -> bool {
+        NETWORK_FSTYPES.contains(&self.fstype.as_str()) || self.flags.iter().any(|f| f == "_netdev")
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Default)]
@@ -111,6 +182,18 @@ impl MountPoints {
         self.home = mnt.clone();
     }
 
+    /// Returns true if any of the mountpoints requires the network to be online
+    pub fn requires_network(&self) -> bool {
+        self.home.is_network() || self.mounts.values().any(|m| m.is_network())
+    }
+
+    /// Returns the devices mounted: the one of the home directory first, then the others
+    pub fn devices(&self) -> Vec<String> {
+        let mut devices = vec![self.home.device().clone()];
+        devices.extend(self.foreach(|_, params| params.device().clone()));
+        devices
+    }
+
     pub fn hash(&self) -> String {
         let mut hasher = Sha512State::default().build_hasher();
 
@@ -118,6 +201,11 @@ impl MountPoints {
         hasher.write(self.home.fstype().as_bytes());
         hasher.write(self.home.flags.concat().as_bytes());
 
+        // only hashed when set, for mounts authorized before quotas existed to stay authorized
+        if let Some(quota) = self.home.quota {
+            hasher.write_u64(quota);
+        }
+
         for (i, m) in self.mounts.iter().enumerate() {
             hasher.write_usize(i);
             hasher.write_u8(0);
@@ -131,6 +219,10 @@ impl MountPoints {
                 hasher.write_usize(i1);
                 hasher.write(a.as_bytes());
             }
+            if let Some(quota) = m.1.quota() {
+                hasher.write_u8(4);
+                hasher.write_u64(quota);
+            }
         }
 
         let numeric_hash: u64 = hasher.finish();
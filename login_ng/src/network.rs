@@ -0,0 +1,129 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::time::Duration;
+
+use thiserror::Error;
+use tokio::time::{sleep, Instant};
+use zbus::{proxy, proxy::CacheProperties, Connection};
+
+/// Default amount of time to wait for the network to come online
+pub const DEFAULT_NETWORK_ONLINE_TIMEOUT: Duration = Duration::from_secs(30);
+
+const NETWORK_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// NM_STATE_CONNECTED_SITE: good enough to reach LAN resources such as NFS servers
+const NM_STATE_CONNECTED_SITE: u32 = 60;
+
+#[derive(Debug, Error)]
+pub enum NetworkWaitError {
+    #[error("DBus error: {0}")]
+    ZbusError(#[from] zbus::Error),
+
+    #[error("Network did not come online within {0:?}")]
+    Timeout(Duration),
+}
+
+#[proxy(
+    interface = "org.freedesktop.NetworkManager",
+    default_service = "org.freedesktop.NetworkManager",
+    default_path = "/org/freedesktop/NetworkManager",
+    gen_blocking = false
+)]
+trait NetworkManager {
+    #[zbus(property)]
+    fn state(&self) -> zbus::Result<u32>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.network1.Manager",
+    default_service = "org.freedesktop.network1",
+    default_path = "/org/freedesktop/network1",
+    gen_blocking = false
+)]
+trait Networkd {
+    #[zbus(property)]
+    fn online_state(&self) -> zbus::Result<String>;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum NetworkStatus {
+    /// The network manager reports the system as online
+    Online,
+
+    /// The network manager is running but the system is not online (yet)
+    Offline,
+
+    /// Neither NetworkManager nor systemd-networkd are managing the network
+    Unmanaged,
+}
+
+/// Queries NetworkManager first and systemd-networkd then for the network state
+pub async fn network_status(connection: &Connection) -> NetworkStatus {
+    if let Ok(proxy) = NetworkManagerProxy::builder(connection)
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await
+    {
+        if let Ok(state) = proxy.state().await {
+            return match state >= NM_STATE_CONNECTED_SITE {
+                true => NetworkStatus::Online,
+                false => NetworkStatus::Offline,
+            };
+        }
+    }
+
+    if let Ok(proxy) = NetworkdProxy::builder(connection)
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await
+    {
+        if let Ok(state) = proxy.online_state().await {
+            return match state.as_str() {
+                "online" | "partial" => NetworkStatus::Online,
+                _ => NetworkStatus::Offline,
+            };
+        }
+    }
+
+    NetworkStatus::Unmanaged
+}
+
+/// Waits until the network is reported online or the timeout expires.
+///
+/// If the network is not managed by either NetworkManager or systemd-networkd there is
+/// nothing to wait for and this function returns immediately.
+pub async fn wait_network_online(timeout: Duration) -> Result<(), NetworkWaitError> {
+    let connection = Connection::system().await?;
+
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        match network_status(&connection).await {
+            NetworkStatus::Online | NetworkStatus::Unmanaged => return Ok(()),
+            NetworkStatus::Offline => {
+                if Instant::now() >= deadline {
+                    return Err(NetworkWaitError::Timeout(timeout));
+                }
+
+                sleep(NETWORK_POLL_INTERVAL).await;
+            }
+        }
+    }
+}
@@ -0,0 +1,247 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::secure::LockedBytes;
+
+/// Directory (only accessible by root) holding the next boot autologin token
+pub const NEXT_BOOT_AUTOLOGIN_DIR: &str = "/var/lib/login_ng/autologin";
+
+/// Identifier of the running boot, changing at every boot
+pub const BOOT_ID_PATH: &str = "/proc/sys/kernel/random/boot_id";
+
+/// Argument of pam_login_ng.so enabling the next boot autologin in a PAM service
+pub const NEXT_BOOT_AUTOLOGIN_PAM_ARG: &str = "next_boot_autologin";
+
+const TOKEN_FILE: &str = "next_boot";
+const CONSUMED_FILE: &str = "next_boot.consumed";
+const BOUND_FILE: &str = "next_boot.boot";
+
+#[derive(Debug, Error)]
+pub enum NextBootAutologinError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] std::io::Error),
+
+    #[error("Invalid username")]
+    InvalidUsername,
+
+    #[error("Malformed autologin token")]
+    MalformedToken,
+
+    #[error("The session of the user cannot be opened without its password")]
+    RequiresPassword,
+}
+
+/// Returns the identifier of the running boot
+pub fn current_boot_id() -> std::io::Result<String> {
+    Ok(std::fs::read_to_string(BOOT_ID_PATH)?.trim().to_string())
+}
+
+/// A single-use autologin armed for the next boot (for example to let an update finish
+/// in the session of a user): the token holds the user and the boot it has been armed in,
+/// and is only honored, once, by the first later boot reading it: every other boot refuses it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NextBootAutologin {
+    dir: PathBuf,
+}
+
+impl Default for NextBootAutologin {
+    fn default() -> Self {
+        Self::new(Path::new(NEXT_BOOT_AUTOLOGIN_DIR))
+    }
+}
+
+impl NextBootAutologin {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        self.dir.as_path()
+    }
+
+    /// Arms the autologin of the given user for the boot following the given one,
+    /// replacing any autologin armed previously
+    pub fn arm(&self, username: &str, boot_id: &str) -> Result<(), NextBootAutologinError> {
+        if username.is_empty() || username.contains(char::is_whitespace) {
+            return Err(NextBootAutologinError::InvalidUsername);
+        }
+
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(self.dir.as_path())?;
+
+        // a new token has not been read by any boot yet
+        self.remove(BOUND_FILE)?;
+
+        // written aside and renamed, so that a greeter never reads half a token
+        let temp_path = self.dir.join(format!("{TOKEN_FILE}.tmp"));
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(temp_path.as_path())?;
+
+        file.write_all(format!("{username}\n{boot_id}\n").as_bytes())?;
+        file.sync_all()?;
+
+        Ok(std::fs::rename(temp_path, self.dir.join(TOKEN_FILE))?)
+    }
+
+    fn remove(&self, file: &str) -> Result<(), NextBootAutologinError> {
+        match std::fs::remove_file(self.dir.join(file)) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(NextBootAutologinError::IOError(err)),
+        }
+    }
+
+    /// Removes the autologin armed and not consumed yet, if any
+    pub fn disarm(&self) -> Result<(), NextBootAutologinError> {
+        self.remove(TOKEN_FILE)?;
+
+        self.remove(BOUND_FILE)
+    }
+
+    /// Binds the token to the given boot unless a boot has read it already:
+    /// returns true if the token belongs to the given boot
+    fn bind(&self, boot_id: &str) -> Result<bool, NextBootAutologinError> {
+        let bound_path = self.dir.join(BOUND_FILE);
+
+        // written aside and linked in place, as linking never replaces the boot that came first
+        let temp_path = self
+            .dir
+            .join(format!("{BOUND_FILE}.{}.tmp", std::process::id()));
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(temp_path.as_path())?;
+
+        file.write_all(format!("{boot_id}\n").as_bytes())?;
+        file.sync_all()?;
+
+        let linked = std::fs::hard_link(temp_path.as_path(), bound_path.as_path());
+        std::fs::remove_file(temp_path.as_path())?;
+
+        match linked {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                Ok(std::fs::read_to_string(bound_path.as_path())?.trim() == boot_id)
+            }
+            Err(err) => Err(NextBootAutologinError::IOError(err)),
+        }
+    }
+
+    fn parse(content: &str) -> Result<(String, String), NextBootAutologinError> {
+        let mut lines = content.lines().map(str::trim);
+
+        match (lines.next(), lines.next()) {
+            (Some(username), Some(boot_id)) if !username.is_empty() && !boot_id.is_empty() => {
+                Ok((username.to_string(), boot_id.to_string()))
+            }
+            _ => Err(NextBootAutologinError::MalformedToken),
+        }
+    }
+
+    /// Returns the user the autologin is armed for and the boot it has been armed in
+    pub fn armed(&self) -> Result<Option<(String, String)>, NextBootAutologinError> {
+        match std::fs::read_to_string(self.dir.join(TOKEN_FILE)) {
+            Ok(content) => Self::parse(content.as_str()).map(Some),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(NextBootAutologinError::IOError(err)),
+        }
+    }
+
+    /// Returns the user to log in automatically during the given boot, without consuming the token:
+    /// the first boot after the one it has been armed in takes it, and a token left over by that boot is dropped
+    pub fn pending(&self, boot_id: &str) -> Option<String> {
+        match self.armed() {
+            Ok(Some((username, armed_boot_id))) if armed_boot_id != boot_id => {
+                match self.bind(boot_id) {
+                    Ok(true) => Some(username),
+                    Ok(false) => {
+                        let _ = self.disarm();
+                        None
+                    }
+                    Err(_) => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Consumes the token if it has been armed for the given user in a boot other than the given one:
+    /// the token is atomically claimed (so that it's honored at most once) and deleted before granting anything.
+    pub fn consume(&self, username: &str, boot_id: &str) -> Result<bool, NextBootAutologinError> {
+        if self.pending(boot_id).as_deref() != Some(username) {
+            return Ok(false);
+        }
+
+        let consumed = self.dir.join(CONSUMED_FILE);
+        match std::fs::rename(self.dir.join(TOKEN_FILE), consumed.as_path()) {
+            Ok(()) => {}
+            // somebody else consumed it in the meantime
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(NextBootAutologinError::IOError(err)),
+        }
+
+        let content = std::fs::read_to_string(consumed.as_path());
+        std::fs::remove_file(consumed.as_path())?;
+        self.remove(BOUND_FILE)?;
+
+        // the token might have been armed again between the check and the rename
+        let (armed_username, armed_boot_id) = Self::parse(content?.as_str())?;
+
+        Ok(armed_username == username && armed_boot_id != boot_id)
+    }
+
+    /// Claims the autologin of the given user for the session to open, returning the main password
+    /// to hand over to it: there is none to give, so the login is only granted if the session of the user
+    /// doesn't need it to unlock mounts or a systemd-homed home. The token is consumed either way,
+    /// as no later login of this boot could use it either.
+    pub fn claim(
+        &self,
+        username: &str,
+        boot_id: &str,
+        needs_main_password: bool,
+    ) -> Result<Option<LockedBytes>, NextBootAutologinError> {
+        if !self.consume(username, boot_id)? {
+            return Ok(None);
+        }
+
+        match needs_main_password {
+            true => Err(NextBootAutologinError::RequiresPassword),
+            false => Ok(Some(LockedBytes::from_slice(&[]))),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use users::{get_user_groups, User};
+
+/// Identity a session process is started with: the user, its primary group
+/// and the supplementary groups the user is a member of.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionCredentials {
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+    groups: Vec<libc::gid_t>,
+}
+
+impl SessionCredentials {
+    pub fn new(uid: libc::uid_t, gid: libc::gid_t, groups: Vec<libc::gid_t>) -> Self {
+        let mut credentials = Self {
+            uid,
+            gid,
+            groups: vec![],
+        };
+
+        // as initgroups(3) does the primary group is part of the supplementary groups
+        for group in std::iter::once(gid).chain(groups) {
+            if !credentials.groups.contains(&group) {
+                credentials.groups.push(group);
+            }
+        }
+
+        credentials
+    }
+
+    /// Resolve the groups of the given user the same way initgroups(3) does.
+    /// NOTE: this performs NSS lookups and must not be called between fork and exec
+    pub fn for_user(user: &User) -> Self {
+        let groups = get_user_groups(user.name(), user.primary_group_id())
+            .unwrap_or_default()
+            .iter()
+            .map(|group| group.gid())
+            .collect();
+
+        Self::new(user.uid(), user.primary_group_id(), groups)
+    }
+
+    pub fn uid(&self) -> libc::uid_t {
+        self.uid
+    }
+
+    pub fn gid(&self) -> libc::gid_t {
+        self.gid
+    }
+
+    pub fn groups(&self) -> &Vec<libc::gid_t> {
+        &self.groups
+    }
+
+    /// Switch the current process to these credentials for good: groups first, as they
+    /// cannot be changed anymore once the user id has been dropped.
+    /// NOTE: only async-signal-safe calls are made, so that it can be used in pre_exec
+    pub fn apply(&self) -> std::io::Result<()> {
+        if unsafe { libc::setgroups(self.groups.len() as _, self.groups.as_ptr()) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        if unsafe { libc::setgid(self.gid) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        if unsafe { libc::setuid(self.uid) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,202 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+};
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::secure::constant_time_eq;
+
+/// Directory (only accessible by root) holding the hashes of the issued recovery tokens
+pub const RECOVERY_DIR: &str = "/var/lib/login_ng/recovery";
+
+// unambiguous characters only: the token has to be read aloud or copied from paper
+const TOKEN_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const TOKEN_GROUPS: usize = 5;
+const TOKEN_GROUP_LEN: usize = 4;
+
+const TOKEN_EXTENSION: &str = "token";
+const REENROLL_EXTENSION: &str = "reenroll";
+
+#[derive(Debug, Error)]
+pub enum RecoveryError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] std::io::Error),
+
+    #[error("Invalid username")]
+    InvalidUsername,
+}
+
+/// Storage of the single-use recovery tokens the administrator issues to locked-out users.
+///
+/// Only the SHA-256 of a token is ever written to disk, and a token is removed as soon
+/// as it is used: the user is then flagged as requiring re-enrollment until the password is changed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecoveryTokens {
+    dir: PathBuf,
+}
+
+impl Default for RecoveryTokens {
+    fn default() -> Self {
+        Self::new(Path::new(RECOVERY_DIR))
+    }
+}
+
+/// Generates a new random token in the XXXX-XXXX-XXXX-XXXX-XXXX form
+pub fn generate_recovery_token() -> String {
+    let mut random = [0u8; TOKEN_GROUPS * TOKEN_GROUP_LEN];
+    OsRng.fill_bytes(&mut random);
+
+    random
+        .chunks(TOKEN_GROUP_LEN)
+        .map(|group| {
+            group
+                .iter()
+                .map(|b| TOKEN_ALPHABET[(*b as usize) % TOKEN_ALPHABET.len()] as char)
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Hashes a token after normalizing it, so that case and dashes typed by the user do not matter
+pub fn hash_recovery_token(token: &str) -> String {
+    let normalized = token
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect::<String>();
+
+    Sha256::digest(normalized.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+impl RecoveryTokens {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        self.dir.as_path()
+    }
+
+    fn user_path(&self, username: &str, extension: &str) -> Result<PathBuf, RecoveryError> {
+        if username.is_empty() || username.contains('/') || username.starts_with('.') {
+            return Err(RecoveryError::InvalidUsername);
+        }
+
+        Ok(self.dir.join(format!("{username}.{extension}")))
+    }
+
+    fn write_private(&self, path: &Path, content: &[u8]) -> Result<(), RecoveryError> {
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(self.dir.as_path())?;
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?;
+
+        file.write_all(content)?;
+
+        Ok(file.sync_all()?)
+    }
+
+    fn remove(path: &Path) -> Result<(), RecoveryError> {
+        match std::fs::remove_file(path) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(RecoveryError::IOError(err)),
+        }
+    }
+
+    /// Issues a new token for the given user, replacing any token issued previously:
+    /// the returned token is not stored anywhere and must be handed over to the user.
+    pub fn issue(&self, username: &str) -> Result<String, RecoveryError> {
+        let token = generate_recovery_token();
+
+        self.write_private(
+            self.user_path(username, TOKEN_EXTENSION)?.as_path(),
+            hash_recovery_token(&token).as_bytes(),
+        )?;
+
+        Ok(token)
+    }
+
+    /// Returns true if a token has been issued to the given user and has not been used yet
+    pub fn is_pending(&self, username: &str) -> bool {
+        self.user_path(username, TOKEN_EXTENSION)
+            .map(|path| path.exists())
+            .unwrap_or(false)
+    }
+
+    /// Checks the given token and invalidates it on success, flagging the user for re-enrollment
+    pub fn redeem(&self, username: &str, token: &str) -> Result<bool, RecoveryError> {
+        let path = self.user_path(username, TOKEN_EXTENSION)?;
+
+        let stored = match std::fs::read(path.as_path()) {
+            Ok(stored) => stored,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(RecoveryError::IOError(err)),
+        };
+
+        if !constant_time_eq(stored.trim_ascii(), hash_recovery_token(token).as_bytes()) {
+            return Ok(false);
+        }
+
+        // the token is single-use: invalidate it before granting anything
+        Self::remove(path.as_path())?;
+
+        self.write_private(self.user_path(username, REENROLL_EXTENSION)?.as_path(), b"")?;
+
+        Ok(true)
+    }
+
+    /// Removes a token that has not been used yet
+    pub fn revoke(&self, username: &str) -> Result<(), RecoveryError> {
+        Self::remove(self.user_path(username, TOKEN_EXTENSION)?.as_path())
+    }
+
+    /// Returns true if the user has logged in with a recovery token and has not changed the password since
+    pub fn requires_reenrollment(&self, username: &str) -> bool {
+        self.user_path(username, REENROLL_EXTENSION)
+            .map(|path| path.exists())
+            .unwrap_or(false)
+    }
+
+    /// Marks the re-enrollment of the given user as completed
+    pub fn complete_reenrollment(&self, username: &str) -> Result<(), RecoveryError> {
+        Self::remove(self.user_path(username, REENROLL_EXTENSION)?.as_path())
+    }
+}
@@ -0,0 +1,149 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    ops::{Deref, DerefMut},
+    sync::atomic::{compiler_fence, Ordering},
+};
+
+/// Prevents the memory region from being swapped out: failures are not fatal
+/// as the amount of lockable memory is limited by RLIMIT_MEMLOCK.
+pub fn lock_memory(data: &[u8]) -> bool {
+    if data.is_empty() {
+        return true;
+    }
+
+    unsafe { libc::mlock(data.as_ptr() as *const libc::c_void, data.len()) == 0 }
+}
+
+/// Allows the memory region to be swapped out again
+pub fn unlock_memory(data: &[u8]) {
+    if data.is_empty() {
+        return;
+    }
+
+    unsafe { libc::munlock(data.as_ptr() as *const libc::c_void, data.len()) };
+}
+
+/// Overwrites the given buffer with zeroes in a way the compiler won't optimize out
+pub fn wipe(data: &mut [u8]) {
+    for byte in data.iter_mut() {
+        unsafe { std::ptr::write_volatile(byte, 0) };
+    }
+
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Compares two byte strings in constant time (with respect to their content):
+/// only the length of the inputs can be inferred from the time taken.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let difference = a
+        .iter()
+        .zip(b.iter())
+        .fold(0u8, |acc, (x, y)| acc | std::hint::black_box(x ^ y));
+
+    std::hint::black_box(difference) == 0
+}
+
+/// Locks every current and future page of the process in memory:
+/// meant for privileged processes handling secrets such as the root service.
+pub fn lock_all_memory() -> std::io::Result<()> {
+    match unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// Prevents core dumps (and ptrace attach from unprivileged processes) of the current process
+pub fn disable_core_dumps() -> std::io::Result<()> {
+    let no_core = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+
+    if unsafe { libc::setrlimit(libc::RLIMIT_CORE, &no_core) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    match unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// A heap buffer for secrets that is kept out of swap while alive and wiped on drop
+pub struct LockedBytes {
+    data: Box<[u8]>,
+    locked: bool,
+}
+
+impl LockedBytes {
+    pub fn new(len: usize) -> Self {
+        let data = vec![0u8; len].into_boxed_slice();
+        let locked = lock_memory(&data);
+
+        Self { data, locked }
+    }
+
+    pub fn from_slice(source: &[u8]) -> Self {
+        let mut result = Self::new(source.len());
+        result.data.copy_from_slice(source);
+        result
+    }
+
+    /// Moves the content of the given vector into a locked buffer, wiping the original
+    pub fn from_vec(mut source: Vec<u8>) -> Self {
+        let result = Self::from_slice(source.as_slice());
+        wipe(source.as_mut_slice());
+        result
+    }
+
+    /// Returns true if the buffer is currently excluded from swap
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+}
+
+impl Deref for LockedBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.data
+    }
+}
+
+impl DerefMut for LockedBytes {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.data
+    }
+}
+
+impl Drop for LockedBytes {
+    fn drop(&mut self) {
+        wipe(&mut self.data);
+
+        if self.locked {
+            unlock_memory(&self.data);
+        }
+    }
+}
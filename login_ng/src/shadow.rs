@@ -0,0 +1,214 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use thiserror::Error;
+
+pub const SHADOW_FILE: &str = "/etc/shadow";
+
+const SECONDS_PER_DAY: u64 = 86400;
+
+#[derive(Debug, Error)]
+pub enum ShadowError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] std::io::Error),
+
+    #[error("Malformed shadow entry")]
+    Malformed,
+}
+
+/// The aging fields of a shadow(5) entry: every value is expressed in days
+/// and None means the field was left empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShadowEntry {
+    name: String,
+    last_change: Option<u64>,
+    min_age: Option<u64>,
+    max_age: Option<u64>,
+    warn_period: Option<u64>,
+    inactive_period: Option<u64>,
+    expire: Option<u64>,
+}
+
+/// Something about the password or the account the user should be told at login
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpiryWarning {
+    /// The password will expire in the given number of days
+    PasswordExpiresSoon(u64),
+
+    /// The password has expired (or the administrator forced a change) and must be changed
+    PasswordExpired,
+
+    /// The account will expire in the given number of days
+    AccountExpiresSoon(u64),
+}
+
+impl std::fmt::Display for ExpiryWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExpiryWarning::PasswordExpiresSoon(0) => write!(f, "Your password expires today"),
+            ExpiryWarning::PasswordExpiresSoon(1) => write!(f, "Your password expires in 1 day"),
+            ExpiryWarning::PasswordExpiresSoon(days) => {
+                write!(f, "Your password expires in {days} days")
+            }
+            ExpiryWarning::PasswordExpired => write!(f, "Your password has expired"),
+            ExpiryWarning::AccountExpiresSoon(0) => write!(f, "Your account expires today"),
+            ExpiryWarning::AccountExpiresSoon(1) => write!(f, "Your account expires in 1 day"),
+            ExpiryWarning::AccountExpiresSoon(days) => {
+                write!(f, "Your account expires in {days} days")
+            }
+        }
+    }
+}
+
+fn parse_days(field: &str) -> Result<Option<u64>, ShadowError> {
+    match field {
+        "" => Ok(None),
+        field => field
+            .parse::<i64>()
+            .map(|days| u64::try_from(days).ok())
+            .map_err(|_| ShadowError::Malformed),
+    }
+}
+
+impl ShadowEntry {
+    /// Parses a single line of the shadow file
+    pub fn parse(line: &str) -> Result<Self, ShadowError> {
+        let fields = line.trim_end().split(':').collect::<Vec<_>>();
+
+        if fields.len() < 8 || fields[0].is_empty() {
+            return Err(ShadowError::Malformed);
+        }
+
+        Ok(Self {
+            name: fields[0].to_string(),
+            last_change: parse_days(fields[2])?,
+            min_age: parse_days(fields[3])?,
+            max_age: parse_days(fields[4])?,
+            warn_period: parse_days(fields[5])?,
+            inactive_period: parse_days(fields[6])?,
+            expire: parse_days(fields[7])?,
+        })
+    }
+
+    /// Looks for the entry of the given user in a shadow file
+    pub fn load_from(path: &Path, username: &str) -> Result<Option<Self>, ShadowError> {
+        let reader = BufReader::new(File::open(path)?);
+
+        for line in reader.lines() {
+            let line = line?;
+
+            if line.split(':').next() != Some(username) {
+                continue;
+            }
+
+            return Ok(Some(Self::parse(&line)?));
+        }
+
+        Ok(None)
+    }
+
+    /// Looks for the entry of the given user in the system shadow file
+    pub fn load(username: &str) -> Result<Option<Self>, ShadowError> {
+        Self::load_from(Path::new(SHADOW_FILE), username)
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn last_change(&self) -> Option<u64> {
+        self.last_change
+    }
+
+    pub fn min_age(&self) -> Option<u64> {
+        self.min_age
+    }
+
+    pub fn max_age(&self) -> Option<u64> {
+        self.max_age
+    }
+
+    pub fn warn_period(&self) -> Option<u64> {
+        self.warn_period
+    }
+
+    pub fn inactive_period(&self) -> Option<u64> {
+        self.inactive_period
+    }
+
+    pub fn expire(&self) -> Option<u64> {
+        self.expire
+    }
+
+    /// Computes the warnings to display on the given day (counted from the epoch),
+    /// following the same rules pam_unix uses.
+    pub fn warnings(&self, today: u64) -> Vec<ExpiryWarning> {
+        let mut warnings = vec![];
+
+        if let Some(expire) = self.expire {
+            let warn_period = self.warn_period.unwrap_or_default();
+            if expire >= today && expire - today <= warn_period {
+                warnings.push(ExpiryWarning::AccountExpiresSoon(expire - today));
+            }
+        }
+
+        match (self.last_change, self.max_age) {
+            // the administrator has requested a password change
+            (Some(0), _) => warnings.push(ExpiryWarning::PasswordExpired),
+            // max_age of 99999 (or more) conventionally disables aging
+            (Some(last_change), Some(max_age)) if max_age < 99999 => {
+                let password_expire = last_change + max_age;
+                let warn_period = self.warn_period.unwrap_or_default();
+
+                if today >= password_expire {
+                    warnings.push(ExpiryWarning::PasswordExpired);
+                } else if password_expire - today <= warn_period {
+                    warnings.push(ExpiryWarning::PasswordExpiresSoon(password_expire - today));
+                }
+            }
+            _ => {}
+        }
+
+        warnings
+    }
+}
+
+/// Returns the number of days elapsed since the epoch, as used in the shadow file
+pub fn days_since_epoch() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / SECONDS_PER_DAY)
+        .unwrap_or_default()
+}
+
+/// Returns the expiry warnings for the given user, or an empty list
+/// if the user has no shadow entry (or it cannot be read).
+pub fn user_expiry_warnings(username: &str) -> Vec<ExpiryWarning> {
+    match ShadowEntry::load(username) {
+        Ok(Some(entry)) => entry.warnings(days_since_epoch()),
+        _ => vec![],
+    }
+}
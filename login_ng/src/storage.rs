@@ -17,23 +17,21 @@
     51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 */
 
-use std::{
-    collections::HashMap,
-    ffi::OsString,
-    path::{Path, PathBuf},
-};
+use std::{collections::HashMap, ffi::OsString, path::PathBuf};
 
 use crate::{
-    auth::{SecondaryAuth, SecondaryAuthMethod, SecondaryPassword},
-    command::SessionCommand,
+    auth::SecondaryAuth,
+    backend::{StorageBackend, StorageChain},
+    command::{PreferredSession, SessionCommand},
+    environment::{IoPriority, SessionEnvironment},
+    history::{LoginRecord, MAX_LOGIN_HISTORY},
     mount::{MountParams, MountPoints},
-    user::{MainPassword, UserAuthData},
+    user::{IntermediateKey, MainPassword, UserAuthData},
 };
 
 use bytevec2::errors;
 use errors::ByteVecError;
 use thiserror::Error;
-use users::{get_user_by_name, os::unix::UserExt};
 
 #[derive(Debug, Error)]
 pub enum StorageError {
@@ -54,15 +52,133 @@ pub enum StorageError {
 
     #[error("Deserialization error")]
     DeserializationError,
+
+    #[error("Invalid session environment: {0}")]
+    InvalidEnvironment(#[from] crate::environment::SessionEnvironmentError),
+
+    #[error("Invalid attribute name: {0}")]
+    InvalidAttributeName(String),
+
+    #[error("Error with pam_login_ng-service: {0}")]
+    ServiceError(String),
+
+    #[error("pam_login_ng-service speaks protocol version {service}, but version {client} is required: reinstall matching versions of login-ng")]
+    IncompatibleService { service: u32, client: u32 },
+
+    #[error("Storing data through pam_login_ng-service is not supported")]
+    ServiceUnavailable,
+
+    #[error("No storage backend is available")]
+    NoBackend,
 }
 
 /// Represents a source of user authentication data
 pub enum StorageSource {
-    /// Load/Store operations will be performed on the autodetected home directory
+    /// Load/Store operations will be performed on the backends of the storage chain
+    /// configured in storage.conf, starting from the autodetected home directory
     Username(String),
 
     /// Load/Store operations will be performed on the given path
     Path(PathBuf),
+
+    /// Load/Store operations will be performed on the given backends, tried in order
+    Chain(Vec<StorageBackend>),
+}
+
+impl StorageSource {
+    /// The backends of the source, in the order they are tried
+    pub fn backends(&self) -> Vec<StorageBackend> {
+        match self {
+            Self::Username(username) => {
+                StorageChain::load(crate::config::config_dir()).backends(username)
+            }
+            Self::Path(pathbuf) => vec![StorageBackend::XAttr(pathbuf.clone())],
+            Self::Chain(backends) => backends.clone(),
+        }
+    }
+
+    /// The backend data is read from: the first one holding data, otherwise the first
+    /// one that can be reached, so that the lack of data is told apart from errors.
+    /// A backend that errors (for example a systemd-homed home that isn't active yet) might
+    /// be holding the data, so the load fails rather than reading it from a lower backend.
+    pub fn load_backend(&self) -> Result<StorageBackend, StorageError> {
+        let mut reachable = None;
+
+        for backend in self.backends() {
+            match backend.get(&manifest_attribute())? {
+                Some(_) => return Ok(backend),
+                None => {
+                    reachable.get_or_insert(backend);
+                }
+            }
+        }
+
+        reachable.ok_or(StorageError::NoBackend)
+    }
+
+    /// The backend data is written to: the one already holding data, as long as it
+    /// accepts writes, otherwise the first one that does. The manifest is written
+    /// to find out, so that loads then find the data in the same backend.
+    pub fn store_backend(&self) -> Result<StorageBackend, StorageError> {
+        let manifest = AuthDataManifest::new()
+            .encode::<u16>()
+            .map_err(StorageError::SerializationError)?;
+
+        let mut backends = self.backends();
+        if let Some(holder) = backends
+            .iter()
+            .position(|backend| matches!(backend.get(&manifest_attribute()), Ok(Some(_))))
+        {
+            let holder = backends.remove(holder);
+            backends.insert(0, holder);
+        }
+
+        let mut first_error = None;
+
+        for backend in backends {
+            match backend.set(&manifest_attribute(), manifest.as_slice()) {
+                Ok(()) => return Ok(backend),
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+
+        Err(first_error.unwrap_or(StorageError::NoBackend))
+    }
+}
+
+fn manifest_attribute() -> String {
+    format!("{}.manifest", crate::DEFAULT_XATTR_NAME)
+}
+
+/// Returns true if the attribute is one login_ng stores: the manifest, the authentication data,
+/// the session preferences, the mounts and the login history
+pub fn is_login_ng_attribute(name: &str) -> bool {
+    let Some(attribute) = name
+        .strip_prefix(crate::DEFAULT_XATTR_NAME)
+        .and_then(|name| name.strip_prefix('.'))
+    else {
+        return false;
+    };
+
+    match attribute.split_once('.') {
+        None => matches!(
+            attribute,
+            "manifest"
+                | "main"
+                | "preferred_session"
+                | "session"
+                | "environment"
+                | "mount"
+                | "mountquota"
+        ),
+        Some((kind, index)) => {
+            matches!(kind, "ik" | "auth" | "mounts" | "mountquota" | "history")
+                && !index.is_empty()
+                && index.bytes().all(|digit| digit.is_ascii_digit())
+        }
+    }
 }
 
 use bytevec2::*;
@@ -116,22 +232,209 @@ impl From<&MountPointSerialized> for (String, MountParams) {
 
 bytevec_decl! {
     #[derive(PartialEq, Eq, Debug, Clone)]
-    struct SessionCommandSerialized {
+    struct LegacySessionCommandSerialized {
         command: String
     }
 }
 
+impl From<LegacySessionCommandSerialized> for SessionCommand {
+    fn from(val: LegacySessionCommandSerialized) -> Self {
+        SessionCommand::new(val.command.clone())
+    }
+}
+
+bytevec_decl! {
+    #[derive(PartialEq, Eq, Debug, Clone)]
+    struct SessionCommandSerialized {
+        command: String,
+        args: Vec<String>,
+        env: Vec<String>,
+        cwd: String
+    }
+}
+
 impl From<&SessionCommand> for SessionCommandSerialized {
     fn from(value: &SessionCommand) -> Self {
         let command = value.command();
+        let args = value.args().clone();
+        let env = value.env_pairs();
+        let cwd = value
+            .cwd()
+            .as_ref()
+            .map(|cwd| cwd.to_string_lossy().to_string())
+            .unwrap_or_default();
 
-        Self { command }
+        Self {
+            command,
+            args,
+            env,
+            cwd,
+        }
     }
 }
 
 impl From<SessionCommandSerialized> for SessionCommand {
     fn from(val: SessionCommandSerialized) -> Self {
+        let env = val
+            .env
+            .iter()
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+
         SessionCommand::new(val.command.clone())
+            .with_args(val.args.clone())
+            .with_envs(env)
+            .with_cwd(match val.cwd.is_empty() {
+                true => None,
+                false => Some(PathBuf::from(val.cwd.clone())),
+            })
+    }
+}
+
+bytevec_decl! {
+    #[derive(PartialEq, Eq, Debug, Clone)]
+    struct PreferredSessionSerialized {
+        desktop: String,
+        command: String,
+        args: Vec<String>,
+        env: Vec<String>,
+        cwd: String
+    }
+}
+
+impl From<&PreferredSession> for PreferredSessionSerialized {
+    fn from(value: &PreferredSession) -> Self {
+        match value {
+            PreferredSession::Desktop(desktop) => Self {
+                desktop: desktop.clone(),
+                command: String::new(),
+                args: vec![],
+                env: vec![],
+                cwd: String::new(),
+            },
+            PreferredSession::Command(command) => {
+                let command = SessionCommandSerialized::from(command);
+
+                Self {
+                    desktop: String::new(),
+                    command: command.command,
+                    args: command.args,
+                    env: command.env,
+                    cwd: command.cwd,
+                }
+            }
+        }
+    }
+}
+
+impl From<PreferredSessionSerialized> for PreferredSession {
+    fn from(val: PreferredSessionSerialized) -> Self {
+        match val.desktop.is_empty() {
+            false => PreferredSession::Desktop(val.desktop),
+            true => PreferredSession::Command(
+                SessionCommandSerialized {
+                    command: val.command,
+                    args: val.args,
+                    env: val.env,
+                    cwd: val.cwd,
+                }
+                .into(),
+            ),
+        }
+    }
+}
+
+bytevec_decl! {
+    #[derive(PartialEq, Eq, Debug, Clone)]
+    struct SessionEnvironmentSerialized {
+        umask: String,
+        locale: Vec<String>,
+        nice: String,
+        io_priority: String
+    }
+}
+
+impl From<&SessionEnvironment> for SessionEnvironmentSerialized {
+    fn from(value: &SessionEnvironment) -> Self {
+        Self {
+            umask: value
+                .umask()
+                .map(|umask| format!("{umask:03o}"))
+                .unwrap_or_default(),
+            locale: value
+                .locale()
+                .iter()
+                .map(|(key, value)| format!("{key}={value}"))
+                .collect(),
+            nice: value
+                .nice()
+                .map(|nice| nice.to_string())
+                .unwrap_or_default(),
+            io_priority: value
+                .io_priority()
+                .map(|io_priority| io_priority.to_string())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl TryFrom<SessionEnvironmentSerialized> for SessionEnvironment {
+    type Error = StorageError;
+
+    fn try_from(val: SessionEnvironmentSerialized) -> Result<Self, Self::Error> {
+        let optional = |value: &String| match value.is_empty() {
+            true => None,
+            false => Some(value.clone()),
+        };
+
+        let mut environment = SessionEnvironment::new()
+            .with_umask(
+                optional(&val.umask)
+                    .map(|umask| crate::environment::parse_umask(&umask))
+                    .transpose()?,
+            )?
+            .with_nice(
+                optional(&val.nice)
+                    .map(|nice| crate::environment::parse_nice(&nice))
+                    .transpose()?,
+            )?
+            .with_io_priority(
+                optional(&val.io_priority)
+                    .map(|io_priority| io_priority.parse::<IoPriority>())
+                    .transpose()?,
+            );
+
+        for (key, value) in val.locale.iter().filter_map(|pair| pair.split_once('=')) {
+            environment = environment.with_locale(key.to_string(), value.to_string())?;
+        }
+
+        Ok(environment)
+    }
+}
+
+bytevec_decl! {
+    #[derive(PartialEq, Eq, Debug, Clone)]
+    struct LoginRecordSerialized {
+        timestamp: u64,
+        host: String,
+        line: String
+    }
+}
+
+impl From<&LoginRecord> for LoginRecordSerialized {
+    fn from(value: &LoginRecord) -> Self {
+        Self {
+            timestamp: value.timestamp(),
+            host: value.host().clone(),
+            line: value.line().clone(),
+        }
+    }
+}
+
+impl From<LoginRecordSerialized> for LoginRecord {
+    fn from(val: LoginRecordSerialized) -> Self {
+        LoginRecord::new(val.timestamp, val.host, val.line)
     }
 }
 
@@ -152,14 +455,11 @@ impl TryFrom<&SecondaryAuth> for AuthDataSerialized {
         let name = value.name();
         let creation_date = value.creation_date();
 
-        let (auth_type, auth_data) = match value.data() {
-            SecondaryAuthMethod::Password(secondary_password) => (
-                0,
-                secondary_password
-                    .encode::<u16>()
-                    .map_err(Self::Error::SerializationError)?,
-            ),
-        };
+        let auth_type = value.method().method_id();
+        let auth_data = value
+            .method()
+            .serialize()
+            .map_err(Self::Error::SerializationError)?;
 
         Ok(Self {
             name,
@@ -174,63 +474,38 @@ impl TryInto<SecondaryAuth> for AuthDataSerialized {
     type Error = StorageError;
 
     fn try_into(self) -> Result<SecondaryAuth, Self::Error> {
-        match self.auth_type {
-            0 => Ok(SecondaryAuth::new_password(
+        match crate::auth::auth_method_decoder(self.auth_type) {
+            Some(decode) => Ok(SecondaryAuth::new(
                 self.name.as_str(),
                 Some(self.creation_date),
-                SecondaryPassword::decode::<u16>(self.auth_data.as_slice())
-                    .map_err(StorageError::SerializationError)?,
+                decode(self.auth_data.as_slice()).map_err(StorageError::SerializationError)?,
             )),
-            _ => Err(StorageError::DeserializationError),
+            None => Err(StorageError::DeserializationError),
         }
     }
 }
 
-fn homedir_by_username(username: &String) -> Result<OsString, StorageError> {
-    let user = get_user_by_name(&username).ok_or(StorageError::UserDiscoveryError)?;
-
-    let systemd_homed_str: OsString = format!("/home/{}.homedir", username).into();
-    let systemd_homed_path = Path::new(systemd_homed_str.as_os_str());
-
-    let home_dir_path = match systemd_homed_path.exists() {
-        true => systemd_homed_str,
-        false => user.home_dir().as_os_str().into(),
-    };
-
-    match Path::new(home_dir_path.as_os_str()).exists() {
-        true => Ok(home_dir_path),
-        false => Err(StorageError::HomeDirNotFound(home_dir_path)),
-    }
-}
-
 pub fn load_user_session_command(
     source: &StorageSource,
 ) -> Result<Option<SessionCommand>, StorageError> {
-    let home_dir_path = match source {
-        StorageSource::Username(username) => homedir_by_username(username)?,
-        StorageSource::Path(pathbuf) => pathbuf.as_os_str().to_os_string(),
-    };
+    let backend = source.load_backend()?;
 
-    let manifest = xattr::get_deref(
-        home_dir_path.as_os_str(),
-        format!("{}.manifest", crate::DEFAULT_XATTR_NAME),
-    )
-    .map_err(StorageError::XAttrError)?;
+    let manifest = backend.get(&manifest_attribute())?;
     if manifest.is_none() {
         return Ok(None);
     }
 
-    match xattr::get_deref(
-        home_dir_path.as_os_str(),
-        format!("{}.session", crate::DEFAULT_XATTR_NAME),
-    )
-    .map_err(StorageError::XAttrError)?
-    {
-        Some(bytes) => Ok(Some(
-            SessionCommandSerialized::decode::<u32>(bytes.as_slice())
-                .map_err(|_| StorageError::DeserializationError)?
-                .into(),
-        )),
+    match backend.get(&format!("{}.session", crate::DEFAULT_XATTR_NAME))? {
+        Some(bytes) => match SessionCommandSerialized::decode::<u32>(bytes.as_slice()) {
+            Ok(session) => Ok(Some(session.into())),
+            // sessions stored before arguments, environment and working directory
+            // were supported only contain the command
+            Err(_) => Ok(Some(
+                LegacySessionCommandSerialized::decode::<u32>(bytes.as_slice())
+                    .map_err(|_| StorageError::DeserializationError)?
+                    .into(),
+            )),
+        },
         None => Ok(None),
     }
 }
@@ -239,24 +514,8 @@ pub fn store_user_session_command(
     settings: &SessionCommand,
     source: &StorageSource,
 ) -> Result<(), StorageError> {
-    let home_dir_path = match source {
-        StorageSource::Username(username) => homedir_by_username(username)?,
-        StorageSource::Path(pathbuf) => pathbuf.as_os_str().to_os_string(),
-    };
-
-    // this is used in case a future format will be required
-    let manifest = AuthDataManifest::new();
-    let manifest_serialization = manifest
-        .encode::<u16>()
-        .map_err(StorageError::SerializationError)?;
-
-    // once everything is serialized perform the writing
-    xattr::set(
-        home_dir_path.as_os_str(),
-        format!("{}.manifest", crate::DEFAULT_XATTR_NAME),
-        manifest_serialization.as_slice(),
-    )
-    .map_err(StorageError::XAttrError)?;
+    // the manifest is written while choosing the backend
+    let backend = source.store_backend()?;
 
     let session_data = SessionCommandSerialized::from(settings);
     let session_serialization = session_data
@@ -264,105 +523,142 @@ pub fn store_user_session_command(
         .map_err(StorageError::SerializationError)?;
 
     // once everything is serialized perform the writing
-    xattr::set(
-        home_dir_path.as_os_str(),
-        format!("{}.session", crate::DEFAULT_XATTR_NAME),
+    backend.set(
+        &format!("{}.session", crate::DEFAULT_XATTR_NAME),
         session_serialization.as_slice(),
-    )
-    .map_err(StorageError::XAttrError)?;
+    )?;
+
+    Ok(())
+}
+
+pub fn load_user_session_environment(
+    source: &StorageSource,
+) -> Result<Option<SessionEnvironment>, StorageError> {
+    let backend = source.load_backend()?;
+
+    match backend.get(&format!("{}.environment", crate::DEFAULT_XATTR_NAME))? {
+        Some(bytes) => Ok(Some(
+            SessionEnvironmentSerialized::decode::<u32>(bytes.as_slice())
+                .map_err(StorageError::SerializationError)?
+                .try_into()?,
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Store the session environment: an empty one is removed
+pub fn store_user_session_environment(
+    environment: &SessionEnvironment,
+    source: &StorageSource,
+) -> Result<(), StorageError> {
+    let attr_name = format!("{}.environment", crate::DEFAULT_XATTR_NAME);
+
+    if environment.is_empty() {
+        return source.load_backend()?.remove(&attr_name);
+    }
+
+    let environment_serialization = SessionEnvironmentSerialized::from(environment)
+        .encode::<u32>()
+        .map_err(StorageError::SerializationError)?;
+
+    source
+        .store_backend()?
+        .set(&attr_name, environment_serialization.as_slice())?;
 
     Ok(())
 }
 
 pub fn load_user_auth_data(source: &StorageSource) -> Result<Option<UserAuthData>, StorageError> {
-    let home_dir_path = match source {
-        StorageSource::Username(username) => homedir_by_username(username)?,
-        StorageSource::Path(pathbuf) => pathbuf.as_os_str().to_os_string(),
-    };
+    let backend = source.load_backend()?;
 
-    let manifest = xattr::get_deref(
-        home_dir_path.as_os_str(),
-        format!("{}.manifest", crate::DEFAULT_XATTR_NAME),
-    )
-    .map_err(StorageError::XAttrError)?;
+    let manifest = backend.get(&manifest_attribute())?;
     if manifest.is_none() {
         return Ok(None);
     }
 
-    let main = xattr::get_deref(
-        home_dir_path.as_os_str(),
-        format!("{}.main", crate::DEFAULT_XATTR_NAME),
-    )
-    .map_err(StorageError::XAttrError)?;
-    if main.is_none() {
-        return Ok(None);
-    }
+    let main = backend.get(&format!("{}.main", crate::DEFAULT_XATTR_NAME))?;
+
+    let preferred_session =
+        backend.get(&format!("{}.preferred_session", crate::DEFAULT_XATTR_NAME))?;
 
     let mut auth_data = UserAuthData::new();
 
+    if let Some(raw_data) = preferred_session {
+        let serialized_data = PreferredSessionSerialized::decode::<u32>(raw_data.as_slice())
+            .map_err(StorageError::SerializationError)?;
+        auth_data.set_preferred_session(Some(serialized_data.into()));
+    }
+
     match main {
         Some(a) => {
             let main = MainPassword::decode::<u16>(a.as_slice())
                 .map_err(StorageError::SerializationError)?;
             auth_data.push_main(main);
         }
-        None => return Ok(None),
+        // a preferred session can be set without any authentication data
+        None => match auth_data.preferred_session().is_some() {
+            true => return Ok(Some(auth_data)),
+            false => return Ok(None),
+        },
     };
 
-    let xattrs = xattr::list_deref(home_dir_path.as_os_str()).map_err(StorageError::XAttrError)?;
-    for attr in xattrs.into_iter() {
-        if let Some(s) = attr.to_str() {
-            if s.starts_with(format!("{}.auth.", crate::DEFAULT_XATTR_NAME).as_str()) {
-                let raw_data = xattr::get_deref(home_dir_path.as_os_str(), s)
-                    .map_err(StorageError::XAttrError)?
-                    .unwrap();
-                let serialized_data = AuthDataSerialized::decode::<u32>(raw_data.as_slice())?;
+    for attr in backend.list()?.into_iter() {
+        if attr.starts_with(format!("{}.ik.", crate::DEFAULT_XATTR_NAME).as_str()) {
+            let Some(raw_data) = backend.get(&attr)? else {
+                continue;
+            };
+            let intermediate_key = IntermediateKey::decode::<u16>(raw_data.as_slice())
+                .map_err(StorageError::SerializationError)?;
 
-                let secondary_auth: SecondaryAuth = serialized_data.try_into()?;
+            auth_data.push_intermediate_key(intermediate_key);
+        } else if attr.starts_with(format!("{}.auth.", crate::DEFAULT_XATTR_NAME).as_str()) {
+            let Some(raw_data) = backend.get(&attr)? else {
+                continue;
+            };
+            let serialized_data = AuthDataSerialized::decode::<u32>(raw_data.as_slice())?;
 
-                auth_data.push_secondary(secondary_auth);
-            }
+            let secondary_auth: SecondaryAuth = serialized_data.try_into()?;
+
+            auth_data.push_secondary(secondary_auth);
         }
     }
 
     Ok(Some(auth_data))
 }
 
+/// Remove the login-ng data from every backend that can be reached
 pub fn remove_user_data(source: &StorageSource) -> Result<(), StorageError> {
-    let home_dir_path = match source {
-        StorageSource::Username(username) => homedir_by_username(username)?,
-        StorageSource::Path(pathbuf) => pathbuf.as_os_str().to_os_string(),
-    };
+    let mut removed = false;
+    let mut first_error = None;
+
+    for backend in source.backends() {
+        let attrs = match backend.list() {
+            Ok(attrs) => attrs,
+            Err(err) => {
+                first_error.get_or_insert(err);
+                continue;
+            }
+        };
 
-    let xattrs = xattr::list_deref(home_dir_path.as_os_str()).map_err(StorageError::XAttrError)?;
-    for attr in xattrs.into_iter() {
-        if attr
-            .to_string_lossy()
-            .starts_with(crate::DEFAULT_XATTR_NAME)
-        {
-            xattr::remove_deref(home_dir_path.as_os_str(), attr.as_os_str())
-                .map_err(StorageError::XAttrError)?
+        for attr in attrs.into_iter() {
+            if attr.starts_with(crate::DEFAULT_XATTR_NAME) {
+                backend.remove(&attr)?
+            }
         }
+
+        removed = true;
     }
 
-    Ok(())
+    match (removed, first_error) {
+        (false, Some(err)) => Err(err),
+        _ => Ok(()),
+    }
 }
 
 pub fn store_user_auth_data(
     auth_data: UserAuthData,
     source: &StorageSource,
 ) -> Result<(), StorageError> {
-    let home_dir_path = match source {
-        StorageSource::Username(username) => homedir_by_username(username)?,
-        StorageSource::Path(pathbuf) => pathbuf.as_os_str().to_os_string(),
-    };
-
-    // this is used in case a future format will be required
-    let manifest = AuthDataManifest::new();
-    let manifest_serialization = manifest
-        .encode::<u16>()
-        .map_err(StorageError::SerializationError)?;
-
     let maybe_main_password_serialization = match auth_data.main_password() {
         Some(m) => Some(
             m.encode::<u16>()
@@ -371,35 +667,48 @@ pub fn store_user_auth_data(
         None => None,
     };
 
-    // remove everything that was already present
-    let xattrs = xattr::list_deref(home_dir_path.as_os_str()).map_err(StorageError::XAttrError)?;
-    for attr in xattrs.into_iter() {
-        let current_xattr = attr.to_string_lossy();
+    // the manifest is written while choosing the backend
+    let backend = source.store_backend()?;
 
-        if current_xattr.starts_with(format!("{}.auth", crate::DEFAULT_XATTR_NAME).as_str())
-            || current_xattr.starts_with(format!("{}.main", crate::DEFAULT_XATTR_NAME).as_str())
+    // remove everything that was already present
+    for attr in backend.list()?.into_iter() {
+        if attr.starts_with(format!("{}.auth", crate::DEFAULT_XATTR_NAME).as_str())
+            || attr.starts_with(format!("{}.main", crate::DEFAULT_XATTR_NAME).as_str())
+            || attr.starts_with(format!("{}.ik.", crate::DEFAULT_XATTR_NAME).as_str())
+            || attr.starts_with(format!("{}.preferred_session", crate::DEFAULT_XATTR_NAME).as_str())
         {
-            xattr::remove_deref(home_dir_path.as_os_str(), attr.as_os_str())
-                .map_err(StorageError::XAttrError)?
+            backend.remove(&attr)?
         }
     }
 
-    // once everything is serialized perform the writing
-    xattr::set(
-        home_dir_path.as_os_str(),
-        format!("{}.manifest", crate::DEFAULT_XATTR_NAME),
-        manifest_serialization.as_slice(),
-    )
-    .map_err(StorageError::XAttrError)?;
+    if let Some(session) = auth_data.preferred_session() {
+        let raw_data = PreferredSessionSerialized::from(session)
+            .encode::<u32>()
+            .map_err(StorageError::SerializationError)?;
+
+        backend.set(
+            &format!("{}.preferred_session", crate::DEFAULT_XATTR_NAME),
+            raw_data.as_slice(),
+        )?;
+    }
 
     if let Some(data) = &maybe_main_password_serialization {
         // save the main password first so that if something bad happens after one or more secondary auth may be usable
-        xattr::set(
-            home_dir_path.as_os_str(),
-            format!("{}.main", crate::DEFAULT_XATTR_NAME),
+        backend.set(
+            &format!("{}.main", crate::DEFAULT_XATTR_NAME),
             data.as_slice(),
-        )
-        .map_err(StorageError::XAttrError)?;
+        )?;
+
+        for (index, val) in auth_data.intermediate_keys().enumerate() {
+            let raw_data = val
+                .encode::<u16>()
+                .map_err(StorageError::SerializationError)?;
+
+            backend.set(
+                &format!("{}.ik.{}", crate::DEFAULT_XATTR_NAME, index),
+                raw_data.as_slice(),
+            )?
+        }
 
         for (index, val) in auth_data.secondary().enumerate() {
             let serialized_data: AuthDataSerialized = val.try_into()?;
@@ -407,37 +716,47 @@ pub fn store_user_auth_data(
                 .encode::<u32>()
                 .map_err(StorageError::SerializationError)?;
 
-            xattr::set(
-                home_dir_path.as_os_str(),
-                format!("{}.auth.{}", crate::DEFAULT_XATTR_NAME, index),
+            backend.set(
+                &format!("{}.auth.{}", crate::DEFAULT_XATTR_NAME, index),
                 raw_data.as_slice(),
-            )
-            .map_err(StorageError::XAttrError)?
+            )?
         }
     };
     Ok(())
 }
 
+/// Quotas are stored apart from the mounts they refer to, keeping the mounts format unchanged
+fn load_mount_quota(
+    backend: &StorageBackend,
+    attr_name: String,
+) -> Result<Option<u64>, StorageError> {
+    let raw_data = backend.get(&attr_name)?;
+
+    Ok(raw_data
+        .and_then(|raw_data| <[u8; 8]>::try_from(raw_data.as_slice()).ok())
+        .map(u64::from_le_bytes))
+}
+
+fn store_mount_quota(
+    backend: &StorageBackend,
+    attr_name: String,
+    quota: Option<u64>,
+) -> Result<(), StorageError> {
+    match quota {
+        Some(quota) => backend.set(&attr_name, &quota.to_le_bytes()),
+        None => Ok(()),
+    }
+}
+
 pub fn load_user_mountpoints(source: &StorageSource) -> Result<Option<MountPoints>, StorageError> {
-    let home_dir_path = match source {
-        StorageSource::Username(username) => homedir_by_username(username)?,
-        StorageSource::Path(pathbuf) => pathbuf.as_os_str().to_os_string(),
-    };
+    let backend = source.load_backend()?;
 
-    let manifest = xattr::get_deref(
-        home_dir_path.as_os_str(),
-        format!("{}.manifest", crate::DEFAULT_XATTR_NAME),
-    )
-    .map_err(StorageError::XAttrError)?;
+    let manifest = backend.get(&manifest_attribute())?;
     if manifest.is_none() {
         return Ok(None);
     }
 
-    let main = xattr::get_deref(
-        home_dir_path.as_os_str(),
-        format!("{}.mount", crate::DEFAULT_XATTR_NAME),
-    )
-    .map_err(StorageError::XAttrError)?;
+    let main = backend.get(&format!("{}.mount", crate::DEFAULT_XATTR_NAME))?;
     if main.is_none() {
         return Ok(None);
     }
@@ -449,64 +768,56 @@ pub fn load_user_mountpoints(source: &StorageSource) -> Result<Option<MountPoint
         ),
         None => return Ok(None),
     };
+    let home_quota = load_mount_quota(
+        &backend,
+        format!("{}.mountquota", crate::DEFAULT_XATTR_NAME),
+    )?;
 
     let mut mounts = HashMap::new();
 
-    let xattrs = xattr::list_deref(home_dir_path.as_os_str()).map_err(StorageError::XAttrError)?;
-    for attr in xattrs.into_iter() {
-        if let Some(s) = attr.to_str() {
-            if s.starts_with(format!("{}.mounts.", crate::DEFAULT_XATTR_NAME).as_str()) {
-                let raw_data = xattr::get_deref(home_dir_path.as_os_str(), s)
-                    .map_err(StorageError::XAttrError)?
-                    .unwrap();
+    for attr in backend.list()?.into_iter() {
+        if let Some(index) =
+            attr.strip_prefix(format!("{}.mounts.", crate::DEFAULT_XATTR_NAME).as_str())
+        {
+            let Some(raw_data) = backend.get(&attr)? else {
+                continue;
+            };
+
+            let secondary_auth = <(String, MountParams)>::from(
+                &MountPointSerialized::decode::<u32>(raw_data.as_slice())?,
+            );
 
-                let secondary_auth = <(String, MountParams)>::from(
-                    &MountPointSerialized::decode::<u32>(raw_data.as_slice())?,
-                );
+            let quota = load_mount_quota(
+                &backend,
+                format!("{}.mountquota.{}", crate::DEFAULT_XATTR_NAME, index),
+            )?;
 
-                mounts.insert(secondary_auth.0, secondary_auth.1);
-            }
+            mounts.insert(secondary_auth.0, secondary_auth.1.with_quota(quota));
         }
     }
 
-    Ok(Some(MountPoints::new(mount_data.1, mounts)))
+    Ok(Some(MountPoints::new(
+        mount_data.1.with_quota(home_quota),
+        mounts,
+    )))
 }
 
 pub fn store_user_mountpoints(
     mountpoints_data: Option<MountPoints>,
     source: &StorageSource,
 ) -> Result<(), StorageError> {
-    let home_dir_path = match source {
-        StorageSource::Username(username) => homedir_by_username(username)?,
-        StorageSource::Path(pathbuf) => pathbuf.as_os_str().to_os_string(),
-    };
-
-    // this is used in case a future format will be required
-    let manifest = AuthDataManifest::new();
-    let manifest_serialization = manifest
-        .encode::<u16>()
-        .map_err(StorageError::SerializationError)?;
+    // the manifest is written while choosing the backend
+    let backend = source.store_backend()?;
 
     // remove everything that was already present
-    let xattrs = xattr::list_deref(home_dir_path.as_os_str()).map_err(StorageError::XAttrError)?;
-    for attr in xattrs.into_iter() {
-        let current_xattr = attr.to_string_lossy();
-
-        if current_xattr.starts_with(format!("{}.mount", crate::DEFAULT_XATTR_NAME).as_str())
-            || current_xattr.starts_with(format!("{}.mounts.", crate::DEFAULT_XATTR_NAME).as_str())
+    for attr in backend.list()?.into_iter() {
+        if attr.starts_with(format!("{}.mount", crate::DEFAULT_XATTR_NAME).as_str())
+            || attr.starts_with(format!("{}.mounts.", crate::DEFAULT_XATTR_NAME).as_str())
         {
-            xattr::remove_deref(home_dir_path.as_os_str(), attr.as_os_str())
-                .map_err(StorageError::XAttrError)?
+            backend.remove(&attr)?
         }
     }
 
-    xattr::set(
-        home_dir_path.as_os_str(),
-        format!("{}.manifest", crate::DEFAULT_XATTR_NAME),
-        manifest_serialization.as_slice(),
-    )
-    .map_err(StorageError::XAttrError)?;
-
     let Some(mountpoints) = mountpoints_data else {
         return Ok(());
     };
@@ -528,21 +839,93 @@ pub fn store_user_mountpoints(
             .encode::<u32>()
             .map_err(StorageError::SerializationError)?;
 
-        xattr::set(
-            home_dir_path.as_os_str(),
-            format!("{}.mounts.{}", crate::DEFAULT_XATTR_NAME, index),
+        store_mount_quota(
+            &backend,
+            format!("{}.mountquota.{}", crate::DEFAULT_XATTR_NAME, index),
+            val.1.quota(),
+        )?;
+
+        backend.set(
+            &format!("{}.mounts.{}", crate::DEFAULT_XATTR_NAME, index),
             raw_data.as_slice(),
-        )
-        .map_err(StorageError::XAttrError)?
+        )?
     }
 
+    store_mount_quota(
+        &backend,
+        format!("{}.mountquota", crate::DEFAULT_XATTR_NAME),
+        mountpoints.mount().quota(),
+    )?;
+
     // save the home mount last so that if something bad happens an invalid mount won't be attempted
-    xattr::set(
-        home_dir_path.as_os_str(),
-        format!("{}.mount", crate::DEFAULT_XATTR_NAME),
+    backend.set(
+        &format!("{}.mount", crate::DEFAULT_XATTR_NAME),
         main_mount.as_slice(),
-    )
-    .map_err(StorageError::XAttrError)?;
+    )?;
+
+    Ok(())
+}
+
+/// Loads the login history of the user: the most recent login is the last element
+pub fn load_user_login_history(source: &StorageSource) -> Result<Vec<LoginRecord>, StorageError> {
+    let backend = source.load_backend()?;
+
+    let mut history = vec![];
+
+    for attr in backend.list()?.into_iter() {
+        if attr.starts_with(format!("{}.history.", crate::DEFAULT_XATTR_NAME).as_str()) {
+            let Some(raw_data) = backend.get(&attr)? else {
+                continue;
+            };
+
+            let record: LoginRecord = LoginRecordSerialized::decode::<u32>(raw_data.as_slice())
+                .map_err(|_| StorageError::DeserializationError)?
+                .into();
+
+            history.push(record);
+        }
+    }
+
+    history.sort_by_key(|record| record.timestamp());
+
+    Ok(history)
+}
+
+/// Appends a login to the user history, discarding the oldest records
+/// so that at most MAX_LOGIN_HISTORY records are kept.
+pub fn store_user_login_record(
+    record: &LoginRecord,
+    source: &StorageSource,
+) -> Result<(), StorageError> {
+    let mut history = load_user_login_history(source)?;
+    history.push(record.clone());
+
+    let skip = history.len().saturating_sub(MAX_LOGIN_HISTORY);
+
+    let mut serialized_history = vec![];
+    for record in history.iter().skip(skip) {
+        serialized_history.push(
+            LoginRecordSerialized::from(record)
+                .encode::<u32>()
+                .map_err(StorageError::SerializationError)?,
+        );
+    }
+
+    let backend = source.store_backend()?;
+
+    // remove the old history only after the new one has been serialized
+    for attr in backend.list()?.into_iter() {
+        if attr.starts_with(format!("{}.history.", crate::DEFAULT_XATTR_NAME).as_str()) {
+            backend.remove(&attr)?
+        }
+    }
+
+    for (index, raw_data) in serialized_history.iter().enumerate() {
+        backend.set(
+            &format!("{}.history.{}", crate::DEFAULT_XATTR_NAME, index),
+            raw_data.as_slice(),
+        )?
+    }
 
     Ok(())
 }
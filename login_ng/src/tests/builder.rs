@@ -0,0 +1,100 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::collections::HashMap;
+
+use crate::{
+    builder::*,
+    command::PreferredSession,
+    mount::{MountParams, MountPoints},
+};
+
+#[test]
+fn builder_complete() {
+    let mounts = MountPoints::new(
+        MountParams::new(
+            String::from("/dev/disk/by-label/home"),
+            String::from("ext4"),
+            vec![],
+        ),
+        HashMap::new(),
+    );
+
+    let config = User::builder()
+        .main_password("main password <3")
+        .intermediate_key("intermediate_key")
+        .secondary_password("autologin", "")
+        .secondary_password("pin", "1234")
+        .mounts(mounts.clone())
+        .default_session(PreferredSession::Desktop(String::from("plasma")))
+        .build()
+        .unwrap();
+
+    let main = String::from("main password <3");
+    assert_eq!(
+        config.auth().main_by_auth(&Some(String::new())).unwrap(),
+        main
+    );
+    assert_eq!(
+        config
+            .auth()
+            .main_by_auth(&Some(String::from("1234")))
+            .unwrap(),
+        main
+    );
+    assert_eq!(config.auth().secondary().count(), 2);
+    assert_eq!(
+        config.auth().preferred_session(),
+        &Some(PreferredSession::Desktop(String::from("plasma")))
+    );
+    assert_eq!(config.mounts(), &Some(mounts));
+}
+
+#[test]
+fn builder_invariants() {
+    assert!(matches!(
+        User::builder().secondary_password("pin", "1234").build(),
+        Err(UserBuilderError::SecondaryWithoutMain)
+    ));
+
+    assert!(matches!(
+        User::builder().main_password("main").build(),
+        Err(UserBuilderError::MainWithoutIntermediate)
+    ));
+
+    assert!(matches!(
+        User::builder().intermediate_key("intermediate").build(),
+        Err(UserBuilderError::IntermediateWithoutMain)
+    ));
+
+    assert!(matches!(
+        User::builder()
+            .main_password("main")
+            .intermediate_key("intermediate")
+            .secondary_password("pin", "1234")
+            .secondary_password("pin", "5678")
+            .build(),
+        Err(UserBuilderError::DuplicatedSecondary(name)) if name == "pin"
+    ));
+
+    // an empty configuration is valid: the user simply has no login-ng data
+    let empty = User::builder().build().unwrap();
+    assert!(!empty.auth().has_main());
+    assert!(empty.mounts().is_none());
+}
@@ -0,0 +1,93 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use crate::config::*;
+
+fn mode(path: &Path) -> u32 {
+    std::fs::metadata(path).unwrap().permissions().mode() & 0o7777
+}
+
+#[test]
+fn config_dir_initialization() {
+    let dir = Path::new("test_config_dir");
+    let _ = std::fs::remove_dir_all(dir);
+
+    init_config_dir(dir).unwrap();
+    assert_eq!(mode(dir), 0o700);
+
+    // an existing directory is checked, not modified
+    std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+    init_config_dir(dir).unwrap();
+    assert_eq!(mode(dir), 0o755);
+
+    std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o777)).unwrap();
+    assert!(matches!(
+        init_config_dir(dir),
+        Err(ConfigError::UnsafePermissions(_, 0o777))
+    ));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn config_file_permissions() {
+    let dir = Path::new("test_config_files");
+    let _ = std::fs::remove_dir_all(dir);
+    init_config_dir(dir).unwrap();
+
+    let config = dir.join("service.conf");
+    std::fs::write(&config, "[Sessions]\nmax_per_user = 1\n").unwrap();
+    std::fs::set_permissions(&config, std::fs::Permissions::from_mode(0o644)).unwrap();
+    assert!(read_config_file(&config).is_ok());
+    assert!(unsafe_config_files(dir).unwrap().is_empty());
+
+    // world-writable configuration files are refused
+    std::fs::set_permissions(&config, std::fs::Permissions::from_mode(0o666)).unwrap();
+    assert!(matches!(
+        read_config_file(&config),
+        Err(ConfigError::UnsafePermissions(_, 0o666))
+    ));
+    assert_eq!(unsafe_config_files(dir).unwrap().len(), 1);
+
+    // secrets must not be readable by anyone else
+    let secret = dir.join("private_key_pkcs1.pem");
+    write_private_file(&secret, b"secret").unwrap();
+    assert_eq!(mode(&secret), 0o600);
+    assert_eq!(read_private_file(&secret).unwrap(), "secret");
+
+    std::fs::set_permissions(&secret, std::fs::Permissions::from_mode(0o644)).unwrap();
+    assert!(read_private_file(&secret).is_err());
+
+    // rewriting fixes the mode
+    write_private_file(&secret, b"other").unwrap();
+    assert_eq!(mode(&secret), 0o600);
+
+    // a symlink planted in place of a secret is not followed
+    let target = dir.join("target");
+    std::fs::write(&target, "untouched").unwrap();
+    let planted = dir.join("planted.pem");
+    std::os::unix::fs::symlink("target", &planted).unwrap();
+    assert!(write_private_file(&planted, b"secret").is_err());
+    assert_eq!(std::fs::read_to_string(&target).unwrap(), "untouched");
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
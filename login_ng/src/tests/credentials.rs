@@ -0,0 +1,50 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::path::Path;
+
+use crate::credentials::*;
+
+#[test]
+fn test_credential_names() {
+    assert_eq!(credential_name("user").unwrap(), "login_ng.user");
+    assert_eq!(keyring_description("user"), "login_ng:user");
+
+    assert!(credential_name("").is_err());
+    assert!(credential_name("../user").is_err());
+}
+
+#[test]
+fn test_read_credential() {
+    let dir_name = "test_credentials";
+    std::fs::create_dir_all(dir_name).unwrap();
+
+    std::fs::write(Path::new(dir_name).join("login_ng.cred_user"), b"s3cret\n").unwrap();
+
+    let secret = read_credential(Path::new(dir_name), "cred_user")
+        .unwrap()
+        .unwrap();
+    assert_eq!(&secret[..], b"s3cret");
+
+    assert!(read_credential(Path::new(dir_name), "other_user")
+        .unwrap()
+        .is_none());
+
+    std::fs::remove_dir_all(dir_name).unwrap();
+}
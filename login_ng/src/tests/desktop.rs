@@ -0,0 +1,93 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use crate::desktop::*;
+
+#[test]
+fn test_desktop_session_parse() {
+    let content = "# comment\n[Desktop Entry]\nName=Plasma (Wayland)\nExec=/usr/bin/startplasma-wayland --flag \"quoted arg\" %U\nDesktopNames=KDE;Plasma;\nType=Application\n\n[Desktop Action other]\nExec=ignored\n";
+
+    let session = DesktopSession::parse("plasma", content, SessionType::Wayland).unwrap();
+
+    assert_eq!(session.id(), "plasma");
+    assert_eq!(session.name(), "Plasma (Wayland)");
+    assert_eq!(
+        session.exec(),
+        &vec![
+            String::from("/usr/bin/startplasma-wayland"),
+            String::from("--flag"),
+            String::from("quoted arg"),
+        ]
+    );
+    assert_eq!(
+        session.desktop_names(),
+        &vec![String::from("KDE"), String::from("Plasma")]
+    );
+    assert!(session.matches("plasma"));
+    assert!(session.matches("kde"));
+    assert!(!session.matches("gnome"));
+
+    let command = session.session_command();
+    assert_eq!(command.command(), "/usr/bin/startplasma-wayland");
+    assert_eq!(command.args().len(), 2);
+    assert_eq!(
+        command.env().get("XDG_CURRENT_DESKTOP"),
+        Some(&String::from("KDE:Plasma"))
+    );
+    assert_eq!(
+        command.env().get("XDG_SESSION_TYPE"),
+        Some(&String::from("wayland"))
+    );
+}
+
+#[test]
+fn test_desktop_session_x11() {
+    let content = "[Desktop Entry]\nName=Plasma (X11)\nExec=/usr/bin/startplasma-x11\n";
+
+    let session = DesktopSession::parse("plasmax11", content, SessionType::X11).unwrap();
+
+    // X11 sessions need an X server: those are started through the launcher
+    let command = session.session_command();
+    assert_eq!(command.command(), crate::xorg::X11_SESSION_LAUNCHER);
+    assert_eq!(
+        command.args(),
+        &vec![
+            String::from("--x11"),
+            String::from("--command"),
+            String::from("/usr/bin/startplasma-x11"),
+        ]
+    );
+    assert_eq!(
+        command.env().get("XDG_SESSION_TYPE"),
+        Some(&String::from("x11"))
+    );
+}
+
+#[test]
+fn test_desktop_session_invalid() {
+    assert!(DesktopSession::parse("x", "Exec=foo\n", SessionType::X11).is_err());
+    assert!(DesktopSession::parse("x", "[Desktop Entry]\nName=x\n", SessionType::X11).is_err());
+    assert!(DesktopSession::parse("x", "[Desktop Entry]\nExec=\"foo\n", SessionType::X11).is_err());
+    assert!(DesktopSession::parse(
+        "x",
+        "[Desktop Entry]\nExec=foo\nHidden=true\n",
+        SessionType::X11
+    )
+    .is_err());
+}
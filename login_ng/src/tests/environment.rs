@@ -0,0 +1,131 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use crate::environment::*;
+
+#[test]
+fn session_environment_parsing() {
+    assert_eq!(parse_umask("077"), Ok(0o077));
+    assert_eq!(parse_umask("0022"), Ok(0o022));
+    assert!(parse_umask("1777").is_err());
+    assert!(parse_umask("088").is_err());
+
+    assert_eq!(parse_nice("-5"), Ok(-5));
+    assert!(parse_nice("20").is_err());
+
+    assert_eq!("idle".parse::<IoPriority>(), Ok(IoPriority::Idle));
+    assert_eq!(
+        "best-effort".parse::<IoPriority>(),
+        Ok(IoPriority::BestEffort(4))
+    );
+    assert_eq!(
+        "realtime:0".parse::<IoPriority>(),
+        Ok(IoPriority::RealTime(0))
+    );
+    assert!("idle:3".parse::<IoPriority>().is_err());
+    assert!("best-effort:8".parse::<IoPriority>().is_err());
+    assert_eq!(IoPriority::BestEffort(2).to_string(), "best-effort:2");
+
+    assert!(is_locale_variable("LANG"));
+    assert!(is_locale_variable("LC_TIME"));
+    assert!(!is_locale_variable("LC_"));
+    assert!(!is_locale_variable("PATH"));
+
+    assert!(SessionEnvironment::new()
+        .with_locale(String::from("LD_PRELOAD"), String::from("evil.so"))
+        .is_err());
+}
+
+#[test]
+fn session_environment_serialization() {
+    let dir_name = "test_session_environment";
+    let source = crate::storage::StorageSource::Path(std::path::PathBuf::from(dir_name));
+
+    std::fs::create_dir(dir_name).unwrap();
+
+    let environment = SessionEnvironment::new()
+        .with_umask(Some(0o077))
+        .unwrap()
+        .with_locale(String::from("LANG"), String::from("it_IT.UTF-8"))
+        .unwrap()
+        .with_nice(Some(-3))
+        .unwrap()
+        .with_io_priority(Some(IoPriority::Idle));
+
+    crate::storage::store_user_session_environment(&environment, &source).unwrap();
+    let reloaded = crate::storage::load_user_session_environment(&source);
+
+    // an empty environment removes the stored one
+    crate::storage::store_user_session_environment(&SessionEnvironment::new(), &source).unwrap();
+    let cleared = crate::storage::load_user_session_environment(&source);
+
+    std::fs::remove_dir(dir_name).unwrap();
+
+    assert_eq!(reloaded.unwrap(), Some(environment));
+    assert_eq!(cleared.unwrap(), None);
+}
+
+#[test]
+fn environment_d_parsing() {
+    let mut env = std::collections::BTreeMap::new();
+
+    parse_environment_d(
+        "# comment\n\nEDITOR=vim\nPATH=$HOME/bin:${PATH}\nBROWSER=${BROWSER:-firefox}\nDEBUG=${EDITOR:+1}\nPRICE=\\$5\nnot an assignment\nBAD KEY=1\n",
+        &mut env,
+    );
+
+    assert_eq!(env.get("EDITOR").unwrap(), "vim");
+    assert_eq!(env.get("PATH").unwrap(), "/bin:");
+    assert_eq!(env.get("BROWSER").unwrap(), "firefox");
+    assert_eq!(env.get("DEBUG").unwrap(), "1");
+    assert_eq!(env.get("PRICE").unwrap(), "$5");
+    assert!(!env.contains_key("BAD KEY"));
+    assert_eq!(env.len(), 5);
+}
+
+#[test]
+fn environment_d_loading() {
+    let dir = std::path::PathBuf::from("./test_environment_d");
+    std::fs::create_dir_all(&dir).unwrap();
+
+    std::fs::write(dir.join("20-editor.conf"), "EDITOR=emacs\nPAGER=less\n").unwrap();
+    std::fs::write(dir.join("10-editor.conf"), "EDITOR=vim\n").unwrap();
+    std::fs::write(dir.join("ignored.txt"), "PAGER=more\n").unwrap();
+
+    // a FIFO must be skipped rather than waited on
+    let fifo = std::ffi::CString::new("./test_environment_d/15-fifo.conf").unwrap();
+    assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) }, 0);
+    std::os::unix::fs::symlink("ignored.txt", dir.join("30-link.conf")).unwrap();
+
+    let uid = users::get_current_uid();
+
+    let env = load_environment_d(&dir, uid);
+    let foreign = load_environment_d(&dir, uid + 1);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // later files override earlier ones
+    assert_eq!(env.get("EDITOR").unwrap(), "emacs");
+    assert_eq!(env.get("PAGER").unwrap(), "less");
+
+    // files of other users are not trusted
+    assert!(foreign.is_empty());
+
+    assert!(load_environment_d(&dir, uid).is_empty());
+}
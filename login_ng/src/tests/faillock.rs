@@ -0,0 +1,120 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::time::Duration;
+
+use crate::faillock::*;
+
+#[test]
+fn test_faillock_conf() {
+    let config = FaillockConfig::parse(
+        "# comment\n dir = /tmp/faillock\ndeny=5 # inline comment\nunlock_time = never\naudit\n",
+    );
+
+    assert_eq!(config.dir(), std::path::Path::new("/tmp/faillock"));
+    assert_eq!(config.deny(), 5);
+    assert_eq!(config.fail_interval(), Duration::from_secs(900));
+    assert_eq!(config.unlock_time(), None);
+}
+
+#[test]
+fn test_faillock_lockout() {
+    let config = FaillockConfig::parse("deny = 3\nfail_interval = 60\nunlock_time = 120");
+
+    let records = vec![
+        TallyRecord::new("tty1", 1000),
+        TallyRecord::new("tty1", 1010),
+    ];
+    assert_eq!(
+        lock_status(&config, &records, false, 1020),
+        LockStatus::Unlocked
+    );
+
+    let records = vec![
+        TallyRecord::new("tty1", 1000),
+        TallyRecord::new("tty1", 1010),
+        TallyRecord::new("tty1", 1020),
+    ];
+    assert!(matches!(
+        lock_status(&config, &records, false, 1030),
+        LockStatus::Locked { until: Some(_) }
+    ));
+
+    // root is not subject to lockouts unless even_deny_root is set
+    assert_eq!(
+        lock_status(&config, &records, true, 1030),
+        LockStatus::Unlocked
+    );
+
+    // the lock expires after unlock_time
+    assert_eq!(
+        lock_status(&config, &records, false, 1020 + 121),
+        LockStatus::Unlocked
+    );
+}
+
+#[test]
+fn test_faillock_tally_file() {
+    let dir_name = "test_faillock";
+
+    let config = FaillockConfig::parse(format!("dir = {dir_name}").as_str());
+
+    record_failure(&config, "user", "tty1").unwrap();
+    record_failure(&config, "user", "tty2").unwrap();
+
+    let tally = read_tally(&config, "user");
+    let reset = reset_tally(&config, "user");
+    let tally_after_reset = read_tally(&config, "user");
+
+    std::fs::remove_dir_all(dir_name).unwrap();
+
+    let tally = tally.unwrap();
+    assert_eq!(tally.len(), 2);
+    assert_eq!(tally[0].source(), "tty1");
+    assert_eq!(tally[1].source(), "tty2");
+    assert!(tally.iter().all(|record| record.is_valid()));
+
+    reset.unwrap();
+    assert!(tally_after_reset.unwrap().is_empty());
+}
+
+#[test]
+fn test_faillock_tally_traversal() {
+    let dir_name = "test_faillock_traversal";
+
+    let config = FaillockConfig::parse(format!("dir = {dir_name}/tally").as_str());
+
+    for username in ["", "..", "../escaped", "../../etc/x", ".hidden", "a/b"] {
+        assert!(matches!(
+            record_failure(&config, username, "tty1"),
+            Err(FaillockError::InvalidUsername(_))
+        ));
+        assert!(matches!(
+            read_tally(&config, username),
+            Err(FaillockError::InvalidUsername(_))
+        ));
+        assert!(matches!(
+            reset_tally(&config, username),
+            Err(FaillockError::InvalidUsername(_))
+        ));
+    }
+
+    // nothing has been written, not even the tally directory
+    assert!(!std::path::Path::new(dir_name).exists());
+}
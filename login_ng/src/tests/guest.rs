@@ -0,0 +1,66 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::path::Path;
+
+use crate::guest::*;
+
+#[test]
+fn test_guest_conf() {
+    let config = GuestConfig::parse("");
+    assert!(!config.enabled());
+    assert!(!config.is_guest(DEFAULT_GUEST_USER));
+
+    let config = GuestConfig::parse(
+        "[Other]\nuser = other\n\n[Guest]\nenabled = true # inline comment\nuser = visitor\ntmpfs_size = 1G\n",
+    );
+    assert!(config.enabled());
+    assert_eq!(config.user(), "visitor");
+    assert_eq!(config.tmpfs_size(), "1G");
+    assert!(config.is_guest("visitor"));
+    assert!(!config.is_guest(DEFAULT_GUEST_USER));
+
+    // root can never be turned into a guest and mount options cannot be injected
+    let config = GuestConfig::parse("[Guest]\nenabled = yes\nuser = root\ntmpfs_size = 1G,exec\n");
+    assert_eq!(config.user(), DEFAULT_GUEST_USER);
+    assert_eq!(config.tmpfs_size(), DEFAULT_GUEST_TMPFS_SIZE);
+}
+
+#[test]
+fn test_guest_account_marker() {
+    let dir = Path::new("test_guest_accounts");
+
+    assert!(!is_guest_account(dir, "visitor", 1500));
+
+    mark_guest_account(dir, "visitor", 1500).unwrap();
+    assert!(is_guest_account(dir, "visitor", 1500));
+
+    // an account recreated with another uid is not the one login-ng created
+    assert!(!is_guest_account(dir, "visitor", 1501));
+    assert!(!is_guest_account(dir, "other", 1500));
+
+    assert!(mark_guest_account(dir, "../visitor", 1500).is_err());
+    assert!(!is_guest_account(
+        dir,
+        "../test_guest_accounts/visitor",
+        1500
+    ));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
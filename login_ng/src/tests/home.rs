@@ -0,0 +1,94 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::home::*;
+
+fn mode(path: &Path) -> u32 {
+    std::fs::metadata(path).unwrap().permissions().mode() & 0o7777
+}
+
+#[test]
+fn test_home_conf() {
+    // opt-in, as pam_mkhomedir is
+    let config = HomeConfig::parse("");
+    assert!(!config.create());
+    assert_eq!(config.skel(), Some(&PathBuf::from(DEFAULT_SKEL_DIR)));
+    assert_eq!(config.mode(), DEFAULT_HOME_MODE);
+
+    let config = HomeConfig::parse("[Home]\ncreate = true\nskel = /etc/skel.ldap\nmode = 0750\n");
+    assert!(config.create());
+    assert_eq!(config.skel(), Some(&PathBuf::from("/etc/skel.ldap")));
+    assert_eq!(config.mode(), 0o750);
+
+    // relative skeletons and special bits are refused, an empty skel disables the copy
+    let config = HomeConfig::parse("[Home]\nskel = skel\nmode = 4755\n");
+    assert_eq!(config.skel(), Some(&PathBuf::from(DEFAULT_SKEL_DIR)));
+    assert_eq!(config.mode(), DEFAULT_HOME_MODE);
+
+    let config = HomeConfig::parse("[Home]\nskel =\n");
+    assert_eq!(config.skel(), None);
+}
+
+#[test]
+fn home_creation() {
+    let dir = Path::new("test_home_creation");
+    let _ = std::fs::remove_dir_all(dir);
+
+    let skel = dir.join("skel");
+    std::fs::create_dir_all(skel.join(".config")).unwrap();
+    std::fs::write(skel.join(".bashrc"), "# bashrc\n").unwrap();
+    std::fs::write(skel.join(".config").join("app.conf"), "key = value\n").unwrap();
+    std::os::unix::fs::symlink(".bashrc", skel.join(".profile")).unwrap();
+
+    let config = HomeConfig::parse(
+        format!(
+            "[Home]\ncreate = yes\nskel = {}\nmode = 0750\n",
+            std::fs::canonicalize(&skel).unwrap().display()
+        )
+        .as_str(),
+    );
+
+    let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+
+    let home = dir.join("home").join("user");
+    assert!(config.create_home(&home, uid, gid).unwrap());
+    assert_eq!(mode(&home), 0o750);
+    assert_eq!(
+        std::fs::read_to_string(home.join(".config").join("app.conf")).unwrap(),
+        "key = value\n"
+    );
+    assert_eq!(
+        std::fs::read_link(home.join(".profile")).unwrap(),
+        PathBuf::from(".bashrc")
+    );
+
+    // an existing home is never touched
+    std::fs::remove_file(home.join(".bashrc")).unwrap();
+    assert!(!config.create_home(&home, uid, gid).unwrap());
+    assert!(!home.join(".bashrc").exists());
+
+    let disabled = HomeConfig::parse("[Home]\ncreate = no\n");
+    assert!(!disabled.create_home(&dir.join("other"), uid, gid).unwrap());
+    assert!(!dir.join("other").exists());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
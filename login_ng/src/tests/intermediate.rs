@@ -0,0 +1,147 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+#[test]
+fn test_independent_intermediate_keys() {
+    let correct_main = "main password <3".to_string();
+    let intermediate = "intermediate_key".to_string();
+    let laptop_key = "laptop key".to_string();
+    let backup_key = "backup key".to_string();
+
+    let mut user_cfg = crate::user::UserAuthData::new();
+    user_cfg.set_main(&correct_main, &intermediate).unwrap();
+    user_cfg
+        .add_intermediate_key("laptop", intermediate.as_bytes(), laptop_key.as_bytes())
+        .unwrap();
+
+    // any intermediate key can be used to add another one
+    user_cfg
+        .add_intermediate_key("backup", laptop_key.as_bytes(), backup_key.as_bytes())
+        .unwrap();
+    assert!(user_cfg
+        .add_intermediate_key("backup", intermediate.as_bytes(), b"another")
+        .is_err());
+
+    // each key unlocks the main password on its own
+    for key in [&intermediate, &laptop_key, &backup_key] {
+        assert_eq!(user_cfg.main(key).unwrap(), correct_main);
+    }
+
+    // secondary methods can wrap any of them
+    user_cfg
+        .add_secondary_password("pin", &laptop_key, &String::from("1234"))
+        .unwrap();
+    assert_eq!(
+        user_cfg.main_by_auth(&Some(String::from("1234"))).unwrap(),
+        correct_main
+    );
+
+    // revoking a key does not affect the others
+    assert!(user_cfg.remove_intermediate_key("laptop"));
+    assert!(!user_cfg.remove_intermediate_key("laptop"));
+    assert!(user_cfg.main(&laptop_key).is_err());
+    assert!(user_cfg.main_by_auth(&Some(String::from("1234"))).is_err());
+    assert_eq!(user_cfg.main(&backup_key).unwrap(), correct_main);
+    assert_eq!(user_cfg.main(&intermediate).unwrap(), correct_main);
+}
+
+#[test]
+fn test_intermediate_keys_main_change() {
+    let first_main = "main password <3".to_string();
+    let second_main = "new main password".to_string();
+    let intermediate = "intermediate_key".to_string();
+    let backup_key = "backup key".to_string();
+
+    let mut user_cfg = crate::user::UserAuthData::new();
+    user_cfg.set_main(&first_main, &intermediate).unwrap();
+    user_cfg
+        .add_intermediate_key("backup", intermediate.as_bytes(), backup_key.as_bytes())
+        .unwrap();
+
+    // an additional key is accepted as long as the main password is unchanged
+    user_cfg.set_main(&first_main, &backup_key).unwrap();
+    assert!(user_cfg.set_main(&second_main, &backup_key).is_err());
+
+    // additional keys cannot be re-wrapped: the change is refused until they are revoked
+    user_cfg
+        .add_secondary_password("pin", &backup_key, &String::from("1234"))
+        .unwrap();
+    assert!(user_cfg.set_main(&second_main, &intermediate).is_err());
+    assert_eq!(user_cfg.main(&intermediate).unwrap(), first_main);
+    assert_eq!(
+        user_cfg.main_by_auth(&Some(String::from("1234"))).unwrap(),
+        first_main
+    );
+
+    assert!(user_cfg.remove_intermediate_key("backup"));
+    user_cfg.set_main(&second_main, &intermediate).unwrap();
+    assert!(user_cfg.main(&backup_key).is_err());
+    assert_eq!(user_cfg.main(&intermediate).unwrap(), second_main);
+}
+
+#[test]
+fn test_main_change_keeps_secondary_passwords() {
+    let first_main = "main password <3".to_string();
+    let second_main = "new main password".to_string();
+    let intermediate = "intermediate_key".to_string();
+    let secondary = "secondary password".to_string();
+
+    let mut user_cfg = crate::user::UserAuthData::new();
+    user_cfg.set_main(&first_main, &intermediate).unwrap();
+    user_cfg
+        .add_secondary_password("password", &intermediate, &secondary)
+        .unwrap();
+
+    // secondary passwords unlock the primary intermediate key, that wraps the new main password
+    user_cfg.set_main(&second_main, &intermediate).unwrap();
+    assert_eq!(
+        user_cfg.main_by_auth(&Some(secondary.clone())).unwrap(),
+        second_main
+    );
+    assert!(user_cfg.main_by_auth(&Some(first_main)).is_err());
+}
+
+#[test]
+fn test_intermediate_keys_serialization() {
+    let correct_main = "main password <3".to_string();
+    let intermediate = "intermediate_key".to_string();
+    let backup_key = "backup key".to_string();
+
+    let dir_name = "test_intermediate";
+    let source = crate::storage::StorageSource::Path(std::path::PathBuf::from(dir_name));
+
+    {
+        let mut user_cfg = crate::user::UserAuthData::new();
+        user_cfg.set_main(&correct_main, &intermediate).unwrap();
+        user_cfg
+            .add_intermediate_key("backup", intermediate.as_bytes(), backup_key.as_bytes())
+            .unwrap();
+
+        std::fs::create_dir(dir_name).unwrap();
+        crate::storage::store_user_auth_data(user_cfg, &source).unwrap();
+    }
+
+    let reloaded = crate::storage::load_user_auth_data(&source);
+    std::fs::remove_dir(dir_name).unwrap();
+
+    let reloaded = reloaded.unwrap().unwrap();
+    let names: Vec<String> = reloaded.intermediate_keys().map(|key| key.name()).collect();
+    assert_eq!(names, vec![String::from("backup")]);
+    assert_eq!(reloaded.main(&backup_key).unwrap(), correct_main);
+}
@@ -0,0 +1,67 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::time::Duration;
+
+use crate::{keycache::*, user::UserAuthData};
+
+#[test]
+fn test_key_cache_config() {
+    let config = KeyCacheConfig::parse("[KeyCache]\nenabled = true\nttl = 60\n");
+    assert!(config.enabled());
+    assert_eq!(config.ttl(), Duration::from_secs(60));
+
+    // the cache is opt-in and invalid values keep the defaults
+    let config = KeyCacheConfig::parse("[KeyCache]\nttl = 0\n\n[Other]\nenabled = true\n");
+    assert!(!config.enabled());
+    assert_eq!(config.ttl(), DEFAULT_KEY_CACHE_TTL);
+
+    assert_eq!(KeyCacheConfig::parse(""), KeyCacheConfig::default());
+    assert_eq!(key_cache_description("user"), "login_ng-cache:user");
+}
+
+#[test]
+fn test_sealed_unlock() {
+    let cache_key = [7u8; 32];
+    let payload = seal_unlock(&cache_key, b"1234", b"hash", b"main password").unwrap();
+
+    assert_eq!(
+        &open_unlock(&payload, &cache_key, b"1234", b"hash").unwrap()[..],
+        b"main password"
+    );
+    assert!(open_unlock(&payload, &cache_key, b"4321", b"hash").is_none());
+    assert!(open_unlock(&payload[..40], &cache_key, b"1234", b"hash").is_none());
+
+    // an unlock cached before the main password changed is refused
+    assert!(open_unlock(&payload, &cache_key, b"1234", b"new hash").is_none());
+
+    // the secret alone is not enough: an unlock sealed without the cache key is refused
+    assert!(open_unlock(&payload, &[8u8; 32], b"1234", b"hash").is_none());
+    let forged = seal_unlock(&[], b"1234", b"hash", b"main password").unwrap();
+    assert!(open_unlock(&forged, &cache_key, b"1234", b"hash").is_none());
+
+    // a disabled cache never stores nor finds anything
+    let config = KeyCacheConfig::default();
+    config
+        .store("keycache_user", b"1234", &UserAuthData::new(), b"main")
+        .unwrap();
+    assert!(config
+        .lookup("keycache_user", b"1234", &UserAuthData::new())
+        .is_none());
+}
@@ -17,7 +17,26 @@
     51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 */
 
+pub mod builder;
+pub mod config;
+pub mod credentials;
+pub mod desktop;
+pub mod environment;
+pub mod faillock;
+pub mod guest;
+pub mod home;
+pub mod intermediate;
+pub mod keycache;
 pub mod main;
+pub mod motd;
+pub mod mount;
+pub mod nextboot;
+pub mod privileges;
+pub mod recovery;
 pub mod secondary;
+pub mod secure;
+pub mod shadow;
 pub mod storage;
 pub mod user;
+pub mod vt;
+pub mod xorg;
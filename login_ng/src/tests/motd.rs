@@ -0,0 +1,85 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::motd::*;
+
+#[test]
+fn test_motd_render() {
+    let dir_name = "test_motd_render";
+    let scripts_dir = Path::new(dir_name).join("update-motd.d");
+    std::fs::create_dir_all(&scripts_dir).unwrap();
+
+    for (name, content, mode) in [
+        ("20-second", "#!/bin/sh\necho second\n", 0o755),
+        ("10-first", "#!/bin/sh\necho first\n", 0o755),
+        ("30-failing", "#!/bin/sh\necho failing\nexit 1\n", 0o755),
+        ("40-disabled", "#!/bin/sh\necho disabled\n", 0o644),
+    ] {
+        let script = scripts_dir.join(name);
+        std::fs::write(&script, content).unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    let motd_file = Path::new(dir_name).join("motd");
+    std::fs::write(&motd_file, "static notice\n").unwrap();
+
+    let motd = Motd::new(motd_file, scripts_dir);
+    assert_eq!(motd.scripts().len(), 3);
+    assert_eq!(motd.render(), "first\nsecond\nstatic notice\n");
+
+    assert!(!Motd::hushed(Path::new(dir_name)));
+    std::fs::write(Path::new(dir_name).join(HUSHLOGIN_FILE), "").unwrap();
+    assert!(Motd::hushed(Path::new(dir_name)));
+
+    std::fs::remove_dir_all(dir_name).unwrap();
+}
+
+#[test]
+fn test_motd_timeout() {
+    let dir_name = "test_motd_timeout";
+    let scripts_dir = Path::new(dir_name).join("update-motd.d");
+    std::fs::create_dir_all(&scripts_dir).unwrap();
+
+    for (name, content) in [
+        ("10-hanging", "#!/bin/sh\necho hanging\nexec sleep 30\n"),
+        ("20-late", "#!/bin/sh\necho late\n"),
+    ] {
+        let script = scripts_dir.join(name);
+        std::fs::write(&script, content).unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    let motd_file = Path::new(dir_name).join("motd");
+    std::fs::write(&motd_file, "static notice\n").unwrap();
+
+    // the hanging script is killed and uses up the time of the following ones,
+    // but the static message is still shown
+    let start = Instant::now();
+    let motd = Motd::new(motd_file, scripts_dir).with_timeout(Duration::from_millis(300));
+    assert_eq!(motd.render(), "static notice\n");
+    assert!(start.elapsed() < Duration::from_secs(10));
+
+    std::fs::remove_dir_all(dir_name).unwrap();
+}
@@ -0,0 +1,95 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::collections::HashMap;
+
+use crate::mount::{parse_size, MountParams, MountPoints, QuotaKind};
+
+#[test]
+fn test_network_mounts() {
+    let local = MountParams::new("/dev/sda2".to_string(), "ext4".to_string(), vec![]);
+    let nfs = MountParams::new(
+        "server:/export/home".to_string(),
+        "nfs4".to_string(),
+        vec!["rw".to_string()],
+    );
+    let netdev = MountParams::new(
+        "/dev/mapper/iscsi".to_string(),
+        "xfs".to_string(),
+        vec!["_netdev".to_string()],
+    );
+
+    assert!(!local.is_network());
+    assert!(nfs.is_network());
+    assert!(netdev.is_network());
+
+    let mounts = MountPoints::new(local.clone(), HashMap::new());
+    assert!(!mounts.requires_network());
+    assert!(mounts
+        .with_premount(&"/home/user/shared".to_string(), &nfs)
+        .requires_network());
+    assert!(mounts.with_mount(&netdev).requires_network());
+}
+
+#[test]
+fn test_mount_devices() {
+    let home = MountParams::new("/dev/sda2".to_string(), "ext4".to_string(), vec![]);
+    let shared = MountParams::new("/dev/sdb1".to_string(), "xfs".to_string(), vec![]);
+
+    let mounts = MountPoints::new(home, HashMap::new());
+    assert_eq!(mounts.devices(), vec!["/dev/sda2".to_string()]);
+
+    let mounts = mounts.with_premount(&"/home/user/shared".to_string(), &shared);
+    assert_eq!(
+        mounts.devices(),
+        vec!["/dev/sda2".to_string(), "/dev/sdb1".to_string()]
+    );
+}
+
+#[test]
+fn test_mount_quota() {
+    assert_eq!(parse_size("4096"), Some(4096));
+    assert_eq!(parse_size("512M"), Some(512 << 20));
+    assert_eq!(parse_size("10g"), Some(10 << 30));
+    assert_eq!(parse_size("0G"), None);
+    assert_eq!(parse_size("G"), None);
+    assert_eq!(parse_size("-1"), None);
+    assert_eq!(parse_size("99999999T"), None);
+
+    let data = MountParams::new("/dev/sdb1".to_string(), "xfs".to_string(), vec![]);
+    assert_eq!(data.quota_kind(), Some(QuotaKind::Project));
+    assert_eq!(
+        MountParams::new("/dev/sdb1".to_string(), "btrfs".to_string(), vec![]).quota_kind(),
+        Some(QuotaKind::Qgroup)
+    );
+    assert_eq!(
+        MountParams::new("/dev/sdb1".to_string(), "vfat".to_string(), vec![]).quota_kind(),
+        None
+    );
+
+    // a quota changes the authorization hash, no quota keeps the existing one
+    let mounts = MountPoints::new(data.clone(), HashMap::new());
+    let limited = mounts.with_mount(&data.clone().with_quota(Some(10 << 30)));
+    assert_eq!(
+        mounts.hash(),
+        mounts.with_mount(&data.clone().with_quota(None)).hash()
+    );
+    assert_ne!(mounts.hash(), limited.hash());
+    assert_eq!(limited.mount().quota(), Some(10 << 30));
+}
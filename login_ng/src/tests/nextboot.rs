@@ -0,0 +1,157 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::path::Path;
+
+use crate::nextboot::*;
+
+#[test]
+fn test_next_boot_autologin_single_use() {
+    let dir = Path::new("test_next_boot_autologin");
+    let _ = std::fs::remove_dir_all(dir);
+
+    let autologin = NextBootAutologin::new(dir);
+    assert_eq!(autologin.armed().unwrap(), None);
+    assert_eq!(autologin.pending("boot-b"), None);
+
+    autologin.arm("updating_user", "boot-a").unwrap();
+    assert_eq!(
+        autologin.armed().unwrap(),
+        Some((String::from("updating_user"), String::from("boot-a")))
+    );
+
+    // not honored in the boot it has been armed in, nor for other users
+    assert_eq!(autologin.pending("boot-a"), None);
+    assert!(!autologin.consume("updating_user", "boot-a").unwrap());
+    assert!(!autologin.consume("other_user", "boot-b").unwrap());
+
+    assert_eq!(
+        autologin.pending("boot-b"),
+        Some(String::from("updating_user"))
+    );
+    assert!(autologin.consume("updating_user", "boot-b").unwrap());
+
+    // a token cannot be used twice
+    assert_eq!(autologin.armed().unwrap(), None);
+    assert!(!autologin.consume("updating_user", "boot-b").unwrap());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_next_boot_autologin_disarm() {
+    let dir = Path::new("test_next_boot_autologin_disarm");
+    let _ = std::fs::remove_dir_all(dir);
+
+    let autologin = NextBootAutologin::new(dir);
+    autologin.disarm().unwrap();
+
+    autologin.arm("updating_user", "boot-a").unwrap();
+    autologin.arm("other_user", "boot-a").unwrap();
+    assert_eq!(
+        autologin.pending("boot-b"),
+        Some(String::from("other_user"))
+    );
+
+    autologin.disarm().unwrap();
+    assert_eq!(autologin.pending("boot-b"), None);
+
+    assert!(matches!(
+        autologin.arm("", "boot-a"),
+        Err(NextBootAutologinError::InvalidUsername)
+    ));
+    assert!(matches!(
+        autologin.arm("two users", "boot-a"),
+        Err(NextBootAutologinError::InvalidUsername)
+    ));
+
+    std::fs::write(dir.join("next_boot"), "updating_user\n").unwrap();
+    assert!(matches!(
+        autologin.armed(),
+        Err(NextBootAutologinError::MalformedToken)
+    ));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_current_boot_id() {
+    let boot_id = current_boot_id().unwrap();
+
+    assert!(!boot_id.is_empty());
+    assert_eq!(boot_id, current_boot_id().unwrap());
+}
+
+#[test]
+fn test_next_boot_autologin_claim() {
+    let dir = Path::new("test_next_boot_autologin_claim");
+    let _ = std::fs::remove_dir_all(dir);
+
+    let autologin = NextBootAutologin::new(dir);
+    assert!(autologin
+        .claim("updating_user", "boot-b", false)
+        .unwrap()
+        .is_none());
+
+    autologin.arm("updating_user", "boot-a").unwrap();
+
+    assert!(autologin
+        .claim("other_user", "boot-b", false)
+        .unwrap()
+        .is_none());
+    assert!(autologin
+        .claim("updating_user", "boot-a", false)
+        .unwrap()
+        .is_none());
+
+    // a session that needs the password to be opened cannot use the token, that is dropped
+    assert!(matches!(
+        autologin.claim("updating_user", "boot-b", true),
+        Err(NextBootAutologinError::RequiresPassword)
+    ));
+    assert_eq!(autologin.armed().unwrap(), None);
+    assert_eq!(autologin.pending("boot-b"), None);
+
+    // the first boot reading the token takes it: any later boot refuses (and drops) it
+    autologin.arm("updating_user", "boot-a").unwrap();
+    assert_eq!(
+        autologin.pending("boot-b"),
+        Some(String::from("updating_user"))
+    );
+    assert!(autologin
+        .claim("updating_user", "boot-c", false)
+        .unwrap()
+        .is_none());
+    assert_eq!(autologin.armed().unwrap(), None);
+
+    // the session is then opened with the (empty) main password handed over by the claim
+    autologin.arm("updating_user", "boot-a").unwrap();
+    let main_password = autologin
+        .claim("updating_user", "boot-b", false)
+        .unwrap()
+        .unwrap();
+    assert!(main_password.is_empty());
+    assert_eq!(autologin.pending("boot-b"), None);
+    assert!(autologin
+        .claim("updating_user", "boot-b", false)
+        .unwrap()
+        .is_none());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
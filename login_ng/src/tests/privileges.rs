@@ -0,0 +1,74 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use crate::privileges::SessionCredentials;
+
+#[test]
+fn credentials_include_primary_group() {
+    let credentials = SessionCredentials::new(1000, 1000, vec![10, 1000, 27, 10]);
+
+    assert_eq!(credentials.uid(), 1000);
+    assert_eq!(credentials.gid(), 1000);
+
+    // the primary group comes first and groups are not repeated
+    assert_eq!(credentials.groups(), &vec![1000, 10, 27]);
+}
+
+#[test]
+fn credentials_for_user() {
+    let root = users::get_user_by_uid(0).unwrap();
+    let credentials = SessionCredentials::for_user(&root);
+
+    assert_eq!(credentials.uid(), 0);
+    assert_eq!(credentials.gid(), root.primary_group_id());
+    assert_eq!(credentials.groups().first(), Some(&root.primary_group_id()));
+
+    // every group listing the user as a member is included, as initgroups(3) does
+    for group in users::get_user_groups(root.name(), root.primary_group_id()).unwrap() {
+        assert!(credentials.groups().contains(&group.gid()));
+    }
+}
+
+#[test]
+fn credentials_apply_in_child() {
+    // only root can change its groups
+    if users::get_current_uid() != 0 {
+        return;
+    }
+
+    let nobody = match users::get_user_by_name("nobody") {
+        Some(nobody) => nobody,
+        None => return,
+    };
+
+    let credentials = SessionCredentials::new(nobody.uid(), nobody.primary_group_id(), vec![12345]);
+
+    let output = unsafe {
+        use std::os::unix::process::CommandExt;
+
+        std::process::Command::new("id")
+            .pre_exec(move || credentials.apply())
+            .output()
+            .unwrap()
+    };
+
+    let id = String::from_utf8_lossy(&output.stdout);
+    assert!(id.contains(&format!("uid={}", nobody.uid())));
+    assert!(id.contains("12345"));
+}
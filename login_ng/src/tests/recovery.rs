@@ -0,0 +1,64 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::path::Path;
+
+use crate::recovery::*;
+
+#[test]
+fn test_recovery_token_format() {
+    let token = generate_recovery_token();
+
+    assert_eq!(token.len(), 24);
+    assert_eq!(token.split('-').count(), 5);
+    assert_ne!(token, generate_recovery_token());
+
+    assert_eq!(
+        hash_recovery_token(&token),
+        hash_recovery_token(&token.to_lowercase().replace('-', " "))
+    );
+}
+
+#[test]
+fn test_recovery_token_single_use() {
+    let tokens = RecoveryTokens::new(Path::new("test_recovery"));
+
+    let token = tokens.issue("recovery_user").unwrap();
+    assert!(tokens.is_pending("recovery_user"));
+    assert!(!tokens.requires_reenrollment("recovery_user"));
+
+    assert!(!tokens
+        .redeem("recovery_user", "AAAA-AAAA-AAAA-AAAA-AAAA")
+        .unwrap());
+    assert!(!tokens.redeem("other_user", &token).unwrap());
+
+    assert!(tokens.redeem("recovery_user", &token).unwrap());
+    assert!(!tokens.is_pending("recovery_user"));
+    assert!(tokens.requires_reenrollment("recovery_user"));
+
+    // a token cannot be used twice
+    assert!(!tokens.redeem("recovery_user", &token).unwrap());
+
+    tokens.complete_reenrollment("recovery_user").unwrap();
+    assert!(!tokens.requires_reenrollment("recovery_user"));
+
+    assert!(tokens.issue("../escape").is_err());
+
+    std::fs::remove_dir_all("test_recovery").unwrap();
+}
@@ -65,3 +65,159 @@ fn test_secondary() {
 
     assert_eq!(tested, secondary_passwords.len());
 }
+
+#[test]
+fn test_binary_secrets() {
+    let correct_main = [0xffu8, 0x00, b'm', b'a', b'i', b'n', 0x80];
+    let intermediate = [0xc3u8, 0x28, b'i', b'k'];
+    let hardware_key = [0xfeu8, 0xed, 0xfa, 0xce, 0x00, 0x01];
+
+    let mut user_cfg = crate::user::UserAuthData::new();
+    user_cfg
+        .set_main_secret(&correct_main, &intermediate)
+        .unwrap();
+    user_cfg
+        .add_secondary_secret("hardware", &intermediate, &hardware_key)
+        .unwrap();
+
+    // bytes must survive the round trip unchanged
+    assert_eq!(
+        &*user_cfg.main_by_secret(Some(&hardware_key)).unwrap(),
+        &correct_main
+    );
+    assert_eq!(
+        &*user_cfg.main_by_secret(Some(&intermediate)).unwrap(),
+        &correct_main
+    );
+    assert!(user_cfg.check_main_secret(&correct_main).unwrap());
+
+    // a text API cannot represent the main password: it must fail instead of mangling it
+    user_cfg
+        .add_secondary_secret("pin", &intermediate, b"1234")
+        .unwrap();
+    assert!(matches!(
+        user_cfg.main_by_auth(&Some(String::from("1234"))),
+        Err(crate::error::UserOperationError::User(
+            crate::user::UserAuthDataError::NonUtf8Secret
+        ))
+    ));
+    assert!(matches!(
+        crate::secret_to_string(&correct_main),
+        Err(crate::user::UserAuthDataError::NonUtf8Secret)
+    ));
+}
+
+// a method unlocking the intermediate key with a fixed token, for testing purposes only
+#[derive(Debug, Clone)]
+struct TokenMethod {
+    token: Vec<u8>,
+    intermediate: Vec<u8>,
+}
+
+const TOKEN_AUTH_METHOD_ID: u32 = 0x70a0;
+
+impl crate::auth::AuthMethod for TokenMethod {
+    fn method_id(&self) -> u32 {
+        TOKEN_AUTH_METHOD_ID
+    }
+
+    fn type_name(&self) -> String {
+        String::from("token")
+    }
+
+    fn unlock(
+        &self,
+        secret: Option<&[u8]>,
+    ) -> Result<crate::secure::LockedBytes, crate::error::UserOperationError> {
+        match secret {
+            Some(token) if token == self.token.as_slice() => Ok(
+                crate::secure::LockedBytes::from_vec(self.intermediate.clone()),
+            ),
+            _ => Err(crate::error::UserOperationError::User(
+                crate::user::UserAuthDataError::CouldNotAuthenticate,
+            )),
+        }
+    }
+
+    fn serialize(&self) -> Result<Vec<u8>, bytevec2::errors::ByteVecError> {
+        let mut data = vec![self.token.len() as u8];
+        data.extend_from_slice(&self.token);
+        data.extend_from_slice(&self.intermediate);
+        Ok(data)
+    }
+
+    fn clone_method(&self) -> Box<dyn crate::auth::AuthMethod> {
+        Box::new(self.clone())
+    }
+}
+
+fn decode_token(
+    data: &[u8],
+) -> Result<Box<dyn crate::auth::AuthMethod>, bytevec2::errors::ByteVecError> {
+    let len = data[0] as usize;
+    Ok(Box::new(TokenMethod {
+        token: data[1..1 + len].to_vec(),
+        intermediate: data[1 + len..].to_vec(),
+    }))
+}
+
+#[test]
+fn test_registered_auth_method() {
+    let correct_main = "main password <3".to_string();
+    let intermediate = "intermediate_key".to_string();
+    let token = b"token".to_vec();
+
+    assert!(crate::auth::register_auth_method(
+        TOKEN_AUTH_METHOD_ID,
+        decode_token
+    ));
+    assert!(!crate::auth::register_auth_method(
+        TOKEN_AUTH_METHOD_ID,
+        decode_token
+    ));
+    assert!(!crate::auth::register_auth_method(
+        crate::auth::PASSWORD_AUTH_METHOD_ID,
+        decode_token
+    ));
+
+    let dir_name = "test_auth_method";
+    let source = crate::storage::StorageSource::Path(std::path::PathBuf::from(dir_name));
+
+    {
+        let mut user_cfg = crate::user::UserAuthData::new();
+        user_cfg.set_main(&correct_main, &intermediate).unwrap();
+        user_cfg
+            .add_secondary_method("token", intermediate.as_bytes(), |intermediate| {
+                Ok(Box::new(TokenMethod {
+                    token: token.clone(),
+                    intermediate: intermediate.to_vec(),
+                }))
+            })
+            .unwrap();
+
+        assert_eq!(
+            &*user_cfg.main_by_secret(Some(&token)).unwrap(),
+            correct_main.as_bytes()
+        );
+
+        std::fs::create_dir(dir_name).unwrap();
+        crate::storage::store_user_auth_data(user_cfg, &source).unwrap();
+    }
+
+    let reloaded = crate::storage::load_user_auth_data(&source);
+    std::fs::remove_dir(dir_name).unwrap();
+
+    let reloaded = reloaded.unwrap().unwrap();
+    assert_eq!(
+        reloaded
+            .secondary()
+            .map(|s| s.type_name())
+            .collect::<Vec<_>>(),
+        vec![String::from("token")]
+    );
+    assert_eq!(
+        &*reloaded.main_by_secret(Some(&token)).unwrap(),
+        correct_main.as_bytes()
+    );
+    assert!(reloaded.main_by_secret(Some(b"wrong")).is_err());
+}
@@ -0,0 +1,40 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use crate::secure::{constant_time_eq, wipe, LockedBytes};
+
+#[test]
+fn test_locked_bytes() {
+    let secret = b"main password <3".to_vec();
+
+    let locked = LockedBytes::from_vec(secret.clone());
+    assert_eq!(&*locked, secret.as_slice());
+
+    let mut buffer = secret.clone();
+    wipe(buffer.as_mut_slice());
+    assert!(buffer.iter().all(|b| *b == 0));
+}
+
+#[test]
+fn test_constant_time_eq() {
+    assert!(constant_time_eq(b"", b""));
+    assert!(constant_time_eq(b"one time token", b"one time token"));
+    assert!(!constant_time_eq(b"one time token", b"one time tokem"));
+    assert!(!constant_time_eq(b"one time token", b"one time"));
+}
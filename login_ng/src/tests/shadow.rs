@@ -0,0 +1,61 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use crate::shadow::*;
+
+#[test]
+fn test_shadow_parse() {
+    let entry = ShadowEntry::parse("user:$6$salt$hash:19000:0:90:7::20000:").unwrap();
+
+    assert_eq!(entry.name(), "user");
+    assert_eq!(entry.last_change(), Some(19000));
+    assert_eq!(entry.min_age(), Some(0));
+    assert_eq!(entry.max_age(), Some(90));
+    assert_eq!(entry.warn_period(), Some(7));
+    assert_eq!(entry.inactive_period(), None);
+    assert_eq!(entry.expire(), Some(20000));
+
+    assert!(ShadowEntry::parse("user:x:1").is_err());
+    assert!(ShadowEntry::parse("user:x:abc:0:90:7:::").is_err());
+}
+
+#[test]
+fn test_shadow_warnings() {
+    let entry = ShadowEntry::parse("user:x:19000:0:90:7:::").unwrap();
+
+    assert_eq!(entry.warnings(19000), vec![]);
+    assert_eq!(
+        entry.warnings(19087),
+        vec![ExpiryWarning::PasswordExpiresSoon(3)]
+    );
+    assert_eq!(entry.warnings(19090), vec![ExpiryWarning::PasswordExpired]);
+
+    let forced = ShadowEntry::parse("user:x:0:0:99999:7:::").unwrap();
+    assert_eq!(forced.warnings(19000), vec![ExpiryWarning::PasswordExpired]);
+
+    let no_aging = ShadowEntry::parse("user:x:19000:0:99999:7:::").unwrap();
+    assert_eq!(no_aging.warnings(30000), vec![]);
+
+    let account = ShadowEntry::parse("user:x:19000::::3:19010:").unwrap();
+    assert_eq!(
+        account.warnings(19008),
+        vec![ExpiryWarning::AccountExpiresSoon(2)]
+    );
+    assert_eq!(account.warnings(19000), vec![]);
+}
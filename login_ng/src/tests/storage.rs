@@ -117,3 +117,224 @@ fn test_secondary_password_serialization() {
 
     assert_eq!(tested, secondary_passwords.len());
 }
+
+#[test]
+fn test_login_history_serialization() {
+    let dir_name = "test_history";
+
+    let source = crate::storage::StorageSource::Path(std::path::PathBuf::from(dir_name));
+
+    std::fs::create_dir(dir_name).unwrap();
+
+    let max = crate::history::MAX_LOGIN_HISTORY as u64;
+    for timestamp in 0..(max + 4) {
+        let record = crate::history::LoginRecord::new(
+            timestamp,
+            String::from("host"),
+            format!("tty{timestamp}"),
+        );
+
+        crate::storage::store_user_login_record(&record, &source).unwrap();
+    }
+
+    let history = crate::storage::load_user_login_history(&source);
+
+    std::fs::remove_dir(dir_name).unwrap();
+
+    let history = history.unwrap();
+    assert_eq!(history.len() as u64, max);
+    assert_eq!(history.first().unwrap().timestamp(), 4);
+    assert_eq!(history.last().unwrap().timestamp(), max + 3);
+    assert_eq!(history.last().unwrap().line(), &format!("tty{}", max + 3));
+}
+
+#[test]
+fn test_session_command_serialization() {
+    let dir_name = "test_session_command";
+
+    let source = crate::storage::StorageSource::Path(std::path::PathBuf::from(dir_name));
+
+    std::fs::create_dir(dir_name).unwrap();
+
+    let command = crate::command::SessionCommand::new(String::from("/usr/bin/startplasma"))
+        .with_args(vec![String::from("--wayland"), String::from("-v")])
+        .with_env(String::from("XDG_SESSION_TYPE"), String::from("wayland"))
+        .with_env(String::from("EMPTY"), String::new())
+        .with_cwd(Some(std::path::PathBuf::from("/tmp")));
+
+    crate::storage::store_user_session_command(&command, &source).unwrap();
+    let loaded = crate::storage::load_user_session_command(&source);
+
+    std::fs::remove_dir(dir_name).unwrap();
+
+    assert_eq!(loaded.unwrap().unwrap(), command);
+}
+
+#[test]
+fn test_preferred_session_serialization() {
+    let dir_name = "test_preferred_session";
+
+    let source = crate::storage::StorageSource::Path(std::path::PathBuf::from(dir_name));
+
+    std::fs::create_dir(dir_name).unwrap();
+
+    let mut user_cfg = crate::user::UserAuthData::new();
+    user_cfg.set_preferred_session(Some(crate::command::PreferredSession::Desktop(
+        String::from("plasma"),
+    )));
+    crate::storage::store_user_auth_data(user_cfg, &source).unwrap();
+    let desktop = crate::storage::load_user_auth_data(&source);
+
+    let command = crate::command::SessionCommand::new(String::from("/usr/bin/sway"))
+        .with_args(vec![String::from("--unsupported-gpu")])
+        .with_env(String::from("WLR_NO_HARDWARE_CURSORS"), String::from("1"));
+    let mut user_cfg = crate::user::UserAuthData::new();
+    user_cfg.set_preferred_session(Some(crate::command::PreferredSession::Command(
+        command.clone(),
+    )));
+    crate::storage::store_user_auth_data(user_cfg, &source).unwrap();
+    let command_loaded = crate::storage::load_user_auth_data(&source);
+
+    crate::storage::store_user_auth_data(crate::user::UserAuthData::new(), &source).unwrap();
+    let cleared = crate::storage::load_user_auth_data(&source);
+
+    std::fs::remove_dir(dir_name).unwrap();
+
+    let desktop = desktop.unwrap().unwrap();
+    assert!(!desktop.has_main());
+    assert_eq!(
+        desktop.preferred_session(),
+        &Some(crate::command::PreferredSession::Desktop(String::from(
+            "plasma"
+        )))
+    );
+
+    assert_eq!(
+        command_loaded.unwrap().unwrap().preferred_session(),
+        &Some(crate::command::PreferredSession::Command(command))
+    );
+
+    assert!(cleared.unwrap().is_none());
+}
+
+#[test]
+fn test_directory_backend_serialization() {
+    let dir_name = "test_directory_backend";
+
+    let source =
+        crate::storage::StorageSource::Chain(vec![crate::backend::StorageBackend::Directory(
+            std::path::PathBuf::from(dir_name),
+        )]);
+
+    let command = crate::command::SessionCommand::new(String::from("/usr/bin/startplasma"))
+        .with_args(vec![String::from("--wayland")]);
+
+    // the directory is created on the first write
+    crate::storage::store_user_session_command(&command, &source).unwrap();
+    let loaded = crate::storage::load_user_session_command(&source);
+
+    crate::storage::remove_user_data(&source).unwrap();
+    let removed = crate::storage::load_user_session_command(&source);
+
+    let _ = std::fs::remove_dir_all(dir_name);
+
+    assert_eq!(loaded.unwrap().unwrap(), command);
+    assert!(removed.unwrap().is_none());
+}
+
+#[test]
+fn test_storage_chain_fallback() {
+    let dir_name = "test_storage_chain_fallback";
+
+    // the home directory of a user that doesn't exist cannot be used
+    let source = crate::storage::StorageSource::Chain(vec![
+        crate::backend::StorageBackend::Home(String::from("login_ng_no_such_user")),
+        crate::backend::StorageBackend::Directory(std::path::PathBuf::from(dir_name)),
+    ]);
+
+    let mut user_cfg = crate::user::UserAuthData::new();
+    user_cfg
+        .set_main(&String::from("main"), &String::from("intermediate"))
+        .unwrap();
+    crate::storage::store_user_auth_data(user_cfg, &source).unwrap();
+
+    // the home might be holding data it cannot give now: it is not silently skipped
+    let holder = source.load_backend();
+    let reloaded = crate::storage::load_user_auth_data(&source);
+
+    let directory =
+        crate::storage::StorageSource::Chain(vec![crate::backend::StorageBackend::Directory(
+            std::path::PathBuf::from(dir_name),
+        )]);
+    let directory_holder = directory.load_backend();
+    let directory_reloaded = crate::storage::load_user_auth_data(&directory);
+
+    let _ = std::fs::remove_dir_all(dir_name);
+
+    assert!(holder.is_err());
+    assert!(reloaded.is_err());
+    assert_eq!(
+        directory_holder.unwrap(),
+        crate::backend::StorageBackend::Directory(std::path::PathBuf::from(dir_name))
+    );
+    assert!(directory_reloaded.unwrap().unwrap().has_main());
+}
+
+#[test]
+fn test_storage_chain_parse() {
+    use crate::backend::{StorageBackendKind, StorageChain};
+
+    let chain = StorageChain::parse("[Storage]\nchain = directory, service # no homes\n");
+    assert_eq!(
+        chain.kinds(),
+        &vec![StorageBackendKind::Directory, StorageBackendKind::Service]
+    );
+
+    let chain = StorageChain::parse("[Other]\nchain = service\n[Storage]\nchain = service home\n");
+    assert_eq!(
+        chain.kinds(),
+        &vec![StorageBackendKind::Service, StorageBackendKind::Home]
+    );
+
+    assert_eq!(
+        StorageChain::parse("[Storage]\nchain = nowhere\n"),
+        StorageChain::default()
+    );
+    assert_eq!(StorageChain::parse(""), StorageChain::default());
+
+    // usernames that are not a valid file name only get the home backend
+    assert_eq!(
+        StorageChain::default().backends("../etc"),
+        vec![crate::backend::StorageBackend::Home(String::from("../etc"))]
+    );
+}
+
+#[test]
+fn test_login_ng_attribute_names() {
+    use crate::storage::is_login_ng_attribute;
+
+    for name in [
+        "user.login-ng.manifest",
+        "user.login-ng.main",
+        "user.login-ng.ik.0",
+        "user.login-ng.auth.12",
+        "user.login-ng.mounts.3",
+        "user.login-ng.mountquota",
+        "user.login-ng.history.15",
+    ] {
+        assert!(is_login_ng_attribute(name), "{name}");
+    }
+
+    for name in [
+        "user.login-ng",
+        "user.login-ng.",
+        "user.login-ng.unknown",
+        "user.login-ng.auth.",
+        "user.login-ng.auth.x",
+        "user.login-ng.main.0",
+        "user.other.main",
+        "main",
+    ] {
+        assert!(!is_login_ng_attribute(name), "{name}");
+    }
+}
@@ -0,0 +1,31 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use crate::vt::*;
+
+#[test]
+fn tty_to_vt() {
+    assert_eq!(vt_of_tty("tty2"), Some(2));
+    assert_eq!(vt_of_tty("/dev/tty12"), Some(12));
+    assert_eq!(vt_of_tty("/dev/tty3"), Some(3));
+    assert_eq!(vt_of_tty("tty0"), None);
+    assert_eq!(vt_of_tty("/dev/pts/1"), None);
+    assert_eq!(vt_of_tty("/dev/pts/3"), None);
+    assert_eq!(vt_of_tty(":0"), None);
+}
@@ -0,0 +1,103 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::path::Path;
+
+use crate::xorg::*;
+
+#[test]
+fn test_x11_conf() {
+    let config = XServerConfig::parse("");
+    assert_eq!(config.server(), DEFAULT_X_SERVER);
+    assert_eq!(config.timeout(), DEFAULT_X_SERVER_TIMEOUT);
+
+    let config = XServerConfig::parse(
+        "[X11]\nserver = /usr/bin/Xvfb # inline comment\nargs = -nolisten tcp -dpi 96\ntimeout_secs = 3\n",
+    );
+    assert_eq!(config.server(), "/usr/bin/Xvfb");
+    assert_eq!(config.args().len(), 4);
+    assert_eq!(config.timeout().as_secs(), 3);
+
+    // the server must be given by absolute path
+    let config = XServerConfig::parse("[X11]\nserver = Xorg\ntimeout_secs = 0\n");
+    assert_eq!(config.server(), DEFAULT_X_SERVER);
+    assert_eq!(config.timeout(), DEFAULT_X_SERVER_TIMEOUT);
+}
+
+#[test]
+fn test_x_server_argv() {
+    let config = XServerConfig::default();
+    let auth = Path::new("/run/user/1000/1/Xauthority");
+
+    assert_eq!(
+        config.server_argv(1, Some(2), auth),
+        vec![
+            DEFAULT_X_SERVER,
+            ":1",
+            "vt2",
+            "-keeptty",
+            "-novtswitch",
+            "-auth",
+            "/run/user/1000/1/Xauthority",
+            "-nolisten",
+            "tcp"
+        ]
+    );
+    assert!(!config
+        .server_argv(0, None, auth)
+        .iter()
+        .any(|arg| arg.starts_with("vt")));
+}
+
+#[test]
+fn test_xauth_entry() {
+    let cookie = [0xabu8; XAUTH_COOKIE_LEN];
+    let entry = xauth_entry("host", 12, &cookie);
+
+    let mut expected = vec![0x01, 0x00, 0x00, 0x04];
+    expected.extend_from_slice(b"host");
+    expected.extend_from_slice(&[0x00, 0x02]);
+    expected.extend_from_slice(b"12");
+    expected.extend_from_slice(&[0x00, 0x12]);
+    expected.extend_from_slice(b"MIT-MAGIC-COOKIE-1");
+    expected.extend_from_slice(&[0x00, 0x10]);
+    expected.extend_from_slice(&cookie);
+
+    assert_eq!(entry, expected);
+    assert_ne!(generate_cookie(), generate_cookie());
+}
+
+#[test]
+fn test_x11_launcher_args() {
+    let exec = vec![
+        String::from("/usr/bin/startplasma-x11"),
+        String::from("--flag"),
+    ];
+
+    assert_eq!(
+        x11_launcher_args(&exec),
+        vec![
+            "--x11",
+            "--command",
+            "/usr/bin/startplasma-x11",
+            "--command-arg",
+            "--flag"
+        ]
+    );
+}
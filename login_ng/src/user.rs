@@ -17,6 +17,8 @@
     51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 */
 
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use bytevec2::*;
 
 use aes_gcm::{
@@ -30,7 +32,9 @@ use bcrypt::{hash, verify, DEFAULT_COST};
 use thiserror::Error;
 
 use crate::auth::*;
+use crate::command::PreferredSession;
 use crate::error::*;
+use crate::secure::LockedBytes;
 
 #[derive(Debug, Copy, Clone, Error)]
 pub enum UserAuthDataError {
@@ -42,8 +46,12 @@ pub enum UserAuthDataError {
     CouldNotAuthenticate,
     #[error("Authentication method unsupported")]
     MatchingAuthNotProvided,
-    #[error("Invalid password (probably contains invalid characters)")]
-    InvalidPassword,
+    #[error("The secret is not valid UTF-8 text")]
+    NonUtf8Secret,
+    #[error("An intermediate key with the same name already exists")]
+    DuplicatedIntermediateKey,
+    #[error("Additional intermediate keys wrap the current main password: revoke them before changing it")]
+    IntermediateKeysInUse,
 }
 
 bytevec_decl! {
@@ -194,8 +202,8 @@ bytevec_decl! {
 
 impl MainPassword {
     pub fn new(
-        main: &Vec<u8>,
-        intermediate_key: &String,
+        main: &[u8],
+        intermediate_key: &[u8],
         intermediate_salt: &[u8; 32],
     ) -> Result<Self, UserOperationError> {
         let main_hash = hash(main, DEFAULT_COST).map_err(UserOperationError::HashingError)?;
@@ -203,8 +211,7 @@ impl MainPassword {
         let intermediate_key_hash =
             hash(intermediate_key, DEFAULT_COST).map_err(UserOperationError::HashingError)?;
 
-        let intermediate_derived_key =
-            crate::derive_key(intermediate_key.as_str(), intermediate_salt);
+        let intermediate_derived_key = crate::derive_key(intermediate_key, intermediate_salt);
 
         let key = Key::<Aes256Gcm>::from_slice(&intermediate_derived_key);
 
@@ -213,7 +220,7 @@ impl MainPassword {
 
         let temp: [u8; 12] = main_nonce.into();
 
-        match main_cipher.encrypt(&main_nonce, main.as_ref()) {
+        match main_cipher.encrypt(&main_nonce, main) {
             Ok(enc_main) => Ok(Self {
                 main_hash,
                 enc_main,
@@ -225,9 +232,9 @@ impl MainPassword {
         }
     }
 
-    pub fn plain(&self, ik_or_main: &String) -> Result<Vec<u8>, UserOperationError> {
+    pub fn plain(&self, ik_or_main: &[u8]) -> Result<Vec<u8>, UserOperationError> {
         if verify(ik_or_main, self.main_hash.as_str()).map_err(UserOperationError::HashingError)? {
-            return Ok(crate::password_to_vec(ik_or_main));
+            return Ok(ik_or_main.to_vec());
         }
 
         // provided data was not the main password itself: threat it as the intermediate key
@@ -242,8 +249,7 @@ impl MainPassword {
         }
 
         let temp: [u8; 32] = self.intermediate_key_salt.into();
-        let intermediate_derived_key =
-            crate::derive_key(intermediate_key.as_str(), temp.as_slice());
+        let intermediate_derived_key = crate::derive_key(intermediate_key, temp.as_slice());
 
         let key = Key::<Aes256Gcm>::from_slice(&intermediate_derived_key);
 
@@ -266,46 +272,166 @@ impl MainPassword {
         Ok(decrypted_main)
     }
 
-    pub fn check(&self, main_password: &String) -> Result<bool, UserOperationError> {
-        let main_password_hash =
-            hash(main_password, DEFAULT_COST).map_err(UserOperationError::HashingError)?;
+    pub fn check(&self, main_password: &[u8]) -> Result<bool, UserOperationError> {
+        // bcrypt salts every hash: verify() re-hashes with the stored salt and compares in constant time
+        verify(main_password, self.main_hash.as_str()).map_err(UserOperationError::HashingError)
+    }
+
+    pub(crate) fn hash(&self) -> &str {
+        self.main_hash.as_str()
+    }
+}
+
+bytevec_decl! {
+    /// An additional key wrapping the main password, on top of the one set along with it:
+    /// each one unlocks the main password on its own and can be revoked without affecting the others.
+    #[derive(PartialEq, Eq, Debug, Clone)]
+    pub struct IntermediateKey {
+        name: String,
+        creation_date: u64,
+
+        enc_main: Vec<u8>,
+        enc_main_nonce: AuthDataNonce,
+
+        key_salt: AuthDataSalt,
+        key_hash: String
+    }
+}
+
+impl IntermediateKey {
+    // WARNING: it is the user responsibility to check that the main password is the stored one
+    pub fn new(name: &str, main: &[u8], key: &[u8]) -> Result<Self, UserOperationError> {
+        let key_salt =
+            <[u8; 32]>::try_from(Aes256Gcm::generate_key(&mut OsRng).to_vec().as_slice()).unwrap();
+
+        let key_hash = hash(key, DEFAULT_COST).map_err(UserOperationError::HashingError)?;
+
+        let derived_key = crate::derive_key(key, &key_salt);
+
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&derived_key));
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+        let enc_main = cipher
+            .encrypt(&nonce, main)
+            .map_err(UserOperationError::EncryptionError)?;
+
+        let temp: [u8; 12] = nonce.into();
+
+        Ok(Self {
+            name: String::from(name),
+            creation_date: match SystemTime::now().duration_since(UNIX_EPOCH) {
+                Ok(from_epoch) => from_epoch.as_secs(),
+                Err(_err) => 0u64,
+            },
+            enc_main,
+            enc_main_nonce: AuthDataNonce::from(temp),
+            key_salt: AuthDataSalt::from(key_salt),
+            key_hash,
+        })
+    }
+
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    pub fn creation_date(&self) -> u64 {
+        self.creation_date
+    }
+
+    pub fn matches(&self, key: &[u8]) -> Result<bool, UserOperationError> {
+        verify(key, self.key_hash.as_str()).map_err(UserOperationError::HashingError)
+    }
+
+    /// Get the main password if the given key is this one
+    pub fn plain(&self, key: &[u8]) -> Result<LockedBytes, UserOperationError> {
+        if !self.matches(key)? {
+            return Err(UserOperationError::User(
+                UserAuthDataError::WrongIntermediateKey,
+            ));
+        }
+
+        let temp: [u8; 32] = self.key_salt.into();
+        let derived_key = crate::derive_key(key, temp.as_slice());
 
-        Ok(self.main_hash == main_password_hash)
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&derived_key));
+
+        let temp: [u8; 12] = self.enc_main_nonce.into();
+
+        Ok(LockedBytes::from_vec(
+            cipher
+                .decrypt(Nonce::from_slice(temp.as_slice()), self.enc_main.as_ref())
+                .map_err(UserOperationError::EncryptionError)?,
+        ))
     }
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct UserAuthData {
     main: Option<MainPassword>,
+    intermediates: Vec<IntermediateKey>,
     auth: Vec<SecondaryAuth>,
+    session: Option<PreferredSession>,
 }
 
 impl UserAuthData {
     pub fn new() -> Self {
         Self {
             main: None,
+            intermediates: vec![],
             auth: vec![],
+            session: None,
         }
     }
 
+    /// The session the user prefers to be started when none is explicitly requested
+    pub fn preferred_session(&self) -> &Option<PreferredSession> {
+        &self.session
+    }
+
+    pub fn set_preferred_session(&mut self, session: Option<PreferredSession>) {
+        self.session = session;
+    }
+
     pub fn add_secondary_password(
         &mut self,
         name: &str,
         intermediate: &String,
         secondary_password: &String,
     ) -> Result<(), UserOperationError> {
-        if !crate::is_valid_password(secondary_password) {
-            return Err(UserOperationError::User(UserAuthDataError::InvalidPassword));
-        }
+        self.add_secondary_secret(name, intermediate.as_bytes(), secondary_password.as_bytes())
+    }
+
+    /// Same as add_secondary_password, for secrets that are not necessarily text
+    pub fn add_secondary_secret(
+        &mut self,
+        name: &str,
+        intermediate: &[u8],
+        secondary_secret: &[u8],
+    ) -> Result<(), UserOperationError> {
+        self.add_secondary_method(name, intermediate, |intermediate| {
+            Ok(Box::new(SecondaryPassword::new(
+                intermediate,
+                secondary_secret,
+            )?))
+        })
+    }
 
+    /// Add a secondary authentication method of any kind: enroll receives the verified
+    /// intermediate key and returns the method protecting it.
+    pub fn add_secondary_method<F>(
+        &mut self,
+        name: &str,
+        intermediate: &[u8],
+        enroll: F,
+    ) -> Result<(), UserOperationError>
+    where
+        F: FnOnce(&[u8]) -> Result<Box<dyn AuthMethod>, UserOperationError>,
+    {
         // this makes the check about correctness of the intermediate key
-        let _ = self.main(intermediate)?;
+        let _ = self.main_secret(intermediate)?;
 
-        self.auth.push(SecondaryAuth::new_password(
-            name,
-            None,
-            SecondaryPassword::new(intermediate, secondary_password)?,
-        ));
+        self.auth
+            .push(SecondaryAuth::new(name, None, enroll(intermediate)?));
 
         Ok(())
     }
@@ -314,9 +440,19 @@ impl UserAuthData {
         self.main.is_some()
     }
 
+    /// The stored hash of the main password, changing whenever the main password does
+    pub(crate) fn main_hash(&self) -> Option<&str> {
+        self.main.as_ref().map(MainPassword::hash)
+    }
+
     /// Check if the given main passowrd is the same as the stored one
     /// NOTE: this is NOT the same as a PAM authentication
     pub fn check_main(&self, main_password: &String) -> Result<bool, UserOperationError> {
+        self.check_main_secret(main_password.as_bytes())
+    }
+
+    /// Same as check_main, for main passwords that are not necessarily text
+    pub fn check_main_secret(&self, main_password: &[u8]) -> Result<bool, UserOperationError> {
         let Some(stored_main) = &self.main else {
             return Err(UserOperationError::User(
                 UserAuthDataError::MainPasswordNotSet,
@@ -332,22 +468,29 @@ impl UserAuthData {
         &self,
         secondary_password: &Option<String>,
     ) -> Result<String, UserOperationError> {
-        let main = self.main.as_ref().ok_or(UserOperationError::User(
-            UserAuthDataError::MainPasswordNotSet,
-        ))?;
-
-        if let Some(provided_pw) = secondary_password {
-            if !crate::is_valid_password(provided_pw) {
-                return Err(UserOperationError::User(UserAuthDataError::InvalidPassword));
-            } else if let Ok(main_pw) = main.plain(provided_pw) {
-                return Ok(crate::vec_to_password(&main_pw));
+        let main = self.main_by_secret(secondary_password.as_ref().map(|pw| pw.as_bytes()))?;
+
+        Ok(crate::secret_to_string(&main)?)
+    }
+
+    /// Same as main_by_auth, for secrets and main passwords that are not necessarily text
+    pub fn main_by_secret(&self, secret: Option<&[u8]>) -> Result<LockedBytes, UserOperationError> {
+        if self.main.is_none() {
+            return Err(UserOperationError::User(
+                UserAuthDataError::MainPasswordNotSet,
+            ));
+        }
+
+        if let Some(provided_secret) = secret {
+            if let Ok(main_pw) = self.main_secret(provided_secret) {
+                return Ok(main_pw);
             }
         }
 
         for sec_auth in self.auth.iter() {
-            if let Ok(intermediate) = sec_auth.intermediate(secondary_password) {
-                if let Ok(main_pw_as_vec) = main.plain(&intermediate) {
-                    return Ok(crate::vec_to_password(&main_pw_as_vec));
+            if let Ok(intermediate) = sec_auth.intermediate(secret) {
+                if let Ok(main_pw) = self.main_secret(&intermediate) {
+                    return Ok(main_pw);
                 }
             }
         }
@@ -358,18 +501,72 @@ impl UserAuthData {
     }
 
     pub fn main(&self, intermediate_key: &String) -> Result<String, UserOperationError> {
-        if !crate::is_valid_password(intermediate_key) {
-            return Err(UserOperationError::User(UserAuthDataError::InvalidPassword));
-        }
+        let main = self.main_secret(intermediate_key.as_bytes())?;
 
-        match &self.main {
-            Some(main) => Ok(crate::vec_to_password(
-                (main.plain(intermediate_key)?).as_ref(),
-            )),
-            None => Err(UserOperationError::User(
+        Ok(crate::secret_to_string(&main)?)
+    }
+
+    /// Same as main, for intermediate keys and main passwords that are not necessarily text:
+    /// the primary intermediate key and every additional one are accepted.
+    pub fn main_secret(&self, intermediate_key: &[u8]) -> Result<LockedBytes, UserOperationError> {
+        let Some(main) = &self.main else {
+            return Err(UserOperationError::User(
                 UserAuthDataError::MainPasswordNotSet,
-            )),
+            ));
+        };
+
+        let err = match main.plain(intermediate_key) {
+            Ok(main_pw) => return Ok(LockedBytes::from_vec(main_pw)),
+            Err(err) => err,
+        };
+
+        for key in self.intermediates.iter() {
+            if let Ok(main_pw) = key.plain(intermediate_key) {
+                // keys wrapping a previous main password are no good
+                if main.check(&main_pw)? {
+                    return Ok(main_pw);
+                }
+            }
         }
+
+        Err(err)
+    }
+
+    /// Add a named intermediate key that unlocks the main password on its own:
+    /// the main password is obtained through an already existing intermediate key.
+    pub fn add_intermediate_key(
+        &mut self,
+        name: &str,
+        intermediate_key: &[u8],
+        new_key: &[u8],
+    ) -> Result<(), UserOperationError> {
+        if self.intermediates.iter().any(|key| key.name == name) {
+            return Err(UserOperationError::User(
+                UserAuthDataError::DuplicatedIntermediateKey,
+            ));
+        }
+
+        let main = self.main_secret(intermediate_key)?;
+
+        self.intermediates
+            .push(IntermediateKey::new(name, &main, new_key)?);
+
+        Ok(())
+    }
+
+    /// Revoke the intermediate key with the given name: returns false if there is none.
+    /// NOTE: secondary authentication methods unlocking that key stop working as well
+    pub fn remove_intermediate_key(&mut self, name: &str) -> bool {
+        let count = self.intermediates.len();
+
+        self.intermediates.retain(|key| key.name != name);
+
+        self.intermediates.len() != count
+    }
+
+    /// Intermediate keys in addition to the one set along with the main password
+    pub fn intermediate_keys(&self) -> std::slice::Iter<IntermediateKey> {
+        self.intermediates.iter()
     }
 
     pub fn set_main(
@@ -377,33 +574,53 @@ impl UserAuthData {
         main: &String,
         intermediate_key: &String,
     ) -> Result<(), UserOperationError> {
-        if !crate::is_valid_password(main) {
-            return Err(UserOperationError::User(UserAuthDataError::InvalidPassword));
-        }
-
-        if !crate::is_valid_password(intermediate_key) {
-            return Err(UserOperationError::User(UserAuthDataError::InvalidPassword));
-        }
+        self.set_main_secret(main.as_bytes(), intermediate_key.as_bytes())
+    }
 
+    /// Same as set_main, for main passwords and intermediate keys that are not necessarily text.
+    /// NOTE: additional intermediate keys cannot be re-wrapped without knowing them, so the main password
+    /// can only be changed once they have been revoked (see remove_intermediate_key)
+    pub fn set_main_secret(
+        &mut self,
+        main: &[u8],
+        intermediate_key: &[u8],
+    ) -> Result<(), UserOperationError> {
         match &self.main {
             Some(m) => {
+                let unchanged = m.check(main)?;
+
                 if !verify(intermediate_key, &m.intermediate_key_hash)
                     .map_err(UserOperationError::HashingError)?
                 {
+                    // other intermediate keys already wrap the stored main password
+                    for key in self.intermediates.iter() {
+                        if unchanged && key.matches(intermediate_key)? {
+                            return Ok(());
+                        }
+                    }
+
                     return Err(UserOperationError::User(
                         UserAuthDataError::WrongIntermediateKey,
                     ));
                 }
 
+                // other intermediate keys would keep wrapping the previous main password,
+                // silently locking out the secondary authentication methods unlocking them
+                if !unchanged && !self.intermediates.is_empty() {
+                    return Err(UserOperationError::User(
+                        UserAuthDataError::IntermediateKeysInUse,
+                    ));
+                }
+
                 let temp: [u8; 32] = m.intermediate_key_salt.into();
-                let mp = MainPassword::new(&crate::password_to_vec(main), intermediate_key, &temp)?;
+                let mp = MainPassword::new(main, intermediate_key, &temp)?;
 
                 self.main = Some(mp);
 
                 Ok(())
             }
             None => match MainPassword::new(
-                &crate::password_to_vec(main),
+                main,
                 intermediate_key,
                 // generate a new random salt using the aes-gcm library (it will create a 32 bytes key)
                 &<[u8; 32]>::try_from(Aes256Gcm::generate_key(&mut OsRng).to_vec().as_slice())
@@ -431,6 +648,10 @@ impl UserAuthData {
         self.main = Some(value);
     }
 
+    pub(crate) fn push_intermediate_key(&mut self, value: IntermediateKey) {
+        self.intermediates.push(value);
+    }
+
     pub(crate) fn push_secondary(&mut self, value: SecondaryAuth) {
         self.auth.push(value);
     }
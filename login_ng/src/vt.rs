@@ -0,0 +1,74 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{fs::OpenOptions, os::fd::AsRawFd};
+
+// from linux/vt.h
+pub const VT_OPENQRY: libc::c_ulong = 0x5600;
+pub const VT_ACTIVATE: libc::c_ulong = 0x5606;
+
+/// The console device that can be queried about and switch every VT
+pub const CONSOLE_PATH: &str = "/dev/tty0";
+
+/// Turns the -1 returned by a failing ioctl into the error in errno
+pub fn ioctl_result(result: libc::c_int) -> std::io::Result<()> {
+    match result {
+        -1 => Err(std::io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+/// The VT number of a terminal: either ttyN (as in PAM_TTY) or /dev/ttyN
+pub fn vt_of_tty(tty: &str) -> Option<u32> {
+    tty.strip_prefix("/dev/")
+        .unwrap_or(tty)
+        .strip_prefix("tty")?
+        .parse::<u32>()
+        .ok()
+        .filter(|vt| *vt > 0)
+}
+
+/// Returns the number of the first VT not opened by any process, None if all of them are in use
+pub fn first_free_vt() -> std::io::Result<Option<u32>> {
+    let console = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(CONSOLE_PATH)?;
+
+    let mut number: libc::c_int = -1;
+    ioctl_result(unsafe {
+        libc::ioctl(
+            console.as_raw_fd(),
+            VT_OPENQRY,
+            &mut number as *mut libc::c_int,
+        )
+    })?;
+
+    Ok(u32::try_from(number).ok().filter(|number| *number > 0))
+}
+
+/// Switch to the given VT: the switch completes asynchronously
+pub fn activate_vt(vt: u32) -> std::io::Result<()> {
+    let console = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(CONSOLE_PATH)?;
+
+    ioctl_result(unsafe { libc::ioctl(console.as_raw_fd(), VT_ACTIVATE, vt as libc::c_int) })
+}
@@ -0,0 +1,247 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+
+/// Configuration file of X11 sessions, in the login_ng configuration directory
+pub const X11_CONFIG_FILE: &str = "x11.conf";
+
+/// X server started for X11 sessions
+pub const DEFAULT_X_SERVER: &str = "/usr/bin/Xorg";
+
+/// Arguments always given to the X server, after the display, VT and auth ones
+pub const DEFAULT_X_SERVER_ARGS: [&str; 2] = ["-nolisten", "tcp"];
+
+/// Time the X server is given to create its socket before the session is abandoned
+pub const DEFAULT_X_SERVER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Program X11 desktop sessions are wrapped into: it starts and supervises the X server
+pub const X11_SESSION_LAUNCHER: &str = "login_ng-session";
+
+/// Directory holding the sockets of local X servers
+pub const X11_SOCKET_DIR: &str = "/tmp/.X11-unix";
+
+/// Highest display number looked at when searching a free one
+const MAX_DISPLAY: u32 = 64;
+
+/// FamilyLocal in the Xauthority file format
+const XAUTH_FAMILY_LOCAL: u16 = 256;
+
+const XAUTH_COOKIE_NAME: &str = "MIT-MAGIC-COOKIE-1";
+
+pub const XAUTH_COOKIE_LEN: usize = 16;
+
+/// X11 sessions: the X server to start and how long to wait for it
+#[derive(Debug, Clone, PartialEq)]
+pub struct XServerConfig {
+    server: String,
+    args: Vec<String>,
+    timeout: Duration,
+}
+
+impl Default for XServerConfig {
+    fn default() -> Self {
+        Self {
+            server: String::from(DEFAULT_X_SERVER),
+            args: DEFAULT_X_SERVER_ARGS
+                .iter()
+                .map(|arg| String::from(*arg))
+                .collect(),
+            timeout: DEFAULT_X_SERVER_TIMEOUT,
+        }
+    }
+}
+
+impl XServerConfig {
+    /// Parses the [X11] section of a x11.conf file: unknown or invalid settings are ignored
+    pub fn parse(content: &str) -> Self {
+        let mut config = Self::default();
+        let mut in_section = false;
+
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                in_section = line.eq_ignore_ascii_case("[x11]");
+                continue;
+            }
+
+            if !in_section {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+
+            let value = value.trim();
+            match key.trim() {
+                "server" if value.starts_with('/') => {
+                    config.server = String::from(value);
+                }
+                "args" => {
+                    config.args = value.split_whitespace().map(String::from).collect();
+                }
+                "timeout_secs" => {
+                    if let Ok(secs) = value.parse::<u64>() {
+                        if secs > 0 {
+                            config.timeout = Duration::from_secs(secs);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        config
+    }
+
+    pub fn load(config_dir: &Path) -> Self {
+        match crate::config::read_config_file(&config_dir.join(X11_CONFIG_FILE)) {
+            Ok(content) => Self::parse(&content),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn server(&self) -> &String {
+        &self.server
+    }
+
+    pub fn args(&self) -> &Vec<String> {
+        &self.args
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Full command line of the X server for the given display, VT and server auth file
+    pub fn server_argv(&self, display: u32, vt: Option<u32>, auth: &Path) -> Vec<String> {
+        let mut argv = vec![self.server.clone(), format!(":{display}")];
+
+        if let Some(vt) = vt {
+            // the server must stay on the VT of the session, sharing it with no one else
+            argv.push(format!("vt{vt}"));
+            argv.push(String::from("-keeptty"));
+            argv.push(String::from("-novtswitch"));
+        }
+
+        argv.push(String::from("-auth"));
+        argv.push(auth.to_string_lossy().into_owned());
+        argv.extend(self.args.iter().cloned());
+
+        argv
+    }
+}
+
+/// Socket a local X server listens on
+pub fn display_socket(display: u32) -> PathBuf {
+    PathBuf::from(X11_SOCKET_DIR).join(format!("X{display}"))
+}
+
+/// The lowest display number with neither a lock file nor a socket
+pub fn free_display() -> Option<u32> {
+    (0..MAX_DISPLAY).find(|display| {
+        !Path::new(format!("/tmp/.X{display}-lock").as_str()).exists()
+            && !display_socket(*display).exists()
+    })
+}
+
+/// Whether the X server of the given display is accepting connections
+pub fn is_display_ready(display: u32) -> bool {
+    display_socket(display).exists()
+}
+
+pub fn generate_cookie() -> [u8; XAUTH_COOKIE_LEN] {
+    let mut cookie = [0u8; XAUTH_COOKIE_LEN];
+    OsRng.fill_bytes(&mut cookie);
+    cookie
+}
+
+/// Name of this machine, as X clients look it up in the Xauthority file
+pub fn hostname() -> String {
+    let mut buf = [0u8; 256];
+
+    match unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } {
+        0 => {
+            let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+            String::from_utf8_lossy(&buf[..len]).into_owned()
+        }
+        _ => String::from("localhost"),
+    }
+}
+
+/// A single Xauthority record granting local connections to the display with the given cookie
+pub fn xauth_entry(hostname: &str, display: u32, cookie: &[u8]) -> Vec<u8> {
+    let mut entry = vec![];
+
+    entry.extend_from_slice(&XAUTH_FAMILY_LOCAL.to_be_bytes());
+    for field in [
+        hostname.as_bytes(),
+        format!("{display}").as_bytes(),
+        XAUTH_COOKIE_NAME.as_bytes(),
+        cookie,
+    ] {
+        entry.extend_from_slice(&(field.len() as u16).to_be_bytes());
+        entry.extend_from_slice(field);
+    }
+
+    entry
+}
+
+/// Write the Xauthority file for the display: readable only by its owner
+pub fn write_xauthority(path: &Path, display: u32, cookie: &[u8]) -> std::io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+
+    file.write_all(xauth_entry(hostname().as_str(), display, cookie).as_slice())?;
+    file.sync_all()
+}
+
+/// Arguments of the launcher that starts the given X11 session command on its own X server
+pub fn x11_launcher_args(exec: &[String]) -> Vec<String> {
+    let mut args = vec![String::from("--x11")];
+
+    if let Some((command, command_args)) = exec.split_first() {
+        args.push(String::from("--command"));
+        args.push(command.clone());
+
+        for arg in command_args.iter() {
+            args.push(String::from("--command-arg"));
+            args.push(arg.clone());
+        }
+    }
+
+    args
+}
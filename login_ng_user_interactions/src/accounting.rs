@@ -0,0 +1,145 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    ffi::{CStr, CString},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use login_ng::{
+    history::LoginRecord,
+    storage::{store_user_login_record, StorageError, StorageSource},
+};
+
+/// Security-relevant events reported to the audit subsystem (when the audit feature is enabled)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AuditEvent {
+    Authentication,
+    SessionStart,
+    SessionEnd,
+}
+
+/// The login records file AccountsService (and last) reads login times and history from
+const WTMP_FILE: &str = "/var/log/wtmp";
+
+extern "C" {
+    // glibc: not exposed by the libc crate
+    fn updwtmpx(wtmpx_file: *const libc::c_char, utmpx: *const libc::utmpx);
+}
+
+/// Returns the hostname of this machine
+pub fn hostname() -> String {
+    let mut buf = [0 as libc::c_char; 256];
+
+    match unsafe { libc::gethostname(buf.as_mut_ptr(), buf.len() - 1) } {
+        0 => unsafe { CStr::from_ptr(buf.as_ptr()) }
+            .to_string_lossy()
+            .into_owned(),
+        _ => String::new(),
+    }
+}
+
+/// Returns the terminal line (i.e. "tty1") of the standard input, empty if it's not a terminal
+pub fn terminal_line() -> String {
+    let name = unsafe { libc::ttyname(libc::STDIN_FILENO) };
+    if name.is_null() {
+        return String::new();
+    }
+
+    let name = unsafe { CStr::from_ptr(name) }.to_string_lossy();
+
+    name.strip_prefix("/dev/").unwrap_or(&name).to_string()
+}
+
+fn copy_str(dst: &mut [libc::c_char], src: &str) {
+    // leave room for the terminating NUL
+    for (d, s) in dst.iter_mut().take(dst.len() - 1).zip(src.bytes()) {
+        *d = s as libc::c_char;
+    }
+}
+
+fn write_wtmp(ut_type: libc::c_short, username: &str, pid: u32, line: &str, host: &str) {
+    let mut entry: libc::utmpx = unsafe { std::mem::zeroed() };
+
+    entry.ut_type = ut_type;
+    entry.ut_pid = pid as libc::pid_t;
+    copy_str(&mut entry.ut_line, line);
+    copy_str(&mut entry.ut_id, line.trim_start_matches("tty"));
+    copy_str(&mut entry.ut_user, username);
+    copy_str(&mut entry.ut_host, host);
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    entry.ut_tv.tv_sec = now.as_secs() as _;
+    entry.ut_tv.tv_usec = now.subsec_micros() as _;
+
+    let Ok(wtmp_file) = CString::new(WTMP_FILE) else {
+        return;
+    };
+
+    unsafe { updwtmpx(wtmp_file.as_ptr(), &entry) };
+}
+
+/// Records a successful login for the given user in the system login records,
+/// so that AccountsService and last can report it.
+pub fn record_login(username: &str, pid: u32) -> LoginRecord {
+    let record = LoginRecord::now(hostname(), terminal_line());
+
+    write_wtmp(
+        libc::USER_PROCESS,
+        username,
+        pid,
+        record.line(),
+        record.host(),
+    );
+
+    record
+}
+
+/// Appends the given login to the login-ng history of the user
+pub fn store_login_history(username: &str, record: &LoginRecord) -> Result<(), StorageError> {
+    store_user_login_record(record, &StorageSource::Username(username.to_string()))
+}
+
+/// Records the end of a session previously recorded with record_login
+pub fn record_logout(record: &LoginRecord, pid: u32) {
+    write_wtmp(libc::DEAD_PROCESS, "", pid, record.line(), "");
+}
+
+/// Reports the event to the kernel audit subsystem: a no-op if the audit feature is disabled
+#[cfg(feature = "audit")]
+pub fn audit_event(event: AuditEvent, username: &str, success: bool) {
+    use login_ng::audit::{audit_log, audit_message, AuditRecordType};
+
+    let (record_type, op) = match event {
+        AuditEvent::Authentication => (AuditRecordType::UserAuth, "PAM:authentication"),
+        AuditEvent::SessionStart => (AuditRecordType::UserStart, "PAM:session_open"),
+        AuditEvent::SessionEnd => (AuditRecordType::UserEnd, "PAM:session_close"),
+    };
+
+    let message = audit_message(op, username, &terminal_line(), success);
+    if let Err(err) = audit_log(record_type, &message) {
+        eprintln!("Unable to write the audit record: {err}");
+    }
+}
+
+/// Reports the event to the kernel audit subsystem: a no-op if the audit feature is disabled
+#[cfg(not(feature = "audit"))]
+pub fn audit_event(_event: AuditEvent, _username: &str, _success: bool) {}
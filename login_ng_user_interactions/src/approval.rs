@@ -0,0 +1,113 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::time::{Duration, Instant};
+
+use login_ng::secure::LockedBytes;
+use qrcode::{render::unicode::Dense1x2, QrCode};
+use thiserror::Error;
+use zbus::{blocking::Connection, proxy};
+
+// from pam_login_ng-common ServiceOperationResult
+const RESULT_OK: u32 = 0;
+const RESULT_APPROVAL_PENDING: u32 = 17;
+
+/// The (code, message, offending item) report returned by the service
+type ServiceReport = (u32, String, String);
+
+/// How often the service is asked whether the challenge has been approved
+const APPROVAL_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Error)]
+pub enum ApprovalError {
+    #[error("DBus error: {0}")]
+    ZbusError(#[from] zbus::Error),
+
+    #[error("Error generating the QR code: {0}")]
+    QrError(#[from] qrcode::types::QrError),
+
+    #[error("The service refused the request: {0}")]
+    Refused(String),
+
+    #[error("The login has not been approved in time")]
+    TimedOut,
+}
+
+#[proxy(
+    interface = "org.neroreflex.login_ng_session1",
+    default_service = "org.neroreflex.login_ng_session",
+    default_path = "/org/zbus/login_ng_session"
+)]
+trait LoginApproval {
+    fn request_approval(&self, username: &str) -> zbus::Result<(ServiceReport, String, String)>;
+
+    fn approval_status(
+        &self,
+        challenge: &str,
+        username: &str,
+    ) -> zbus::Result<(ServiceReport, Vec<u8>)>;
+
+    fn cancel_approval(&self, challenge: &str) -> zbus::Result<ServiceReport>;
+}
+
+/// Renders the given payload as a QR code made of unicode half blocks, suitable for a TTY
+pub fn render_qr(payload: &str) -> Result<String, ApprovalError> {
+    Ok(QrCode::new(payload.as_bytes())?
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .quiet_zone(true)
+        .build())
+}
+
+/// Asks the service for a login challenge, hands the rendered QR code over to `show`
+/// and waits for a companion device to approve it: the returned secret is the main password
+/// the secondary password known by the device has unlocked in the service, to be used in place
+/// of the user password. The secondary password itself never leaves the service.
+pub fn phone_approved_login<F>(
+    username: &str,
+    timeout: Duration,
+    mut show: F,
+) -> Result<LockedBytes, ApprovalError>
+where
+    F: FnMut(&str),
+{
+    let connection = Connection::system()?;
+    let proxy = LoginApprovalProxyBlocking::new(&connection)?;
+
+    let ((code, message, _), challenge, payload) = proxy.request_approval(username)?;
+    if code != RESULT_OK {
+        return Err(ApprovalError::Refused(message));
+    }
+
+    show(render_qr(&payload)?.as_str());
+
+    let started = Instant::now();
+    while started.elapsed() < timeout {
+        match proxy.approval_status(&challenge, username)? {
+            ((RESULT_OK, _, _), main_password) => return Ok(LockedBytes::from_vec(main_password)),
+            ((RESULT_APPROVAL_PENDING, _, _), _) => std::thread::sleep(APPROVAL_POLL_INTERVAL),
+            ((_, message, _), _) => return Err(ApprovalError::Refused(message)),
+        }
+    }
+
+    let _ = proxy.cancel_approval(&challenge);
+
+    Err(ApprovalError::TimedOut)
+}
@@ -0,0 +1,157 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    io,
+    os::unix::net::UnixDatagram,
+    path::{Path, PathBuf},
+};
+
+use configparser::ini::Ini;
+
+/// Directory systemd places the password requests in for agents to answer
+pub const ASK_PASSWORD_DIR: &str = "/run/systemd/ask-password";
+
+/// A password requested by systemd (for example the passphrase of an encrypted disk),
+/// described by an ask.* file of the ask-password directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct PasswordRequest {
+    socket: PathBuf,
+    message: String,
+    id: Option<String>,
+    pid: Option<i32>,
+    not_after: Option<u64>,
+    echo: bool,
+}
+
+impl PasswordRequest {
+    /// Parse the Ask section of an ask.* file: None if there is no socket to answer to
+    pub fn parse(content: &str) -> Option<Self> {
+        let mut config = Ini::new();
+        config.read(String::from(content)).ok()?;
+
+        let socket = config
+            .get("Ask", "socket")
+            .filter(|socket| !socket.is_empty())
+            .map(PathBuf::from)?;
+
+        Some(Self {
+            socket,
+            message: config
+                .get("Ask", "message")
+                .filter(|message| !message.is_empty())
+                .unwrap_or(String::from("Password")),
+            id: config.get("Ask", "id").filter(|id| !id.is_empty()),
+            pid: config
+                .get("Ask", "pid")
+                .and_then(|pid| pid.parse().ok())
+                .filter(|pid| *pid > 0),
+            // zero means no deadline
+            not_after: config
+                .get("Ask", "notafter")
+                .and_then(|not_after| not_after.parse().ok())
+                .filter(|not_after| *not_after > 0),
+            echo: config.get("Ask", "echo").is_some_and(|echo| echo == "1"),
+        })
+    }
+
+    pub fn socket(&self) -> &Path {
+        self.socket.as_path()
+    }
+
+    pub fn message(&self) -> &String {
+        &self.message
+    }
+
+    /// Identifies what the password is for, such as cryptsetup:/dev/sdb1
+    pub fn id(&self) -> &Option<String> {
+        &self.id
+    }
+
+    /// Returns true if the answer doesn't need to be hidden while it's typed
+    pub fn echo(&self) -> bool {
+        self.echo
+    }
+
+    /// Returns true if the deadline of the request, in microseconds of CLOCK_MONOTONIC, has passed
+    pub fn is_expired(&self, now_usec: u64) -> bool {
+        self.not_after.is_some_and(|not_after| not_after < now_usec)
+    }
+
+    /// Returns true if the process that asked for the password does not exist anymore
+    pub fn is_orphaned(&self) -> bool {
+        match self.pid {
+            Some(pid) => {
+                let exists = unsafe { libc::kill(pid, 0) } == 0;
+
+                // EPERM means it exists but belongs to someone else
+                !exists && io::Error::last_os_error().raw_os_error() == Some(libc::ESRCH)
+            }
+            None => false,
+        }
+    }
+
+    /// Send the password, or refuse to give one, to the process that asked for it
+    pub fn answer(&self, password: Option<&str>) -> io::Result<()> {
+        let reply = match password {
+            Some(password) => format!("+{password}"),
+            None => String::from("-"),
+        };
+
+        UnixDatagram::unbound()?.send_to(reply.as_bytes(), self.socket.as_path())?;
+
+        Ok(())
+    }
+}
+
+/// Current time of CLOCK_MONOTONIC in microseconds, the clock deadlines of the requests refer to
+pub fn monotonic_usec() -> u64 {
+    let mut now = unsafe { std::mem::zeroed::<libc::timespec>() };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+
+    (now.tv_sec as u64) * 1_000_000 + (now.tv_nsec as u64) / 1_000
+}
+
+/// The password requests waiting in the given directory that can still be answered
+pub fn pending_password_requests(dir: &Path) -> Vec<PasswordRequest> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+
+    let now = monotonic_usec();
+
+    let mut files = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with("ask."))
+        })
+        .collect::<Vec<_>>();
+
+    // oldest requests (as named by systemd) first
+    files.sort();
+
+    files
+        .iter()
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .filter_map(|content| PasswordRequest::parse(content.as_str()))
+        .filter(|request| !request.is_expired(now) && !request.is_orphaned())
+        .collect()
+}
@@ -23,13 +23,22 @@ use std::{
     sync::{Arc, Mutex},
 };
 
-use crate::{prompt_password, prompt_plain};
+use std::time::Duration;
+
+use crate::{
+    prompt_masked_stderr_with_timeout, prompt_masked_with_timeout, prompt_password_with_timeout,
+    prompt_plain_with_timeout, prompt_secret_stderr_with_timeout, prompt_stderr_with_timeout,
+};
 #[cfg(feature = "pam")]
 use pam_client2::{ConversationHandler, ErrorCode};
 
-use crate::{conversation::*, login::LoginUserInteractionHandler};
+use crate::{
+    conversation::*, greeter::Greeter, login::LoginUserInteractionHandler, theme::Theme, vt::Vt,
+};
 
 use login_ng::{
+    credentials::provisioned_secret,
+    secret_to_string,
     storage::{load_user_auth_data, StorageSource},
     user::UserAuthData,
 };
@@ -67,6 +76,7 @@ impl ConversationPrompter for TrivialCommandLineConversationPrompter {
 pub struct CommandLineConversation {
     answerer: Option<Arc<Mutex<dyn ConversationPrompter>>>,
     recorder: Option<Arc<Mutex<dyn ConversationRecorder>>>,
+    prompt_timeout: Option<Duration>,
 }
 
 #[cfg(feature = "pam")]
@@ -77,12 +87,21 @@ impl CommandLineConversation {
         answerer: Option<Arc<Mutex<dyn ConversationPrompter>>>,
         recorder: Option<Arc<Mutex<dyn ConversationRecorder>>>,
     ) -> Self {
-        Self { answerer, recorder }
+        Self {
+            answerer,
+            recorder,
+            prompt_timeout: None,
+        }
     }
 
     pub fn attach_recorder(&mut self, recorder: Arc<Mutex<dyn ConversationRecorder>>) {
         self.recorder = Some(recorder)
     }
+
+    /// Aborts the conversation if the user leaves a prompt unanswered for longer than the timeout
+    pub fn set_prompt_timeout(&mut self, prompt_timeout: Option<Duration>) {
+        self.prompt_timeout = prompt_timeout
+    }
 }
 
 #[cfg(feature = "pam")]
@@ -101,11 +120,14 @@ impl ConversationHandler for CommandLineConversation {
             Some(ref ans) => match ans.lock() {
                 Ok(mut guard) => match guard.echo_on_prompt(&prompt) {
                     Some(answer) => answer,
-                    None => prompt_plain(prompt.as_str()).map_err(|_err| ErrorCode::CONV_ERR)?,
+                    None => prompt_plain_with_timeout(prompt.as_str(), self.prompt_timeout)
+                        .map_err(|_err| ErrorCode::CONV_ERR)?,
                 },
-                Err(_) => prompt_plain(prompt.as_str()).map_err(|_err| ErrorCode::CONV_ERR)?,
+                Err(_) => prompt_plain_with_timeout(prompt.as_str(), self.prompt_timeout)
+                    .map_err(|_err| ErrorCode::CONV_ERR)?,
             },
-            None => prompt_plain(prompt.as_str()).map_err(|_err| ErrorCode::CONV_ERR)?,
+            None => prompt_plain_with_timeout(prompt.as_str(), self.prompt_timeout)
+                .map_err(|_err| ErrorCode::CONV_ERR)?,
         };
 
         if let Some(recorder) = &self.recorder {
@@ -124,11 +146,14 @@ impl ConversationHandler for CommandLineConversation {
             Some(ref ans) => match ans.lock() {
                 Ok(mut guard) => match guard.echo_off_prompt(&prompt) {
                     Some(answer) => answer,
-                    None => prompt_password(prompt.as_str()).map_err(|_err| ErrorCode::CONV_ERR)?,
+                    None => prompt_password_with_timeout(prompt.as_str(), self.prompt_timeout)
+                        .map_err(|_err| ErrorCode::CONV_ERR)?,
                 },
-                Err(_) => prompt_password(prompt.as_str()).map_err(|_err| ErrorCode::CONV_ERR)?,
+                Err(_) => prompt_password_with_timeout(prompt.as_str(), self.prompt_timeout)
+                    .map_err(|_err| ErrorCode::CONV_ERR)?,
             },
-            None => prompt_password(prompt.as_str()).map_err(|_err| ErrorCode::CONV_ERR)?,
+            None => prompt_password_with_timeout(prompt.as_str(), self.prompt_timeout)
+                .map_err(|_err| ErrorCode::CONV_ERR)?,
         };
 
         if let Some(recorder) = &self.recorder {
@@ -163,6 +188,40 @@ impl ConversationHandler for CommandLineConversation {
             None => {}
         };
     }
+
+    fn radio_prompt(&mut self, msg: &CStr) -> Result<bool, ErrorCode> {
+        let prompt = format!("{}", msg.to_string_lossy());
+
+        let answer = match self.answerer {
+            Some(ref ans) => match ans.lock() {
+                Ok(mut guard) => guard.radio_prompt(&prompt),
+                Err(_) => None,
+            },
+            None => None,
+        };
+
+        match answer {
+            Some(answer) => Ok(answer),
+            None => Ok(is_affirmative(
+                prompt_plain_with_timeout(format!("{prompt} [y/N] ").as_str(), self.prompt_timeout)
+                    .map_err(|_err| ErrorCode::CONV_ERR)?
+                    .as_str(),
+            )),
+        }
+    }
+
+    fn binary_prompt(&mut self, data_type: u8, data: &[u8]) -> Result<(u8, Vec<u8>), ErrorCode> {
+        let answer = match self.answerer {
+            Some(ref ans) => match ans.lock() {
+                Ok(mut guard) => guard.binary_prompt(data_type, data),
+                Err(_) => None,
+            },
+            None => None,
+        };
+
+        // a terminal cannot exchange binary data: an empty reply lets the module fall back
+        Ok(answer.unwrap_or((data_type, vec![])))
+    }
 }
 
 #[derive(Default)]
@@ -174,6 +233,20 @@ pub struct CommandLineLoginUserInteractionHandler {
     maybe_username: Option<String>,
 
     maybe_password: Option<String>,
+
+    prompt_timeout: Option<Duration>,
+
+    default_timeout: Option<Duration>,
+
+    default_username: Option<String>,
+
+    default_secret: Option<String>,
+
+    vt: Option<Vt>,
+
+    greeter: Option<Greeter>,
+
+    theme: Theme,
 }
 
 impl CommandLineLoginUserInteractionHandler {
@@ -194,10 +267,115 @@ impl CommandLineLoginUserInteractionHandler {
             maybe_user,
             maybe_username,
             maybe_password,
+            prompt_timeout: None,
+            default_timeout: None,
+            default_username: None,
+            default_secret: None,
+            vt: None,
+            greeter: None,
+            theme: Theme::plain(),
+        }
+    }
+
+    /// Style prompts and messages printed on the terminal (those of a greeter are styled by the greeter)
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Leave prompting and printing to the given unprivileged greeter, that enforces
+    /// its own timeouts: secrets are still matched against the user configuration here.
+    pub fn with_greeter(mut self, greeter: Greeter) -> Self {
+        self.greeter = Some(greeter);
+        self
+    }
+
+    /// Release the given (already active) VT to the session and reclaim it once the session exits
+    pub fn with_vt(mut self, vt: Vt) -> Self {
+        self.vt = Some(vt);
+        self
+    }
+
+    /// Gives up on prompts left unanswered for longer than the given timeout:
+    /// the login attempt is aborted and the screen cleared.
+    pub fn with_prompt_timeout(mut self, prompt_timeout: Option<Duration>) -> Self {
+        self.prompt_timeout = prompt_timeout;
+        self
+    }
+
+    /// Answers prompts left unanswered for the given time with the given defaults
+    /// (for example the last user, or an empty secret to go through the autologin path),
+    /// allowing kiosk-style prompts that continue on their own.
+    pub fn with_default_answers(
+        mut self,
+        default_timeout: Duration,
+        default_username: Option<String>,
+        default_secret: Option<String>,
+    ) -> Self {
+        self.default_timeout = Some(default_timeout);
+        self.default_username = default_username;
+        self.default_secret = default_secret;
+        self
+    }
+
+    /// Overwrite the secrets provided up-front so that they do not linger in memory
+    pub fn wipe_secrets(&mut self) {
+        for secret in [self.maybe_password.take(), self.default_secret.take()]
+            .into_iter()
+            .flatten()
+        {
+            let mut bytes = secret.into_bytes();
+            login_ng::secure::wipe(bytes.as_mut_slice());
+        }
+    }
+
+    fn read_secret(&mut self, msg: &str) -> Result<String, Box<dyn std::error::Error>> {
+        if let Some(greeter) = &mut self.greeter {
+            return Ok(greeter.prompt_secret(msg)?);
+        }
+
+        let msg = self.theme.prompt(msg);
+        let msg = msg.as_str();
+
+        match (
+            &self.default_timeout,
+            &self.default_secret,
+            self.theme.mask(),
+        ) {
+            (Some(timeout), Some(default), Some(mask)) => {
+                prompt_masked_stderr_with_timeout(msg, mask, Some(*timeout), Some(default.as_str()))
+            }
+            (Some(timeout), Some(default), None) => {
+                prompt_secret_stderr_with_timeout(msg, Some(*timeout), Some(default.as_str()))
+            }
+            (_, _, Some(mask)) => prompt_masked_with_timeout(msg, mask, self.prompt_timeout),
+            (_, _, None) => prompt_password_with_timeout(msg, self.prompt_timeout),
+        }
+    }
+
+    fn read_plain(&mut self, msg: &str) -> Result<String, Box<dyn std::error::Error>> {
+        if let Some(greeter) = &mut self.greeter {
+            return Ok(greeter.prompt_plain(msg)?);
+        }
+
+        let msg = self.theme.prompt(msg);
+        let msg = msg.as_str();
+
+        match (&self.default_timeout, &self.default_username) {
+            (Some(timeout), Some(default)) => {
+                prompt_stderr_with_timeout(msg, Some(*timeout), Some(default.as_str()))
+            }
+            _ => prompt_plain_with_timeout(msg, self.prompt_timeout),
         }
     }
 }
 
+impl Drop for CommandLineLoginUserInteractionHandler {
+    fn drop(&mut self) {
+        self.wipe_secrets()
+    }
+}
+
 impl LoginUserInteractionHandler for CommandLineLoginUserInteractionHandler {
     fn provide_username(&mut self, username: &String) {
         self.maybe_user =
@@ -210,6 +388,18 @@ impl LoginUserInteractionHandler for CommandLineLoginUserInteractionHandler {
                 if let Ok(main_password) = user_cfg.main_by_auth(&Some(String::new())) {
                     return Some(main_password);
                 }
+
+                // a secret provisioned for this machine also allows unattended logins
+                if let Some(username) = &self.maybe_username {
+                    if let Some((_, secret)) = provisioned_secret(username, false) {
+                        if let Ok(main_password) = user_cfg.main_by_secret(Some(&secret)) {
+                            // the conversation needs text: a binary main password cannot be passed on
+                            if let Ok(main_password) = secret_to_string(&main_password) {
+                                return Some(main_password);
+                            }
+                        }
+                    }
+                }
             }
         }
 
@@ -221,7 +411,7 @@ impl LoginUserInteractionHandler for CommandLineLoginUserInteractionHandler {
                 },
                 None => Some(password.clone()),
             },
-            None => match prompt_password(msg.as_str()) {
+            None => match self.read_secret(msg.as_str()) {
                 Ok(provided_secret) => match &self.maybe_user {
                     Some(user_cfg) => match user_cfg.main_by_auth(&Some(provided_secret.clone())) {
                         Ok(main_password) => Some(main_password),
@@ -237,15 +427,47 @@ impl LoginUserInteractionHandler for CommandLineLoginUserInteractionHandler {
     fn prompt_plain(&mut self, msg: &String) -> Option<String> {
         match &self.maybe_username {
             Some(username) => Some(username.clone()),
-            None => prompt_plain(msg.as_str()).ok(),
+            None => self.read_plain(msg.as_str()).ok(),
         }
     }
 
     fn print_info(&mut self, msg: &String) {
-        println!("{}", msg)
+        if let Some(greeter) = &mut self.greeter {
+            if greeter.print_info(msg).is_ok() {
+                return;
+            }
+        }
+
+        println!("{}", self.theme.info(msg))
     }
 
     fn print_error(&mut self, msg: &String) {
-        eprintln!("{}", msg)
+        if let Some(greeter) = &mut self.greeter {
+            if greeter.print_error(msg).is_ok() {
+                return;
+            }
+        }
+
+        eprintln!("{}", self.theme.error(msg))
+    }
+
+    fn session_starting(&mut self) {
+        // the greeter must not keep the terminal open (and be able to inject input) during the session:
+        // dropping it waits for it to exit
+        drop(self.greeter.take());
+
+        if let Some(vt) = &self.vt {
+            if let Err(err) = vt.release() {
+                eprintln!("{err}");
+            }
+        }
+    }
+
+    fn session_ended(&mut self) {
+        if let Some(vt) = &self.vt {
+            if let Err(err) = vt.reclaim() {
+                eprintln!("{err}");
+            }
+        }
     }
 }
@@ -26,7 +26,10 @@ use pam_client2::{ConversationHandler, ErrorCode};
 #[cfg(feature = "pam")]
 use std::{
     ffi::{CStr, CString},
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
 };
 
 #[derive(Debug, Clone)]
@@ -112,18 +115,73 @@ pub trait ConversationPrompter {
     fn display_info(&mut self, prompt: &String);
 
     fn display_error(&mut self, prompt: &String);
+
+    /// Answer a yes/no question: None asks the user
+    fn radio_prompt(&mut self, _prompt: &String) -> Option<bool> {
+        None
+    }
+
+    /// Answer a binary prompt: None replies with empty data
+    fn binary_prompt(&mut self, _data_type: u8, _data: &[u8]) -> Option<(u8, Vec<u8>)> {
+        None
+    }
+}
+
+/// Interpret the answer to a yes/no question
+pub fn is_affirmative(answer: &str) -> bool {
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
 }
 
 #[cfg(feature = "pam")]
 #[derive(Clone)]
 pub struct ProxyLoginUserInteractionHandlerConversation {
     inner: Arc<Mutex<dyn LoginUserInteractionHandler>>,
+    quiet: bool,
+    /// Shared by the clones handed to PAM, to tell whether a step produced any message
+    messages: Arc<AtomicUsize>,
 }
 
 #[cfg(feature = "pam")]
 impl ProxyLoginUserInteractionHandlerConversation {
     pub fn new(inner: Arc<Mutex<dyn LoginUserInteractionHandler>>) -> Self {
-        Self { inner }
+        Self {
+            inner,
+            quiet: false,
+            messages: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Number of informational and error messages received so far
+    pub fn messages(&self) -> usize {
+        self.messages.load(Ordering::Relaxed)
+    }
+
+    /// Drop informational messages, only routing prompts and errors to the handler
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.quiet = quiet;
+    }
+
+    pub fn quiet(&self) -> bool {
+        self.quiet
+    }
+
+    /// Tell the handler who is logging in, once the PAM stack knows
+    pub fn provide_username(&mut self, username: &String) {
+        if let Ok(mut guard) = self.inner.lock() {
+            guard.provide_username(username)
+        }
+    }
+
+    pub fn session_starting(&mut self) {
+        if let Ok(mut guard) = self.inner.lock() {
+            guard.session_starting()
+        }
+    }
+
+    pub fn session_ended(&mut self) {
+        if let Ok(mut guard) = self.inner.lock() {
+            guard.session_ended()
+        }
     }
 }
 
@@ -150,6 +208,12 @@ impl ConversationHandler for ProxyLoginUserInteractionHandlerConversation {
     }
 
     fn text_info(&mut self, msg: &CStr) {
+        self.messages.fetch_add(1, Ordering::Relaxed);
+
+        if self.quiet {
+            return;
+        }
+
         let msg = format!("{}", msg.to_string_lossy());
 
         match self.inner.lock().map_err(|_| ErrorCode::CONV_ERR) {
@@ -162,6 +226,8 @@ impl ConversationHandler for ProxyLoginUserInteractionHandlerConversation {
     }
 
     fn error_msg(&mut self, msg: &CStr) {
+        self.messages.fetch_add(1, Ordering::Relaxed);
+
         let msg = format!("{}", msg.to_string_lossy());
 
         match self.inner.lock().map_err(|_| ErrorCode::CONV_ERR) {
@@ -172,4 +238,18 @@ impl ConversationHandler for ProxyLoginUserInteractionHandlerConversation {
             ),
         }
     }
+
+    fn radio_prompt(&mut self, msg: &CStr) -> Result<bool, ErrorCode> {
+        let msg = format!("{}", msg.to_string_lossy());
+
+        let mut guard = self.inner.lock().map_err(|_| ErrorCode::CONV_ERR)?;
+        guard.prompt_radio(&msg).ok_or(ErrorCode::CONV_ERR)
+    }
+
+    fn binary_prompt(&mut self, data_type: u8, data: &[u8]) -> Result<(u8, Vec<u8>), ErrorCode> {
+        let mut guard = self.inner.lock().map_err(|_| ErrorCode::CONV_ERR)?;
+        Ok(guard
+            .prompt_binary(data_type, data)
+            .unwrap_or((data_type, vec![])))
+    }
 }
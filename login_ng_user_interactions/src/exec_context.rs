@@ -0,0 +1,214 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{fs::OpenOptions, io::Write, path::Path};
+
+use configparser::ini::Ini;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ExecContextError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] std::io::Error),
+
+    #[error("SELinux error setting context {0}")]
+    SELinux(String),
+}
+
+/// The security context the user session will be executed with
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecContext {
+    /// A SELinux context as computed by libselinux
+    SELinux(String),
+
+    /// The name of an AppArmor profile
+    AppArmor(String),
+}
+
+#[cfg(feature = "selinux")]
+mod selinux {
+    use std::ffi::{CStr, CString};
+
+    #[link(name = "selinux")]
+    extern "C" {
+        fn is_selinux_enabled() -> libc::c_int;
+        fn getseuserbyname(
+            linuxuser: *const libc::c_char,
+            seuser: *mut *mut libc::c_char,
+            level: *mut *mut libc::c_char,
+        ) -> libc::c_int;
+        fn get_default_context_with_level(
+            user: *const libc::c_char,
+            level: *const libc::c_char,
+            fromcon: *const libc::c_char,
+            newcon: *mut *mut libc::c_char,
+        ) -> libc::c_int;
+        fn setexeccon(con: *const libc::c_char) -> libc::c_int;
+        fn freecon(con: *mut libc::c_char);
+    }
+
+    pub(super) fn enabled() -> bool {
+        unsafe { is_selinux_enabled() > 0 }
+    }
+
+    /// Equivalent of what pam_selinux does to compute the context of a new login
+    pub(super) fn default_context(username: &str) -> Option<String> {
+        let username = CString::new(username).ok()?;
+
+        let mut seuser = std::ptr::null_mut();
+        let mut level = std::ptr::null_mut();
+        if unsafe { getseuserbyname(username.as_ptr(), &mut seuser, &mut level) } != 0 {
+            return None;
+        }
+
+        let mut context = std::ptr::null_mut();
+        let result = unsafe {
+            get_default_context_with_level(seuser, level, std::ptr::null(), &mut context)
+        };
+
+        unsafe {
+            libc::free(seuser as *mut libc::c_void);
+            libc::free(level as *mut libc::c_void);
+        }
+
+        if result != 0 || context.is_null() {
+            return None;
+        }
+
+        let result = unsafe { CStr::from_ptr(context) }
+            .to_string_lossy()
+            .into_owned();
+
+        unsafe { freecon(context) };
+
+        Some(result)
+    }
+
+    pub(super) fn set_exec_context(context: Option<&str>) -> bool {
+        match context {
+            Some(context) => match CString::new(context) {
+                Ok(context) => unsafe { setexeccon(context.as_ptr()) == 0 },
+                Err(_) => false,
+            },
+            None => unsafe { setexeccon(std::ptr::null()) == 0 },
+        }
+    }
+}
+
+fn apparmor_enabled() -> bool {
+    std::fs::read_to_string("/sys/module/apparmor/parameters/enabled")
+        .map(|content| content.trim() == "Y")
+        .unwrap_or(false)
+}
+
+/// Reads the AppArmor profile of the user from apparmor.conf:
+/// the [Profiles] section maps usernames to profiles, with "default" used as a fallback
+fn apparmor_profile(username: &str) -> Option<String> {
+    let dir_path_str = match std::fs::exists("/usr/lib/login_ng/").unwrap_or(false) {
+        true => "/usr/lib/login_ng/",
+        false => "/etc/login_ng/",
+    };
+
+    let content = std::fs::read_to_string(Path::new(dir_path_str).join("apparmor.conf")).ok()?;
+
+    let mut config = Ini::new_cs();
+    config.read(content).ok()?;
+
+    config
+        .get("Profiles", username)
+        .or_else(|| config.get("Profiles", "default"))
+        .filter(|profile| !profile.is_empty())
+}
+
+/// The path of the given AppArmor attribute of the calling thread: the per-LSM interface
+/// is preferred when available as the generic one may belong to another LSM
+fn apparmor_attr_path(attr: &str) -> String {
+    let apparmor_path = format!("/proc/thread-self/attr/apparmor/{attr}");
+    match Path::new(apparmor_path.as_str()).exists() {
+        true => apparmor_path,
+        false => format!("/proc/thread-self/attr/{attr}"),
+    }
+}
+
+fn write_apparmor_exec(value: &str) -> Result<(), ExecContextError> {
+    let mut attr = OpenOptions::new()
+        .write(true)
+        .open(apparmor_attr_path("exec"))?;
+    attr.write_all(value.as_bytes())?;
+
+    Ok(())
+}
+
+/// The label of the calling thread, without the mode suffix such as " (enforce)"
+fn apparmor_current_label() -> Result<String, ExecContextError> {
+    let current = std::fs::read_to_string(apparmor_attr_path("current"))?;
+    let current = current.trim_end_matches(['\n', '\0']);
+
+    Ok(match current.rsplit_once(" (") {
+        Some((label, _)) => String::from(label),
+        None => String::from(current),
+    })
+}
+
+/// Computes the security context the session of the given user should be executed with,
+/// or None if no LSM is active or nothing has been configured for the user.
+pub fn default_exec_context(username: &str) -> Option<ExecContext> {
+    #[cfg(feature = "selinux")]
+    if selinux::enabled() {
+        return selinux::default_context(username).map(ExecContext::SELinux);
+    }
+
+    if apparmor_enabled() {
+        return apparmor_profile(username).map(ExecContext::AppArmor);
+    }
+
+    None
+}
+
+/// Sets the context the next program executed by the calling thread will run with
+pub fn set_exec_context(context: &ExecContext) -> Result<(), ExecContextError> {
+    match context {
+        #[cfg(feature = "selinux")]
+        ExecContext::SELinux(context) => match selinux::set_exec_context(Some(context)) {
+            true => Ok(()),
+            false => Err(ExecContextError::SELinux(context.clone())),
+        },
+        #[cfg(not(feature = "selinux"))]
+        ExecContext::SELinux(context) => Err(ExecContextError::SELinux(context.clone())),
+        ExecContext::AppArmor(profile) => write_apparmor_exec(format!("exec {profile}").as_str()),
+    }
+}
+
+/// Restores the default behaviour of executing programs with the context of the caller
+pub fn reset_exec_context(context: &ExecContext) -> Result<(), ExecContextError> {
+    match context {
+        #[cfg(feature = "selinux")]
+        ExecContext::SELinux(context) => match selinux::set_exec_context(None) {
+            true => Ok(()),
+            false => Err(ExecContextError::SELinux(context.clone())),
+        },
+        #[cfg(not(feature = "selinux"))]
+        ExecContext::SELinux(_) => Ok(()),
+        // the exec attribute cannot be cleared: point it back to the profile of the caller,
+        // so that the next exec doesn't switch to the one of the session
+        ExecContext::AppArmor(_) => {
+            write_apparmor_exec(format!("exec {}", apparmor_current_label()?).as_str())
+        }
+    }
+}
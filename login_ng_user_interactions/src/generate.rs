@@ -0,0 +1,553 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::str::FromStr;
+
+use argh::{ArgsInfo, CommandInfoWithArgs, FlagInfo, FlagInfoKind, FromArgs, Optionality};
+
+/// Name of the subcommand generating shell completions and manual pages: it is not part of
+/// the argh definitions of the programs, so that it doesn't show in their help
+pub const GENERATE_SUBCOMMAND: &str = "generate";
+
+/// What the generate subcommand emits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GenerateTarget {
+    Bash,
+    Zsh,
+    Fish,
+    Man,
+}
+
+impl FromStr for GenerateTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Self::Bash),
+            "zsh" => Ok(Self::Zsh),
+            "fish" => Ok(Self::Fish),
+            "man" => Ok(Self::Man),
+            _ => Err(format!(
+                "unknown target {s}: expected bash, zsh, fish or man"
+            )),
+        }
+    }
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// Generate shell completions or the manual page
+pub struct GenerateArgs {
+    #[argh(positional)]
+    /// what to generate: bash, zsh, fish or man
+    pub target: GenerateTarget,
+}
+
+/// Handles `<command> generate <target>`, printing the requested file on stdout:
+/// returns the exit code if the program has been invoked that way
+pub fn generate_from_env<T: ArgsInfo>(command: &str) -> Option<i32> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) != Some(GENERATE_SUBCOMMAND) {
+        return None;
+    }
+
+    let rest: Vec<&str> = args[1..].iter().map(String::as_str).collect();
+    match GenerateArgs::from_args(&[command, GENERATE_SUBCOMMAND], &rest) {
+        Ok(generate_args) => {
+            print!(
+                "{}",
+                generate(generate_args.target, command, &T::get_args_info())
+            );
+            Some(0)
+        }
+        Err(early_exit) => match early_exit.status {
+            Ok(()) => {
+                println!("{}", early_exit.output);
+                Some(0)
+            }
+            Err(()) => {
+                eprintln!("{}", early_exit.output);
+                Some(1)
+            }
+        },
+    }
+}
+
+pub fn generate(target: GenerateTarget, command: &str, info: &CommandInfoWithArgs) -> String {
+    match target {
+        GenerateTarget::Bash => bash_completion(command, info),
+        GenerateTarget::Zsh => zsh_completion(command, info),
+        GenerateTarget::Fish => fish_completion(command, info),
+        GenerateTarget::Man => man_page(command, info),
+    }
+}
+
+/// Calls the visitor on the command and on every subcommand below it, parents first,
+/// along with the words leading to each of them
+fn visit(
+    path: &mut Vec<String>,
+    info: &CommandInfoWithArgs,
+    visitor: &mut dyn FnMut(&[String], &CommandInfoWithArgs),
+) {
+    visitor(path.as_slice(), info);
+
+    for subcommand in info.commands.iter() {
+        path.push(String::from(subcommand.name));
+        visit(path, &subcommand.command, visitor);
+        path.pop();
+    }
+}
+
+/// The name of the shell function completing the given command words
+fn function_name(path: &[String]) -> String {
+    let name: String = path
+        .join("_")
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c,
+            false => '_',
+        })
+        .collect();
+
+    format!("_{name}")
+}
+
+fn long_name(flag: &FlagInfo) -> String {
+    format!("--{}", flag.long.trim_start_matches('-'))
+}
+
+fn short_name(flag: &FlagInfo) -> Option<String> {
+    flag.short.map(|short| format!("-{short}"))
+}
+
+/// Every spelling of the flag: the short one first, if any
+fn flag_names(flag: &FlagInfo) -> Vec<String> {
+    short_name(flag)
+        .into_iter()
+        .chain([long_name(flag)])
+        .collect()
+}
+
+fn flag_value(flag: &FlagInfo) -> Option<&str> {
+    match &flag.kind {
+        FlagInfoKind::Option { arg_name } => Some(*arg_name),
+        FlagInfoKind::Switch => None,
+    }
+}
+
+/// Values of options about users are usernames, everything else is completed as a path
+fn completes_users(flag: &FlagInfo) -> bool {
+    flag.long.contains("user")
+}
+
+fn one_line(description: &str) -> String {
+    description.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn single_quoted(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+pub fn bash_completion(command: &str, info: &CommandInfoWithArgs) -> String {
+    let mut walk = String::new();
+    let mut values = String::new();
+    let mut words = String::new();
+
+    visit(&mut vec![String::from(command)], info, &mut |path, info| {
+        let cmd = path.join(" ");
+
+        for flag in info.flags.iter() {
+            if flag_value(flag).is_none() {
+                continue;
+            }
+
+            let patterns = flag_names(flag)
+                .iter()
+                .map(|name| format!("\"{cmd},{name}\""))
+                .collect::<Vec<_>>()
+                .join("|");
+            let compgen = match completes_users(flag) {
+                true => "-u",
+                false => "-f",
+            };
+
+            walk.push_str(&format!("            {patterns}) ((i++)) ;;\n"));
+            values.push_str(&format!(
+                "        {patterns}) COMPREPLY=($(compgen {compgen} -- \"${{cur}}\")); return 0 ;;\n"
+            ));
+        }
+
+        for subcommand in info.commands.iter() {
+            walk.push_str(&format!(
+                "            \"{cmd},{0}\") cmd=\"{cmd} {0}\" ;;\n",
+                subcommand.name
+            ));
+        }
+
+        let opts = info
+            .flags
+            .iter()
+            .flat_map(flag_names)
+            .chain(info.commands.iter().map(|sub| String::from(sub.name)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let files = match info.positionals.is_empty() {
+            true => "",
+            false => " files=1",
+        };
+        words.push_str(&format!("        \"{cmd}\") opts=\"{opts}\"{files} ;;\n"));
+    });
+
+    let function = function_name(&[String::from(command)]);
+
+    format!(
+        "# bash completion for {command}
+
+{function}() {{
+    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"
+    local prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"
+    local cmd=\"{command}\"
+    local opts=\"\"
+    local files=\"\"
+    local i
+
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case \"${{cmd}},${{COMP_WORDS[i]}}\" in
+{walk}        esac
+    done
+
+    case \"${{cmd}},${{prev}}\" in
+{values}    esac
+
+    case \"${{cmd}}\" in
+{words}    esac
+
+    COMPREPLY=($(compgen -W \"${{opts}}\" -- \"${{cur}}\"))
+    if [[ -n \"${{files}}\" ]]; then
+        COMPREPLY+=($(compgen -f -- \"${{cur}}\"))
+    fi
+}}
+
+complete -F {function} {command}
+"
+    )
+}
+
+/// Escapes the characters _arguments gives a meaning to in descriptions
+fn zsh_description(description: &str) -> String {
+    one_line(description)
+        .replace('\\', "\\\\")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+fn zsh_flag_spec(flag: &FlagInfo) -> String {
+    let repeating = matches!(flag.optionality, Optionality::Repeating);
+    let description = format!("[{}]", zsh_description(flag.description));
+    let value = match flag_value(flag) {
+        Some(arg_name) if completes_users(flag) => format!(":{arg_name}:_users"),
+        Some(arg_name) => format!(":{arg_name}:_files"),
+        None => String::new(),
+    };
+
+    match (short_name(flag), repeating) {
+        (Some(short), false) => format!(
+            "'({short} {long})'{{{short},{long}}}{}",
+            single_quoted(&format!("{description}{value}")),
+            long = long_name(flag)
+        ),
+        (Some(short), true) => format!(
+            "'*'{{{short},{long}}}{}",
+            single_quoted(&format!("{description}{value}")),
+            long = long_name(flag)
+        ),
+        (None, false) => single_quoted(&format!("{}{description}{value}", long_name(flag))),
+        (None, true) => single_quoted(&format!("*{}{description}{value}", long_name(flag))),
+    }
+}
+
+pub fn zsh_completion(command: &str, info: &CommandInfoWithArgs) -> String {
+    let mut functions = String::new();
+
+    visit(&mut vec![String::from(command)], info, &mut |path, info| {
+        let mut specs: Vec<String> = info.flags.iter().map(zsh_flag_spec).collect();
+
+        for positional in info.positionals.iter() {
+            let prefix = match positional.optionality {
+                Optionality::None => ":",
+                Optionality::Optional => "::",
+                _ => "*:",
+            };
+            specs.push(single_quoted(&format!(
+                "{prefix}{}:_files",
+                positional.name
+            )));
+        }
+
+        let mut body = String::new();
+        if info.commands.is_empty() && specs.is_empty() {
+            body.push_str("    _message 'no arguments'\n");
+        } else if info.commands.is_empty() {
+            body.push_str(&format!(
+                "    _arguments \\\n        {}\n",
+                specs.join(" \\\n        ")
+            ));
+        } else {
+            specs.push(String::from("': :->command'"));
+            specs.push(String::from("'*:: :->argument'"));
+
+            let commands = info
+                .commands
+                .iter()
+                .map(|sub| {
+                    single_quoted(&format!(
+                        "{}:{}",
+                        sub.name,
+                        one_line(sub.command.description)
+                    ))
+                })
+                .collect::<Vec<_>>()
+                .join("\n                ");
+            let dispatch = info
+                .commands
+                .iter()
+                .map(|sub| {
+                    let mut sub_path = path.to_vec();
+                    sub_path.push(String::from(sub.name));
+                    format!(
+                        "                {}) {} ;;\n",
+                        sub.name,
+                        function_name(&sub_path)
+                    )
+                })
+                .collect::<String>();
+
+            body.push_str(&format!(
+                "    local context state state_descr line
+    typeset -A opt_args
+
+    _arguments -C \\
+        {specs} && return
+
+    case $state in
+        command)
+            local -a commands
+            commands=(
+                {commands}
+            )
+            _describe -t commands 'command' commands
+            ;;
+        argument)
+            case $line[1] in
+{dispatch}            esac
+            ;;
+    esac
+",
+                specs = specs.join(" \\\n        ")
+            ));
+        }
+
+        functions.push_str(&format!("\n{}() {{\n{body}}}\n", function_name(path)));
+    });
+
+    format!(
+        "#compdef {command}
+{functions}
+{} \"$@\"
+",
+        function_name(&[String::from(command)])
+    )
+}
+
+pub fn fish_completion(command: &str, info: &CommandInfoWithArgs) -> String {
+    let mut completions = format!("# fish completion for {command}\n");
+
+    visit(&mut vec![String::from(command)], info, &mut |path, info| {
+        // the subcommands leading here must have been typed, and none of the following ones yet
+        let mut conditions: Vec<String> = path[1..]
+            .iter()
+            .map(|word| format!("__fish_seen_subcommand_from {word}"))
+            .collect();
+        if !info.commands.is_empty() {
+            let names = info
+                .commands
+                .iter()
+                .map(|sub| sub.name)
+                .collect::<Vec<_>>()
+                .join(" ");
+            conditions.push(format!("not __fish_seen_subcommand_from {names}"));
+        }
+
+        let condition = match conditions.is_empty() {
+            true => String::new(),
+            false => format!(" -n {}", single_quoted(&conditions.join("; and "))),
+        };
+
+        for flag in info.flags.iter() {
+            let short = match flag.short {
+                Some(short) => format!(" -s {short}"),
+                None => String::new(),
+            };
+            let value = match flag_value(flag) {
+                Some(_) if completes_users(flag) => " -r -f -a '(__fish_complete_users)'",
+                Some(_) => " -r -F",
+                None => "",
+            };
+
+            completions.push_str(&format!(
+                "complete -c {command}{condition}{short} -l {}{value} -d {}\n",
+                flag.long.trim_start_matches('-'),
+                single_quoted(&one_line(flag.description))
+            ));
+        }
+
+        for subcommand in info.commands.iter() {
+            completions.push_str(&format!(
+                "complete -c {command}{condition} -f -a {} -d {}\n",
+                subcommand.name,
+                single_quoted(&one_line(subcommand.command.description))
+            ));
+        }
+    });
+
+    completions
+}
+
+/// Escapes text for roff, so that no line of it is taken for a request
+fn roff(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let line = line.trim().replace('\\', "\\e").replace('-', "\\-");
+            match line.starts_with('.') || line.starts_with('\'') {
+                true => format!("\\&{line}"),
+                false => line,
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The arguments accepted by the command, as in its usage line
+fn usage(info: &CommandInfoWithArgs) -> String {
+    let mut words: Vec<String> = info
+        .flags
+        .iter()
+        .filter(|flag| long_name(flag) != "--help")
+        .map(|flag| {
+            let name = short_name(flag).unwrap_or_else(|| long_name(flag));
+            let word = match flag_value(flag) {
+                Some(arg_name) => format!("{name} <{arg_name}>"),
+                None => name,
+            };
+
+            match flag.optionality {
+                Optionality::None => word,
+                Optionality::Repeating => format!("[{word}...]"),
+                _ => format!("[{word}]"),
+            }
+        })
+        .collect();
+
+    words.extend(
+        info.positionals
+            .iter()
+            .map(|positional| match positional.optionality {
+                Optionality::None => format!("<{}>", positional.name),
+                Optionality::Optional => format!("[<{}>]", positional.name),
+                _ => format!("[<{}>...]", positional.name),
+            }),
+    );
+
+    if !info.commands.is_empty() {
+        words.push(String::from("<command> [<args>]"));
+    }
+
+    words.join(" ")
+}
+
+fn man_options(info: &CommandInfoWithArgs) -> String {
+    info.flags
+        .iter()
+        .map(|flag| {
+            let names = flag_names(flag)
+                .iter()
+                .map(|name| format!("\\fB{}\\fR", roff(name)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let value = match flag_value(flag) {
+                Some(arg_name) => format!(" \\fI{}\\fR", roff(arg_name)),
+                None => String::new(),
+            };
+
+            format!(
+                ".TP\n{names}{value}\n{}\n",
+                roff(&one_line(flag.description))
+            )
+        })
+        .collect()
+}
+
+pub fn man_page(command: &str, info: &CommandInfoWithArgs) -> String {
+    let mut page = format!(
+        ".TH \"{}\" \"1\" \"\" \"login-ng {}\" \"User Commands\"
+.SH NAME
+{} \\- {}
+.SH SYNOPSIS
+.B {}
+{}
+.SH DESCRIPTION
+{}
+",
+        roff(&command.to_uppercase()),
+        login_ng::LIBRARY_VERSION,
+        roff(command),
+        roff(&one_line(info.description)),
+        roff(command),
+        roff(&usage(info)),
+        roff(info.description),
+    );
+
+    if !info.flags.is_empty() {
+        page.push_str(".SH OPTIONS\n");
+        page.push_str(&man_options(info));
+    }
+
+    if !info.commands.is_empty() {
+        page.push_str(".SH COMMANDS\n");
+    }
+
+    for subcommand in info.commands.iter() {
+        visit(
+            &mut vec![String::from(command), String::from(subcommand.name)],
+            &subcommand.command,
+            &mut |path, info| {
+                page.push_str(&format!(
+                    ".SS \"{}\"\n{}\n.PP\n.B {}\n{}\n",
+                    roff(&path.join(" ")),
+                    roff(info.description),
+                    roff(&path.join(" ")),
+                    roff(&usage(info))
+                ));
+                page.push_str(&man_options(info));
+            },
+        );
+    }
+
+    page
+}
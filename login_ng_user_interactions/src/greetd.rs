@@ -17,13 +17,21 @@
     51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 */
 
-use crate::login::*;
+use crate::{i18n::tr, login::*};
 
 use std::{
+    collections::BTreeMap,
     os::unix::net::UnixStream,
     sync::{Arc, Mutex},
 };
 
+use login_ng::{
+    command::SessionCommand,
+    faillock::FaillockConfig,
+    storage::{load_user_session_environment, StorageSource},
+    users::{os::unix::UserExt, User},
+};
+
 use greetd_ipc::{codec::SyncCodec, AuthMessageType, ErrorType, Request, Response};
 
 use thiserror::Error;
@@ -42,6 +50,9 @@ pub enum GreetdLoginError {
     #[error("No username provided")]
     NoUsernameProvided,
 
+    #[error("Account locked due to too many failed login attempts: {0}")]
+    Locked(String),
+
     #[error("Mutex error")]
     MutexError,
 }
@@ -79,20 +90,26 @@ impl LoginExecutor for GreetdLoginExecutor {
             .lock()
             .map_err(|_| LoginError::GreetdError(GreetdLoginError::MutexError))?;
 
-        let username =
-            match maybe_username {
-                Some(username) => username.clone(),
-                None => prompter.prompt_plain(&String::from("login: ")).ok_or(
-                    LoginError::GreetdError(GreetdLoginError::NoUsernameProvided),
-                )?,
-            };
+        let username = match maybe_username {
+            Some(username) => username.clone(),
+            None => prompter
+                .prompt_plain(&tr("login-prompt", "login: "))
+                .ok_or(LoginError::GreetdError(
+                    GreetdLoginError::NoUsernameProvided,
+                ))?,
+        };
 
         prompter.provide_username(&username);
 
+        // as the PAM executor does: do not even prompt for secrets if the account has been locked
+        if let Some(message) = lockout_message(&FaillockConfig::load(), &username) {
+            return Err(LoginError::GreetdError(GreetdLoginError::Locked(message)));
+        }
+
         let mut next_request = Request::CreateSession {
             username: username.clone(),
         };
-        let mut starting = false;
+        let mut started: Option<(User, SessionCommand, BTreeMap<String, String>)> = None;
         loop {
             next_request
                 .write_to(&mut stream)
@@ -106,8 +123,27 @@ impl LoginExecutor for GreetdLoginExecutor {
                     auth_message_type,
                 } => {
                     let response = match auth_message_type {
-                        AuthMessageType::Visible => prompter.prompt_plain(&auth_message),
-                        AuthMessageType::Secret => prompter.prompt_secret(&auth_message),
+                        AuthMessageType::Visible | AuthMessageType::Secret => {
+                            let answer = match auth_message_type {
+                                AuthMessageType::Visible => prompter.prompt_plain(&auth_message),
+                                _ => prompter.prompt_secret(&auth_message),
+                            };
+
+                            // an unanswered prompt (i.e. timed out) aborts the attempt
+                            if answer.is_none() {
+                                Request::CancelSession
+                                    .write_to(&mut stream)
+                                    .map_err(|err| {
+                                        LoginError::GreetdError(GreetdLoginError::GreetdIpcError(
+                                            err,
+                                        ))
+                                    })?;
+
+                                return Ok(LoginResult::Failure);
+                            }
+
+                            answer
+                        }
                         AuthMessageType::Info => {
                             eprintln!("info: {}", auth_message);
                             None
@@ -121,19 +157,49 @@ impl LoginExecutor for GreetdLoginExecutor {
                     next_request = Request::PostAuthMessageResponse { response };
                 }
                 Response::Success => {
-                    if starting {
-                        return Ok(LoginResult::Success);
+                    if let Some((user, command, env)) = started {
+                        return Ok(LoginResult::Success {
+                            uid: user.uid(),
+                            home: user.home_dir().to_path_buf(),
+                            username,
+                            command,
+                            env,
+                        });
                     } else {
-                        starting = true;
+                        // the session must not be started for an account that cannot be resolved:
+                        // callers would otherwise act on made up (i.e. root) details
+                        let Some(user) = login_ng::users::get_user_by_name(&username) else {
+                            Request::CancelSession
+                                .write_to(&mut stream)
+                                .map_err(|err| {
+                                    LoginError::GreetdError(GreetdLoginError::GreetdIpcError(err))
+                                })?;
+
+                            return Err(LoginError::UserDiscoveryError);
+                        };
 
                         // The retrival of default session MUST be done after the account has been unlocked
                         let command =
                             retrieve_session_command_for_user(&username, retrival_strategy);
 
-                        next_request = Request::StartSession {
-                            env: vec![],
-                            cmd: vec![command.command()], // TODO: arguments?
+                        if let Some(motd) = motd_for_user(&username) {
+                            prompter.print_info(&motd);
                         }
+
+                        // greetd starts the session once the greeter has exited
+                        prompter.session_starting();
+
+                        let env = greetd_session_env(&username, &command);
+
+                        next_request = Request::StartSession {
+                            env: env
+                                .iter()
+                                .map(|(key, value)| format!("{key}={value}"))
+                                .collect(),
+                            cmd: greetd_session_cmd(&command),
+                        };
+
+                        started = Some((user, command, env));
                     }
                 }
                 Response::Error {
@@ -158,3 +224,38 @@ impl LoginExecutor for GreetdLoginExecutor {
         }
     }
 }
+
+/// The environment the PAM executor gives the session on top of the PAM one:
+/// the user environment.d(5) files, the locale stored for the user and the
+/// variables of the session command (XDG_SESSION_TYPE, XDG_CURRENT_DESKTOP, ...)
+fn greetd_session_env(username: &str, command: &SessionCommand) -> BTreeMap<String, String> {
+    let mut env = user_environment_d(username);
+
+    if let Ok(Some(session_environment)) =
+        load_user_session_environment(&StorageSource::Username(String::from(username)))
+    {
+        env.extend(session_environment.locale().clone());
+    }
+
+    env.extend(command.env().clone());
+
+    env
+}
+
+/// greetd has no notion of a working directory: when one is requested
+/// the session is started through a shell that changes into it first.
+fn greetd_session_cmd(command: &SessionCommand) -> Vec<String> {
+    match command.cwd() {
+        Some(cwd) => {
+            let mut cmd = vec![
+                String::from("/bin/sh"),
+                String::from("-c"),
+                String::from("cd \"$0\" && exec \"$@\""),
+                cwd.to_string_lossy().to_string(),
+            ];
+            cmd.extend(command.argv());
+            cmd
+        }
+        None => command.argv(),
+    }
+}
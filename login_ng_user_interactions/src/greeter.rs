@@ -0,0 +1,313 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    io::{Read, Write},
+    os::{
+        fd::{AsRawFd, RawFd},
+        unix::{
+            fs::{chown, PermissionsExt},
+            net::UnixStream,
+            process::CommandExt,
+        },
+    },
+    path::{Path, PathBuf},
+    process::{Child, Command},
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+use login_ng::{privileges::SessionCredentials, users::get_user_by_name};
+
+use crate::login::LoginUserInteractionHandler;
+
+/// The dedicated unprivileged user the prompts are run as
+pub const GREETER_USER: &str = "login-ng-greeter";
+
+/// Private runtime directory of the greeter, used as its home and XDG_RUNTIME_DIR
+pub const GREETER_RUNTIME_DIR: &str = "/run/login_ng/greeter";
+
+/// The file descriptor the greeter finds its end of the socket on
+pub const GREETER_SOCKET_FD: RawFd = 3;
+
+/// Environment variables passed on to the greeter: everything else is cleared
+const GREETER_ENVIRONMENT: [&str; 4] = ["TERM", "LANG", "LANGUAGE", "LC_ALL"];
+
+/// Time the greeter is given to exit once its socket is closed before it is killed
+const GREETER_EXIT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Longest message accepted from the other end: the greeter is not trusted
+const MAX_MESSAGE_LENGTH: usize = 64 * 1024;
+
+const REQUEST_PLAIN: u8 = 1;
+const REQUEST_SECRET: u8 = 2;
+const REQUEST_INFO: u8 = 3;
+const REQUEST_ERROR: u8 = 4;
+
+const REPLY_ANSWER: u8 = 0;
+const REPLY_NO_ANSWER: u8 = 1;
+const REPLY_DONE: u8 = 2;
+
+#[derive(Debug, Error)]
+pub enum GreeterError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] std::io::Error),
+
+    #[error("The greeter user {0} does not exist")]
+    UnknownUser(String),
+
+    #[error("Unsafe greeter runtime directory {0}")]
+    UnsafeRuntimeDir(PathBuf),
+
+    #[error("Malformed message from the greeter")]
+    Protocol,
+
+    #[error("The greeter gave no answer")]
+    NoAnswer,
+}
+
+fn write_message(stream: &mut UnixStream, tag: u8, payload: &[u8]) -> Result<(), GreeterError> {
+    if payload.len() > MAX_MESSAGE_LENGTH {
+        return Err(GreeterError::Protocol);
+    }
+
+    let mut message = Vec::with_capacity(5 + payload.len());
+    message.push(tag);
+    message.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    message.extend_from_slice(payload);
+
+    // answers carry secrets: do not leave copies around
+    let result = stream
+        .write_all(message.as_slice())
+        .and_then(|_| stream.flush());
+    login_ng::secure::wipe(message.as_mut_slice());
+
+    Ok(result?)
+}
+
+/// Read a message: None if the other end has closed the socket
+fn read_message(stream: &mut UnixStream) -> Result<Option<(u8, String)>, GreeterError> {
+    let mut header = [0u8; 5];
+    match stream.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(GreeterError::IOError(err)),
+    }
+
+    let length = u32::from_le_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if length > MAX_MESSAGE_LENGTH {
+        return Err(GreeterError::Protocol);
+    }
+
+    let mut payload = vec![0u8; length];
+    stream.read_exact(payload.as_mut_slice())?;
+
+    match String::from_utf8(payload) {
+        Ok(payload) => Ok(Some((header[0], payload))),
+        Err(err) => {
+            login_ng::secure::wipe(err.into_bytes().as_mut_slice());
+            Err(GreeterError::Protocol)
+        }
+    }
+}
+
+/// Create the private runtime directory of the greeter, owned by it and not accessible to others
+fn prepare_runtime_dir(uid: libc::uid_t, gid: libc::gid_t) -> Result<PathBuf, GreeterError> {
+    let runtime_dir = Path::new(GREETER_RUNTIME_DIR);
+
+    if let Some(parent) = runtime_dir.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    match std::fs::symlink_metadata(runtime_dir) {
+        Ok(metadata) if !metadata.is_dir() => {
+            return Err(GreeterError::UnsafeRuntimeDir(runtime_dir.to_path_buf()))
+        }
+        Ok(_) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => std::fs::create_dir(runtime_dir)?,
+        Err(err) => return Err(GreeterError::IOError(err)),
+    }
+
+    chown(runtime_dir, Some(uid), Some(gid))?;
+    std::fs::set_permissions(runtime_dir, std::fs::Permissions::from_mode(0o700))?;
+
+    Ok(runtime_dir.to_path_buf())
+}
+
+/// The privileged end of the greeter: the unprivileged process reading
+/// the keyboard, that is asked to show messages and prompt the user.
+pub struct Greeter {
+    stream: UnixStream,
+    process: Option<Child>,
+}
+
+impl Greeter {
+    /// Run this executable again as the greeter user with the given arguments:
+    /// it is expected to call [`serve_greeter`] on [`GREETER_SOCKET_FD`].
+    /// NOTE: switching user requires root
+    pub fn spawn(args: &[String]) -> Result<Self, GreeterError> {
+        let user = get_user_by_name(GREETER_USER)
+            .ok_or(GreeterError::UnknownUser(String::from(GREETER_USER)))?;
+
+        let runtime_dir = prepare_runtime_dir(user.uid(), user.primary_group_id())?;
+
+        let (stream, greeter_stream) = UnixStream::pair()?;
+        let greeter_fd = greeter_stream.as_raw_fd();
+
+        let credentials = SessionCredentials::for_user(&user);
+
+        let mut command = Command::new(std::env::current_exe()?);
+        command
+            .args(args)
+            .env_clear()
+            .envs(
+                GREETER_ENVIRONMENT
+                    .iter()
+                    .filter_map(|name| std::env::var_os(name).map(|value| (name, value))),
+            )
+            .env("USER", GREETER_USER)
+            .env("HOME", &runtime_dir)
+            .env("XDG_RUNTIME_DIR", &runtime_dir)
+            .current_dir(&runtime_dir);
+
+        unsafe {
+            command.pre_exec(move || {
+                // dup2 does not preserve close-on-exec, unless the descriptor is already in place
+                let result = match greeter_fd == GREETER_SOCKET_FD {
+                    true => libc::fcntl(greeter_fd, libc::F_SETFD, 0),
+                    false => libc::dup2(greeter_fd, GREETER_SOCKET_FD),
+                };
+
+                if result == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+
+                credentials.apply()
+            });
+        }
+
+        let process = Some(command.spawn()?);
+
+        Ok(Self { stream, process })
+    }
+
+    /// The privileged end of a greeter that is already connected to the other end of the socket
+    #[cfg(test)]
+    pub(crate) fn from_stream(stream: UnixStream) -> Self {
+        Self {
+            stream,
+            process: None,
+        }
+    }
+
+    fn prompt(&mut self, tag: u8, msg: &str) -> Result<String, GreeterError> {
+        write_message(&mut self.stream, tag, msg.as_bytes())?;
+
+        match read_message(&mut self.stream)? {
+            Some((REPLY_ANSWER, answer)) => Ok(answer),
+            Some((REPLY_NO_ANSWER, _)) => Err(GreeterError::NoAnswer),
+            Some(_) | None => Err(GreeterError::Protocol),
+        }
+    }
+
+    fn print(&mut self, tag: u8, msg: &str) -> Result<(), GreeterError> {
+        write_message(&mut self.stream, tag, msg.as_bytes())?;
+
+        match read_message(&mut self.stream)? {
+            Some((REPLY_DONE, _)) => Ok(()),
+            Some(_) | None => Err(GreeterError::Protocol),
+        }
+    }
+
+    pub fn prompt_plain(&mut self, msg: &str) -> Result<String, GreeterError> {
+        self.prompt(REQUEST_PLAIN, msg)
+    }
+
+    pub fn prompt_secret(&mut self, msg: &str) -> Result<String, GreeterError> {
+        self.prompt(REQUEST_SECRET, msg)
+    }
+
+    pub fn print_info(&mut self, msg: &str) -> Result<(), GreeterError> {
+        self.print(REQUEST_INFO, msg)
+    }
+
+    pub fn print_error(&mut self, msg: &str) -> Result<(), GreeterError> {
+        self.print(REQUEST_ERROR, msg)
+    }
+}
+
+impl Drop for Greeter {
+    fn drop(&mut self) {
+        // the greeter exits as soon as it finds the socket closed
+        let _ = self.stream.shutdown(std::net::Shutdown::Both);
+
+        let Some(mut process) = self.process.take() else {
+            return;
+        };
+
+        // one that does not must not be left holding the terminal
+        let deadline = Instant::now() + GREETER_EXIT_TIMEOUT;
+        while let Ok(None) = process.try_wait() {
+            if Instant::now() >= deadline {
+                let _ = process.kill();
+                let _ = process.wait();
+                return;
+            }
+
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+/// The unprivileged end of the greeter: answer the requests of the privileged
+/// process with the given handler until the socket gets closed.
+pub fn serve_greeter(
+    mut stream: UnixStream,
+    handler: &mut dyn LoginUserInteractionHandler,
+) -> Result<(), GreeterError> {
+    while let Some((tag, msg)) = read_message(&mut stream)? {
+        let answer = match tag {
+            REQUEST_PLAIN => handler.prompt_plain(&msg),
+            REQUEST_SECRET => handler.prompt_secret(&msg),
+            REQUEST_INFO => {
+                handler.print_info(&msg);
+                write_message(&mut stream, REPLY_DONE, &[])?;
+                continue;
+            }
+            REQUEST_ERROR => {
+                handler.print_error(&msg);
+                write_message(&mut stream, REPLY_DONE, &[])?;
+                continue;
+            }
+            _ => return Err(GreeterError::Protocol),
+        };
+
+        match answer {
+            Some(answer) => {
+                let result = write_message(&mut stream, REPLY_ANSWER, answer.as_bytes());
+                login_ng::secure::wipe(answer.into_bytes().as_mut_slice());
+                result?
+            }
+            None => write_message(&mut stream, REPLY_NO_ANSWER, &[])?,
+        }
+    }
+
+    Ok(())
+}
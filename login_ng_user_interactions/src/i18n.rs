@@ -0,0 +1,156 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{collections::HashMap, path::Path, sync::OnceLock};
+
+/// Directory holding the message catalogs: one <locale>.ftl file per language
+pub const LOCALE_DIR: &str = "/usr/share/login_ng/locale";
+
+/// Messages of a Fluent catalog: only the simple `id = value` subset is supported,
+/// with `{ $name }` placeables and values continued on indented lines.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Catalog {
+    messages: HashMap<String, String>,
+}
+
+impl Catalog {
+    pub fn parse(content: &str) -> Self {
+        let mut messages = HashMap::new();
+        let mut current: Option<(String, String)> = None;
+
+        for line in content.lines() {
+            let is_continuation = line.starts_with([' ', '\t']) && !line.trim().is_empty();
+
+            if is_continuation {
+                if let Some((_, value)) = &mut current {
+                    if !value.is_empty() {
+                        value.push('\n');
+                    }
+                    value.push_str(line.trim());
+                }
+                continue;
+            }
+
+            if let Some((id, value)) = current.take() {
+                messages.insert(id, value);
+            }
+
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some((id, value)) = line.split_once('=') {
+                // trailing spaces are kept as prompts usually end with one
+                current = Some((id.trim().to_string(), value.trim_start().to_string()));
+            }
+        }
+
+        if let Some((id, value)) = current.take() {
+            messages.insert(id, value);
+        }
+
+        Self { messages }
+    }
+
+    /// Load the catalog that best matches the given locale from the given directory
+    pub fn load_from(dir: &Path, locale: &str) -> Option<Self> {
+        locale_candidates(locale)
+            .iter()
+            .find_map(|candidate| {
+                std::fs::read_to_string(dir.join(format!("{candidate}.ftl"))).ok()
+            })
+            .map(|content| Self::parse(content.as_str()))
+    }
+
+    pub fn get(&self, id: &str) -> Option<&String> {
+        self.messages.get(id)
+    }
+
+    /// Translate the message with the given id, falling back to the given (english) text,
+    /// and replace the placeables with the given arguments
+    pub fn format(&self, id: &str, default: &str, args: &[(&str, String)]) -> String {
+        let mut message = self
+            .get(id)
+            .cloned()
+            .unwrap_or_else(|| String::from(default));
+
+        for (name, value) in args.iter() {
+            message = message
+                .replace(format!("{{ ${name} }}").as_str(), value)
+                .replace(format!("{{${name}}}").as_str(), value);
+        }
+
+        message
+    }
+}
+
+/// Detect the locale for messages from the environment, following the gettext precedence
+pub fn detect_locale() -> Option<String> {
+    ["LANGUAGE", "LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .filter_map(|value| value.split(':').next().map(String::from))
+        .find(|value| !value.is_empty() && value != "C" && value != "POSIX")
+}
+
+/// Catalog names to try for a locale, from the most to the least specific:
+/// it_IT.UTF-8@euro gives it_IT, it-IT and it
+pub fn locale_candidates(locale: &str) -> Vec<String> {
+    let locale = locale.split(['.', '@']).next().unwrap_or_default();
+
+    let mut candidates = vec![];
+    if locale.is_empty() {
+        return candidates;
+    }
+
+    candidates.push(locale.to_string());
+    if locale.contains('_') {
+        candidates.push(locale.replace('_', "-"));
+    }
+
+    if let Some(language) = locale.split(['_', '-']).next() {
+        if language != locale {
+            candidates.push(language.to_string());
+        }
+    }
+
+    candidates
+}
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+/// The catalog for the locale of the environment, empty if none is installed
+pub fn catalog() -> &'static Catalog {
+    CATALOG.get_or_init(|| {
+        detect_locale()
+            .and_then(|locale| Catalog::load_from(Path::new(LOCALE_DIR), locale.as_str()))
+            .unwrap_or_default()
+    })
+}
+
+/// Translate a message
+pub fn tr(id: &str, default: &str) -> String {
+    catalog().format(id, default, &[])
+}
+
+/// Translate a message replacing its placeables
+pub fn tr_args(id: &str, default: &str, args: &[(&str, String)]) -> String {
+    catalog().format(id, default, args)
+}
@@ -0,0 +1,153 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+static INTERRUPT_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+/// Process (and process group) id of the running session, 0 if there is none
+static SESSION_LEADER: AtomicI32 = AtomicI32::new(0);
+
+fn signal_session(signal: libc::c_int) {
+    let leader = SESSION_LEADER.load(Ordering::SeqCst);
+    if leader > 0 {
+        unsafe { libc::kill(-leader, signal) };
+    }
+}
+
+extern "C" fn on_interrupt(signal: libc::c_int) {
+    // only async-signal-safe operations here: the interrupted prompt does the cleanup
+    INTERRUPT_SIGNAL.store(signal, Ordering::SeqCst);
+    INTERRUPTED.store(true, Ordering::SeqCst);
+
+    // as login(1) does a terminated login also hangs up the session
+    if signal == libc::SIGTERM {
+        signal_session(libc::SIGTERM);
+        signal_session(libc::SIGHUP);
+    }
+}
+
+extern "C" fn on_hangup(signal: libc::c_int) {
+    signal_session(signal);
+}
+
+/// Turns SIGINT and SIGTERM into a request to abort the login:
+/// a pending prompt fails with `ErrorKind::Interrupted` instead of the process
+/// being killed while the terminal has echo disabled.
+pub fn install_interrupt_handlers() -> std::io::Result<()> {
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        let mut action = unsafe { std::mem::zeroed::<libc::sigaction>() };
+        action.sa_sigaction = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        // no SA_RESTART: blocking reads on the TTY have to return EINTR
+        action.sa_flags = 0;
+        unsafe { libc::sigemptyset(&mut action.sa_mask) };
+
+        if unsafe { libc::sigaction(signal, &action, std::ptr::null_mut()) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// Make the calling process the leader of a new session and, on a TTY login, take the terminal
+/// on standard input as its controlling terminal so that job control works as in a login shell.
+/// NOTE: meant for pre_exec: it must run before privileges are dropped
+pub fn become_session_leader() -> std::io::Result<()> {
+    if unsafe { libc::setsid() } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    if unsafe { libc::isatty(libc::STDIN_FILENO) } == 1 {
+        // the terminal is still the controlling one of login-ng: steal it
+        if unsafe { libc::ioctl(libc::STDIN_FILENO, libc::TIOCSCTTY, 1) } == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// While the session runs SIGHUP is forwarded to its process group (as login(1) does)
+/// instead of terminating login-ng, that has to outlive the session to close it.
+pub fn forward_signals_to_session(leader: libc::pid_t) -> std::io::Result<()> {
+    SESSION_LEADER.store(leader, Ordering::SeqCst);
+
+    let mut action = unsafe { std::mem::zeroed::<libc::sigaction>() };
+    action.sa_sigaction = on_hangup as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    action.sa_flags = libc::SA_RESTART;
+    unsafe { libc::sigemptyset(&mut action.sa_mask) };
+
+    match unsafe { libc::sigaction(libc::SIGHUP, &action, std::ptr::null_mut()) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// The session has ended: hang up the processes left in its process group,
+/// as closing the terminal would, and stop forwarding signals.
+pub fn hang_up_session() {
+    signal_session(libc::SIGHUP);
+
+    SESSION_LEADER.store(0, Ordering::SeqCst);
+
+    unsafe { libc::signal(libc::SIGHUP, libc::SIG_DFL) };
+}
+
+/// Check if the login has been interrupted by a signal
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// The signal that has interrupted the login, if any
+pub fn interrupt_signal() -> Option<libc::c_int> {
+    match INTERRUPT_SIGNAL.load(Ordering::SeqCst) {
+        0 => None,
+        signal => Some(signal),
+    }
+}
+
+/// Terminal settings saved at startup, to be restored when the login is aborted
+pub struct TerminalState {
+    fd: libc::c_int,
+    termios: libc::termios,
+}
+
+impl TerminalState {
+    /// Save the settings of the terminal on standard input, if it is one
+    pub fn save() -> Option<Self> {
+        let fd = libc::STDIN_FILENO;
+
+        let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+        match unsafe { libc::tcgetattr(fd, &mut termios) } {
+            0 => Some(Self { fd, termios }),
+            _ => None,
+        }
+    }
+
+    /// Discard pending input and restore the saved settings (echo included)
+    pub fn restore(&self) {
+        unsafe {
+            libc::tcflush(self.fd, libc::TCIFLUSH);
+            libc::tcsetattr(self.fd, libc::TCSANOW, &self.termios);
+        }
+    }
+}
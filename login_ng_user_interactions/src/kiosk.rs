@@ -0,0 +1,134 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::path::Path;
+use std::time::Duration;
+
+use configparser::ini::Ini;
+
+use login_ng::command::SessionCommand;
+
+use crate::login::config_dir;
+
+/// Configuration file of the kiosk mode, in the login_ng configuration directory
+pub const KIOSK_CONFIG_FILE: &str = "kiosk.conf";
+
+/// Session manager used to keep the kiosk application running
+pub const KIOSK_SESSION_MANAGER: &str = "login_ng-session";
+
+/// A designated account logged in without prompting, that runs a single
+/// application restarted by the session manager every time it exits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KioskConfig {
+    enabled: bool,
+    user: String,
+    command: SessionCommand,
+    restart_delay: Duration,
+}
+
+impl KioskConfig {
+    pub fn new(user: String, command: SessionCommand) -> Self {
+        Self {
+            enabled: true,
+            user,
+            command,
+            restart_delay: Duration::from_secs(2),
+        }
+    }
+
+    pub fn with_restart_delay(mut self, restart_delay: Duration) -> Self {
+        self.restart_delay = restart_delay;
+        self
+    }
+
+    /// Parse the Kiosk section of a kiosk.conf file: user and cmd are mandatory,
+    /// args are whitespace-separated
+    pub fn parse(content: String) -> Option<Self> {
+        let mut config = Ini::new();
+        config.read(content).ok()?;
+
+        let user = config
+            .get("Kiosk", "user")
+            .filter(|user| !user.is_empty())?;
+        let cmd = config.get("Kiosk", "cmd").filter(|cmd| !cmd.is_empty())?;
+
+        let args = config
+            .get("Kiosk", "args")
+            .map(|args| args.split_whitespace().map(String::from).collect())
+            .unwrap_or_default();
+
+        let mut kiosk = Self::new(user, SessionCommand::new(cmd).with_args(args));
+
+        kiosk.enabled = config
+            .getbool("Kiosk", "enabled")
+            .ok()
+            .flatten()
+            .unwrap_or(false);
+
+        if let Ok(Some(secs)) = config.getuint("Kiosk", "restart_delay_secs") {
+            kiosk.restart_delay = Duration::from_secs(secs);
+        }
+
+        Some(kiosk)
+    }
+
+    pub fn load_from(path: &Path) -> Option<Self> {
+        Self::parse(std::fs::read_to_string(path).ok()?)
+    }
+
+    /// Load kiosk.conf from the login_ng configuration directory
+    pub fn load() -> Option<Self> {
+        Self::load_from(&Path::new(config_dir()).join(KIOSK_CONFIG_FILE))
+    }
+
+    /// Whether the configuration asks for the kiosk mode even without --kiosk
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn user(&self) -> &String {
+        &self.user
+    }
+
+    pub fn command(&self) -> &SessionCommand {
+        &self.command
+    }
+
+    pub fn restart_delay(&self) -> Duration {
+        self.restart_delay
+    }
+
+    /// The session to start for the kiosk account: the session manager
+    /// running the kiosk application and restarting it forever.
+    pub fn session_command(&self) -> SessionCommand {
+        let mut args = vec![
+            String::from("--kiosk"),
+            self.command.command(),
+            String::from("--kiosk-restart-delay"),
+            self.restart_delay.as_secs().to_string(),
+        ];
+
+        for arg in self.command.args() {
+            args.push(String::from("--kiosk-arg"));
+            args.push(arg.clone());
+        }
+
+        SessionCommand::new(String::from(KIOSK_SESSION_MANAGER)).with_args(args)
+    }
+}
@@ -17,9 +17,22 @@
     51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 */
 
+pub mod accounting;
+#[cfg(feature = "approval")]
+pub mod approval;
+pub mod askpass;
 pub mod cli;
 pub mod conversation;
+pub mod exec_context;
+pub mod generate;
+pub mod greeter;
+pub mod i18n;
+pub mod interrupt;
+pub mod kiosk;
 pub mod login;
+pub mod plymouth;
+pub mod theme;
+pub mod vt;
 
 #[cfg(feature = "pam")]
 pub mod pam;
@@ -27,6 +40,12 @@ pub mod pam;
 #[cfg(feature = "greetd")]
 pub mod greetd;
 
+#[cfg(feature = "mock")]
+pub mod mock;
+
+#[cfg(test)]
+pub(crate) mod tests;
+
 pub use rpassword::prompt_password;
 
 #[cfg(feature = "pam")]
@@ -34,10 +53,18 @@ pub extern crate pam_client2;
 
 pub const DEFAULT_CMD: &str = "/bin/sh";
 
-/// Reads a password from the TTY
-fn read_plain(stream: std::fs::File) -> std::io::Result<String> {
+/// ANSI sequence that clears the terminal and moves the cursor to the top-left corner
+pub const CLEAR_SCREEN: &str = "\x1b[2J\x1b[1;1H";
+
+/// Reads a line from the TTY, giving up if the user does not complete it before the timeout expires
+fn read_plain(
+    stream: std::fs::File,
+    timeout: Option<std::time::Duration>,
+) -> std::io::Result<String> {
     use std::io::BufRead;
 
+    wait_for_line(&stream, timeout)?;
+
     let mut reader = std::io::BufReader::new(stream);
 
     let mut answer = String::new();
@@ -46,6 +73,55 @@ fn read_plain(stream: std::fs::File) -> std::io::Result<String> {
     fix_line_issues(answer)
 }
 
+/// Waits until a whole line (a single byte in non-canonical mode) is available on the TTY:
+/// on timeout or interruption (see [`interrupt::install_interrupt_handlers`]) it is discarded.
+fn wait_for_line(
+    stream: &std::fs::File,
+    timeout: Option<std::time::Duration>,
+) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let mut pollfd = libc::pollfd {
+        fd: stream.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+
+    let timeout_ms = match timeout {
+        Some(timeout) => timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int,
+        None => -1,
+    };
+
+    loop {
+        match unsafe { libc::poll(&mut pollfd, 1, timeout_ms) } {
+            -1 => {
+                let err = std::io::Error::last_os_error();
+                if err.kind() != std::io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+
+                if interrupt::interrupted() {
+                    unsafe { libc::tcflush(stream.as_raw_fd(), libc::TCIFLUSH) };
+
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::Interrupted,
+                        "interrupted by a signal",
+                    ));
+                }
+            }
+            0 => {
+                unsafe { libc::tcflush(stream.as_raw_fd(), libc::TCIFLUSH) };
+
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    "no answer was provided in time",
+                ));
+            }
+            _ => return Ok(()),
+        }
+    }
+}
+
 /// Normalizes the return of `read_line()` in the context of a CLI application
 fn fix_line_issues(mut line: String) -> std::io::Result<String> {
     if !line.ends_with('\n') {
@@ -75,16 +151,316 @@ fn fix_line_issues(mut line: String) -> std::io::Result<String> {
 }
 
 pub fn prompt_plain(prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+    prompt_plain_with_timeout(prompt, None)
+}
+
+/// How what the user types is shown back while answering a prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Echo {
+    /// As typed, for visible answers
+    Visible,
+
+    /// Not at all, for secrets
+    Hidden,
+
+    /// As one masking character per typed character, for secrets
+    Masked(char),
+}
+
+/// Progress through an escape sequence sent by the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Escape {
+    None,
+    Started,
+    Sequence,
+}
+
+/// Reads a secret from the (non-canonical mode) TTY one byte at a time, writing the mask
+/// to the output for every character typed and erasing it on backspace (or on Ctrl-U
+/// for the whole line), giving up if the line is not completed before the timeout expires.
+fn read_masked(
+    stream: std::fs::File,
+    output: &mut dyn std::io::Write,
+    mask: char,
+    timeout: Option<std::time::Duration>,
+) -> std::io::Result<String> {
+    use std::io::Read;
+
+    let deadline = timeout.map(|timeout| std::time::Instant::now() + timeout);
+
+    let mut stream = stream;
+    // reserved up-front so that growing it does not leave copies of the secret around
+    let mut answer: Vec<u8> = Vec::with_capacity(1024);
+    let mut escape = Escape::None;
+    let mut mask_bytes = [0u8; 4];
+    let mask = mask.encode_utf8(&mut mask_bytes).as_bytes();
+
+    let result = loop {
+        let remaining =
+            deadline.map(|deadline| deadline.saturating_duration_since(std::time::Instant::now()));
+
+        if let Err(err) = wait_for_line(&stream, remaining) {
+            break Err(err);
+        }
+
+        let mut byte = [0u8; 1];
+        match stream.read(&mut byte) {
+            Ok(0) => {
+                break Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "unexpected end of file",
+                ))
+            }
+            Ok(_) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => break Err(err),
+        }
+
+        // keys such as arrows send escape sequences, that are not part of secrets
+        let escaped = escape != Escape::None || byte[0] == 0x1b;
+        escape = match (escape, byte[0]) {
+            (Escape::None, 0x1b) => Escape::Started,
+            (Escape::Started, b'[' | b'O') => Escape::Sequence,
+            (Escape::Sequence, 0x20..=0x3f) => Escape::Sequence,
+            _ => Escape::None,
+        };
+        if escaped {
+            continue;
+        }
+
+        let erased = match byte[0] {
+            b'\n' | b'\r' => break Ok(()),
+            // Ctrl-D on an empty line, just like a canonical mode read
+            0x04 if answer.is_empty() => {
+                break Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "unexpected end of file",
+                ))
+            }
+            // backspace and delete remove the last character, with all of its UTF-8 bytes
+            0x08 | 0x7f => match answer.iter().rposition(|byte| byte & 0xc0 != 0x80) {
+                Some(start) => {
+                    login_ng::secure::wipe(&mut answer[start..]);
+                    answer.truncate(start);
+                    1
+                }
+                None => 0,
+            },
+            // Ctrl-U removes the whole line
+            0x15 => {
+                let typed = answer.iter().filter(|byte| *byte & 0xc0 != 0x80).count();
+                login_ng::secure::wipe(answer.as_mut_slice());
+                answer.clear();
+                typed
+            }
+            // other control characters (such as those of escape sequences) are not part of secrets
+            byte if byte < 0x20 => 0,
+            byte => {
+                answer.push(byte);
+
+                // a masking character for the first byte of each UTF-8 sequence
+                if byte & 0xc0 != 0x80 {
+                    if let Err(err) = output.write_all(mask).and_then(|_| output.flush()) {
+                        break Err(err);
+                    }
+                }
+
+                0
+            }
+        };
+
+        if erased > 0 {
+            let _ = output
+                .write_all(b"\x08 \x08".repeat(erased).as_slice())
+                .and_then(|_| output.flush());
+        }
+    };
+
+    if let Err(err) = result {
+        login_ng::secure::wipe(answer.as_mut_slice());
+        return Err(err);
+    }
+
+    String::from_utf8(answer).map_err(|err| {
+        login_ng::secure::wipe(err.into_bytes().as_mut_slice());
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "the answer is not valid UTF-8",
+        )
+    })
+}
+
+/// Writes the prompt to the given output (the TTY if none) and reads the answer from the TTY,
+/// with echo disabled or masked if the answer is secret, giving up after the timeout.
+fn prompt_tty(
+    prompt: &str,
+    output: Option<&mut dyn std::io::Write>,
+    echo: Echo,
+    timeout: Option<std::time::Duration>,
+) -> std::io::Result<String> {
     use std::io::Write;
+    use std::os::fd::AsRawFd;
 
-    let mut stream = std::fs::OpenOptions::new()
+    let stream = std::fs::OpenOptions::new()
         .write(true)
         .read(true)
         .open("/dev/tty")?;
 
-    Ok(stream
+    let fd = stream.as_raw_fd();
+
+    let secret = echo != Echo::Visible;
+
+    let mut original = unsafe { std::mem::zeroed::<libc::termios>() };
+    if secret {
+        if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let mut hidden = original;
+        hidden.c_lflag &= !(libc::ECHO | libc::ECHONL);
+        match echo {
+            // the masking characters are written as each byte arrives
+            Echo::Masked(_) => {
+                hidden.c_lflag &= !libc::ICANON;
+                hidden.c_cc[libc::VMIN] = 1;
+                hidden.c_cc[libc::VTIME] = 0;
+            }
+            _ => hidden.c_lflag |= libc::ICANON,
+        }
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &hidden) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    let mut tty_output = stream.try_clone()?;
+    let output: &mut dyn Write = match output {
+        Some(output) => output,
+        None => &mut tty_output,
+    };
+
+    let result = output
         .write_all(prompt.to_string().as_bytes())
-        .and_then(|_| stream.flush())
-        .and_then(|_| read_plain(stream))
-        .map_err(Box::new)?)
+        .and_then(|_| output.flush())
+        .and_then(|_| match echo {
+            Echo::Masked(mask) => read_masked(stream, &mut *output, mask, timeout),
+            _ => read_plain(stream, timeout),
+        });
+
+    if secret {
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &original) };
+
+        // echo is off: move past the line the user has (not) typed
+        if result.is_ok() {
+            let _ = output.write_all(b"\n").and_then(|_| output.flush());
+        }
+    }
+
+    result
+}
+
+/// Resolves an unanswered prompt: the default answer is used if there is one,
+/// otherwise the screen is cleared and the timeout error returned.
+fn resolve_timeout(
+    result: std::io::Result<String>,
+    output: &mut dyn std::io::Write,
+    default: Option<&str>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    match result {
+        Err(err) if err.kind() == std::io::ErrorKind::TimedOut => match default {
+            Some(default) => {
+                let _ = output.write_all(b"\n").and_then(|_| output.flush());
+                Ok(String::from(default))
+            }
+            None => {
+                let _ = output
+                    .write_all(CLEAR_SCREEN.as_bytes())
+                    .and_then(|_| output.flush());
+                Err(Box::new(err))
+            }
+        },
+        result => Ok(result.map_err(Box::new)?),
+    }
+}
+
+/// Prompts for a visible answer on the TTY, aborting with an `ErrorKind::TimedOut`
+/// error if the user does not complete the line before the timeout expires.
+pub fn prompt_plain_with_timeout(
+    prompt: &str,
+    timeout: Option<std::time::Duration>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let result = prompt_tty(prompt, None, Echo::Visible, timeout);
+
+    let mut tty = std::fs::OpenOptions::new().write(true).open("/dev/tty")?;
+    resolve_timeout(result, &mut tty, None)
+}
+
+/// Prompts for a secret on the TTY with echo disabled, aborting with an `ErrorKind::TimedOut`
+/// error if the user does not complete the line before the timeout expires.
+pub fn prompt_password_with_timeout(
+    prompt: &str,
+    timeout: Option<std::time::Duration>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let result = prompt_tty(prompt, None, Echo::Hidden, timeout);
+
+    let mut tty = std::fs::OpenOptions::new().write(true).open("/dev/tty")?;
+    resolve_timeout(result, &mut tty, None)
+}
+
+/// Prompts for a secret on the TTY showing the mask for every character typed, aborting with an
+/// `ErrorKind::TimedOut` error if the user does not complete the line before the timeout expires.
+pub fn prompt_masked_with_timeout(
+    prompt: &str,
+    mask: char,
+    timeout: Option<std::time::Duration>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let result = prompt_tty(prompt, None, Echo::Masked(mask), timeout);
+
+    let mut tty = std::fs::OpenOptions::new().write(true).open("/dev/tty")?;
+    resolve_timeout(result, &mut tty, None)
+}
+
+/// Prompts on stderr for a visible answer read from the TTY: if the line is not completed
+/// before the timeout expires the default answer is returned, or an `ErrorKind::TimedOut`
+/// error if there is none.
+pub fn prompt_stderr_with_timeout(
+    prompt: &str,
+    timeout: Option<std::time::Duration>,
+    default: Option<&str>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut stderr = std::io::stderr();
+
+    let result = prompt_tty(prompt, Some(&mut stderr), Echo::Visible, timeout);
+
+    resolve_timeout(result, &mut stderr, default)
+}
+
+/// Prompts on stderr for a secret read from the TTY with echo disabled: if the line is not
+/// completed before the timeout expires the default answer is returned, or an
+/// `ErrorKind::TimedOut` error if there is none.
+pub fn prompt_secret_stderr_with_timeout(
+    prompt: &str,
+    timeout: Option<std::time::Duration>,
+    default: Option<&str>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut stderr = std::io::stderr();
+
+    let result = prompt_tty(prompt, Some(&mut stderr), Echo::Hidden, timeout);
+
+    resolve_timeout(result, &mut stderr, default)
+}
+
+/// Prompts on stderr for a secret read from the TTY showing the mask for every character typed:
+/// if the line is not completed before the timeout expires the default answer is returned,
+/// or an `ErrorKind::TimedOut` error if there is none.
+pub fn prompt_masked_stderr_with_timeout(
+    prompt: &str,
+    mask: char,
+    timeout: Option<std::time::Duration>,
+    default: Option<&str>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut stderr = std::io::stderr();
+
+    let result = prompt_tty(prompt, Some(&mut stderr), Echo::Masked(mask), timeout);
+
+    resolve_timeout(result, &mut stderr, default)
 }
@@ -17,21 +17,49 @@
     51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 */
 
-use std::path::{Path, PathBuf};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use configparser::ini::Ini;
 
 use login_ng::users::os::unix::UserExt;
 use thiserror::Error;
 
+use crate::i18n::{tr, tr_args};
+
 use login_ng::{
-    command::SessionCommand,
-    storage::{load_user_session_command, StorageSource},
+    command::{PreferredSession, SessionCommand},
+    desktop::find_session,
+    environment::{load_environment_d, USER_ENVIRONMENT_D_DIR},
+    faillock::{lock_status, read_tally, FaillockConfig, LockStatus},
+    guest::GuestConfig,
+    home::HomeConfig,
+    motd::Motd,
+    storage::{load_user_auth_data, load_user_session_command, StorageSource},
 };
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+pub mod facade;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum LoginResult {
-    Success,
+    /// The user has been authenticated and the session has been started
+    Success {
+        username: String,
+        uid: u32,
+        home: PathBuf,
+        command: SessionCommand,
+        env: BTreeMap<String, String>,
+    },
+    /// The user has been authenticated and the account validated, but as requested
+    /// (see the dry run of the executors) no session has been opened
+    Authenticated {
+        username: String,
+        uid: u32,
+        password_expired: bool,
+    },
     Failure,
 }
 
@@ -50,6 +78,50 @@ pub enum LoginError {
 
     #[error("No login backend available")]
     NoLoginSupport,
+
+    #[error("Login interrupted")]
+    Interrupted,
+
+    #[error("Authentication failed")]
+    AuthenticationFailed,
+
+    #[error("The account has expired")]
+    AccountExpired,
+
+    #[error("Permission denied")]
+    PermissionDenied,
+
+    #[error("Maximum number of authentication attempts reached")]
+    MaxTries,
+
+    #[error("Unable to retrieve the user credentials")]
+    CredentialsUnavailable,
+}
+
+impl LoginError {
+    /// Explains the failure to the user, telling a wrong password apart from an unusable account
+    pub fn user_message(&self) -> String {
+        match self {
+            Self::AuthenticationFailed => tr("login-auth-failed", "Wrong username or password."),
+            Self::AccountExpired => tr(
+                "login-account-expired",
+                "The account has expired: contact the system administrator.",
+            ),
+            Self::PermissionDenied => tr(
+                "login-permission-denied",
+                "The account is not allowed to log in here.",
+            ),
+            Self::MaxTries => tr(
+                "login-max-tries",
+                "Too many failed attempts: the account has been locked.",
+            ),
+            Self::CredentialsUnavailable => tr(
+                "login-credentials-unavailable",
+                "The credentials of the account are not available.",
+            ),
+            _ => self.to_string(),
+        }
+    }
 }
 
 pub trait LoginUserInteractionHandler {
@@ -62,6 +134,24 @@ pub trait LoginUserInteractionHandler {
     fn print_info(&mut self, msg: &String);
 
     fn print_error(&mut self, msg: &String);
+
+    /// Ask a yes/no question (PAM_RADIO_TYPE): by default the answer is read as plain text
+    fn prompt_radio(&mut self, msg: &String) -> Option<bool> {
+        self.prompt_plain(msg)
+            .map(|answer| crate::conversation::is_affirmative(&answer))
+    }
+
+    /// Exchange binary data with the PAM module (PAM_BINARY_PROMPT): returning None
+    /// replies with empty data, telling the module the client does not support it
+    fn prompt_binary(&mut self, _data_type: u8, _data: &[u8]) -> Option<(u8, Vec<u8>)> {
+        None
+    }
+
+    /// The user has been authenticated and the session is about to be started
+    fn session_starting(&mut self) {}
+
+    /// The session started by this process has exited
+    fn session_ended(&mut self) {}
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -69,6 +159,9 @@ pub enum SessionCommandRetrival {
     Defined(SessionCommand),
     AutodedectFromPath(PathBuf),
     AutodetectFromUserHome,
+    /// Use the preferred session stored in the login-ng user configuration,
+    /// autodetecting it from the user home when none has been set
+    FromUserConfig,
 }
 
 /// Interface that allows a user to authenticate and perform actions
@@ -93,10 +186,7 @@ pub(crate) fn load_session_from_conf(content: String) -> SessionCommand {
 }
 
 pub(crate) fn system_defined_with_crate_fallback() -> SessionCommand {
-    let dir_path_str = match std::fs::exists("/usr/lib/login_ng/").unwrap_or(false) {
-        true => "/usr/lib/login_ng/",
-        false => "/etc/login_ng/",
-    };
+    let dir_path_str = config_dir();
 
     match std::fs::read_to_string(Path::new(dir_path_str).join("default_session.conf")) {
         Ok(content) => load_session_from_conf(content),
@@ -104,11 +194,96 @@ pub(crate) fn system_defined_with_crate_fallback() -> SessionCommand {
     }
 }
 
-pub(crate) fn user_default_command_with_system_fallback(username: &String) -> SessionCommand {
-    let dir_path_str = match std::fs::exists("/usr/lib/login_ng/").unwrap_or(false) {
+pub(crate) fn config_dir() -> &'static str {
+    match std::fs::exists("/usr/lib/login_ng/").unwrap_or(false) {
         true => "/usr/lib/login_ng/",
         false => "/etc/login_ng/",
-    };
+    }
+}
+
+/// The message of the day to show the user before the session starts:
+/// None if the user has a .hushlogin file or there is nothing to show
+pub fn motd_for_user(username: &str) -> Option<String> {
+    if let Some(user) = login_ng::users::get_user_by_name(username) {
+        if Motd::hushed(user.home_dir()) {
+            return None;
+        }
+    }
+
+    let motd = Motd::default().render();
+    let motd = motd.trim_end();
+
+    match motd.is_empty() {
+        true => None,
+        false => Some(String::from(motd)),
+    }
+}
+
+/// Why the account has been locked by too many failed attempts, telling when to retry:
+/// None if the user can log in. Recovery tokens do not bypass the lockout, as issuing
+/// one resets the tally instead
+pub fn lockout_message(config: &FaillockConfig, username: &str) -> Option<String> {
+    let records = read_tally(config, username).unwrap_or_default();
+
+    let is_root = login_ng::users::get_user_by_name(username)
+        .map(|user| user.uid() == 0)
+        .unwrap_or(false);
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    match lock_status(config, &records, is_root, now) {
+        LockStatus::Unlocked => None,
+        LockStatus::Locked { until } => Some(
+            match until.and_then(|until| until.duration_since(SystemTime::now()).ok()) {
+                Some(remaining) => tr_args(
+                    "locked-retry",
+                    "retry in { $seconds } seconds",
+                    &[("seconds", (remaining.as_secs() + 1).to_string())],
+                ),
+                None => tr("locked-contact-admin", "contact the system administrator"),
+            },
+        ),
+    }
+}
+
+/// Guest sessions configuration (guest.conf), shared with pam_login_ng-service
+pub fn guest_config() -> GuestConfig {
+    GuestConfig::load(Path::new(config_dir()))
+}
+
+/// Variables the user set in the environment.d(5) files of the home directory
+pub fn user_environment_d(username: &str) -> BTreeMap<String, String> {
+    match login_ng::users::get_user_by_name(username) {
+        Some(user) => load_environment_d(&user.home_dir().join(USER_ENVIRONMENT_D_DIR), user.uid()),
+        None => BTreeMap::new(),
+    }
+}
+
+/// Creation of missing home directories (home.conf)
+pub fn home_config() -> HomeConfig {
+    HomeConfig::load(Path::new(config_dir()))
+}
+
+/// Read the installed desktop session configured as the default one
+/// (the desktop key of the Session section in default_session.conf),
+/// matched against either the desktop file name or its DesktopNames.
+pub(crate) fn configured_desktop_session() -> Option<SessionCommand> {
+    let content =
+        std::fs::read_to_string(Path::new(config_dir()).join("default_session.conf")).ok()?;
+
+    let mut config = Ini::new();
+    config.read(content).ok()?;
+
+    let desktop = config.get("Session", "desktop")?;
+
+    find_session(desktop.as_str()).map(|session| session.session_command())
+}
+
+pub(crate) fn user_default_command_with_system_fallback(username: &String) -> SessionCommand {
+    let dir_path_str = config_dir();
 
     match login_ng::users::get_user_by_name(username) {
         Some(logged_user) => match logged_user.shell().to_str() {
@@ -127,6 +302,20 @@ pub(crate) fn user_default_command_with_system_fallback(username: &String) -> Se
     }
 }
 
+/// Resolve the preferred session stored in the user configuration:
+/// a desktop session that is no longer installed is ignored.
+pub(crate) fn preferred_session_for_user(username: &String) -> Option<SessionCommand> {
+    let user_cfg = load_user_auth_data(&StorageSource::Username(username.clone())).ok()??;
+
+    match user_cfg.preferred_session() {
+        Some(PreferredSession::Command(session_cmd)) => Some(session_cmd.clone()),
+        Some(PreferredSession::Desktop(desktop)) => {
+            find_session(desktop.as_str()).map(|session| session.session_command())
+        }
+        None => None,
+    }
+}
+
 pub(crate) fn retrieve_session_command_for_user(
     username: &String,
     retrival_strategy: &SessionCommandRetrival,
@@ -135,13 +324,20 @@ pub(crate) fn retrieve_session_command_for_user(
         SessionCommandRetrival::Defined(cmd) => return cmd.clone(),
         SessionCommandRetrival::AutodedectFromPath(path) => StorageSource::Path(path.clone()),
         SessionCommandRetrival::AutodetectFromUserHome => StorageSource::Username(username.clone()),
+        SessionCommandRetrival::FromUserConfig => {
+            if let Some(session_cmd) = preferred_session_for_user(username) {
+                return session_cmd;
+            }
+
+            StorageSource::Username(username.clone())
+        }
     };
 
     match load_user_session_command(&storage_source) {
-        Ok(maybe_command) => match maybe_command {
+        Ok(Some(session_cmd)) => session_cmd,
+        Ok(None) | Err(_) => match configured_desktop_session() {
             Some(session_cmd) => session_cmd,
             None => user_default_command_with_system_fallback(username),
         },
-        Err(_err) => user_default_command_with_system_fallback(username),
     }
 }
@@ -0,0 +1,349 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use login_ng::command::SessionCommand;
+use thiserror::Error;
+
+use super::{
+    guest_config, retrieve_session_command_for_user, LoginError, LoginResult,
+    LoginUserInteractionHandler, SessionCommandRetrival,
+};
+
+#[derive(Debug, Error)]
+pub enum LoginSessionError {
+    #[error("The login has already been started")]
+    AlreadyStarted,
+
+    #[error("The login has not been started")]
+    NotStarted,
+
+    #[error("The login has already terminated")]
+    Terminated,
+
+    #[error("The login thread panicked")]
+    Panicked,
+
+    #[error("Error starting the login thread: {0}")]
+    Spawn(#[from] std::io::Error),
+
+    #[error("Guest sessions are not enabled")]
+    GuestDisabled,
+}
+
+/// The backend used to authenticate the user and start the session
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoginBackend {
+    /// Authenticate with PAM and run the session as a child process
+    #[cfg(feature = "pam")]
+    Pam { allow_autologin: bool },
+
+    /// Authenticate through the greetd socket at the given path
+    #[cfg(feature = "greetd")]
+    Greetd(String),
+
+    /// Run the PAM executor against in-process accounts, stopping once the account has been
+    /// validated as in a dry run: for tests, see [`crate::mock::mock_pam_executor`]
+    #[cfg(feature = "mock")]
+    Mock(crate::mock::MockAccounts),
+}
+
+/// Something the backend wants to show to, or ask, the user
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoginPrompt {
+    /// A question whose answer must not be echoed: answer with [`LoginSession::answer`]
+    Secret(String),
+
+    /// A question whose answer can be echoed: answer with [`LoginSession::answer`]
+    Plain(String),
+
+    /// An informational message, no answer expected
+    Info(String),
+
+    /// An error message, no answer expected
+    Error(String),
+}
+
+impl LoginPrompt {
+    pub fn expects_answer(&self) -> bool {
+        matches!(self, LoginPrompt::Secret(_) | LoginPrompt::Plain(_))
+    }
+}
+
+struct ChannelInteractionHandler {
+    prompts: Sender<LoginPrompt>,
+    answers: Receiver<Option<String>>,
+    username: Arc<Mutex<Option<String>>>,
+}
+
+impl ChannelInteractionHandler {
+    fn ask(&mut self, prompt: LoginPrompt) -> Option<String> {
+        self.prompts.send(prompt).ok()?;
+
+        // a dropped LoginSession cancels the login
+        self.answers.recv().ok().flatten()
+    }
+}
+
+impl LoginUserInteractionHandler for ChannelInteractionHandler {
+    fn provide_username(&mut self, username: &String) {
+        if let Ok(mut guard) = self.username.lock() {
+            *guard = Some(username.clone());
+        }
+    }
+
+    fn prompt_secret(&mut self, msg: &String) -> Option<String> {
+        self.ask(LoginPrompt::Secret(msg.clone()))
+    }
+
+    fn prompt_plain(&mut self, msg: &String) -> Option<String> {
+        self.ask(LoginPrompt::Plain(msg.clone()))
+    }
+
+    fn print_info(&mut self, msg: &String) {
+        let _ = self.prompts.send(LoginPrompt::Info(msg.clone()));
+    }
+
+    fn print_error(&mut self, msg: &String) {
+        let _ = self.prompts.send(LoginPrompt::Error(msg.clone()));
+    }
+}
+
+/// High-level API for embedding login-ng in third-party greeters: a single login attempt
+/// running the chosen backend in a background thread, with the conversation turned into
+/// prompts to be polled and answered, so that a greeter does not need to stitch together
+/// conversation, proxy and executor types itself.
+pub struct LoginSession {
+    backend: LoginBackend,
+    maybe_username: Option<String>,
+    retrival_strategy: SessionCommandRetrival,
+    username: Arc<Mutex<Option<String>>>,
+    prompts: Option<Receiver<LoginPrompt>>,
+    answers: Option<Sender<Option<String>>>,
+    worker: Option<JoinHandle<Result<LoginResult, LoginError>>>,
+    result: Option<Result<LoginResult, LoginError>>,
+}
+
+impl LoginSession {
+    pub fn new(backend: LoginBackend) -> Self {
+        Self {
+            backend,
+            maybe_username: None,
+            retrival_strategy: SessionCommandRetrival::FromUserConfig,
+            username: Arc::new(Mutex::new(None)),
+            prompts: None,
+            answers: None,
+            worker: None,
+            result: None,
+        }
+    }
+
+    /// Feed the username up-front: if not given the backend will prompt for it
+    pub fn with_username(mut self, username: Option<String>) -> Self {
+        self.maybe_username = username;
+        self
+    }
+
+    /// Log in the guest account (the "guest session" button of a greeter):
+    /// fails if guest sessions are not enabled in guest.conf
+    pub fn with_guest(self) -> Result<Self, LoginSessionError> {
+        let guest = guest_config();
+
+        match guest.enabled() {
+            true => Ok(self.with_username(Some(guest.user().clone()))),
+            false => Err(LoginSessionError::GuestDisabled),
+        }
+    }
+
+    /// Choose how the session command is retrieved once the user is authenticated
+    pub fn with_session(mut self, retrival_strategy: SessionCommandRetrival) -> Self {
+        self.retrival_strategy = retrival_strategy;
+        self
+    }
+
+    /// Start the login: prompts become available through [`LoginSession::poll_prompt`]
+    pub fn start(&mut self) -> Result<(), LoginSessionError> {
+        if self.worker.is_some() || self.result.is_some() {
+            return Err(LoginSessionError::AlreadyStarted);
+        }
+
+        let (prompts_tx, prompts_rx) = channel();
+        let (answers_tx, answers_rx) = channel();
+
+        let backend = self.backend.clone();
+        let maybe_username = self.maybe_username.clone();
+        let retrival_strategy = self.retrival_strategy.clone();
+        let username = self.username.clone();
+
+        let worker = std::thread::Builder::new()
+            .name(String::from("login_ng-session"))
+            .spawn(move || {
+                let handler: Arc<Mutex<dyn LoginUserInteractionHandler>> =
+                    Arc::new(Mutex::new(ChannelInteractionHandler {
+                        prompts: prompts_tx,
+                        answers: answers_rx,
+                        username,
+                    }));
+
+                execute(backend, handler, &maybe_username, &retrival_strategy)
+            })?;
+
+        self.prompts = Some(prompts_rx);
+        self.answers = Some(answers_tx);
+        self.worker = Some(worker);
+
+        Ok(())
+    }
+
+    /// Return the next prompt, waiting at most the given time for one
+    /// (forever if no timeout is given): None when nothing is pending
+    /// or the login has terminated.
+    pub fn poll_prompt(&mut self, timeout: Option<Duration>) -> Option<LoginPrompt> {
+        let prompts = self.prompts.as_ref()?;
+
+        match timeout {
+            Some(timeout) if timeout.is_zero() => match prompts.try_recv() {
+                Ok(prompt) => Some(prompt),
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => None,
+            },
+            Some(timeout) => match prompts.recv_timeout(timeout) {
+                Ok(prompt) => Some(prompt),
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => None,
+            },
+            None => prompts.recv().ok(),
+        }
+    }
+
+    /// Answer the last [`LoginPrompt::Secret`] or [`LoginPrompt::Plain`] prompt:
+    /// None cancels the login.
+    pub fn answer(&mut self, answer: Option<String>) -> Result<(), LoginSessionError> {
+        let Some(answers) = self.answers.as_ref() else {
+            return Err(match self.result {
+                Some(_) => LoginSessionError::Terminated,
+                None => LoginSessionError::NotStarted,
+            });
+        };
+
+        answers
+            .send(answer)
+            .map_err(|_| LoginSessionError::Terminated)
+    }
+
+    /// Answer a prompt with the given secret
+    pub fn feed_secret(&mut self, secret: String) -> Result<(), LoginSessionError> {
+        self.answer(Some(secret))
+    }
+
+    /// Check whether the backend has terminated
+    pub fn is_finished(&self) -> bool {
+        match &self.worker {
+            Some(worker) => worker.is_finished(),
+            None => self.result.is_some(),
+        }
+    }
+
+    /// The result of the login if it has terminated, without blocking
+    pub fn result(&mut self) -> Option<&Result<LoginResult, LoginError>> {
+        if self
+            .worker
+            .as_ref()
+            .is_some_and(|worker| worker.is_finished())
+        {
+            let _ = self.wait();
+        }
+
+        self.result.as_ref()
+    }
+
+    /// Block until the backend terminates and return the result of the login:
+    /// questions asked from now on are answered with a cancellation.
+    pub fn wait(&mut self) -> Result<&Result<LoginResult, LoginError>, LoginSessionError> {
+        if let Some(worker) = self.worker.take() {
+            // unblock a backend waiting for an answer that will never come
+            self.answers = None;
+
+            let result = worker.join().map_err(|_| LoginSessionError::Panicked)?;
+            self.result = Some(result);
+        }
+
+        self.result.as_ref().ok_or(LoginSessionError::NotStarted)
+    }
+
+    /// The username being authenticated, once known to the backend
+    pub fn username(&self) -> Option<String> {
+        self.username
+            .lock()
+            .ok()
+            .and_then(|guard| guard.clone())
+            .or(self.maybe_username.clone())
+    }
+
+    /// The session command that is (or would be) started for the user
+    pub fn session_command(&self) -> Option<SessionCommand> {
+        self.username()
+            .map(|username| retrieve_session_command_for_user(&username, &self.retrival_strategy))
+    }
+}
+
+#[cfg_attr(
+    not(any(feature = "pam", feature = "greetd", feature = "mock")),
+    allow(unused_variables)
+)]
+fn execute(
+    backend: LoginBackend,
+    handler: Arc<Mutex<dyn LoginUserInteractionHandler>>,
+    maybe_username: &Option<String>,
+    retrival_strategy: &SessionCommandRetrival,
+) -> Result<LoginResult, LoginError> {
+    match backend {
+        #[cfg(feature = "pam")]
+        LoginBackend::Pam { allow_autologin } => {
+            use super::LoginExecutor;
+
+            let conversation =
+                crate::conversation::ProxyLoginUserInteractionHandlerConversation::new(handler);
+
+            crate::pam::PamLoginExecutor::new(conversation, allow_autologin)
+                .execute(maybe_username, retrival_strategy)
+        }
+        #[cfg(feature = "greetd")]
+        LoginBackend::Greetd(greetd_sock) => {
+            use super::LoginExecutor;
+
+            crate::greetd::GreetdLoginExecutor::new(greetd_sock, handler)
+                .execute(maybe_username, retrival_strategy)
+        }
+        #[cfg(feature = "mock")]
+        LoginBackend::Mock(accounts) => {
+            use super::LoginExecutor;
+
+            crate::mock::mock_pam_executor(accounts, handler)
+                .with_dry_run(true)
+                .execute(maybe_username, retrival_strategy)
+        }
+    }
+}
@@ -0,0 +1,518 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    collections::BTreeMap,
+    ffi::CString,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use login_ng::{faillock::FaillockConfig, secure::constant_time_eq};
+use pam_client2::{ConversationHandler, ErrorCode, Flag};
+
+use crate::{
+    conversation::ProxyLoginUserInteractionHandlerConversation,
+    login::{LoginError, LoginUserInteractionHandler},
+    pam::{PamFailure, PamLoginExecutor, PamSession, PamTransaction},
+};
+
+/// How the account management of the mock backends treats an account
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockAccountState {
+    /// The account can log in
+    Active,
+
+    /// Authenticates, but the account has expired (PAM_ACCT_EXPIRED)
+    Expired,
+
+    /// Authenticates, but is not allowed to log in (PAM_PERM_DENIED)
+    Denied,
+
+    /// The credentials cannot be retrieved (PAM_CRED_UNAVAIL)
+    Unavailable,
+
+    /// Authenticates, but the password must be changed (PAM_NEW_AUTHTOK_REQD)
+    PasswordExpired,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockAccount {
+    password: String,
+    uid: u32,
+    home: PathBuf,
+    state: MockAccountState,
+}
+
+impl MockAccount {
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    pub fn home(&self) -> &PathBuf {
+        &self.home
+    }
+
+    pub fn state(&self) -> MockAccountState {
+        self.state
+    }
+}
+
+/// The accounts the mock backends authenticate against, in place of the PAM stack.
+///
+/// NOTE: the PAM executor still resolves the authenticated user through NSS, so accounts
+/// given to [`mock_pam_executor`] must exist on the system (i.e. the user running the tests).
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockAccounts {
+    accounts: BTreeMap<String, MockAccount>,
+    faillock_config: FaillockConfig,
+}
+
+impl Default for MockAccounts {
+    fn default() -> Self {
+        // the tally of the real accounts must not be touched, nor lock them out
+        let dir = std::env::temp_dir().join("login_ng-mock-faillock");
+
+        Self {
+            accounts: BTreeMap::new(),
+            faillock_config: FaillockConfig::parse(&format!("deny = 0\ndir = {}", dir.display())),
+        }
+    }
+}
+
+impl MockAccounts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep the faillock tally of the PAM executor with the given settings: by default
+    /// it is kept in a temporary directory and never locks an account out
+    pub fn with_faillock_config(mut self, faillock_config: FaillockConfig) -> Self {
+        self.faillock_config = faillock_config;
+        self
+    }
+
+    pub fn faillock_config(&self) -> &FaillockConfig {
+        &self.faillock_config
+    }
+
+    /// Add an active account with its home directory in /home
+    pub fn with_account(mut self, username: &str, password: &str, uid: u32) -> Self {
+        self.accounts.insert(
+            String::from(username),
+            MockAccount {
+                password: String::from(password),
+                uid,
+                home: PathBuf::from("/home").join(username),
+                state: MockAccountState::Active,
+            },
+        );
+        self
+    }
+
+    pub fn with_state(mut self, username: &str, state: MockAccountState) -> Self {
+        if let Some(account) = self.accounts.get_mut(username) {
+            account.state = state;
+        }
+        self
+    }
+
+    pub fn account(&self, username: &str) -> Option<&MockAccount> {
+        self.accounts.get(username)
+    }
+
+    /// Authenticate and validate the account as the PAM stack would, failing with
+    /// the same errors the PAM executor reports: unknown users are not told apart
+    /// from wrong passwords.
+    pub fn authenticate(&self, username: &str, password: &str) -> Result<&MockAccount, LoginError> {
+        let account = self
+            .accounts
+            .get(username)
+            .filter(|account| constant_time_eq(account.password.as_bytes(), password.as_bytes()))
+            .ok_or(LoginError::AuthenticationFailed)?;
+
+        match account.state {
+            MockAccountState::Active | MockAccountState::PasswordExpired => Ok(account),
+            MockAccountState::Expired => Err(LoginError::AccountExpired),
+            MockAccountState::Denied => Err(LoginError::PermissionDenied),
+            MockAccountState::Unavailable => Err(LoginError::CredentialsUnavailable),
+        }
+    }
+}
+
+/// The user answering prompts through the conversation of the executor
+fn ask(
+    conversation: &mut ProxyLoginUserInteractionHandlerConversation,
+    prompt: &str,
+    echo: bool,
+) -> Result<String, PamFailure> {
+    let prompt = CString::new(prompt)
+        .map_err(|err| PamFailure::new(ErrorCode::CONV_ERR, &err.to_string()))?;
+
+    let answer = match echo {
+        true => conversation.prompt_echo_on(prompt.as_c_str()),
+        false => conversation.prompt_echo_off(prompt.as_c_str()),
+    }
+    .map_err(|code| PamFailure::new(code, "Conversation error"))?;
+
+    Ok(answer.to_string_lossy().to_string())
+}
+
+/// In-process stand-in for the PAM stack, authenticating against the mock accounts: the
+/// questions go through the conversation of the executor exactly as those of PAM modules do,
+/// so that [`PamLoginExecutor`] itself is what runs. See [`mock_pam_executor`].
+pub struct MockPamTransaction {
+    accounts: MockAccounts,
+    username: Option<String>,
+    user_prompt: String,
+    authenticated: bool,
+    conversation: ProxyLoginUserInteractionHandlerConversation,
+}
+
+impl MockPamTransaction {
+    pub fn new(
+        accounts: MockAccounts,
+        username: Option<&str>,
+        conversation: ProxyLoginUserInteractionHandlerConversation,
+    ) -> Self {
+        Self {
+            accounts,
+            username: username.map(String::from),
+            user_prompt: String::from("login: "),
+            authenticated: false,
+            conversation,
+        }
+    }
+
+    fn authenticated_account(&self) -> Result<&MockAccount, PamFailure> {
+        self.username
+            .as_ref()
+            .filter(|_| self.authenticated)
+            .and_then(|username| self.accounts.account(username))
+            .ok_or_else(|| {
+                PamFailure::new(
+                    ErrorCode::PERM_DENIED,
+                    "The user has not been authenticated",
+                )
+            })
+    }
+}
+
+impl PamTransaction for MockPamTransaction {
+    fn set_user_prompt(&mut self, prompt: Option<&str>) -> Result<(), PamFailure> {
+        if let Some(prompt) = prompt {
+            self.user_prompt = String::from(prompt);
+        }
+
+        Ok(())
+    }
+
+    fn authenticate(&mut self, _flags: Flag) -> Result<(), PamFailure> {
+        self.authenticated = false;
+
+        let username = match &self.username {
+            Some(username) => username.clone(),
+            None => {
+                let username = ask(&mut self.conversation, &self.user_prompt, true)?;
+                self.username = Some(username.clone());
+                username
+            }
+        };
+
+        let password = ask(&mut self.conversation, "Password: ", false)?;
+
+        // unknown users are not told apart from wrong passwords
+        match self.accounts.authenticate(&username, &password) {
+            Ok(_) | Err(LoginError::AccountExpired) | Err(LoginError::PermissionDenied) => {
+                self.authenticated = true;
+                Ok(())
+            }
+            Err(LoginError::CredentialsUnavailable) => Err(PamFailure::new(
+                ErrorCode::CRED_UNAVAIL,
+                "Authentication credentials unavailable",
+            )),
+            Err(_) => Err(PamFailure::new(
+                ErrorCode::AUTH_ERR,
+                "Authentication failure",
+            )),
+        }
+    }
+
+    fn user(&self) -> Result<String, PamFailure> {
+        self.username
+            .clone()
+            .ok_or_else(|| PamFailure::new(ErrorCode::USER_UNKNOWN, "No user"))
+    }
+
+    fn acct_mgmt(&mut self, _flags: Flag) -> Result<(), PamFailure> {
+        match self.authenticated_account()?.state() {
+            MockAccountState::Active => Ok(()),
+            MockAccountState::PasswordExpired => Err(PamFailure::new(
+                ErrorCode::NEW_AUTHTOK_REQD,
+                "Authentication token is no longer valid; new one required",
+            )),
+            MockAccountState::Expired => Err(PamFailure::new(
+                ErrorCode::ACCT_EXPIRED,
+                "User account has expired",
+            )),
+            MockAccountState::Denied | MockAccountState::Unavailable => {
+                Err(PamFailure::new(ErrorCode::PERM_DENIED, "Permission denied"))
+            }
+        }
+    }
+
+    fn chauthtok(&mut self, _flags: Flag) -> Result<(), PamFailure> {
+        self.authenticated_account()?;
+
+        let password = ask(&mut self.conversation, "New password: ", false)?;
+        if ask(&mut self.conversation, "Retype new password: ", false)? != password {
+            return Err(PamFailure::new(
+                ErrorCode::AUTHTOK_ERR,
+                "Sorry, passwords do not match.",
+            ));
+        }
+
+        let username = self.user()?;
+        if let Some(account) = self.accounts.accounts.get_mut(&username) {
+            account.password = password;
+            account.state = MockAccountState::Active;
+        }
+
+        Ok(())
+    }
+
+    fn open_session(&mut self, _flags: Flag) -> Result<Box<dyn PamSession + '_>, PamFailure> {
+        let username = self.user()?;
+        let home = self.authenticated_account()?.home().clone();
+
+        Ok(Box::new(MockPamSession {
+            env: BTreeMap::from([
+                (String::from("USER"), username),
+                (String::from("HOME"), home.to_string_lossy().to_string()),
+            ]),
+        }))
+    }
+}
+
+struct MockPamSession {
+    env: BTreeMap<String, String>,
+}
+
+impl PamSession for MockPamSession {
+    fn envlist(&self) -> BTreeMap<String, String> {
+        self.env.clone()
+    }
+}
+
+/// The PAM executor, running its transactions against the mock accounts instead of the PAM stack
+pub fn mock_pam_executor(
+    accounts: MockAccounts,
+    prompter: Arc<Mutex<dyn LoginUserInteractionHandler>>,
+) -> PamLoginExecutor {
+    let faillock_config = accounts.faillock_config().clone();
+
+    PamLoginExecutor::new(
+        ProxyLoginUserInteractionHandlerConversation::new(prompter),
+        false,
+    )
+    .with_faillock_config(Some(faillock_config))
+    .with_transaction(Box::new(move |_service, username, conversation| {
+        Ok(Box::new(MockPamTransaction::new(
+            accounts.clone(),
+            username,
+            conversation,
+        )))
+    }))
+}
+
+#[cfg(feature = "greetd")]
+pub use self::greetd::*;
+
+#[cfg(feature = "greetd")]
+mod greetd {
+    use std::{
+        os::unix::net::{UnixListener, UnixStream},
+        path::{Path, PathBuf},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+        thread::JoinHandle,
+        time::Duration,
+    };
+
+    use greetd_ipc::{codec::SyncCodec, AuthMessageType, ErrorType, Request, Response};
+
+    use super::MockAccounts;
+
+    /// A session the mock greetd has been asked to start
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct MockStartedSession {
+        pub username: String,
+        pub cmd: Vec<String>,
+        pub env: Vec<String>,
+    }
+
+    /// In-process greetd speaking its IPC protocol on a unix socket, authenticating
+    /// against the mock accounts: point the greetd executor at [`MockGreetd::socket`].
+    pub struct MockGreetd {
+        path: PathBuf,
+        stop: Arc<AtomicBool>,
+        started: Arc<Mutex<Vec<MockStartedSession>>>,
+        worker: Option<JoinHandle<()>>,
+    }
+
+    fn error(error_type: ErrorType, description: &str) -> Response {
+        Response::Error {
+            error_type,
+            description: String::from(description),
+        }
+    }
+
+    fn serve(
+        mut stream: UnixStream,
+        accounts: &MockAccounts,
+        started: &Mutex<Vec<MockStartedSession>>,
+    ) {
+        let mut username: Option<String> = None;
+        let mut authenticated = false;
+
+        // a closed connection or a malformed request ends the conversation
+        while let Ok(request) = Request::read_from(&mut stream) {
+            let response = match request {
+                Request::CreateSession { username: user } => match username {
+                    Some(_) => error(ErrorType::Error, "a session is already being configured"),
+                    None => {
+                        username = Some(user);
+                        Response::AuthMessage {
+                            auth_message_type: AuthMessageType::Secret,
+                            auth_message: String::from("Password: "),
+                        }
+                    }
+                },
+                Request::PostAuthMessageResponse { response } => match &username {
+                    Some(user) if !authenticated => {
+                        match accounts.authenticate(user, &response.unwrap_or_default()) {
+                            Ok(_) => {
+                                authenticated = true;
+                                Response::Success
+                            }
+                            Err(err) => {
+                                username = None;
+                                error(ErrorType::AuthError, &err.to_string())
+                            }
+                        }
+                    }
+                    _ => error(ErrorType::Error, "no authentication in progress"),
+                },
+                Request::StartSession { cmd, env } => match &username {
+                    Some(user) if authenticated => {
+                        if let Ok(mut started) = started.lock() {
+                            started.push(MockStartedSession {
+                                username: user.clone(),
+                                cmd,
+                                env,
+                            });
+                        }
+
+                        Response::Success
+                    }
+                    _ => error(ErrorType::Error, "the session has not been authenticated"),
+                },
+                Request::CancelSession => {
+                    username = None;
+                    authenticated = false;
+                    Response::Success
+                }
+            };
+
+            if response.write_to(&mut stream).is_err() {
+                return;
+            }
+        }
+    }
+
+    impl MockGreetd {
+        /// Listen on the given socket path, serving one connection at a time
+        pub fn spawn(path: &Path, accounts: MockAccounts) -> std::io::Result<Self> {
+            let _ = std::fs::remove_file(path);
+
+            let listener = UnixListener::bind(path)?;
+            listener.set_nonblocking(true)?;
+
+            let stop = Arc::new(AtomicBool::new(false));
+            let started = Arc::new(Mutex::new(vec![]));
+
+            let worker = {
+                let stop = stop.clone();
+                let started = started.clone();
+
+                std::thread::Builder::new()
+                    .name(String::from("login_ng-mock-greetd"))
+                    .spawn(move || {
+                        while !stop.load(Ordering::Relaxed) {
+                            match listener.accept() {
+                                Ok((stream, _)) => {
+                                    if stream.set_nonblocking(false).is_ok() {
+                                        serve(stream, &accounts, &started);
+                                    }
+                                }
+                                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                                    std::thread::sleep(Duration::from_millis(10));
+                                }
+                                Err(_) => break,
+                            }
+                        }
+                    })?
+            };
+
+            Ok(Self {
+                path: path.to_path_buf(),
+                stop,
+                started,
+                worker: Some(worker),
+            })
+        }
+
+        /// The socket to be given to the greetd executor (what greetd puts in GREETD_SOCK)
+        pub fn socket(&self) -> String {
+            self.path.to_string_lossy().to_string()
+        }
+
+        /// The sessions started so far, in order
+        pub fn started_sessions(&self) -> Vec<MockStartedSession> {
+            self.started
+                .lock()
+                .map(|started| started.clone())
+                .unwrap_or_default()
+        }
+    }
+
+    impl Drop for MockGreetd {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::Relaxed);
+
+            if let Some(worker) = self.worker.take() {
+                let _ = worker.join();
+            }
+
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
@@ -17,14 +17,153 @@
     51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 */
 
-use std::{os::unix::process::CommandExt, path::Path, process::Command};
+use std::{
+    collections::BTreeMap,
+    ffi::CString,
+    os::unix::process::CommandExt,
+    path::Path,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use pam_client2::{Context, Flag};
+use pam_client2::{Context, ConversationHandler, ErrorCode, Flag, Session};
 use thiserror::Error;
 
-use crate::{conversation::ProxyLoginUserInteractionHandlerConversation, login::*};
+use crate::{
+    accounting::{audit_event, record_login, record_logout, store_login_history, AuditEvent},
+    conversation::ProxyLoginUserInteractionHandlerConversation,
+    exec_context::{default_exec_context, reset_exec_context, set_exec_context},
+    i18n::{tr, tr_args},
+    interrupt::{become_session_leader, forward_signals_to_session, hang_up_session, interrupted},
+    login::*,
+};
 
-use login_ng::users::{get_user_by_name, os::unix::UserExt};
+use login_ng::{
+    environment::SessionEnvironment,
+    faillock::{read_tally, record_failure, reset_tally, FaillockConfig},
+    privileges::SessionCredentials,
+    recovery::RecoveryTokens,
+    shadow::{user_expiry_warnings, ExpiryWarning},
+    storage::{load_user_session_environment, StorageSource},
+    users::{get_user_by_name, os::unix::UserExt},
+};
+
+/// A failed call to the PAM stack: the code it returned and its description
+#[derive(Debug)]
+pub struct PamFailure {
+    code: ErrorCode,
+    message: String,
+}
+
+impl PamFailure {
+    pub fn new(code: ErrorCode, message: &str) -> Self {
+        Self {
+            code,
+            message: String::from(message),
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+}
+
+impl std::fmt::Display for PamFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl From<pam_client2::Error> for PamFailure {
+    fn from(err: pam_client2::Error) -> Self {
+        Self {
+            code: err.code(),
+            message: err.to_string(),
+        }
+    }
+}
+
+/// The steps of a PAM transaction the executor goes through: implemented by the pam-client2
+/// context and, with the mock feature, by an in-process one so that the executor can be tested
+/// without a PAM stack.
+pub trait PamTransaction {
+    fn set_user_prompt(&mut self, prompt: Option<&str>) -> Result<(), PamFailure>;
+
+    fn authenticate(&mut self, flags: Flag) -> Result<(), PamFailure>;
+
+    /// The user being authenticated, as known to (or asked by) the PAM modules
+    fn user(&self) -> Result<String, PamFailure>;
+
+    fn acct_mgmt(&mut self, flags: Flag) -> Result<(), PamFailure>;
+
+    fn chauthtok(&mut self, flags: Flag) -> Result<(), PamFailure>;
+
+    /// Opens the session, that is closed once the returned value is dropped
+    fn open_session(&mut self, flags: Flag) -> Result<Box<dyn PamSession + '_>, PamFailure>;
+}
+
+/// A session opened by [`PamTransaction::open_session`]
+pub trait PamSession {
+    /// The environment variables set by the PAM modules
+    fn envlist(&self) -> BTreeMap<String, String>;
+}
+
+impl<ConvT: ConversationHandler> PamTransaction for Context<ConvT> {
+    fn set_user_prompt(&mut self, prompt: Option<&str>) -> Result<(), PamFailure> {
+        Ok(Context::set_user_prompt(self, prompt)?)
+    }
+
+    fn authenticate(&mut self, flags: Flag) -> Result<(), PamFailure> {
+        Ok(Context::authenticate(self, flags)?)
+    }
+
+    fn user(&self) -> Result<String, PamFailure> {
+        Ok(Context::user(self)?)
+    }
+
+    fn acct_mgmt(&mut self, flags: Flag) -> Result<(), PamFailure> {
+        Ok(Context::acct_mgmt(self, flags)?)
+    }
+
+    fn chauthtok(&mut self, flags: Flag) -> Result<(), PamFailure> {
+        Ok(Context::chauthtok(self, flags)?)
+    }
+
+    fn open_session(&mut self, flags: Flag) -> Result<Box<dyn PamSession + '_>, PamFailure> {
+        Ok(Box::new(Context::open_session(self, flags)?))
+    }
+}
+
+impl<ConvT: ConversationHandler> PamSession for Session<'_, ConvT> {
+    fn envlist(&self) -> BTreeMap<String, String> {
+        Session::envlist(self)
+            .iter_tuples()
+            .map(|(key, value)| {
+                (
+                    key.to_string_lossy().to_string(),
+                    value.to_string_lossy().to_string(),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Starts the PAM transaction of a login attempt for the given service and, if known, user
+pub type PamTransactionFactory = Box<
+    dyn Fn(
+        &str,
+        Option<&str>,
+        ProxyLoginUserInteractionHandlerConversation,
+    ) -> Result<Box<dyn PamTransaction>, PamFailure>,
+>;
+
+fn pam_context(
+    service: &str,
+    username: Option<&str>,
+    conversation: ProxyLoginUserInteractionHandlerConversation,
+) -> Result<Box<dyn PamTransaction>, PamFailure> {
+    Ok(Box::new(Context::new(service, username, conversation)?))
+}
 
 #[derive(Debug, Error)]
 pub enum PamLoginError {
@@ -48,11 +187,24 @@ pub enum PamLoginError {
 
     #[error("Unable to find the username")]
     UnknownUsername,
+
+    #[error("Error setting the security context: {0}")]
+    ExecContext(String),
+
+    #[error("Account locked due to too many failed login attempts: {0}")]
+    Locked(String),
+
+    #[error("Error changing the expired password: {0}")]
+    PasswordChange(String),
 }
 
 pub struct PamLoginExecutor {
     conversation: ProxyLoginUserInteractionHandlerConversation,
     allow_autologin: bool,
+    quiet: bool,
+    dry_run: bool,
+    transaction: PamTransactionFactory,
+    faillock_config: Option<FaillockConfig>,
 }
 
 impl PamLoginExecutor {
@@ -63,8 +215,188 @@ impl PamLoginExecutor {
         Self {
             conversation,
             allow_autologin,
+            quiet: false,
+            dry_run: false,
+            transaction: Box::new(pam_context),
+            faillock_config: None,
+        }
+    }
+
+    /// Start the PAM transactions with the given factory instead of the system PAM stack
+    pub fn with_transaction(mut self, transaction: PamTransactionFactory) -> Self {
+        self.transaction = transaction;
+        self
+    }
+
+    /// Keep the faillock tally with the given settings instead of those of faillock.conf
+    pub fn with_faillock_config(mut self, faillock_config: Option<FaillockConfig>) -> Self {
+        self.faillock_config = faillock_config;
+        self
+    }
+
+    /// Stop once the user has been authenticated and the account validated, without
+    /// changing an expired password nor opening a session: [`LoginResult::Authenticated`]
+    /// is returned instead, allowing to check the PAM stack without logging in.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Pass PAM_SILENT to the PAM stack and suppress informational messages:
+    /// only prompts and errors reach the user
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.conversation.set_quiet(quiet);
+        self.quiet = quiet;
+        self
+    }
+
+    fn pam_flags(&self) -> Flag {
+        match self.quiet {
+            true => Flag::SILENT,
+            false => Flag::NONE,
+        }
+    }
+
+    fn check_lockout(config: &FaillockConfig, username: &str) -> Result<(), LoginError> {
+        match lockout_message(config, username) {
+            Some(message) => Err(LoginError::PamError(PamLoginError::Locked(message))),
+            None => Ok(()),
+        }
+    }
+
+    /// Keeps the faillock tally up to date when pam_faillock is not part of the PAM stack
+    fn update_tally(config: &FaillockConfig, username: &str, attempt_start: u64, success: bool) {
+        // usernames typed at the prompt that are not accounts have no tally to fill /run with
+        if get_user_by_name(username).is_none() {
+            return;
+        }
+
+        match success {
+            true => {
+                let _ = reset_tally(config, username);
+            }
+            false => {
+                let tally = read_tally(config, username).unwrap_or_default();
+
+                // pam_faillock has already recorded the failure
+                if tally.iter().any(|record| record.time() >= attempt_start) {
+                    return;
+                }
+
+                if let Err(err) = record_failure(config, username, &terminal_line()) {
+                    eprintln!("Unable to record the failed login attempt: {err}");
+                }
+            }
+        }
+    }
+
+    fn display_info(&mut self, msg: &str) {
+        if let Ok(msg) = CString::new(msg) {
+            self.conversation.text_info(msg.as_c_str());
+        }
+    }
+
+    fn display_home_error(&mut self, error: String) {
+        if let Ok(msg) = CString::new(tr_args(
+            "home-creation-failed",
+            "Unable to create the home directory: { $error }",
+            &[("error", error)],
+        )) {
+            self.conversation.error_msg(msg.as_c_str());
+        }
+    }
+
+    fn display_session_environment_error(&mut self, error: String) {
+        if let Ok(msg) = CString::new(tr_args(
+            "session-environment-failed",
+            "Unable to apply the session environment: { $error }",
+            &[("error", error)],
+        )) {
+            self.conversation.error_msg(msg.as_c_str());
         }
     }
+
+    /// Offers the user to change an expired password: the login is aborted if the offer is declined
+    fn change_expired_password(
+        &mut self,
+        context: &mut dyn PamTransaction,
+    ) -> Result<(), LoginError> {
+        let expired = localized_warning(&ExpiryWarning::PasswordExpired);
+
+        let question = CString::new(tr_args(
+            "password-change-offer",
+            "{ $expired }. Change it now? [y/N] ",
+            &[("expired", expired.clone())],
+        ))
+        .map_err(|err| LoginError::PamError(PamLoginError::Validation(err.to_string())))?;
+
+        let accepted = self
+            .conversation
+            .prompt_echo_on(question.as_c_str())
+            .map(|answer| {
+                matches!(
+                    answer.to_string_lossy().trim().to_lowercase().as_str(),
+                    "y" | "yes"
+                )
+            })
+            .unwrap_or(false);
+
+        if !accepted {
+            return Err(LoginError::PamError(PamLoginError::Validation(expired)));
+        }
+
+        context
+            .chauthtok(Flag::CHANGE_EXPIRED_AUTHTOK)
+            .map_err(|err| LoginError::PamError(PamLoginError::PasswordChange(err.to_string())))
+    }
+}
+
+fn localized_warning(warning: &ExpiryWarning) -> String {
+    match warning {
+        ExpiryWarning::PasswordExpiresSoon(0) => {
+            tr("password-expires-today", "Your password expires today")
+        }
+        ExpiryWarning::PasswordExpiresSoon(1) => tr(
+            "password-expires-tomorrow",
+            "Your password expires in 1 day",
+        ),
+        ExpiryWarning::PasswordExpiresSoon(days) => tr_args(
+            "password-expires-in",
+            "Your password expires in { $days } days",
+            &[("days", days.to_string())],
+        ),
+        ExpiryWarning::PasswordExpired => tr("password-expired", "Your password has expired"),
+        ExpiryWarning::AccountExpiresSoon(0) => {
+            tr("account-expires-today", "Your account expires today")
+        }
+        ExpiryWarning::AccountExpiresSoon(1) => {
+            tr("account-expires-tomorrow", "Your account expires in 1 day")
+        }
+        ExpiryWarning::AccountExpiresSoon(days) => tr_args(
+            "account-expires-in",
+            "Your account expires in { $days } days",
+            &[("days", days.to_string())],
+        ),
+    }
+}
+
+/// PAM failures the user can act upon get their own LoginError, anything else is reported as is
+fn pam_failure(err: PamFailure, fallback: fn(String) -> PamLoginError) -> LoginError {
+    match err.code() {
+        ErrorCode::AUTH_ERR => LoginError::AuthenticationFailed,
+        ErrorCode::ACCT_EXPIRED => LoginError::AccountExpired,
+        ErrorCode::PERM_DENIED => LoginError::PermissionDenied,
+        ErrorCode::MAXTRIES => LoginError::MaxTries,
+        ErrorCode::CRED_UNAVAIL => LoginError::CredentialsUnavailable,
+        _ => LoginError::PamError(fallback(err.to_string())),
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
 }
 
 impl LoginExecutor for PamLoginExecutor {
@@ -73,9 +405,19 @@ impl LoginExecutor for PamLoginExecutor {
         maybe_username: &Option<String>,
         retrival_strategy: &SessionCommandRetrival,
     ) -> Result<LoginResult, LoginError> {
-        let user_prompt = Some("username: ");
+        let user_prompt = tr("username-prompt", "username: ");
+
+        let faillock_config = self
+            .faillock_config
+            .clone()
+            .unwrap_or_else(FaillockConfig::load);
 
-        let mut context = Context::new(
+        // do not even prompt for secrets if the account has been locked by too many failures
+        if let Some(username) = maybe_username {
+            Self::check_lockout(&faillock_config, username)?;
+        }
+
+        let mut context = (self.transaction)(
             match self.allow_autologin {
                 true => "login_ng-autologin",
                 false => "login_ng",
@@ -86,18 +428,55 @@ impl LoginExecutor for PamLoginExecutor {
         .expect("Failed to initialize PAM context");
 
         context
-            .set_user_prompt(user_prompt)
+            .set_user_prompt(Some(user_prompt.as_str()))
             .map_err(|err| LoginError::PamError(PamLoginError::SetPrompt(err.to_string())))?;
 
+        let attempt_start = unix_now();
+
         // Authenticate the user (ask for password, 2nd-factor token, fingerprint, etc.)
-        context
-            .authenticate(Flag::NONE)
-            .map_err(|err| LoginError::PamError(PamLoginError::Authentication(err.to_string())))?;
+        let authentication = context.authenticate(self.pam_flags());
 
-        // Validate the account (is not locked, expired, etc.)
-        context
-            .acct_mgmt(Flag::NONE)
-            .map_err(|err| LoginError::PamError(PamLoginError::Validation(err.to_string())))?;
+        let attempted_username = context
+            .user()
+            .ok()
+            .or(maybe_username.clone())
+            .unwrap_or_default();
+
+        audit_event(
+            AuditEvent::Authentication,
+            attempted_username.as_str(),
+            authentication.is_ok(),
+        );
+
+        // a username typed at the prompt has not been checked before authenticating: a locked
+        // account stays locked whatever the outcome, as a success would otherwise reset the tally
+        let lockout = match attempted_username.is_empty() {
+            true => Ok(()),
+            false => Self::check_lockout(&faillock_config, &attempted_username),
+        };
+
+        // the attempt is recorded even if aborted by a signal: otherwise interrupting the login
+        // right after a wrong password would be a way to guess without ever being locked out
+        if !attempted_username.is_empty() {
+            self.conversation.provide_username(&attempted_username);
+
+            if lockout.is_ok() {
+                Self::update_tally(
+                    &faillock_config,
+                    &attempted_username,
+                    attempt_start,
+                    authentication.is_ok(),
+                );
+            }
+        }
+
+        if interrupted() {
+            return Err(LoginError::Interrupted);
+        }
+
+        lockout?;
+
+        authentication.map_err(|err| pam_failure(err, PamLoginError::Authentication))?;
 
         // Get resulting user name and map to a user id
         let username = context
@@ -105,40 +484,206 @@ impl LoginExecutor for PamLoginExecutor {
             .map_err(|err| LoginError::PamError(PamLoginError::GetUser(err.to_string())))?;
         let logged_user = get_user_by_name(&username).ok_or(LoginError::UserDiscoveryError)?;
 
+        // Validate the account (is not locked, expired, etc.)
+        let messages_before_validation = self.conversation.messages();
+        let password_expired = match context.acct_mgmt(self.pam_flags()) {
+            Ok(()) => false,
+            Err(err) if err.code() == ErrorCode::NEW_AUTHTOK_REQD => true,
+            Err(err) => return Err(pam_failure(err, PamLoginError::Validation)),
+        };
+
+        // Warn the user about the password or account being about to expire, unless the
+        // account modules (such as pam_unix) already did while validating the account
+        let warnings = user_expiry_warnings(&username);
+        if self.conversation.messages() == messages_before_validation {
+            for warning in warnings.iter() {
+                if *warning != ExpiryWarning::PasswordExpired {
+                    self.display_info(&localized_warning(warning));
+                }
+            }
+        }
+
+        if self.dry_run {
+            return Ok(LoginResult::Authenticated {
+                uid: logged_user.uid(),
+                password_expired: password_expired
+                    || warnings.contains(&ExpiryWarning::PasswordExpired),
+                username,
+            });
+        }
+
+        if password_expired && RecoveryTokens::default().requires_reenrollment(&username) {
+            self.display_info(&tr(
+                "recovery-reenroll",
+                "A recovery token has been used: a new password must be chosen",
+            ));
+        }
+
+        if password_expired || warnings.contains(&ExpiryWarning::PasswordExpired) {
+            self.change_expired_password(context.as_mut())?;
+
+            // the whole password stack has succeeded: the new password has been stored
+            let recovery = RecoveryTokens::default();
+            if recovery.requires_reenrollment(&username) {
+                if let Err(err) = recovery.complete_reenrollment(&username) {
+                    eprintln!("Unable to complete the re-enrollment after recovery: {err}");
+                }
+            }
+        }
+
         // Open session and initialize credentials
-        let session = context
-            .open_session(Flag::NONE)
-            .map_err(|err| LoginError::PamError(PamLoginError::Open(err.to_string())))?;
+        let session = context.open_session(self.pam_flags());
+
+        audit_event(AuditEvent::SessionStart, &username, session.is_ok());
+
+        let session =
+            session.map_err(|err| LoginError::PamError(PamLoginError::Open(err.to_string())))?;
+
+        // as pam_mkhomedir(8) does: accounts without a home directory get one on their first login
+        if let Err(err) = home_config().create_home(
+            logged_user.home_dir(),
+            logged_user.uid(),
+            logged_user.primary_group_id(),
+        ) {
+            self.display_home_error(err.to_string());
+        }
 
         // The retrival of default session MUST be done after the account has been unlocked
         let command = retrieve_session_command_for_user(&username, &retrival_strategy);
 
+        // as login(1) does, once the home directory is available for .hushlogin to be found
+        if let Some(motd) = motd_for_user(&username) {
+            self.display_info(&motd);
+        }
+
+        // umask, locale and priorities configured for the user: the session is started
+        // without them rather than not at all if they cannot be applied
+        let session_environment =
+            match load_user_session_environment(&StorageSource::Username(username.clone())) {
+                Ok(session_environment) => session_environment.unwrap_or_default(),
+                Err(err) => {
+                    self.display_session_environment_error(err.to_string());
+                    SessionEnvironment::default()
+                }
+            };
+
+        let process_settings = match session_environment.apply() {
+            Ok(process_settings) => Some(process_settings),
+            Err(err) => {
+                self.display_session_environment_error(err.to_string());
+                None
+            }
+        };
+
+        self.conversation.session_starting();
+
+        // resolved here as NSS lookups are not safe between fork and exec
+        let credentials = SessionCredentials::for_user(&logged_user);
+
+        // The PAM environment, extended with the one of the user and of the session command
+        let mut environment = session.envlist();
+        environment.extend(user_environment_d(&username));
+        environment.extend(session_environment.locale().clone());
+        environment.extend(command.env().clone());
+
         // Run a process in the PAM environment
-        let _result = Command::new(command.command())
+        let mut session_command = Command::new(command.command());
+        session_command
+            .args(command.args())
             .env_clear()
-            .envs(session.envlist().iter_tuples())
-            .uid(logged_user.uid())
-            .gid(logged_user.primary_group_id())
-            /*
-            // unstable feature
-            .groups(
-                logged_user
-                    .groups()
-                    .unwrap_or(vec![])
-                    .iter()
-                    .filter(|g| g.gid() != logged_user.primary_group_id())
-                    .map(|g| g.gid())
-                    .collect::<Vec<u32>>()
-                    .as_slice(),
-            )
-            */
-            .current_dir(match logged_user.home_dir().exists() {
-                true => logged_user.home_dir(),
-                false => Path::new("/"),
-            })
-            .status()
-            .map_err(|err| LoginError::PamError(PamLoginError::Execution(err.to_string())))?;
+            .envs(&environment)
+            .current_dir(match command.cwd() {
+                Some(cwd) => cwd.as_path(),
+                None => match logged_user.home_dir().exists() {
+                    true => logged_user.home_dir(),
+                    false => Path::new("/"),
+                },
+            });
+
+        // CommandExt::uid drops every supplementary group: switch user in pre_exec instead,
+        // after the session has been set up as that requires privileges
+        unsafe {
+            session_command.pre_exec(become_session_leader);
+            session_command.pre_exec(move || credentials.apply());
+        }
+
+        // Run the session with the security context (SELinux or AppArmor) defined for the user:
+        // it is armed right before spawning and reset whatever the outcome, so that nothing
+        // else executed by this process afterwards runs with it
+        let exec_context = default_exec_context(&username);
+        let armed = match &exec_context {
+            Some(exec_context) => set_exec_context(exec_context),
+            None => Ok(()),
+        };
+
+        let child = match armed {
+            Ok(()) => session_command
+                .spawn()
+                .map_err(|err| PamLoginError::Execution(err.to_string())),
+            Err(err) => Err(PamLoginError::ExecContext(err.to_string())),
+        };
+
+        if let Some(process_settings) = &process_settings {
+            if let Err(err) = process_settings.restore() {
+                self.display_session_environment_error(err.to_string());
+            }
+        }
+
+        if let Some(exec_context) = &exec_context {
+            if let Err(err) = reset_exec_context(exec_context) {
+                if let Ok(msg) = CString::new(tr_args(
+                    "exec-context-reset-failed",
+                    "Unable to reset the exec context: { $error }",
+                    &[("error", err.to_string())],
+                )) {
+                    self.conversation.error_msg(msg.as_c_str());
+                }
+            }
+        }
+
+        let mut child = match child {
+            Ok(child) => child,
+            Err(err) => {
+                self.conversation.session_ended();
+                return Err(LoginError::PamError(err));
+            }
+        };
+
+        if let Err(err) = forward_signals_to_session(child.id() as libc::pid_t) {
+            eprintln!("Unable to forward signals to the session: {err}");
+        }
+
+        let login_record = record_login(&username, child.id());
+        if let Err(err) = store_login_history(&username, &login_record) {
+            if let Ok(msg) = CString::new(tr_args(
+                "history-update-failed",
+                "Unable to update the login history: { $error }",
+                &[("error", err.to_string())],
+            )) {
+                self.conversation.error_msg(msg.as_c_str());
+            }
+        }
+
+        let result = child
+            .wait()
+            .map_err(|err| LoginError::PamError(PamLoginError::Execution(err.to_string())));
+
+        hang_up_session();
+
+        record_logout(&login_record, child.id());
+
+        self.conversation.session_ended();
+
+        audit_event(AuditEvent::SessionEnd, &username, true);
+
+        result?;
 
-        Ok(LoginResult::Success)
+        Ok(LoginResult::Success {
+            username,
+            uid: logged_user.uid(),
+            home: logged_user.home_dir().to_path_buf(),
+            command,
+            env: environment,
+        })
     }
 }
@@ -0,0 +1,165 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    fs::{File, OpenOptions},
+    os::fd::AsRawFd,
+    process::Command,
+    sync::atomic::{AtomicI32, Ordering},
+};
+
+use thiserror::Error;
+
+use crate::vt::{
+    set_vt_mode, KDSETMODE, KD_TEXT, VT_ACKACQ, VT_AUTO, VT_PROCESS, VT_RELDISP, VT_RELEASE,
+};
+
+const PLYMOUTH_CMD: &str = "plymouth";
+
+/// The VT currently owned by this process, used by the VT switch signal handlers
+static VT_FD: AtomicI32 = AtomicI32::new(-1);
+
+#[derive(Debug, Error)]
+pub enum PlymouthError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] std::io::Error),
+
+    #[error("plymouth {0} exited with an error")]
+    CommandFailed(String),
+
+    #[error("Could not take over the VT: {0}")]
+    VtError(std::io::Error),
+}
+
+fn plymouth(args: &[&str]) -> Result<(), PlymouthError> {
+    match Command::new(PLYMOUTH_CMD).args(args).status()?.success() {
+        true => Ok(()),
+        false => Err(PlymouthError::CommandFailed(args.join(" "))),
+    }
+}
+
+/// Returns true if a plymouth splash is currently running
+pub fn plymouth_running() -> bool {
+    plymouth(&["--ping"]).is_ok()
+}
+
+/// Asks plymouth to quit while keeping the last frame of the splash on screen
+pub fn plymouth_quit_retain_splash() -> Result<(), PlymouthError> {
+    plymouth(&["quit", "--retain-splash"])
+}
+
+/// Asks plymouth to stop drawing and release the VT without quitting
+pub fn plymouth_deactivate() -> Result<(), PlymouthError> {
+    plymouth(&["deactivate"])
+}
+
+/// Gives back the VT to plymouth after a previous deactivation
+pub fn plymouth_reactivate() -> Result<(), PlymouthError> {
+    plymouth(&["reactivate"])
+}
+
+extern "C" fn vt_release(_signal: libc::c_int) {
+    let fd = VT_FD.load(Ordering::SeqCst);
+    if fd >= 0 {
+        // allow the kernel to switch away from this VT
+        unsafe { libc::ioctl(fd, VT_RELDISP, VT_RELEASE) };
+    }
+}
+
+extern "C" fn vt_acquire(_signal: libc::c_int) {
+    let fd = VT_FD.load(Ordering::SeqCst);
+    if fd >= 0 {
+        unsafe { libc::ioctl(fd, VT_RELDISP, VT_ACKACQ) };
+    }
+}
+
+/// Ownership of the VT the login prompt is displayed on: VT switches are
+/// acknowledged by this process while it's alive and given back to the kernel on drop.
+pub struct VtTakeover {
+    tty: File,
+}
+
+impl VtTakeover {
+    pub fn new() -> Result<Self, PlymouthError> {
+        let tty = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .map_err(PlymouthError::VtError)?;
+
+        let fd = tty.as_raw_fd();
+
+        // plymouth may have left the console in graphics mode
+        if unsafe { libc::ioctl(fd, KDSETMODE, KD_TEXT) } != 0 {
+            return Err(PlymouthError::VtError(std::io::Error::last_os_error()));
+        }
+
+        VT_FD.store(fd, Ordering::SeqCst);
+
+        unsafe {
+            libc::signal(libc::SIGUSR1, vt_release as libc::sighandler_t);
+            libc::signal(libc::SIGUSR2, vt_acquire as libc::sighandler_t);
+        }
+
+        if let Err(err) = set_vt_mode(fd, VT_PROCESS) {
+            VT_FD.store(-1, Ordering::SeqCst);
+            return Err(PlymouthError::VtError(err));
+        }
+
+        Ok(Self { tty })
+    }
+}
+
+impl Drop for VtTakeover {
+    fn drop(&mut self) {
+        let _ = set_vt_mode(self.tty.as_raw_fd(), VT_AUTO);
+
+        VT_FD.store(-1, Ordering::SeqCst);
+
+        unsafe {
+            libc::signal(libc::SIGUSR1, libc::SIG_DFL);
+            libc::signal(libc::SIGUSR2, libc::SIG_DFL);
+        }
+    }
+}
+
+/// Takes over the VT from a running plymouth splash (if any):
+/// plymouth is deactivated first, so that it stops drawing on the console, then
+/// the VT is taken over and plymouth is asked to quit leaving the splash on screen.
+///
+/// If the VT cannot be taken over plymouth is reactivated and keeps the VT.
+pub fn plymouth_handoff() -> Result<Option<VtTakeover>, PlymouthError> {
+    if !plymouth_running() {
+        return Ok(None);
+    }
+
+    plymouth_deactivate()?;
+
+    let takeover = match VtTakeover::new() {
+        Ok(takeover) => takeover,
+        Err(err) => {
+            let _ = plymouth_reactivate();
+            return Err(err);
+        }
+    };
+
+    plymouth_quit_retain_splash()?;
+
+    Ok(Some(takeover))
+}
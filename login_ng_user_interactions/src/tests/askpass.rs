@@ -0,0 +1,110 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{os::unix::net::UnixDatagram, path::Path};
+
+use crate::askpass::{monotonic_usec, pending_password_requests, PasswordRequest};
+
+const REQUEST: &str = "[Ask]
+PID=1
+Socket=/run/systemd/ask-password/sck.5e7b2a
+AcceptCached=1
+Echo=0
+NotAfter=0
+Message=Please enter passphrase for disk data (luks-data)
+Icon=drive-harddisk
+Id=cryptsetup:/dev/sdb1
+";
+
+#[test]
+fn password_request_parsing() {
+    let request = PasswordRequest::parse(REQUEST).unwrap();
+
+    assert_eq!(
+        request.socket(),
+        Path::new("/run/systemd/ask-password/sck.5e7b2a")
+    );
+    assert_eq!(
+        request.message(),
+        "Please enter passphrase for disk data (luks-data)"
+    );
+    assert_eq!(request.id(), &Some(String::from("cryptsetup:/dev/sdb1")));
+    assert!(!request.echo());
+
+    // no deadline and pid 1 is always there
+    assert!(!request.is_expired(u64::MAX));
+    assert!(!request.is_orphaned());
+
+    let request =
+        PasswordRequest::parse("[Ask]\nSocket=/tmp/sck\nEcho=1\nNotAfter=1000\n").unwrap();
+    assert_eq!(request.message(), "Password");
+    assert_eq!(request.id(), &None);
+    assert!(request.echo());
+    assert!(!request.is_expired(999));
+    assert!(request.is_expired(monotonic_usec()));
+    assert!(request.is_expired(1001));
+
+    assert_eq!(PasswordRequest::parse("[Ask]\nMessage=Password\n"), None);
+    assert_eq!(PasswordRequest::parse(""), None);
+}
+
+#[test]
+fn password_request_answer() {
+    let dir = Path::new("./password_request_answer_test");
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+
+    let socket = dir.join("sck.0001");
+    let receiver = UnixDatagram::bind(&socket).unwrap();
+
+    let request =
+        PasswordRequest::parse(format!("[Ask]\nSocket={}\n", socket.display()).as_str()).unwrap();
+
+    let mut buffer = [0u8; 64];
+
+    request.answer(Some("hunter2")).unwrap();
+    let len = receiver.recv(&mut buffer).unwrap();
+    assert_eq!(&buffer[..len], b"+hunter2");
+
+    request.answer(None).unwrap();
+    let len = receiver.recv(&mut buffer).unwrap();
+    assert_eq!(&buffer[..len], b"-");
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn pending_password_requests_listing() {
+    let dir = Path::new("./pending_password_requests_test");
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+
+    std::fs::write(dir.join("ask.0001"), REQUEST).unwrap();
+
+    // expired requests are skipped, as are files that are not requests
+    std::fs::write(dir.join("ask.0002"), "[Ask]\nSocket=/tmp/sck\nNotAfter=1\n").unwrap();
+    std::fs::write(dir.join("sck.0001"), "").unwrap();
+
+    let requests = pending_password_requests(dir);
+    assert_eq!(requests, vec![PasswordRequest::parse(REQUEST).unwrap()]);
+
+    assert!(pending_password_requests(&dir.join("missing")).is_empty());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
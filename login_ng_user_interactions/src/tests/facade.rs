@@ -0,0 +1,178 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::time::Duration;
+
+use login_ng::command::SessionCommand;
+
+use crate::{
+    login::{
+        facade::{LoginBackend, LoginPrompt, LoginSession, LoginSessionError},
+        LoginError, LoginResult, SessionCommandRetrival,
+    },
+    mock::*,
+    pam::PamLoginError,
+};
+
+/// The mock backend runs the PAM executor, that resolves the user through NSS
+fn current_user() -> String {
+    login_ng::users::get_current_username()
+        .unwrap()
+        .to_string_lossy()
+        .to_string()
+}
+
+fn accounts() -> MockAccounts {
+    MockAccounts::new().with_account(
+        &current_user(),
+        "s3cret",
+        login_ng::users::get_current_uid(),
+    )
+}
+
+fn login_session(username: Option<&str>) -> LoginSession {
+    LoginSession::new(LoginBackend::Mock(accounts()))
+        .with_username(username.map(String::from))
+        .with_session(SessionCommandRetrival::Defined(SessionCommand::new(
+            String::from("/bin/sh"),
+        )))
+}
+
+#[test]
+fn test_login_session() {
+    let mut session = login_session(Some(&current_user()));
+    session.start().unwrap();
+
+    assert!(matches!(
+        session.poll_prompt(None),
+        Some(LoginPrompt::Secret(_))
+    ));
+
+    // nothing is decided until the question is answered
+    assert!(session.result().is_none());
+
+    session.feed_secret(String::from("s3cret")).unwrap();
+
+    // the mock backend stops once the account has been validated
+    let uid = login_ng::users::get_current_uid();
+    assert!(matches!(
+        session.wait().unwrap(),
+        Ok(LoginResult::Authenticated { uid: authenticated, .. }) if authenticated == uid
+    ));
+    assert!(session.is_finished());
+    assert_eq!(
+        session.session_command(),
+        Some(SessionCommand::new(String::from("/bin/sh")))
+    );
+}
+
+#[test]
+fn test_login_session_username_prompt() {
+    let mut session = login_session(None);
+    assert_eq!(session.username(), None);
+
+    session.start().unwrap();
+
+    let prompt = session.poll_prompt(None).unwrap();
+    assert!(matches!(prompt, LoginPrompt::Plain(_)));
+    assert!(prompt.expects_answer());
+    session.answer(Some(current_user())).unwrap();
+
+    assert!(matches!(
+        session.poll_prompt(None),
+        Some(LoginPrompt::Secret(_))
+    ));
+
+    session.feed_secret(String::from("s3cret")).unwrap();
+    assert!(matches!(
+        session.wait().unwrap(),
+        Ok(LoginResult::Authenticated { .. })
+    ));
+
+    // the username typed in is reported once the backend has resolved it
+    assert_eq!(session.username(), Some(current_user()));
+}
+
+#[test]
+fn test_login_session_failures() {
+    let mut session = login_session(Some(&current_user()));
+    session.start().unwrap();
+    assert!(session.poll_prompt(None).is_some());
+    session.feed_secret(String::from("wrong")).unwrap();
+    assert!(matches!(
+        session.wait().unwrap(),
+        Err(LoginError::AuthenticationFailed)
+    ));
+
+    // answering with None cancels the login, failing the conversation
+    let mut session = login_session(Some(&current_user()));
+    session.start().unwrap();
+    assert!(session.poll_prompt(None).is_some());
+    session.answer(None).unwrap();
+    assert!(matches!(
+        session.wait().unwrap(),
+        Err(LoginError::PamError(PamLoginError::Authentication(_)))
+    ));
+
+    // so does waiting on a question that has not been answered
+    let mut session = login_session(Some(&current_user()));
+    session.start().unwrap();
+    assert!(matches!(
+        session.wait().unwrap(),
+        Err(LoginError::PamError(PamLoginError::Authentication(_)))
+    ));
+}
+
+#[test]
+fn test_login_session_lifecycle() {
+    let mut session = login_session(Some(&current_user()));
+
+    assert!(session.poll_prompt(Some(Duration::ZERO)).is_none());
+    assert!(!session.is_finished());
+    assert!(session.result().is_none());
+    assert!(matches!(
+        session.answer(None),
+        Err(LoginSessionError::NotStarted)
+    ));
+    assert!(matches!(session.wait(), Err(LoginSessionError::NotStarted)));
+
+    session.start().unwrap();
+    assert!(matches!(
+        session.start(),
+        Err(LoginSessionError::AlreadyStarted)
+    ));
+
+    assert!(session.poll_prompt(None).is_some());
+    assert!(session
+        .poll_prompt(Some(Duration::from_millis(10)))
+        .is_none());
+    session.feed_secret(String::from("s3cret")).unwrap();
+    assert!(session.wait().unwrap().is_ok());
+
+    // a terminated login can be neither answered nor restarted
+    assert!(matches!(
+        session.answer(None),
+        Err(LoginSessionError::Terminated)
+    ));
+    assert!(matches!(
+        session.start(),
+        Err(LoginSessionError::AlreadyStarted)
+    ));
+    assert!(session.result().is_some_and(|result| result.is_ok()));
+}
@@ -0,0 +1,115 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use argh::{ArgsInfo, FromArgs};
+
+use crate::generate::{bash_completion, fish_completion, man_page, zsh_completion, GenerateTarget};
+
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
+/// Manage the things
+struct TestArgs {
+    #[argh(option, short = 'u')]
+    /// user owning the things
+    user: Option<String>,
+
+    #[argh(switch)]
+    /// print more [details]
+    verbose: bool,
+
+    #[argh(subcommand)]
+    command: TestCommand,
+}
+
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
+#[argh(subcommand)]
+enum TestCommand {
+    Add(TestAddCommand),
+    Clear(TestClearCommand),
+}
+
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
+/// Add a thing
+#[argh(subcommand, name = "add")]
+struct TestAddCommand {
+    #[argh(positional)]
+    /// file holding the thing
+    file: String,
+}
+
+#[derive(FromArgs, ArgsInfo, PartialEq, Debug)]
+/// Remove every thing
+#[argh(subcommand, name = "clear")]
+struct TestClearCommand {}
+
+#[test]
+fn target_parsing() {
+    assert_eq!("bash".parse::<GenerateTarget>(), Ok(GenerateTarget::Bash));
+    assert_eq!("zsh".parse::<GenerateTarget>(), Ok(GenerateTarget::Zsh));
+    assert_eq!("fish".parse::<GenerateTarget>(), Ok(GenerateTarget::Fish));
+    assert_eq!("man".parse::<GenerateTarget>(), Ok(GenerateTarget::Man));
+    assert!("powershell".parse::<GenerateTarget>().is_err());
+}
+
+#[test]
+fn bash_completion_generation() {
+    let script = bash_completion("things", &TestArgs::get_args_info());
+
+    assert!(script.contains("\"things,-u\"|\"things,--user\") ((i++)) ;;"));
+    assert!(script.contains("\"things,add\") cmd=\"things add\" ;;"));
+    assert!(script.contains("compgen -u -- \"${cur}\""));
+    assert!(script.contains("\"things add\") opts=\"--help\" files=1 ;;"));
+    assert!(script.ends_with("complete -F _things things\n"));
+}
+
+#[test]
+fn zsh_completion_generation() {
+    let script = zsh_completion("things", &TestArgs::get_args_info());
+
+    assert!(script.starts_with("#compdef things\n"));
+    assert!(script.contains("'(-u --user)'{-u,--user}'[user owning the things]:user:_users'"));
+    assert!(script.contains("'--verbose[print more \\[details\\]]'"));
+    assert!(script.contains("'add:Add a thing'"));
+    assert!(script.contains("add) _things_add ;;"));
+    assert!(script.contains("\n_things_clear() {\n"));
+    assert!(script.contains("':file:_files'"));
+}
+
+#[test]
+fn fish_completion_generation() {
+    let script = fish_completion("things", &TestArgs::get_args_info());
+
+    assert!(script.contains(
+        "complete -c things -n 'not __fish_seen_subcommand_from add clear' -s u -l user -r -f -a '(__fish_complete_users)' -d 'user owning the things'"
+    ));
+    assert!(script.contains(
+        "complete -c things -n 'not __fish_seen_subcommand_from add clear' -f -a clear -d 'Remove every thing'"
+    ));
+}
+
+#[test]
+fn man_page_generation() {
+    let page = man_page("things", &TestArgs::get_args_info());
+
+    assert!(page.starts_with(".TH \"THINGS\" \"1\""));
+    assert!(page.contains(".SH NAME\nthings \\- Manage the things\n"));
+    assert!(page.contains("[\\-u <user>] [\\-\\-verbose] <command> [<args>]"));
+    assert!(page
+        .contains(".TP\n\\fB\\-u\\fR, \\fB\\-\\-user\\fR \\fIuser\\fR\nuser owning the things\n"));
+    assert!(page.contains(".SS \"things add\"\nAdd a thing\n.PP\n.B things add\n<file>\n"));
+}
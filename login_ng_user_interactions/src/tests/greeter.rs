@@ -0,0 +1,134 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    collections::VecDeque,
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+};
+
+use crate::{
+    greeter::{serve_greeter, Greeter, GreeterError},
+    login::LoginUserInteractionHandler,
+};
+
+/// Answers the prompts from a script and records the messages it is asked to show
+#[derive(Default)]
+struct RecordingHandler {
+    answers: VecDeque<Option<String>>,
+    shown: Vec<String>,
+}
+
+impl LoginUserInteractionHandler for RecordingHandler {
+    fn provide_username(&mut self, _username: &String) {}
+
+    fn prompt_secret(&mut self, msg: &String) -> Option<String> {
+        self.shown.push(msg.clone());
+        self.answers.pop_front().flatten()
+    }
+
+    fn prompt_plain(&mut self, msg: &String) -> Option<String> {
+        self.shown.push(msg.clone());
+        self.answers.pop_front().flatten()
+    }
+
+    fn print_info(&mut self, msg: &String) {
+        self.shown.push(format!("info: {msg}"));
+    }
+
+    fn print_error(&mut self, msg: &String) {
+        self.shown.push(format!("error: {msg}"));
+    }
+}
+
+#[test]
+fn test_greeter_protocol() {
+    let (privileged, unprivileged) = UnixStream::pair().unwrap();
+
+    let server = std::thread::spawn(move || {
+        let mut handler = RecordingHandler {
+            answers: VecDeque::from([Some(String::from("user")), None, Some(String::from(""))]),
+            shown: vec![],
+        };
+
+        let result = serve_greeter(unprivileged, &mut handler);
+
+        (result, handler.shown)
+    });
+
+    let mut greeter = Greeter::from_stream(privileged);
+    assert_eq!(greeter.prompt_plain("username: ").unwrap(), "user");
+    assert!(matches!(
+        greeter.prompt_secret("password: "),
+        Err(GreeterError::NoAnswer)
+    ));
+    greeter.print_info("welcome").unwrap();
+    greeter.print_error("wrong password").unwrap();
+    assert_eq!(greeter.prompt_secret("password: ").unwrap(), "");
+
+    // closing the socket ends the greeter
+    drop(greeter);
+
+    let (result, shown) = server.join().unwrap();
+    assert!(result.is_ok());
+    assert_eq!(
+        shown,
+        vec![
+            String::from("username: "),
+            String::from("password: "),
+            String::from("info: welcome"),
+            String::from("error: wrong password"),
+            String::from("password: "),
+        ]
+    );
+}
+
+#[test]
+fn test_greeter_protocol_errors() {
+    // the greeter refuses requests it does not know
+    let (mut privileged, unprivileged) = UnixStream::pair().unwrap();
+    privileged.write_all(&[42, 0, 0, 0, 0]).unwrap();
+    assert!(matches!(
+        serve_greeter(unprivileged, &mut RecordingHandler::default()),
+        Err(GreeterError::Protocol)
+    ));
+
+    // and the privileged end replies that are oversized, not UTF-8 or of the wrong kind
+    for reply in [
+        vec![0, 0xff, 0xff, 0xff, 0xff],
+        vec![0, 2, 0, 0, 0, 0xc3, 0x28],
+        vec![2, 0, 0, 0, 0],
+    ] {
+        let (privileged, mut unprivileged) = UnixStream::pair().unwrap();
+
+        let fake = std::thread::spawn(move || {
+            let mut request = [0u8; 5 + 10];
+            unprivileged.read_exact(&mut request).unwrap();
+            unprivileged.write_all(reply.as_slice()).unwrap();
+        });
+
+        let mut greeter = Greeter::from_stream(privileged);
+        assert!(matches!(
+            greeter.prompt_plain("username: "),
+            Err(GreeterError::Protocol)
+        ));
+
+        fake.join().unwrap();
+    }
+}
@@ -0,0 +1,261 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use login_ng::{
+    command::SessionCommand,
+    faillock::{read_tally, FaillockConfig},
+};
+
+use crate::{login::*, mock::*, pam::PamLoginError};
+
+/// Answers the prompts from a script
+struct ScriptedHandler {
+    answers: VecDeque<String>,
+    username: Option<String>,
+}
+
+impl ScriptedHandler {
+    fn new(answers: &[&str]) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self {
+            answers: answers.iter().map(|answer| String::from(*answer)).collect(),
+            username: None,
+        }))
+    }
+}
+
+impl LoginUserInteractionHandler for ScriptedHandler {
+    fn provide_username(&mut self, username: &String) {
+        self.username = Some(username.clone());
+    }
+
+    fn prompt_secret(&mut self, _msg: &String) -> Option<String> {
+        self.answers.pop_front()
+    }
+
+    fn prompt_plain(&mut self, _msg: &String) -> Option<String> {
+        self.answers.pop_front()
+    }
+
+    fn print_info(&mut self, _msg: &String) {}
+
+    fn print_error(&mut self, _msg: &String) {}
+}
+
+/// The PAM executor resolves the user through NSS: the mock accounts are the one running the tests
+fn current_user() -> (String, u32) {
+    (
+        login_ng::users::get_current_username()
+            .unwrap()
+            .to_string_lossy()
+            .to_string(),
+        login_ng::users::get_current_uid(),
+    )
+}
+
+fn accounts() -> MockAccounts {
+    let (username, uid) = current_user();
+
+    MockAccounts::new().with_account(&username, "s3cret", uid)
+}
+
+fn strategy() -> SessionCommandRetrival {
+    SessionCommandRetrival::Defined(SessionCommand::new(String::from("/bin/sh")))
+}
+
+#[test]
+fn test_mock_pam_login() {
+    let (username, uid) = current_user();
+
+    let handler = ScriptedHandler::new(&[username.as_str(), "s3cret"]);
+    let mut executor = mock_pam_executor(accounts(), handler.clone()).with_dry_run(true);
+
+    assert_eq!(
+        executor.execute(&None, &strategy()).unwrap(),
+        LoginResult::Authenticated {
+            username: username.clone(),
+            uid,
+            password_expired: false,
+        }
+    );
+
+    // every scripted answer has been consumed by the conversation
+    let handler = handler.lock().unwrap();
+    assert!(handler.answers.is_empty());
+    assert_eq!(handler.username, Some(username));
+}
+
+#[test]
+fn test_mock_pam_failures() {
+    let (username, _) = current_user();
+    let dir = std::path::PathBuf::from("./test_mock_pam_faillock");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let faillock_config = FaillockConfig::parse(&format!(
+        "dir = {}\ndeny = 1\neven_deny_root",
+        dir.display()
+    ));
+
+    let handler = ScriptedHandler::new(&["wrong", "s3cret"]);
+    let mut executor = mock_pam_executor(
+        accounts().with_faillock_config(faillock_config.clone()),
+        handler.clone(),
+    )
+    .with_dry_run(true);
+
+    let maybe_username = Some(username.clone());
+    assert!(matches!(
+        executor.execute(&maybe_username, &strategy()),
+        Err(LoginError::AuthenticationFailed)
+    ));
+    let tally = read_tally(&faillock_config, &username).unwrap();
+
+    // the right password is not even asked for once the account is locked
+    let locked = executor.execute(&maybe_username, &strategy());
+    let unanswered = handler.lock().unwrap().answers.len();
+
+    // a username typed at the prompt is checked too, and the right password doesn't unlock it
+    let handler = ScriptedHandler::new(&[username.as_str(), "s3cret"]);
+    let mut executor = mock_pam_executor(
+        accounts().with_faillock_config(faillock_config.clone()),
+        handler,
+    )
+    .with_dry_run(true);
+    let prompted = executor.execute(&None, &strategy());
+    let prompted_tally = read_tally(&faillock_config, &username).unwrap();
+
+    // names that are not accounts get no tally
+    let handler = ScriptedHandler::new(&["login_ng_no_such_user", "wrong"]);
+    let mut executor = mock_pam_executor(
+        accounts().with_faillock_config(faillock_config.clone()),
+        handler,
+    )
+    .with_dry_run(true);
+    let _ = executor.execute(&None, &strategy());
+    let unknown_tally = read_tally(&faillock_config, "login_ng_no_such_user").unwrap_or_default();
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(tally.len(), 1);
+    assert!(matches!(
+        locked,
+        Err(LoginError::PamError(PamLoginError::Locked(_)))
+    ));
+    assert_eq!(unanswered, 1);
+    assert!(matches!(
+        prompted,
+        Err(LoginError::PamError(PamLoginError::Locked(_)))
+    ));
+    assert_eq!(prompted_tally.len(), 1);
+    assert!(unknown_tally.is_empty());
+
+    // account management runs after a successful authentication
+    let handler = ScriptedHandler::new(&["s3cret"]);
+    let mut executor = mock_pam_executor(
+        accounts().with_state(&username, MockAccountState::Expired),
+        handler,
+    )
+    .with_dry_run(true);
+    assert!(matches!(
+        executor.execute(&maybe_username, &strategy()),
+        Err(LoginError::AccountExpired)
+    ));
+
+    let handler = ScriptedHandler::new(&["s3cret"]);
+    let mut executor = mock_pam_executor(
+        accounts().with_state(&username, MockAccountState::Denied),
+        handler,
+    )
+    .with_dry_run(true);
+    assert!(matches!(
+        executor.execute(&maybe_username, &strategy()),
+        Err(LoginError::PermissionDenied)
+    ));
+
+    // an unanswered prompt is a failure of the conversation
+    let handler = ScriptedHandler::new(&[]);
+    let mut executor = mock_pam_executor(accounts(), handler).with_dry_run(true);
+    assert!(matches!(
+        executor.execute(&None, &strategy()),
+        Err(LoginError::PamError(PamLoginError::Authentication(_)))
+    ));
+}
+
+#[test]
+fn test_mock_pam_password_expired() {
+    let (username, uid) = current_user();
+
+    let handler = ScriptedHandler::new(&["s3cret"]);
+    let mut executor = mock_pam_executor(
+        accounts().with_state(&username, MockAccountState::PasswordExpired),
+        handler,
+    )
+    .with_dry_run(true);
+
+    assert_eq!(
+        executor
+            .execute(&Some(username.clone()), &strategy())
+            .unwrap(),
+        LoginResult::Authenticated {
+            username,
+            uid,
+            password_expired: true,
+        }
+    );
+}
+
+#[cfg(feature = "greetd")]
+#[test]
+fn test_mock_greetd_login() {
+    use crate::greetd::GreetdLoginExecutor;
+
+    let path = std::path::PathBuf::from("./test_mock_greetd.sock");
+    let (username, _) = current_user();
+    let greetd = MockGreetd::spawn(&path, accounts()).unwrap();
+
+    let strategy = SessionCommandRetrival::Defined(
+        SessionCommand::new(String::from("/bin/sh"))
+            .with_env(String::from("XDG_SESSION_TYPE"), String::from("tty")),
+    );
+
+    let handler = ScriptedHandler::new(&["wrong", "s3cret"]);
+    let mut executor = GreetdLoginExecutor::new(greetd.socket(), handler);
+
+    let username = Some(username);
+    assert_eq!(
+        executor.execute(&username, &strategy).unwrap(),
+        LoginResult::Failure
+    );
+    assert!(matches!(
+        executor.execute(&username, &strategy).unwrap(),
+        LoginResult::Success { .. }
+    ));
+
+    let started = greetd.started_sessions();
+    assert_eq!(started.len(), 1);
+    assert_eq!(Some(started[0].username.clone()), username);
+    assert_eq!(started[0].cmd, vec![String::from("/bin/sh")]);
+    assert!(started[0]
+        .env
+        .contains(&String::from("XDG_SESSION_TYPE=tty")));
+}
@@ -0,0 +1,98 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::path::Path;
+
+use crate::theme::{Color, Style, Theme};
+
+#[test]
+fn color_parsing() {
+    assert_eq!(Color::parse("red"), Some(Color::Indexed(1)));
+    assert_eq!(Color::parse(" Bright-Cyan "), Some(Color::Indexed(14)));
+    assert_eq!(Color::parse("208"), Some(Color::Indexed(208)));
+    assert_eq!(Color::parse("default"), Some(Color::Default));
+    assert_eq!(Color::parse("bright-208"), None);
+    assert_eq!(Color::parse("256"), None);
+    assert_eq!(Color::parse("purple"), None);
+}
+
+#[test]
+fn style_painting() {
+    assert_eq!(
+        Style::new(Color::Indexed(1), true).paint("error"),
+        "\x1b[1;31merror\x1b[0m"
+    );
+    assert_eq!(
+        Style::new(Color::Indexed(12), false).paint("info"),
+        "\x1b[94minfo\x1b[0m"
+    );
+    assert_eq!(
+        Style::new(Color::Indexed(208), false).paint("prompt"),
+        "\x1b[38;5;208mprompt\x1b[0m"
+    );
+    assert_eq!(Style::new(Color::Default, false).paint("plain"), "plain");
+}
+
+#[test]
+fn theme_parsing() {
+    let dir = Path::new("./theme_parsing_test");
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+
+    let header = dir.join("header.txt");
+    std::fs::write(&header, " _             _\n| | ___   __ _(_)_ __\n\n").unwrap();
+
+    let theme = Theme::parse(format!(
+        "[Theme]\nprompt_color = green\nprompt_bold = false\nerror_color = 196\nheader = {}\n",
+        header.display()
+    ))
+    .unwrap();
+
+    assert!(theme.colored());
+    assert_eq!(theme.mask(), None);
+    assert_eq!(theme.prompt("Username: "), "\x1b[32mUsername: \x1b[0m");
+    assert_eq!(theme.error("failed"), "\x1b[1;38;5;196mfailed\x1b[0m");
+    assert_eq!(
+        theme.header(),
+        Some(&String::from(" _             _\n| | ___   __ _(_)_ __"))
+    );
+
+    // without colors everything is printed as-is
+    let theme = theme.with_colors(false);
+    assert_eq!(theme.prompt("Username: "), "Username: ");
+    assert_eq!(theme.info("welcome"), "welcome");
+    assert_eq!(
+        theme.styled_header(),
+        Some(String::from(" _             _\n| | ___   __ _(_)_ __"))
+    );
+
+    let theme = Theme::parse(String::from(
+        "[Theme]\ncolors = false\nheader = /nonexistent\n",
+    ))
+    .unwrap();
+    assert!(!theme.colored());
+    assert_eq!(theme.header(), None);
+
+    let theme = Theme::parse(String::from("[Theme]\nsecret_mask = \u{2022}\n")).unwrap();
+    assert_eq!(theme.mask(), Some('\u{2022}'));
+
+    assert_eq!(Theme::plain().error("failed"), "failed");
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,33 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use crate::vt::VtSelection;
+
+#[test]
+fn test_vt_selection() {
+    assert_eq!("auto".parse::<VtSelection>(), Ok(VtSelection::Auto));
+    assert_eq!("7".parse::<VtSelection>(), Ok(VtSelection::Number(7)));
+
+    assert!("0".parse::<VtSelection>().is_err());
+    assert!("tty7".parse::<VtSelection>().is_err());
+    assert!("".parse::<VtSelection>().is_err());
+
+    assert_eq!(VtSelection::Number(7).to_string(), "7");
+    assert_eq!(VtSelection::Auto.to_string(), "auto");
+}
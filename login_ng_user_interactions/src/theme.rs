@@ -0,0 +1,268 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::io::IsTerminal;
+use std::path::Path;
+
+use configparser::ini::Ini;
+
+use crate::login::config_dir;
+
+/// Configuration file of the prompt styling, in the login_ng configuration directory
+pub const THEME_CONFIG_FILE: &str = "theme.conf";
+
+/// ANSI sequence that resets every attribute set by a style
+const RESET: &str = "\x1b[0m";
+
+/// A color of the terminal palette: one of the eight named ones or an index of the 256-color palette
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Default,
+    Indexed(u8),
+}
+
+impl Color {
+    /// Parse black, red, green, yellow, blue, magenta, cyan, white (optionally prefixed
+    /// by bright-), default or a number between 0 and 255
+    pub fn parse(value: &str) -> Option<Self> {
+        const NAMES: [&str; 8] = [
+            "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+        ];
+
+        let value = value.trim().to_lowercase();
+
+        if value == "default" {
+            return Some(Self::Default);
+        }
+
+        let (bright, name) = match value.strip_prefix("bright-") {
+            Some(name) => (8, name),
+            None => (0, value.as_str()),
+        };
+
+        match NAMES.iter().position(|known| *known == name) {
+            Some(index) => Some(Self::Indexed(bright + index as u8)),
+            None if bright == 0 => value.parse::<u8>().ok().map(Self::Indexed),
+            None => None,
+        }
+    }
+
+    fn sgr(&self) -> Option<String> {
+        match self {
+            Self::Default => None,
+            Self::Indexed(index) if *index < 8 => Some(format!("{}", 30 + index)),
+            Self::Indexed(index) if *index < 16 => Some(format!("{}", 90 + index - 8)),
+            Self::Indexed(index) => Some(format!("38;5;{index}")),
+        }
+    }
+}
+
+/// How a kind of text is printed: its foreground color and weight
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    color: Color,
+    bold: bool,
+}
+
+impl Style {
+    pub fn new(color: Color, bold: bool) -> Self {
+        Self { color, bold }
+    }
+
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    pub fn bold(&self) -> bool {
+        self.bold
+    }
+
+    /// Wrap the text in the escape sequences of the style, if it has any
+    pub fn paint(&self, text: &str) -> String {
+        let mut params = vec![];
+
+        if self.bold {
+            params.push(String::from("1"));
+        }
+
+        if let Some(color) = self.color.sgr() {
+            params.push(color);
+        }
+
+        match params.is_empty() {
+            true => String::from(text),
+            false => format!("\x1b[{}m{text}{RESET}", params.join(";")),
+        }
+    }
+}
+
+/// Styling of what the command line prompt prints: prompts, informational and error messages,
+/// and an optional (ASCII-art) header shown before the first prompt.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    colored: bool,
+    prompt: Style,
+    info: Style,
+    error: Style,
+    header: Option<String>,
+    header_style: Style,
+    mask: Option<char>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            colored: true,
+            prompt: Style::new(Color::Default, true),
+            info: Style::new(Color::Indexed(6), false),
+            error: Style::new(Color::Indexed(1), true),
+            header: None,
+            header_style: Style::new(Color::Default, true),
+            mask: None,
+        }
+    }
+}
+
+impl Theme {
+    /// A theme printing everything as-is, without escape sequences
+    pub fn plain() -> Self {
+        Self::default().with_colors(false)
+    }
+
+    /// Parse the Theme section of a theme.conf file: prompt_color, info_color, error_color and
+    /// header_color take a color (see [`Color::parse`]), prompt_bold and error_bold whether
+    /// the text is bold, header the path of a text file shown before the first prompt and
+    /// secret_mask the character shown for every character of a secret typed (none by default).
+    pub fn parse(content: String) -> Option<Self> {
+        let mut config = Ini::new();
+        config.read(content).ok()?;
+
+        let mut theme = Self::default();
+
+        let style = |color_key: &str, bold_key: &str, default: Style| {
+            Style::new(
+                config
+                    .get("Theme", color_key)
+                    .and_then(|color| Color::parse(&color))
+                    .unwrap_or(default.color()),
+                config
+                    .getbool("Theme", bold_key)
+                    .ok()
+                    .flatten()
+                    .unwrap_or(default.bold()),
+            )
+        };
+
+        theme.prompt = style("prompt_color", "prompt_bold", theme.prompt);
+        theme.info = style("info_color", "info_bold", theme.info);
+        theme.error = style("error_color", "error_bold", theme.error);
+        theme.header_style = style("header_color", "header_bold", theme.header_style);
+
+        theme.colored = config
+            .getbool("Theme", "colors")
+            .ok()
+            .flatten()
+            .unwrap_or(true);
+
+        theme.header = config
+            .get("Theme", "header")
+            .filter(|path| !path.is_empty())
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|header| header.trim_end().to_string())
+            .filter(|header| !header.is_empty());
+
+        theme.mask = config
+            .get("Theme", "secret_mask")
+            .and_then(|mask| mask.chars().next());
+
+        Some(theme)
+    }
+
+    pub fn load_from(path: &Path) -> Option<Self> {
+        Self::parse(std::fs::read_to_string(path).ok()?)
+    }
+
+    /// Load theme.conf from the login_ng configuration directory, falling back to the default theme
+    pub fn load() -> Self {
+        Self::load_from(&Path::new(config_dir()).join(THEME_CONFIG_FILE)).unwrap_or_default()
+    }
+
+    /// Enable or disable the escape sequences: with colors disabled every text is printed as-is
+    pub fn with_colors(mut self, colored: bool) -> Self {
+        self.colored = colored;
+        self
+    }
+
+    pub fn with_header(mut self, header: Option<String>) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Show the given character for every character of a secret typed, instead of nothing at all
+    pub fn with_mask(mut self, mask: Option<char>) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    pub fn mask(&self) -> Option<char> {
+        self.mask
+    }
+
+    pub fn colored(&self) -> bool {
+        self.colored
+    }
+
+    pub fn header(&self) -> Option<&String> {
+        self.header.as_ref()
+    }
+
+    fn paint(&self, style: &Style, text: &str) -> String {
+        match self.colored {
+            true => style.paint(text),
+            false => String::from(text),
+        }
+    }
+
+    pub fn prompt(&self, text: &str) -> String {
+        self.paint(&self.prompt, text)
+    }
+
+    pub fn info(&self, text: &str) -> String {
+        self.paint(&self.info, text)
+    }
+
+    pub fn error(&self, text: &str) -> String {
+        self.paint(&self.error, text)
+    }
+
+    /// The header, styled, if there is one
+    pub fn styled_header(&self) -> Option<String> {
+        self.header
+            .as_ref()
+            .map(|header| self.paint(&self.header_style, header))
+    }
+}
+
+/// Whether escape sequences can be written to the terminal: not when
+/// NO_COLOR (see https://no-color.org) is set or stderr is not a terminal.
+pub fn colors_supported() -> bool {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+
+    !no_color && std::io::stderr().is_terminal() && std::env::var("TERM").as_deref() != Ok("dumb")
+}
@@ -0,0 +1,322 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::Write,
+    os::fd::{AsRawFd, RawFd},
+    str::FromStr,
+};
+
+use login_ng::vt::{ioctl_result, CONSOLE_PATH, VT_ACTIVATE};
+use thiserror::Error;
+
+use crate::CLEAR_SCREEN;
+
+// from linux/vt.h and linux/kd.h
+pub(crate) const VT_SETMODE: libc::c_ulong = 0x5602;
+pub(crate) const VT_GETSTATE: libc::c_ulong = 0x5603;
+pub(crate) const VT_RELDISP: libc::c_ulong = 0x5605;
+pub(crate) const VT_WAITACTIVE: libc::c_ulong = 0x5607;
+pub(crate) const VT_AUTO: libc::c_char = 0x00;
+pub(crate) const VT_PROCESS: libc::c_char = 0x01;
+pub(crate) const VT_RELEASE: libc::c_int = 0x01;
+pub(crate) const VT_ACKACQ: libc::c_int = 0x02;
+pub(crate) const KDSETMODE: libc::c_ulong = 0x4B3A;
+pub(crate) const KD_TEXT: libc::c_int = 0x00;
+
+#[repr(C)]
+struct VtMode {
+    mode: libc::c_char,
+    waitv: libc::c_char,
+    relsig: libc::c_short,
+    acqsig: libc::c_short,
+    frsig: libc::c_short,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct VtStat {
+    v_active: libc::c_ushort,
+    v_signal: libc::c_ushort,
+    v_state: libc::c_ushort,
+}
+
+#[derive(Debug, Error)]
+pub enum VtError {
+    #[error("I/O error on {0}: {1}")]
+    IOError(String, std::io::Error),
+
+    #[error("Invalid VT number {0}")]
+    InvalidNumber(u16),
+
+    #[error("No free VT available")]
+    NoFreeVt,
+}
+
+/// Sets how switches away from the VT are handled: VT_PROCESS makes the kernel ask
+/// for permission with SIGUSR1/SIGUSR2, VT_AUTO lets the kernel switch on its own
+pub(crate) fn set_vt_mode(fd: RawFd, mode: libc::c_char) -> std::io::Result<()> {
+    let vt_mode = VtMode {
+        mode,
+        waitv: 0,
+        relsig: match mode {
+            VT_PROCESS => libc::SIGUSR1 as libc::c_short,
+            _ => 0,
+        },
+        acqsig: match mode {
+            VT_PROCESS => libc::SIGUSR2 as libc::c_short,
+            _ => 0,
+        },
+        frsig: 0,
+    };
+
+    ioctl_result(unsafe { libc::ioctl(fd, VT_SETMODE, &vt_mode as *const VtMode) })
+}
+
+/// Waits for the child that carries on the work of this process, forwarding the signal that
+/// interrupts the wait, then exits with its status
+fn exit_with_child(child: libc::pid_t) -> ! {
+    let mut status = 0;
+    let mut forwarded = false;
+
+    loop {
+        if unsafe { libc::waitpid(child, &mut status, 0) } != -1 {
+            break;
+        }
+
+        if std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted {
+            std::process::exit(1);
+        }
+
+        if let Some(signal) = crate::interrupt::interrupt_signal().filter(|_| !forwarded) {
+            unsafe { libc::kill(child, signal) };
+            forwarded = true;
+        }
+    }
+
+    std::process::exit(match libc::WIFSIGNALED(status) {
+        true => 128 + libc::WTERMSIG(status),
+        false => libc::WEXITSTATUS(status),
+    })
+}
+
+/// Makes the process the leader of a new session, as only a session leader can acquire a
+/// controlling terminal. A process group leader (i.e. started from a shell) cannot create one:
+/// it forks, the child carrying on while the parent waits for it and exits with its status.
+fn become_session_leader() -> std::io::Result<()> {
+    // a service started by systemd is the leader of its session already
+    if unsafe { libc::getsid(0) == libc::getpid() } {
+        return Ok(());
+    }
+
+    if unsafe { libc::getpgrp() == libc::getpid() } {
+        match unsafe { libc::fork() } {
+            -1 => return Err(std::io::Error::last_os_error()),
+            0 => {}
+            child => exit_with_child(child),
+        }
+    }
+
+    match unsafe { libc::setsid() } {
+        -1 => Err(std::io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+fn open_console() -> Result<File, VtError> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(CONSOLE_PATH)
+        .map_err(|err| VtError::IOError(String::from(CONSOLE_PATH), err))
+}
+
+/// Returns the number of the VT currently displayed
+pub fn active_vt() -> Result<u16, VtError> {
+    let console = open_console()?;
+
+    let mut state = VtStat::default();
+    ioctl_result(unsafe {
+        libc::ioctl(console.as_raw_fd(), VT_GETSTATE, &mut state as *mut VtStat)
+    })
+    .map_err(|err| VtError::IOError(String::from(CONSOLE_PATH), err))?;
+
+    Ok(state.v_active)
+}
+
+/// Returns the number of the first VT not opened by any process
+pub fn first_free_vt() -> Result<u16, VtError> {
+    let number = login_ng::vt::first_free_vt()
+        .map_err(|err| VtError::IOError(String::from(CONSOLE_PATH), err))?;
+
+    number
+        .and_then(|number| u16::try_from(number).ok())
+        .ok_or(VtError::NoFreeVt)
+}
+
+/// The VT asked for on the command line: a given number or the first free one
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VtSelection {
+    Number(u16),
+    Auto,
+}
+
+impl FromStr for VtSelection {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(Self::Auto),
+            number => match number.parse::<u16>() {
+                Ok(number) if number > 0 => Ok(Self::Number(number)),
+                _ => Err(format!("invalid VT {value}: expected a number or auto")),
+            },
+        }
+    }
+}
+
+impl fmt::Display for VtSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(number) => write!(f, "{number}"),
+            Self::Auto => write!(f, "auto"),
+        }
+    }
+}
+
+impl VtSelection {
+    /// Open the selected VT, looking up the first free one for auto
+    pub fn open(&self) -> Result<Vt, VtError> {
+        match self {
+            Self::Number(number) => Vt::open(*number),
+            Self::Auto => Vt::open(first_free_vt()?),
+        }
+    }
+}
+
+/// A virtual terminal the greeter runs on: it is activated before prompting,
+/// released to the session once it starts and reclaimed when the session exits.
+#[derive(Debug)]
+pub struct Vt {
+    number: u16,
+    tty: File,
+}
+
+impl Vt {
+    /// Open /dev/ttyN without activating it
+    pub fn open(number: u16) -> Result<Self, VtError> {
+        if number == 0 {
+            return Err(VtError::InvalidNumber(number));
+        }
+
+        let path = Self::path_of(number);
+        let tty = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .map_err(|err| VtError::IOError(path, err))?;
+
+        Ok(Self { number, tty })
+    }
+
+    fn path_of(number: u16) -> String {
+        format!("/dev/tty{number}")
+    }
+
+    pub fn number(&self) -> u16 {
+        self.number
+    }
+
+    pub fn path(&self) -> String {
+        Self::path_of(self.number)
+    }
+
+    pub fn tty(&self) -> &File {
+        &self.tty
+    }
+
+    fn io_error(&self, err: std::io::Error) -> VtError {
+        VtError::IOError(self.path(), err)
+    }
+
+    /// Make this VT the controlling terminal of the process and its standard input,
+    /// output and error, so that prompts are shown there wherever the process was started.
+    ///
+    /// NOTE: unless it leads its session already, the process forks to create a new one:
+    /// this must be called before any thread is started.
+    pub fn attach_stdio(&self) -> Result<(), VtError> {
+        let fd = self.tty.as_raw_fd();
+
+        become_session_leader().map_err(|err| self.io_error(err))?;
+
+        ioctl_result(unsafe { libc::ioctl(fd, libc::TIOCSCTTY, 0) })
+            .map_err(|err| self.io_error(err))?;
+
+        for target in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+            if unsafe { libc::dup2(fd, target) } == -1 {
+                return Err(self.io_error(std::io::Error::last_os_error()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Switch to this VT and wait for the switch to be completed
+    pub fn activate(&self) -> Result<(), VtError> {
+        let fd = self.tty.as_raw_fd();
+        let number = libc::c_int::from(self.number);
+
+        ioctl_result(unsafe { libc::ioctl(fd, VT_ACTIVATE, number) })
+            .map_err(|err| self.io_error(err))?;
+
+        // the switch may be interrupted by a signal: just retry
+        loop {
+            match ioctl_result(unsafe { libc::ioctl(fd, VT_WAITACTIVE, number) }) {
+                Ok(()) => return Ok(()),
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(self.io_error(err)),
+            }
+        }
+    }
+
+    /// Hand the VT over to the session about to be started: the kernel handles
+    /// switches on its own until the session (or its compositor) takes control.
+    pub fn release(&self) -> Result<(), VtError> {
+        let fd = self.tty.as_raw_fd();
+
+        ioctl_result(unsafe { libc::ioctl(fd, KDSETMODE, KD_TEXT) })
+            .map_err(|err| self.io_error(err))?;
+
+        set_vt_mode(fd, VT_AUTO).map_err(|err| self.io_error(err))
+    }
+
+    /// Take the VT back after the session has exited: a crashed compositor may have
+    /// left it in graphics mode, process-controlled or switched to another VT.
+    pub fn reclaim(&self) -> Result<(), VtError> {
+        self.release()?;
+        self.activate()?;
+
+        let mut tty = &self.tty;
+        tty.write_all(CLEAR_SCREEN.as_bytes())
+            .and_then(|_| tty.flush())
+            .map_err(|err| self.io_error(err))
+    }
+}
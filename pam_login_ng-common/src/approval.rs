@@ -0,0 +1,493 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use configparser::ini::Ini;
+use login_ng::{
+    config::read_private_file,
+    secure::{constant_time_eq, LockedBytes},
+};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::{RwLock, Semaphore},
+};
+
+use login_ng::storage::{load_user_auth_data, StorageSource};
+
+/// How long a challenge waits for the companion device before being discarded
+pub const APPROVAL_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Address the approval endpoint listens on when approval.conf does not specify one
+pub const DEFAULT_APPROVAL_LISTEN: &str = "127.0.0.1:8734";
+
+const MAX_REQUEST_SIZE: usize = 4096;
+
+/// How long a companion device has to send the whole request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Requests served at the same time: connections beyond it are refused right away
+const MAX_CONNECTIONS: usize = 16;
+
+/// Shortest pre-shared token accepted in approval.conf
+const MIN_TOKEN_LEN: usize = 16;
+
+#[derive(Debug, Error)]
+pub enum ApprovalError {
+    #[error("Unknown or expired challenge")]
+    UnknownChallenge,
+
+    #[error("The challenge has already been approved")]
+    AlreadyApproved,
+
+    #[error("The approval key is not valid for the user")]
+    InvalidKey,
+
+    #[error("Malformed request")]
+    MalformedRequest,
+
+    #[error("Missing or wrong pre-shared token")]
+    Unauthorized,
+
+    #[error("The request took too long")]
+    Timeout,
+
+    #[error("I/O error: {0}")]
+    IOError(#[from] std::io::Error),
+}
+
+/// The status of a challenge, as seen by the greeter waiting for it: an approved challenge
+/// carries the main password the key has unlocked, so that the key never leaves the service
+pub enum ApprovalStatus {
+    Pending,
+    Approved(LockedBytes),
+    Expired,
+}
+
+struct Approval {
+    username: String,
+    created: Instant,
+    /// A key is being checked: the challenge allows no other attempt meanwhile
+    verifying: bool,
+    main_password: Option<LockedBytes>,
+}
+
+/// Login challenges shown by greeters as QR codes and waiting to be approved
+/// by a companion device knowing one of the user's secondary passwords.
+#[derive(Default)]
+pub struct PendingApprovals {
+    approvals: HashMap<String, Approval>,
+}
+
+fn random_challenge() -> String {
+    (0..32)
+        .map(|_| format!("{:02x}", crate::rand::random::<u8>()))
+        .collect()
+}
+
+impl PendingApprovals {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn purge_expired(&mut self) {
+        self.approvals
+            .retain(|_, approval| approval.created.elapsed() < APPROVAL_TIMEOUT);
+    }
+
+    /// Creates a new challenge for the given user and returns it
+    pub fn create(&mut self, username: &str) -> String {
+        self.purge_expired();
+
+        let challenge = random_challenge();
+
+        self.approvals.insert(
+            challenge.clone(),
+            Approval {
+                username: username.to_string(),
+                created: Instant::now(),
+                verifying: false,
+                main_password: None,
+            },
+        );
+
+        challenge
+    }
+
+    /// Starts checking a key for the given challenge, returning the user it belongs to:
+    /// until the check is completed no other attempt is accepted for the challenge
+    pub fn begin_approval(&mut self, challenge: &str) -> Result<String, ApprovalError> {
+        self.purge_expired();
+
+        let approval = self
+            .approvals
+            .get_mut(challenge)
+            .ok_or(ApprovalError::UnknownChallenge)?;
+
+        if approval.main_password.is_some() || approval.verifying {
+            return Err(ApprovalError::AlreadyApproved);
+        }
+
+        approval.verifying = true;
+
+        Ok(approval.username.clone())
+    }
+
+    /// Completes the check started by begin_approval with the main password the key unlocked:
+    /// None means the key was not valid
+    pub fn complete_approval(
+        &mut self,
+        challenge: &str,
+        main_password: Option<LockedBytes>,
+    ) -> Result<(), ApprovalError> {
+        // a challenge allows a single guess: keys cannot be brute-forced through the endpoint
+        let Some(main_password) = main_password else {
+            self.approvals.remove(challenge);
+            return Err(ApprovalError::InvalidKey);
+        };
+
+        let approval = self
+            .approvals
+            .get_mut(challenge)
+            .ok_or(ApprovalError::UnknownChallenge)?;
+        approval.verifying = false;
+        approval.main_password = Some(main_password);
+
+        Ok(())
+    }
+
+    /// Checks on a challenge for the given user: an approved challenge is consumed
+    pub fn status(&mut self, challenge: &str, username: &str) -> ApprovalStatus {
+        self.purge_expired();
+
+        match self.approvals.get(challenge) {
+            Some(approval) if approval.username == username => match &approval.main_password {
+                Some(_) => match self
+                    .approvals
+                    .remove(challenge)
+                    .and_then(|a| a.main_password)
+                {
+                    Some(main_password) => ApprovalStatus::Approved(main_password),
+                    None => ApprovalStatus::Expired,
+                },
+                None => ApprovalStatus::Pending,
+            },
+            _ => ApprovalStatus::Expired,
+        }
+    }
+
+    /// Discards a challenge the greeter is no longer waiting for
+    pub fn cancel(&mut self, challenge: &str) {
+        self.approvals.remove(challenge);
+    }
+}
+
+/// The main password the given key unlocks in the login-ng data of the given user, if any
+fn unlock_main(username: &str, key: &str) -> Option<LockedBytes> {
+    load_user_auth_data(&StorageSource::Username(username.to_string()))
+        .ok()
+        .flatten()
+        .and_then(|user_cfg| user_cfg.main_by_secret(Some(key.as_bytes())).ok())
+}
+
+/// Approves a challenge if the given key unlocks the login-ng data of the user the challenge
+/// belongs to: the (slow on purpose) check runs without holding the lock on the challenges
+pub async fn approve(
+    approvals: &RwLock<PendingApprovals>,
+    challenge: &str,
+    key: &str,
+) -> Result<(), ApprovalError> {
+    let username = approvals.write().await.begin_approval(challenge)?;
+
+    let candidate = key.to_string();
+    let main_password = tokio::task::spawn_blocking(move || unlock_main(&username, &candidate))
+        .await
+        .unwrap_or(None);
+
+    approvals
+        .write()
+        .await
+        .complete_approval(challenge, main_password)
+}
+
+/// Settings of the phone-approved login flow, read from approval.conf
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApprovalConfig {
+    listen: SocketAddr,
+    url: String,
+    token: String,
+}
+
+impl ApprovalConfig {
+    /// Loads approval.conf from the given directory: None means the flow is disabled,
+    /// as it is when no pre-shared token (of at least 16 characters) has been set.
+    /// The file holds the token, so it is refused if anyone but root can read it.
+    pub fn load(dir: &Path) -> Option<Self> {
+        let mut config = Ini::new();
+        config
+            .read(read_private_file(&dir.join("approval.conf")).ok()?)
+            .ok()?;
+
+        if !config
+            .getboolcoerce("approval", "enabled")
+            .ok()
+            .flatten()
+            .unwrap_or(false)
+        {
+            return None;
+        }
+
+        let listen: SocketAddr = config
+            .get("approval", "listen")
+            .unwrap_or(String::from(DEFAULT_APPROVAL_LISTEN))
+            .parse()
+            .ok()?;
+
+        // the address devices reach the endpoint at might differ from the one it listens on
+        let url = config
+            .get("approval", "url")
+            .unwrap_or(format!("http://{listen}/approve"));
+
+        // companion devices must prove they have been paired before a key is even checked
+        let token = config
+            .get("approval", "token")
+            .filter(|token| token.len() >= MIN_TOKEN_LEN)?;
+
+        Some(Self { listen, url, token })
+    }
+
+    pub fn listen(&self) -> SocketAddr {
+        self.listen
+    }
+
+    pub fn url(&self) -> &str {
+        self.url.as_str()
+    }
+
+    pub fn token(&self) -> &str {
+        self.token.as_str()
+    }
+
+    /// The content of the QR code the greeter displays for the given challenge
+    pub fn qr_payload(&self, challenge: &str, username: &str) -> String {
+        format!(
+            "{}?challenge={}&user={}",
+            self.url,
+            url_encode(challenge),
+            url_encode(username)
+        )
+    }
+}
+
+/// Parses an application/x-www-form-urlencoded body into (challenge, key)
+pub fn parse_approval_form(body: &str) -> Result<(String, String), ApprovalError> {
+    let mut challenge = None;
+    let mut key = None;
+
+    for pair in body.trim().split('&') {
+        let (name, value) = pair
+            .split_once('=')
+            .ok_or(ApprovalError::MalformedRequest)?;
+        let value = url_decode(value).ok_or(ApprovalError::MalformedRequest)?;
+
+        match name {
+            "challenge" => challenge = Some(value),
+            "key" => key = Some(value),
+            _ => {}
+        }
+    }
+
+    match (challenge, key) {
+        (Some(challenge), Some(key)) => Ok((challenge, key)),
+        _ => Err(ApprovalError::MalformedRequest),
+    }
+}
+
+/// Percent-encodes everything but the unreserved characters of RFC 3986
+pub fn url_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                String::from(b as char)
+            }
+            b => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+fn url_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 2;
+            }
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+/// Returns true if the request headers carry the pre-shared token as "Authorization: Bearer <token>"
+pub fn request_authorized(head: &str, token: &str) -> bool {
+    head.lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("authorization"))
+        .and_then(|(_, value)| value.trim().strip_prefix("Bearer "))
+        .map(|provided| constant_time_eq(provided.trim().as_bytes(), token.as_bytes()))
+        .unwrap_or(false)
+}
+
+/// Reads the request, returning the body once the headers have been checked
+async fn read_approval_request(
+    stream: &mut TcpStream,
+    token: &str,
+) -> Result<String, ApprovalError> {
+    let mut request = vec![0u8; MAX_REQUEST_SIZE];
+    let mut len = 0;
+
+    // read until the end of headers and the whole body have been received
+    let body = loop {
+        let read = stream.read(&mut request[len..]).await?;
+        if read == 0 {
+            return Err(ApprovalError::MalformedRequest);
+        }
+        len += read;
+
+        let text = String::from_utf8_lossy(&request[..len]).to_string();
+        if let Some((head, body)) = text.split_once("\r\n\r\n") {
+            if !head.starts_with("POST /approve ") {
+                return Err(ApprovalError::MalformedRequest);
+            }
+
+            if !request_authorized(head, token) {
+                return Err(ApprovalError::Unauthorized);
+            }
+
+            let content_length = head
+                .lines()
+                .filter_map(|line| line.split_once(':'))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+                .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+                .ok_or(ApprovalError::MalformedRequest)?;
+
+            if body.len() >= content_length {
+                break body[..content_length].to_string();
+            }
+        }
+
+        if len == request.len() {
+            return Err(ApprovalError::MalformedRequest);
+        }
+    };
+
+    Ok(body)
+}
+
+async fn handle_approval_request(
+    stream: &mut TcpStream,
+    approvals: Arc<RwLock<PendingApprovals>>,
+    token: &str,
+) -> Result<(), ApprovalError> {
+    // a client trickling bytes must not hold a connection slot forever
+    let body = tokio::time::timeout(REQUEST_TIMEOUT, read_approval_request(stream, token))
+        .await
+        .map_err(|_| ApprovalError::Timeout)??;
+
+    let (challenge, key) = parse_approval_form(&body)?;
+
+    approve(&approvals, &challenge, &key).await
+}
+
+/// Serves the endpoint companion devices use to approve pending challenges
+pub async fn serve_approvals(
+    listener: TcpListener,
+    approvals: Arc<RwLock<PendingApprovals>>,
+    token: String,
+) -> Result<(), ApprovalError> {
+    let slots = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+    let token: Arc<str> = Arc::from(token);
+
+    loop {
+        let (mut stream, peer) = listener.accept().await?;
+
+        let Ok(slot) = slots.clone().try_acquire_owned() else {
+            println!("🚫 Login challenge approval from {peer} refused: too many connections");
+            let _ = stream
+                .write_all(
+                    b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                )
+                .await;
+            continue;
+        };
+
+        let approvals = approvals.clone();
+        let token = token.clone();
+
+        tokio::spawn(async move {
+            let _slot = slot;
+
+            let result = handle_approval_request(&mut stream, approvals, &token).await;
+
+            let status = match &result {
+                Ok(()) => {
+                    println!("✅ Login challenge approved by {peer}");
+                    "200 OK"
+                }
+                Err(ApprovalError::InvalidKey) => {
+                    println!("🚫 Login challenge approval from {peer} refused: invalid key");
+                    "403 Forbidden"
+                }
+                Err(ApprovalError::Unauthorized) => {
+                    println!("🚫 Login challenge approval from {peer} refused: wrong token");
+                    "401 Unauthorized"
+                }
+                Err(ApprovalError::UnknownChallenge) | Err(ApprovalError::AlreadyApproved) => {
+                    "404 Not Found"
+                }
+                Err(ApprovalError::Timeout) => "408 Request Timeout",
+                Err(_) => "400 Bad Request",
+            };
+
+            let _ = stream
+                .write_all(
+                    format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                        .as_bytes(),
+                )
+                .await;
+        });
+    }
+}
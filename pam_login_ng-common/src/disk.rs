@@ -1,32 +1,47 @@
-use std::fs::{self, create_dir, File};
-use std::io::{Read, Write};
-use std::os::unix::fs::PermissionsExt;
 use std::path::PathBuf;
 
+use login_ng::config::{
+    init_config_dir, read_private_file, unsafe_config_files, write_private_file,
+};
+
 use crate::ServiceError;
 
 pub async fn create_directory(dirpath: PathBuf) -> Result<(), ServiceError> {
     let dir_path_str = dirpath.as_os_str().to_string_lossy();
 
-    if !dirpath.as_path().exists() {
-        match create_dir(dirpath.as_path()) {
-            Ok(_) => {
+    let existed = dirpath.as_path().exists();
+
+    match init_config_dir(dirpath.as_path()) {
+        Ok(_) => {
+            if !existed {
                 println!("📁 Directory {dir_path_str} created");
+            }
+        }
+        Err(err) => {
+            eprintln!("❌ Could not use directory {dir_path_str}: {err}");
 
-                let mut permissions = fs::metadata(dirpath.as_path())?.permissions();
-                permissions.set_mode(0o700);
+            return Err(ServiceError::ConfigError(err));
+        }
+    }
 
-                fs::set_permissions(dirpath.as_path(), permissions)?;
+    // files anyone but root can modify are not silently skipped: the service refuses to start
+    match unsafe_config_files(dirpath.as_path()) {
+        Ok(unsafe_files) => {
+            for err in unsafe_files.iter() {
+                eprintln!("🚫 Unsafe configuration file: {err}");
             }
-            Err(err) => {
-                eprintln!("❌ Could not create directory {dir_path_str}: {err}");
 
-                return Err(ServiceError::IOError(err));
+            match unsafe_files.into_iter().next() {
+                Some(err) => Err(ServiceError::ConfigError(err)),
+                None => Ok(()),
             }
         }
-    }
+        Err(err) => {
+            eprintln!("❌ Could not check files in {dir_path_str}: {err}");
 
-    Ok(())
+            Err(ServiceError::ConfigError(err))
+        }
+    }
 }
 
 pub async fn read_file_or_create_default<F>(
@@ -41,11 +56,16 @@ where
 
     let contents = match file_path.exists() {
         true => {
-            let mut contents = String::new();
+            let contents = match read_private_file(file_path) {
+                Ok(contents) => contents,
+                Err(err) => {
+                    eprintln!("❌ Refusing to read {file_path_dbg}: {err}");
+
+                    return Err(ServiceError::ConfigError(err));
+                }
+            };
 
-            let mut file = File::open(file_path)?;
-            let read = file.read_to_string(&mut contents)?;
-            println!("📖 Read private key file of {read} bytes");
+            println!("📖 Read file {file_path_dbg} of {} bytes", contents.len());
 
             contents
         }
@@ -54,30 +74,16 @@ where
 
             let contents = default()?;
 
-            match File::create(file_path) {
-                Ok(mut file) => {
-                    let metadata = file.metadata()?;
-                    let mut perm = metadata.permissions();
-                    perm.set_mode(0o700);
-
-                    fs::set_permissions(file_path, perm)?;
-                    match file.write_all(contents.to_string().as_bytes()) {
-                        Ok(_) => {
-                            println!("✅ Generated key has been saved to {file_path_dbg}")
-                        }
-                        Err(err) => {
-                            eprintln!(
-                                "❌ Failed to write the generated key to {file_path_dbg}: {err}"
-                            );
-
-                            return Err(ServiceError::IOError(err));
-                        }
-                    };
+            match write_private_file(file_path, contents.as_bytes()) {
+                Ok(_) => {
+                    println!("✅ Generated contents have been saved to {file_path_dbg}")
                 }
                 Err(err) => {
-                    eprintln!("❌ Failed to create the file {file_path_dbg}: {err}");
+                    eprintln!(
+                        "❌ Failed to write the generated contents to {file_path_dbg}: {err}"
+                    );
 
-                    return Err(ServiceError::IOError(err));
+                    return Err(ServiceError::ConfigError(err));
                 }
             };
 
@@ -0,0 +1,154 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use thiserror::Error;
+use tokio::process::Command;
+
+use login_ng::{
+    guest::{
+        is_guest_account, mark_guest_account, GuestConfig, GUEST_ACCOUNTS_DIR, GUEST_TEMP_DIRS,
+    },
+    users::{get_user_by_name, uid_t, User},
+};
+
+#[derive(Debug, Error)]
+pub enum GuestError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] std::io::Error),
+
+    #[error("Guest sessions are not enabled for user {0}")]
+    NotEnabled(String),
+
+    #[error("{0} exited with {1}")]
+    CommandFailed(&'static str, std::process::ExitStatus),
+
+    #[error("Guest account {0} is missing after its creation")]
+    MissingAccount(String),
+
+    #[error("Account {0} has not been created by login-ng as a guest account")]
+    NotGuestAccount(String),
+
+    #[error("Cannot record the guest account: {0}")]
+    Marker(#[from] login_ng::config::ConfigError),
+}
+
+async fn run(program: &'static str, args: &[&str]) -> Result<(), GuestError> {
+    let status = Command::new(program).args(args).status().await?;
+
+    match status.success() {
+        true => Ok(()),
+        false => Err(GuestError::CommandFailed(program, status)),
+    }
+}
+
+/// Returns the guest account, creating it on first use: the account has a locked
+/// password (it can only be entered through a guest session) and an empty home
+/// directory that is only ever populated by the tmpfs mounted at login.
+///
+/// An account with the same name that login-ng has not created is refused:
+/// guest sessions would log it in without a password and wipe its files at logout.
+pub async fn ensure_guest_account(
+    config: &GuestConfig,
+    username: &str,
+) -> Result<User, GuestError> {
+    if !config.is_guest(username) {
+        return Err(GuestError::NotEnabled(String::from(username)));
+    }
+
+    if let Some(user) = get_user_by_name(username) {
+        return match is_guest_account(Path::new(GUEST_ACCOUNTS_DIR), username, user.uid()) {
+            true => Ok(user),
+            false => Err(GuestError::NotGuestAccount(String::from(username))),
+        };
+    }
+
+    let home = format!("/home/{username}");
+    run(
+        "useradd",
+        &[
+            "--user-group",
+            "--no-create-home",
+            "--home-dir",
+            home.as_str(),
+            "--comment",
+            "login-ng guest",
+            username,
+        ],
+    )
+    .await?;
+
+    run("usermod", &["--lock", username]).await?;
+
+    let user = get_user_by_name(username)
+        .ok_or_else(|| GuestError::MissingAccount(String::from(username)))?;
+
+    mark_guest_account(Path::new(GUEST_ACCOUNTS_DIR), username, user.uid())?;
+
+    println!("👤 Created guest account {username}");
+
+    Ok(user)
+}
+
+fn remove_owned_entries(dir: &Path, uid: uid_t) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        let Ok(metadata) = std::fs::symlink_metadata(&path) else {
+            continue;
+        };
+
+        if metadata.uid() != uid {
+            continue;
+        }
+
+        let removal = match metadata.is_dir() {
+            true => std::fs::remove_dir_all(&path),
+            false => std::fs::remove_file(&path),
+        };
+
+        if let Err(err) = removal {
+            eprintln!("❌ Error removing guest file {}: {err}", path.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Wipe what a guest session leaves outside of its (already unmounted) home directory:
+/// processes still running as the guest and files in directories shared between users.
+pub async fn wipe_guest_state(username: &str, uid: uid_t) {
+    let uid_str = uid.to_string();
+    match run("pkill", &["--signal", "KILL", "--uid", uid_str.as_str()]).await {
+        Ok(_) => println!("🧹 Killed leftover processes of guest {username}"),
+        // pkill exits with 1 when there was nothing to kill
+        Err(GuestError::CommandFailed(_, status)) if status.code() == Some(1) => {}
+        Err(err) => eprintln!("❌ Error killing leftover processes of guest {username}: {err}"),
+    }
+
+    for dir in GUEST_TEMP_DIRS {
+        if let Err(err) = remove_owned_entries(Path::new(dir), uid) {
+            eprintln!("❌ Error cleaning {dir} from files of guest {username}: {err}");
+        }
+    }
+
+    println!("🧹 Wiped the state of guest {username}");
+}
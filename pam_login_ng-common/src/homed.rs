@@ -0,0 +1,129 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use thiserror::Error;
+use zbus::{proxy, zvariant::OwnedObjectPath, Connection};
+
+#[derive(Debug, Error)]
+pub enum HomedError {
+    #[error("DBus error: {0}")]
+    ZbusError(#[from] zbus::Error),
+
+    #[error("The provided secret is not a valid UTF-8 string")]
+    InvalidSecret,
+
+    #[error("Failed to serialize the secret: {0}")]
+    JsonError(#[from] serde_json::Error),
+}
+
+/// Subset of the org.freedesktop.home1.Manager interface used by login-ng
+#[proxy(
+    interface = "org.freedesktop.home1.Manager",
+    default_service = "org.freedesktop.home1",
+    default_path = "/org/freedesktop/home1"
+)]
+pub trait HomedManager {
+    fn get_home_by_name(
+        &self,
+        user_name: &str,
+    ) -> zbus::Result<(u32, String, u32, String, String, String, OwnedObjectPath)>;
+
+    fn activate_home(&self, user_name: &str, secret: &str) -> zbus::Result<()>;
+
+    fn deactivate_home(&self, user_name: &str) -> zbus::Result<()>;
+
+    fn change_password_home(
+        &self,
+        user_name: &str,
+        new_secret: &str,
+        old_secret: &str,
+    ) -> zbus::Result<()>;
+}
+
+/// Builds the JSON secret object that systemd-homed expects for a plain password
+pub fn homed_secret(password: &[u8]) -> Result<String, HomedError> {
+    let password = std::str::from_utf8(password).map_err(|_| HomedError::InvalidSecret)?;
+
+    Ok(serde_json::to_string(&serde_json::json!({
+        "password": [password]
+    }))?)
+}
+
+/// Returns the state of the home directory of the given user as reported by systemd-homed,
+/// or None if the user is not managed by systemd-homed (or homed is not running).
+pub async fn home_state(connection: &Connection, username: &str) -> Option<String> {
+    let proxy = HomedManagerProxy::new(connection).await.ok()?;
+
+    match proxy.get_home_by_name(username).await {
+        Ok((_uid, state, _gid, _real_name, _home, _shell, _path)) => Some(state),
+        Err(_) => None,
+    }
+}
+
+/// Unlocks and mounts the home directory of a systemd-homed managed user:
+/// returns true if the home has been activated by this call and false if it was active already.
+pub async fn activate_home(
+    connection: &Connection,
+    username: &str,
+    password: &[u8],
+) -> Result<bool, HomedError> {
+    let proxy = HomedManagerProxy::new(connection).await?;
+
+    let (_uid, state, _gid, _real_name, _home, _shell, _path) =
+        proxy.get_home_by_name(username).await?;
+
+    if state.starts_with("active") {
+        return Ok(false);
+    }
+
+    proxy
+        .activate_home(username, homed_secret(password)?.as_str())
+        .await?;
+
+    Ok(true)
+}
+
+/// Unmounts and locks the home directory of a systemd-homed managed user
+pub async fn deactivate_home(connection: &Connection, username: &str) -> Result<(), HomedError> {
+    let proxy = HomedManagerProxy::new(connection).await?;
+
+    proxy.deactivate_home(username).await?;
+
+    Ok(())
+}
+
+/// Re-keys the home directory of a systemd-homed managed user after a password change
+pub async fn change_home_password(
+    connection: &Connection,
+    username: &str,
+    old_password: &[u8],
+    new_password: &[u8],
+) -> Result<(), HomedError> {
+    let proxy = HomedManagerProxy::new(connection).await?;
+
+    proxy
+        .change_password_home(
+            username,
+            homed_secret(new_password)?.as_str(),
+            homed_secret(old_password)?.as_str(),
+        )
+        .await?;
+
+    Ok(())
+}
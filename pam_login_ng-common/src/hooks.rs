@@ -0,0 +1,330 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use configparser::ini::Ini;
+use login_ng::config::{check_config_permissions, read_config_file};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    process::Command,
+    time::timeout,
+};
+
+/// Directory holding one <event>.d directory of executable hooks for each event
+pub const HOOKS_DIR: &str = "/etc/login_ng/hooks";
+
+/// Configuration file of the service, in the login_ng configuration directory
+pub const SERVICE_CONFIG_FILE: &str = "service.conf";
+
+/// How long a hook or the webhook is allowed to run before being abandoned
+pub const HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Error)]
+pub enum HookError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] std::io::Error),
+
+    #[error("Serialization error: {0}")]
+    SerializationError(#[from] serde_json::Error),
+
+    #[error("Timed out")]
+    Timeout,
+
+    #[error("Unsupported webhook URL (only http:// is supported): {0}")]
+    UnsupportedUrl(String),
+
+    #[error("Hook exited with {0}")]
+    HookFailed(std::process::ExitStatus),
+
+    #[error("Webhook replied with: {0}")]
+    WebhookFailed(String),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookEvent {
+    /// The user has been successfully authenticated
+    Success,
+
+    /// An authentication attempt has failed
+    Failure,
+
+    /// A session has been opened
+    SessionOpen,
+
+    /// A session has been closed
+    SessionClose,
+}
+
+impl HookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HookEvent::Success => "success",
+            HookEvent::Failure => "failure",
+            HookEvent::SessionOpen => "session-open",
+            HookEvent::SessionClose => "session-close",
+        }
+    }
+
+    /// Name of the directory holding the hooks for this event
+    pub fn dir_name(&self) -> String {
+        format!("{}.d", self.as_str())
+    }
+}
+
+/// Data handed to hooks (as JSON on stdin) and to the webhook (as the body of a POST request)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoginEvent {
+    event: HookEvent,
+    username: String,
+    timestamp: u64,
+    success: bool,
+    message: String,
+}
+
+impl LoginEvent {
+    pub fn new(event: HookEvent, username: &str, success: bool, message: &str) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_secs())
+            .unwrap_or_default();
+
+        Self {
+            event,
+            username: String::from(username),
+            timestamp,
+            success,
+            message: String::from(message),
+        }
+    }
+
+    pub fn event(&self) -> HookEvent {
+        self.event
+    }
+
+    pub fn username(&self) -> &String {
+        &self.username
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn success(&self) -> bool {
+        self.success
+    }
+
+    pub fn message(&self) -> &String {
+        &self.message
+    }
+}
+
+/// Split an http:// URL into host, port and path
+pub fn parse_http_url(url: &str) -> Result<(String, u16, String), HookError> {
+    let unsupported = || HookError::UnsupportedUrl(String::from(url));
+
+    let rest = url.strip_prefix("http://").ok_or_else(unsupported)?;
+
+    let (authority, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse::<u16>().map_err(|_| unsupported())?),
+        None => (authority, 80),
+    };
+
+    if host.is_empty() {
+        return Err(unsupported());
+    }
+
+    Ok((String::from(host), port, String::from(path)))
+}
+
+/// Hooks and webhook notified about authentication results and session lifecycle changes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hooks {
+    dir: PathBuf,
+    webhook: Option<String>,
+}
+
+impl Default for Hooks {
+    fn default() -> Self {
+        Self::new(PathBuf::from(HOOKS_DIR), None)
+    }
+}
+
+impl Hooks {
+    pub fn new(dir: PathBuf, webhook: Option<String>) -> Self {
+        Self { dir, webhook }
+    }
+
+    /// Load the webhook URL from the [Hooks] section of service.conf in the given directory
+    pub fn load(config_dir: &Path) -> Self {
+        let mut config = Ini::new();
+
+        let webhook = match read_config_file(&config_dir.join(SERVICE_CONFIG_FILE))
+            .map(|content| config.read(content))
+        {
+            Ok(Ok(_)) => config
+                .get("hooks", "webhook")
+                .filter(|webhook| !webhook.is_empty()),
+            _ => None,
+        };
+
+        Self::new(PathBuf::from(HOOKS_DIR), webhook)
+    }
+
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+
+    pub fn webhook(&self) -> &Option<String> {
+        &self.webhook
+    }
+
+    /// Executable hooks for the given event, in lexicographic order: as they run as root,
+    /// symlinks and anything (or in a directory) modifiable by others than root are skipped
+    pub fn scripts(&self, event: HookEvent) -> Vec<PathBuf> {
+        let dir = self.dir.join(event.dir_name());
+        if check_config_permissions(&dir).is_err() {
+            return vec![];
+        }
+
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            return vec![];
+        };
+
+        let mut scripts = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                std::fs::symlink_metadata(path)
+                    .map(|metadata| {
+                        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+                    })
+                    .unwrap_or(false)
+                    && check_config_permissions(path).is_ok()
+            })
+            .collect::<Vec<PathBuf>>();
+
+        scripts.sort();
+
+        scripts
+    }
+
+    /// Run a hook with the event serialized as JSON on its stdin and
+    /// the most relevant fields in LOGIN_NG_* environment variables
+    pub async fn run_script(script: &Path, event: &LoginEvent) -> Result<(), HookError> {
+        let data = serde_json::to_vec(event)?;
+
+        let mut child = Command::new(script)
+            .env("LOGIN_NG_EVENT", event.event().as_str())
+            .env("LOGIN_NG_USER", event.username())
+            .env("LOGIN_NG_SUCCESS", event.success().to_string())
+            .env("LOGIN_NG_MESSAGE", event.message())
+            .stdin(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            // hooks are free to ignore their input
+            let _ = stdin.write_all(data.as_slice()).await;
+        }
+
+        let status = timeout(HOOK_TIMEOUT, child.wait())
+            .await
+            .map_err(|_| HookError::Timeout)??;
+
+        match status.success() {
+            true => Ok(()),
+            false => Err(HookError::HookFailed(status)),
+        }
+    }
+
+    /// POST the event serialized as JSON to the given http:// URL
+    pub async fn post_webhook(url: &str, event: &LoginEvent) -> Result<(), HookError> {
+        let (host, port, path) = parse_http_url(url)?;
+
+        let body = serde_json::to_string(event)?;
+
+        let request = format!(
+            "POST {path} HTTP/1.1\r\nHost: {host}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+
+        let exchange = async {
+            let mut stream = TcpStream::connect((host.as_str(), port)).await?;
+            stream.write_all(request.as_bytes()).await?;
+
+            let mut response = vec![];
+            stream.read_to_end(&mut response).await?;
+
+            Ok::<Vec<u8>, std::io::Error>(response)
+        };
+
+        let response = timeout(HOOK_TIMEOUT, exchange)
+            .await
+            .map_err(|_| HookError::Timeout)??;
+
+        let status_line = String::from_utf8_lossy(&response)
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string();
+
+        match status_line.split_whitespace().nth(1) {
+            Some(code) if code.starts_with('2') => Ok(()),
+            _ => Err(HookError::WebhookFailed(status_line)),
+        }
+    }
+
+    /// Run every hook for the event and then notify the webhook
+    pub async fn dispatch(&self, event: &LoginEvent) {
+        for script in self.scripts(event.event()).iter() {
+            if let Err(err) = Self::run_script(script, event).await {
+                eprintln!("❌ Hook {} errored: {err}", script.display());
+            }
+        }
+
+        if let Some(webhook) = &self.webhook {
+            if let Err(err) = Self::post_webhook(webhook, event).await {
+                eprintln!("❌ Error notifying the webhook {webhook}: {err}");
+            }
+        }
+    }
+
+    /// Notify the event in the background: hooks never delay or alter the login
+    pub fn notify(self: &Arc<Self>, event: LoginEvent) {
+        let hooks = self.clone();
+
+        tokio::spawn(async move { hooks.dispatch(&event).await });
+    }
+}
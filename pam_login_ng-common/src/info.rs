@@ -0,0 +1,106 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use serde::{Deserialize, Serialize};
+use zbus::{zvariant::Type, Error as ZError};
+
+use crate::{session::SessionsProxy, ServiceError};
+
+/// Version of the D-Bus protocol spoken between pam_login_ng-service and its clients,
+/// shared with the storage backend of login_ng that talks to the service as well
+pub const PROTOCOL_VERSION: u32 = login_ng::SERVICE_PROTOCOL_VERSION;
+
+/// Error name of a call to a method the service does not have
+const UNKNOWN_METHOD: &str = "org.freedesktop.DBus.Error.UnknownMethod";
+
+/// What a running pam_login_ng-service is, as returned over D-Bus
+#[derive(Serialize, Deserialize, Type, Clone, Default, PartialEq, Debug)]
+pub struct ServiceInfo {
+    protocol_version: u32,
+    library_version: String,
+    features: Vec<String>,
+}
+
+impl ServiceInfo {
+    pub fn new(protocol_version: u32, library_version: String, features: Vec<String>) -> Self {
+        Self {
+            protocol_version,
+            library_version,
+            features,
+        }
+    }
+
+    /// The protocol, the login_ng version and the features this crate has been built with
+    pub fn current() -> Self {
+        let features = [
+            (cfg!(feature = "logind"), "logind"),
+            (cfg!(feature = "audit"), "audit"),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, feature)| enabled.then(|| String::from(feature)))
+        .collect();
+
+        Self::new(
+            PROTOCOL_VERSION,
+            String::from(login_ng::LIBRARY_VERSION),
+            features,
+        )
+    }
+
+    pub fn protocol_version(&self) -> u32 {
+        self.protocol_version
+    }
+
+    pub fn library_version(&self) -> &String {
+        &self.library_version
+    }
+
+    pub fn features(&self) -> &Vec<String> {
+        &self.features
+    }
+
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.iter().any(|known| known == feature)
+    }
+
+    /// Whether a client speaking the protocol of this crate can talk to the described service
+    pub fn is_compatible(&self) -> bool {
+        self.protocol_version == PROTOCOL_VERSION
+    }
+}
+
+/// Ask the service what it is and refuse to go on if it speaks another protocol:
+/// a service predating the handshake is reported as speaking protocol version 0.
+pub async fn verify_service(proxy: &SessionsProxy<'_>) -> Result<ServiceInfo, ServiceError> {
+    let info = match proxy.get_info().await {
+        Ok(info) => info,
+        Err(ZError::MethodError(name, _, _)) if name.as_str() == UNKNOWN_METHOD => {
+            ServiceInfo::default()
+        }
+        Err(err) => return Err(ServiceError::ZbusError(err)),
+    };
+
+    match info.is_compatible() {
+        true => Ok(info),
+        false => Err(ServiceError::IncompatibleService {
+            service: info.protocol_version(),
+            client: PROTOCOL_VERSION,
+        }),
+    }
+}
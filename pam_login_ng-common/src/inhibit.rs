@@ -0,0 +1,58 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use zbus::zvariant::OwnedFd;
+
+/// Operations delayed while a login attempt or a mount sequence is in flight
+pub const INHIBIT_WHAT: &str = "shutdown:sleep";
+
+/// Name the inhibitor locks are taken with, as shown by systemd-inhibit --list
+pub const INHIBIT_WHO: &str = "login_ng";
+
+/// A logind inhibitor lock blocking shutdown and sleep until it is dropped:
+/// without logind support there is nothing to hold and the lock is empty.
+#[derive(Debug, Default)]
+pub struct InhibitorLock {
+    fd: Option<OwnedFd>,
+}
+
+impl InhibitorLock {
+    /// Take a block inhibitor lock for the given reason
+    #[cfg(feature = "logind")]
+    pub async fn take(why: &str) -> zbus::Result<Self> {
+        let connection = zbus::Connection::system().await?;
+        let manager = crate::logind::logind_manager(&connection).await?;
+
+        let fd = manager
+            .inhibit(INHIBIT_WHAT, INHIBIT_WHO, why, "block")
+            .await?;
+
+        Ok(Self { fd: Some(fd) })
+    }
+
+    /// Take a block inhibitor lock for the given reason: a no-op without logind support
+    #[cfg(not(feature = "logind"))]
+    pub async fn take(_why: &str) -> zbus::Result<Self> {
+        Ok(Self::default())
+    }
+
+    pub fn is_held(&self) -> bool {
+        self.fd.is_some()
+    }
+}
@@ -27,11 +27,26 @@ pub extern crate zbus;
 #[cfg(test)]
 pub(crate) mod tests;
 
+pub mod approval;
 pub mod disk;
+pub mod guest;
+pub mod homed;
+pub mod hooks;
+pub mod info;
+pub mod inhibit;
+pub mod limits;
+#[cfg(feature = "logind")]
+pub mod logind;
+pub mod metrics;
 pub mod mount;
+pub mod quota;
 pub mod result;
+pub mod sealed;
 pub mod security;
 pub mod session;
+pub mod state;
+pub mod storage;
+pub mod switch;
 
 pub const XDG_RUNTIME_DIR_PATH: &str = "/tmp/xdg/";
 
@@ -58,4 +73,13 @@ pub enum ServiceError {
 
     #[error("Join error: {0}")]
     JoinError(#[from] tokio::task::JoinError),
+
+    #[error("Configuration error: {0}")]
+    ConfigError(#[from] login_ng::config::ConfigError),
+
+    #[error("Encryption error: {0}")]
+    SealError(#[from] crate::sealed::SealError),
+
+    #[error("pam_login_ng-service speaks protocol version {service}, but version {client} is required: reinstall matching versions of login-ng")]
+    IncompatibleService { service: u32, client: u32 },
 }
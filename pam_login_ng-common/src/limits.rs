@@ -0,0 +1,177 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{path::Path, time::Duration};
+
+use configparser::ini::Ini;
+use login_ng::{config::read_config_file, network::DEFAULT_NETWORK_ONLINE_TIMEOUT};
+
+use crate::hooks::SERVICE_CONFIG_FILE;
+
+/// Time a one time token handed out to open a session can be used for
+pub const DEFAULT_TOKEN_TTL: Duration = Duration::from_secs(60);
+
+/// Time each mount is given to be cleanly unmounted when the service shuts down
+pub const DEFAULT_UNMOUNT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Limits applied to the sessions opened through the service, read from
+/// the [Sessions] section of service.conf
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SessionLimits {
+    max_per_user: Option<usize>,
+    token_ttl: Duration,
+    unmount_timeout: Duration,
+    network_timeout: Duration,
+    pam_uid: Option<u32>,
+}
+
+impl Default for SessionLimits {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl SessionLimits {
+    pub fn new(max_per_user: Option<usize>) -> Self {
+        Self {
+            max_per_user,
+            token_ttl: DEFAULT_TOKEN_TTL,
+            unmount_timeout: DEFAULT_UNMOUNT_TIMEOUT,
+            network_timeout: DEFAULT_NETWORK_ONLINE_TIMEOUT,
+            pam_uid: None,
+        }
+    }
+
+    pub fn with_token_ttl(mut self, token_ttl: Duration) -> Self {
+        self.token_ttl = token_ttl;
+        self
+    }
+
+    pub fn with_unmount_timeout(mut self, unmount_timeout: Duration) -> Self {
+        self.unmount_timeout = unmount_timeout;
+        self
+    }
+
+    pub fn with_network_timeout(mut self, network_timeout: Duration) -> Self {
+        self.network_timeout = network_timeout;
+        self
+    }
+
+    pub fn with_pam_uid(mut self, pam_uid: Option<u32>) -> Self {
+        self.pam_uid = pam_uid;
+        self
+    }
+
+    /// Parse the given service.conf content: a missing or zero max_per_user means no limit,
+    /// while a missing or zero token_ttl, unmount_timeout or network_timeout (in seconds) keeps the default one.
+    /// pam_uid is the uid the PAM module runs as when the greeter is not root
+    pub fn parse(content: &str) -> Self {
+        let mut config = Ini::new();
+
+        if config.read(String::from(content)).is_err() {
+            return Self::default();
+        }
+
+        let max_per_user = config
+            .getuint("sessions", "max_per_user")
+            .ok()
+            .flatten()
+            .filter(|max| *max > 0)
+            .map(|max| max as usize);
+
+        let token_ttl = config
+            .getuint("sessions", "token_ttl")
+            .ok()
+            .flatten()
+            .filter(|ttl| *ttl > 0)
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TOKEN_TTL);
+
+        let unmount_timeout = config
+            .getuint("sessions", "unmount_timeout")
+            .ok()
+            .flatten()
+            .filter(|timeout| *timeout > 0)
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_UNMOUNT_TIMEOUT);
+
+        let network_timeout = config
+            .getuint("sessions", "network_timeout")
+            .ok()
+            .flatten()
+            .filter(|timeout| *timeout > 0)
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_NETWORK_ONLINE_TIMEOUT);
+
+        let pam_uid = config
+            .getuint("sessions", "pam_uid")
+            .ok()
+            .flatten()
+            .and_then(|uid| u32::try_from(uid).ok());
+
+        Self::new(max_per_user)
+            .with_token_ttl(token_ttl)
+            .with_unmount_timeout(unmount_timeout)
+            .with_network_timeout(network_timeout)
+            .with_pam_uid(pam_uid)
+    }
+
+    /// Load the limits from service.conf in the given directory
+    pub fn load(config_dir: &Path) -> Self {
+        match read_config_file(&config_dir.join(SERVICE_CONFIG_FILE)) {
+            Ok(content) => Self::parse(content.as_str()),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn max_per_user(&self) -> Option<usize> {
+        self.max_per_user
+    }
+
+    pub fn token_ttl(&self) -> Duration {
+        self.token_ttl
+    }
+
+    pub fn unmount_timeout(&self) -> Duration {
+        self.unmount_timeout
+    }
+
+    /// Time the network is waited for before opening a session with mounts that need it
+    pub fn network_timeout(&self) -> Duration {
+        self.network_timeout
+    }
+
+    pub fn pam_uid(&self) -> Option<u32> {
+        self.pam_uid
+    }
+
+    /// Returns true if the caller with the given uid can open or close sessions of the user
+    /// with the given uid: root and the PAM module can act for anyone, other callers only for themselves
+    pub fn may_act_for(&self, caller: u32, owner: Option<u32>) -> bool {
+        caller == 0 || Some(caller) == self.pam_uid || Some(caller) == owner
+    }
+
+    /// Returns true if a user that already has the given number of sessions open can open another one
+    pub fn allows(&self, open_sessions: usize) -> bool {
+        match self.max_per_user {
+            Some(max) => open_sessions < max,
+            None => true,
+        }
+    }
+}
@@ -0,0 +1,122 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use zbus::{
+    proxy,
+    zvariant::{OwnedFd, OwnedObjectPath},
+    Connection,
+};
+
+/// Human-readable name of the login manager: systemd-logind and elogind expose the same
+/// API under the same bus name, so the same build works with either of them
+pub const LOGIND_PROVIDER: &str = "logind";
+
+/// Well-known bus name of the login manager: elogind claims the same name
+/// as systemd-logind, but it is often started via dbus activation on first use.
+pub const LOGIND_BUS_NAME: &str = "org.freedesktop.login1";
+
+/// Object path of the login manager
+pub const LOGIND_MANAGER_PATH: &str = "/org/freedesktop/login1";
+
+/// Subset of the org.freedesktop.login1.Manager interface used by login-ng:
+/// the destination is not hardcoded so that both systemd-logind and elogind can be targeted.
+#[proxy(interface = "org.freedesktop.login1.Manager", gen_blocking = false)]
+pub trait LogindManager {
+    fn get_session(&self, session_id: &str) -> zbus::Result<OwnedObjectPath>;
+
+    #[zbus(name = "GetSessionByPID")]
+    fn get_session_by_pid(&self, pid: u32) -> zbus::Result<OwnedObjectPath>;
+
+    fn list_sessions(&self) -> zbus::Result<Vec<(String, u32, String, String, OwnedObjectPath)>>;
+
+    fn activate_session(&self, session_id: &str) -> zbus::Result<()>;
+
+    fn lock_session(&self, session_id: &str) -> zbus::Result<()>;
+
+    fn unlock_session(&self, session_id: &str) -> zbus::Result<()>;
+
+    fn inhibit(&self, what: &str, who: &str, why: &str, mode: &str) -> zbus::Result<OwnedFd>;
+
+    #[zbus(signal)]
+    fn prepare_for_sleep(&self, start: bool) -> zbus::Result<()>;
+}
+
+/// Subset of the org.freedesktop.login1.Session interface used by login-ng
+#[proxy(interface = "org.freedesktop.login1.Session", gen_blocking = false)]
+pub trait LogindSession {
+    fn activate(&self) -> zbus::Result<()>;
+
+    fn set_locked_hint(&self, locked: bool) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn id(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn name(&self) -> zbus::Result<String>;
+
+    #[zbus(property, name = "VTNr")]
+    fn vtnr(&self) -> zbus::Result<u32>;
+
+    #[zbus(property)]
+    fn active(&self) -> zbus::Result<bool>;
+
+    #[zbus(signal)]
+    fn lock(&self) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn unlock(&self) -> zbus::Result<()>;
+}
+
+/// Creates a proxy to the login manager on the given (system) bus connection
+pub async fn logind_manager(connection: &Connection) -> zbus::Result<LogindManagerProxy<'static>> {
+    LogindManagerProxy::builder(connection)
+        .destination(LOGIND_BUS_NAME)?
+        .path(LOGIND_MANAGER_PATH)?
+        .build()
+        .await
+}
+
+/// Creates a proxy to a session object as returned by the login manager
+pub async fn logind_session(
+    connection: &Connection,
+    path: OwnedObjectPath,
+) -> zbus::Result<LogindSessionProxy<'static>> {
+    LogindSessionProxy::builder(connection)
+        .destination(LOGIND_BUS_NAME)?
+        .path(path)?
+        .build()
+        .await
+}
+
+/// Returns true if the login manager is currently reachable on the given connection
+pub async fn logind_available(connection: &Connection) -> bool {
+    let Ok(dbus) = zbus::fdo::DBusProxy::new(connection).await else {
+        return false;
+    };
+
+    let Ok(name) = zbus::names::WellKnownName::try_from(LOGIND_BUS_NAME) else {
+        return false;
+    };
+
+    match dbus.name_has_owner(name.clone().into()).await {
+        Ok(true) => true,
+        // the login manager may not be running yet if it's dbus-activated (as elogind usually is)
+        _ => dbus.start_service_by_name(name, 0).await.is_ok(),
+    }
+}
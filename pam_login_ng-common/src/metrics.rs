@@ -0,0 +1,301 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    fmt::Write as _,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use configparser::ini::Ini;
+use login_ng::config::read_config_file;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixListener,
+    time::timeout,
+};
+use zbus::zvariant::Type;
+
+use crate::{hooks::SERVICE_CONFIG_FILE, result::ServiceOperationResult};
+
+/// How often the textfile exporter rewrites the metrics file
+pub const METRICS_TEXTFILE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How long a client of the metrics socket is given to send its request
+const METRICS_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Counters of the root service, shared between the D-Bus objects and the exporters
+#[derive(Debug, Default)]
+pub struct Metrics {
+    auth_attempts: AtomicU64,
+    auth_failures: AtomicU64,
+    open_sessions: AtomicU64,
+    mount_failures: AtomicU64,
+    handshake_errors: AtomicU64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_authentication(&self, success: bool) {
+        self.auth_attempts.fetch_add(1, Ordering::Relaxed);
+
+        if !success {
+            self.auth_failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_handshake_error(&self) {
+        self.handshake_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_session_opened(&self) {
+        self.open_sessions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_session_closed(&self) {
+        // never wrap around, even if a close was not matched by an open
+        let _ = self
+            .open_sessions
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |open| {
+                open.checked_sub(1)
+            });
+    }
+
+    /// Account for a session that could not be opened, depending on the reason
+    pub fn record_session_failure(&self, result: ServiceOperationResult) {
+        match result {
+            ServiceOperationResult::PubKeyError
+            | ServiceOperationResult::DataDecryptionFailed
+            | ServiceOperationResult::EncryptionError
+            | ServiceOperationResult::SerializationError => self.record_handshake_error(),
+            ServiceOperationResult::CannotLoadUserMountError
+            | ServiceOperationResult::MountError
+            | ServiceOperationResult::UnauthorizedMount => {
+                self.mount_failures.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            auth_attempts: self.auth_attempts.load(Ordering::Relaxed),
+            auth_failures: self.auth_failures.load(Ordering::Relaxed),
+            open_sessions: self.open_sessions.load(Ordering::Relaxed),
+            mount_failures: self.mount_failures.load(Ordering::Relaxed),
+            handshake_errors: self.handshake_errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The value of every counter at a given time, as returned over D-Bus
+#[derive(Serialize, Deserialize, Type, Clone, Copy, Default, PartialEq, Debug)]
+pub struct MetricsSnapshot {
+    auth_attempts: u64,
+    auth_failures: u64,
+    open_sessions: u64,
+    mount_failures: u64,
+    handshake_errors: u64,
+}
+
+impl MetricsSnapshot {
+    pub fn auth_attempts(&self) -> u64 {
+        self.auth_attempts
+    }
+
+    pub fn auth_failures(&self) -> u64 {
+        self.auth_failures
+    }
+
+    pub fn open_sessions(&self) -> u64 {
+        self.open_sessions
+    }
+
+    pub fn mount_failures(&self) -> u64 {
+        self.mount_failures
+    }
+
+    pub fn handshake_errors(&self) -> u64 {
+        self.handshake_errors
+    }
+
+    /// Render the snapshot in the Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let metrics = [
+            (
+                "login_ng_auth_attempts_total",
+                "counter",
+                "Authentication attempts reported by the PAM module",
+                self.auth_attempts,
+            ),
+            (
+                "login_ng_auth_failures_total",
+                "counter",
+                "Failed authentication attempts reported by the PAM module",
+                self.auth_failures,
+            ),
+            (
+                "login_ng_open_sessions",
+                "gauge",
+                "Sessions currently open",
+                self.open_sessions,
+            ),
+            (
+                "login_ng_mount_failures_total",
+                "counter",
+                "Sessions that could not be opened because of user mounts",
+                self.mount_failures,
+            ),
+            (
+                "login_ng_handshake_errors_total",
+                "counter",
+                "Failures of the key exchange protecting the password sent to the service",
+                self.handshake_errors,
+            ),
+        ];
+
+        let mut output = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(output, "# HELP {name} {help}");
+            let _ = writeln!(output, "# TYPE {name} {kind}");
+            let _ = writeln!(output, "{name} {value}");
+        }
+
+        output
+    }
+}
+
+/// Where metrics are exported, read from the [Metrics] section of service.conf
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetricsConfig {
+    textfile: Option<PathBuf>,
+    socket: Option<PathBuf>,
+}
+
+impl MetricsConfig {
+    pub fn new(textfile: Option<PathBuf>, socket: Option<PathBuf>) -> Self {
+        Self { textfile, socket }
+    }
+
+    /// Parse the given service.conf content: both exporters are disabled unless a path is given
+    pub fn parse(content: &str) -> Self {
+        let mut config = Ini::new();
+
+        if config.read(String::from(content)).is_err() {
+            return Self::default();
+        }
+
+        let path = |key: &str| {
+            config
+                .get("metrics", key)
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from)
+        };
+
+        Self::new(path("textfile"), path("socket"))
+    }
+
+    /// Load the exporters configuration from service.conf in the given directory
+    pub fn load(config_dir: &Path) -> Self {
+        match read_config_file(&config_dir.join(SERVICE_CONFIG_FILE)) {
+            Ok(content) => Self::parse(content.as_str()),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// File for the node_exporter textfile collector
+    pub fn textfile(&self) -> &Option<PathBuf> {
+        &self.textfile
+    }
+
+    /// Unix socket answering every request with the current metrics
+    pub fn socket(&self) -> &Option<PathBuf> {
+        &self.socket
+    }
+}
+
+/// Periodically write the metrics to the given file, replacing it atomically
+/// so that the textfile collector never reads a partial file.
+pub async fn export_metrics_textfile(path: PathBuf, metrics: Arc<Metrics>) -> std::io::Result<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    loop {
+        write_metrics_textfile(&path, &temp_path, &metrics).await?;
+
+        tokio::time::sleep(METRICS_TEXTFILE_INTERVAL).await;
+    }
+}
+
+async fn write_metrics_textfile(
+    path: &Path,
+    temp_path: &Path,
+    metrics: &Metrics,
+) -> std::io::Result<()> {
+    let content = metrics.snapshot().to_prometheus();
+
+    tokio::fs::write(temp_path, content.as_bytes()).await?;
+    tokio::fs::set_permissions(temp_path, std::fs::Permissions::from_mode(0o644)).await?;
+    tokio::fs::rename(temp_path, path).await
+}
+
+/// Write the metrics to the given file one last time, as the exporter would
+pub async fn flush_metrics_textfile(path: &Path, metrics: &Metrics) -> std::io::Result<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+
+    write_metrics_textfile(path, &path.with_file_name(temp_name), metrics).await
+}
+
+/// Answer every connection to the given socket with the current metrics as an
+/// HTTP response, so that it can be scraped with curl --unix-socket or a proxy.
+pub async fn serve_metrics(listener: UnixListener, metrics: Arc<Metrics>) -> std::io::Result<()> {
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let metrics = metrics.clone();
+
+        tokio::spawn(async move {
+            // the request is irrelevant: read it only to not reset the connection
+            let mut request = [0u8; 1024];
+            let _ = timeout(METRICS_REQUEST_TIMEOUT, stream.read(&mut request)).await;
+
+            let body = metrics.snapshot().to_prometheus();
+
+            let _ = stream
+                .write_all(
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                        body.len()
+                    )
+                    .as_bytes(),
+                )
+                .await;
+        });
+    }
+}
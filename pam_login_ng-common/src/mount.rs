@@ -20,21 +20,27 @@
 use login_ng::users;
 use sys_mount::{Mount, Unmount, UnmountDrop, UnmountFlags};
 
-use login_ng::mount::MountPoints;
+use login_ng::config::write_private_file;
+use login_ng::mount::{MountParams, MountPoints};
+use login_ng::secure::constant_time_eq;
 use tokio::sync::RwLock;
 
 use std::collections::HashMap;
-use std::fs::{self, File};
+use std::fs;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use std::{fs::create_dir, path::Path};
 
-use std::io::{self, Write};
+use std::io;
 
+use rsa::RsaPrivateKey;
 use serde::{Deserialize, Serialize};
 use serde_json;
 
-use crate::result::ServiceOperationResult;
+use crate::quota::apply_quota;
+use crate::result::{ServiceOperationReport, ServiceOperationResult};
+use crate::sealed::{seal, unseal};
 use crate::{disk, ServiceError};
 
 use zbus::interface;
@@ -79,12 +85,64 @@ where
         create_dir(mount_path)?;
     }
 
+    // devices might be renumbered across boots: sources can identify them instead
+    let source = resolve_source(Path::new(DISK_BY_DIR), data.2.as_str())?;
+
     match data.0.is_empty() {
-        true => Mount::builder().mount(data.2.as_str(), mount_path.as_os_str()),
+        true => Mount::builder().mount(source.as_str(), mount_path.as_os_str()),
         false => Mount::builder()
             .fstype(data.0.as_str())
             .data(data.1.as_str())
-            .mount(data.2.as_str(), data.3.as_ref()),
+            .mount(source.as_str(), data.3.as_ref()),
+    }
+}
+
+/// Escape a tag value the way udev does when naming the links under /dev/disk
+fn udev_escape(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'0'..=b'9'
+            | b'a'..=b'z'
+            | b'A'..=b'Z'
+            | b'#'
+            | b'+'
+            | b'-'
+            | b'.'
+            | b':'
+            | b'='
+            | b'@'
+            | b'_' => (byte as char).to_string(),
+            _ => format!("\\x{byte:02x}"),
+        })
+        .collect()
+}
+
+/// Resolve a UUID=, LABEL=, PARTUUID= or PARTLABEL= source to the device it identifies
+/// through the links in the given directory: every other source is returned unchanged.
+pub fn resolve_source(disk_dir: &Path, source: &str) -> io::Result<String> {
+    let Some((tag, value)) = source.split_once('=') else {
+        return Ok(String::from(source));
+    };
+
+    let links = match tag {
+        "UUID" => "by-uuid",
+        "LABEL" => "by-label",
+        "PARTUUID" => "by-partuuid",
+        "PARTLABEL" => "by-partlabel",
+        _ => return Ok(String::from(source)),
+    };
+
+    let value = value.trim_matches('"');
+    let link = disk_dir.join(links).join(udev_escape(value));
+
+    match fs::canonicalize(&link) {
+        Ok(device) => Ok(device.to_string_lossy().to_string()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no device with {tag}={value} found"),
+        )),
+        Err(err) => Err(err),
     }
 }
 
@@ -133,6 +191,32 @@ pub(crate) fn mount_xdg(
     }
 }
 
+/// Apply the quota configured for a mount: a mount whose quota cannot be enforced is refused
+fn enforce_quota(
+    mountpoint: &str,
+    params: &MountParams,
+    uid: users::uid_t,
+) -> Result<(), ServiceOperationReport> {
+    if params.quota().is_none() {
+        return Ok(());
+    }
+
+    match apply_quota(Path::new(mountpoint), params, uid) {
+        Ok(()) => {
+            println!("📏 Applied the quota of {mountpoint}");
+            Ok(())
+        }
+        Err(err) => {
+            eprintln!("❌ Error applying the quota of {mountpoint}: {err}");
+            Err(ServiceOperationReport::new(
+                ServiceOperationResult::MountError,
+                format!("cannot apply the quota of {mountpoint}: {err}"),
+                params.device().as_str(),
+            ))
+        }
+    }
+}
+
 pub(crate) fn mount_all(
     mounts: Option<MountPoints>,
     password: Vec<u8>,
@@ -140,26 +224,27 @@ pub(crate) fn mount_all(
     gid: users::gid_t,
     username: String,
     homedir: String,
-) -> Vec<UnmountDrop<Mount>> {
+) -> Result<Vec<UnmountDrop<Mount>>, ServiceOperationReport> {
     let Some(xdg_mounted_dir) = mount_xdg(uid, gid, username.as_str()) else {
-        return vec![];
+        return Err(ServiceOperationReport::new(
+            ServiceOperationResult::MountError,
+            "cannot mount the XDG runtime directory",
+            crate::XDG_RUNTIME_DIR_PATH,
+        ));
     };
 
     // mount xdg folder first
     let mut mounted_devices = vec![xdg_mounted_dir];
 
     if let Some(mounts) = mounts {
-        for m in mounts
-            .foreach(|a, b| {
-                (
-                    b.fstype().clone(),
-                    b.flags().join(",").clone(),
-                    b.device().clone(),
-                    a.clone(),
-                )
-            })
-            .iter()
-        {
+        for (dir, params) in mounts.foreach(|a, b| (a.clone(), b.clone())).iter() {
+            let m = (
+                params.fstype().clone(),
+                params.flags().join(","),
+                params.device().clone(),
+                dir.clone(),
+            );
+
             match mount(m.clone()) {
                 Ok(mount) => {
                     println!(
@@ -170,6 +255,8 @@ pub(crate) fn mount_all(
 
                     // Make the mount temporary, so that it will be unmounted on drop.
                     mounted_devices.push(mount.into_unmount_drop(UnmountFlags::DETACH));
+
+                    enforce_quota(dir, params, uid)?;
                 }
                 Err(err) => {
                     eprintln!(
@@ -179,7 +266,11 @@ pub(crate) fn mount_all(
                         err
                     );
 
-                    return vec![];
+                    return Err(ServiceOperationReport::new(
+                        ServiceOperationResult::MountError,
+                        format!("cannot mount into {}: {err}", m.3.as_str()),
+                        m.2.as_str(),
+                    ));
                 }
             }
         }
@@ -188,7 +279,7 @@ pub(crate) fn mount_all(
             mounts.mount().fstype().clone(),
             mounts.mount().flags().join(","),
             mounts.mount().device().clone(),
-            homedir,
+            homedir.clone(),
         )) {
             Ok(mount) => {
                 println!(
@@ -198,20 +289,187 @@ pub(crate) fn mount_all(
 
                 // Make the mount temporary, so that it will be unmounted on drop.
                 mounted_devices.push(mount.into_unmount_drop(UnmountFlags::DETACH));
+
+                enforce_quota(homedir.as_str(), &mounts.mount(), uid)?;
             }
             Err(err) => {
                 eprintln!("❌ Error mounting user directory: {err}");
-                return vec![];
+                return Err(ServiceOperationReport::new(
+                    ServiceOperationResult::MountError,
+                    format!("cannot mount the home directory: {err}"),
+                    mounts.mount().device().as_str(),
+                ));
             }
         }
     }
 
-    mounted_devices
+    Ok(mounted_devices)
+}
+
+/// Mount the XDG runtime directory and an empty tmpfs as the home directory of a guest:
+/// both are discarded, along with everything the guest has written, once dropped.
+pub(crate) fn mount_guest(
+    uid: users::uid_t,
+    gid: users::gid_t,
+    username: String,
+    homedir: String,
+    tmpfs_size: &str,
+) -> Result<Vec<UnmountDrop<Mount>>, ServiceOperationReport> {
+    let Some(xdg_mounted_dir) = mount_xdg(uid, gid, username.as_str()) else {
+        return Err(ServiceOperationReport::new(
+            ServiceOperationResult::MountError,
+            "cannot mount the XDG runtime directory",
+            crate::XDG_RUNTIME_DIR_PATH,
+        ));
+    };
+
+    match mount((
+        "tmpfs".to_string(),
+        format!("size={tmpfs_size},mode=0700,uid={uid},gid={gid}"),
+        "tmpfs".to_string(),
+        homedir.clone(),
+    )) {
+        Ok(mount) => {
+            println!("🟢 Mounted a tmpfs on home directory for guest '{username}'");
+
+            Ok(vec![
+                xdg_mounted_dir,
+                mount.into_unmount_drop(UnmountFlags::DETACH),
+            ])
+        }
+        Err(err) => {
+            eprintln!("❌ Error mounting the guest home directory: {err}");
+            Err(ServiceOperationReport::new(
+                ServiceOperationResult::MountError,
+                format!("cannot mount the guest home directory: {err}"),
+                homedir,
+            ))
+        }
+    }
+}
+
+/// Directory udev populates with the links identifying block devices
+pub const DISK_BY_DIR: &str = "/dev/disk";
+
+/// Identity of a block device, as udev reports it under /dev/disk
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Eq, Debug)]
+pub struct DeviceIdentity {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    uuid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    partlabel: Option<String>,
+}
+
+impl DeviceIdentity {
+    pub fn new(uuid: Option<String>, partlabel: Option<String>) -> Self {
+        Self { uuid, partlabel }
+    }
+
+    pub fn uuid(&self) -> &Option<String> {
+        &self.uuid
+    }
+
+    pub fn partlabel(&self) -> &Option<String> {
+        &self.partlabel
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.uuid.is_none() && self.partlabel.is_none()
+    }
+
+    /// Identify the device of the given mount source: None if it is not a block device
+    pub fn of_device(disk_dir: &Path, source: &str) -> Option<Self> {
+        use std::os::unix::fs::FileTypeExt;
+
+        let device = resolve_source(disk_dir, source).ok()?;
+        let device = fs::canonicalize(device).ok()?;
+        if !fs::metadata(&device).ok()?.file_type().is_block_device() {
+            return None;
+        }
+
+        Some(Self::lookup(disk_dir, &device))
+    }
+
+    /// Search the by-uuid and by-partlabel links of the given directory for the ones pointing to the given device
+    pub fn lookup(disk_dir: &Path, device: &Path) -> Self {
+        let find = |kind: &str| -> Option<String> {
+            fs::read_dir(disk_dir.join(kind))
+                .ok()?
+                .filter_map(|entry| entry.ok())
+                .find(|entry| {
+                    fs::canonicalize(entry.path())
+                        .map(|target| target == device)
+                        .unwrap_or(false)
+                })
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+        };
+
+        Self::new(find("by-uuid"), find("by-partlabel"))
+    }
+
+    /// Returns true if the actual identity of a device has every element of this one
+    pub fn matches(&self, actual: &DeviceIdentity) -> bool {
+        !self.is_empty()
+            && self
+                .uuid
+                .iter()
+                .all(|uuid| actual.uuid.as_ref() == Some(uuid))
+            && self
+                .partlabel
+                .iter()
+                .all(|partlabel| actual.partlabel.as_ref() == Some(partlabel))
+    }
+}
+
+/// Mounts authorized by the administrator, identified by the hash of their configuration
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(untagged)]
+pub enum MountAuthorization {
+    /// The mounts are authorized whatever device is behind them
+    Hash(String),
+
+    /// The mounts are authorized only if every block device mounted is the one identified
+    Scoped {
+        hash: String,
+        devices: HashMap<String, DeviceIdentity>,
+    },
+}
+
+impl MountAuthorization {
+    pub fn hash(&self) -> &String {
+        match self {
+            MountAuthorization::Hash(hash) => hash,
+            MountAuthorization::Scoped { hash, .. } => hash,
+        }
+    }
+
+    /// Returns true if the given devices are allowed: identify returns the identity of
+    /// a device or None if it is not a block device, that cannot be swapped for another one
+    pub fn allows_devices<F>(&self, devices: &[String], identify: F) -> bool
+    where
+        F: Fn(&str) -> Option<DeviceIdentity>,
+    {
+        let MountAuthorization::Scoped {
+            devices: authorized,
+            ..
+        } = self
+        else {
+            return true;
+        };
+
+        devices
+            .iter()
+            .all(|device| match (authorized.get(device), identify(device)) {
+                (Some(expected), Some(actual)) => expected.matches(&actual),
+                (None, None) => true,
+                _ => false,
+            })
+    }
 }
 
 #[derive(Serialize, Deserialize, Default, Clone, PartialEq, Debug)]
 pub struct MountAuth {
-    authorizations: HashMap<String, Vec<String>>,
+    authorizations: HashMap<String, Vec<MountAuthorization>>,
 }
 
 impl MountAuth {
@@ -229,57 +487,122 @@ impl MountAuth {
         self.authorizations
             .entry(String::from(username))
             .or_default()
-            .push(hash);
+            .push(MountAuthorization::Hash(hash));
+    }
+
+    /// Authorize the mounts only on the devices with the given identities
+    pub fn add_scoped_authorization(
+        &mut self,
+        username: &str,
+        hash: String,
+        devices: HashMap<String, DeviceIdentity>,
+    ) {
+        self.authorizations
+            .entry(String::from(username))
+            .or_default()
+            .push(MountAuthorization::Scoped { hash, devices });
+    }
+
+    fn matching<'a>(
+        &'a self,
+        username: &str,
+        hash: &'a str,
+    ) -> impl Iterator<Item = &'a MountAuthorization> + 'a {
+        self.authorizations
+            .get(&String::from(username))
+            .into_iter()
+            .flatten()
+            .filter(move |value| constant_time_eq(value.hash().as_bytes(), hash.as_bytes()))
     }
 
     pub fn authorized(&self, username: &str, hash: String) -> bool {
-        match self.authorizations.get(&String::from(username)) {
-            Some(values) => values.contains(&hash),
-            None => false,
-        }
+        self.matching(username, hash.as_str()).count() > 0
+    }
+
+    /// Returns true if the mounts are authorized and the given devices are the ones they have been authorized on
+    pub fn authorized_on<F>(
+        &self,
+        username: &str,
+        hash: String,
+        devices: &[String],
+        identify: F,
+    ) -> bool
+    where
+        F: Fn(&str) -> Option<DeviceIdentity>,
+    {
+        self.matching(username, hash.as_str())
+            .any(|authorization| authorization.allows_devices(devices, &identify))
     }
 }
 
 pub struct MountAuthOperations {
     file_path: PathBuf,
+    service_key: Option<Arc<RsaPrivateKey>>,
+    encrypt: bool,
 }
 
 impl MountAuthOperations {
     pub fn new(file_path: PathBuf) -> Self {
-        Self { file_path }
+        Self {
+            file_path,
+            service_key: None,
+            encrypt: false,
+        }
+    }
+
+    /// Read authorizations encrypted with the service key and,
+    /// if requested, keep them encrypted at rest from now on.
+    pub fn with_service_key(mut self, service_key: Arc<RsaPrivateKey>, encrypt: bool) -> Self {
+        self.service_key = Some(service_key);
+        self.encrypt = encrypt;
+        self
+    }
+
+    pub fn file_path(&self) -> &Path {
+        self.file_path.as_path()
     }
 
     pub(crate) async fn read_auth_file(&self) -> Result<MountAuth, ServiceError> {
-        match disk::read_file_or_create_default(self.file_path.clone(), || {
+        let auth_str = disk::read_file_or_create_default(self.file_path.clone(), || {
             serde_json::to_string_pretty(&MountAuth::default()).map_err(ServiceError::JsonError)
         })
-        .await
-        {
-            Ok(auth_str) => MountAuth::new(auth_str.as_str()),
-            Err(err) => Err(err),
+        .await?;
+
+        let Some(service_key) = &self.service_key else {
+            return MountAuth::new(auth_str.as_str());
+        };
+
+        match unseal(service_key, auth_str.as_str())? {
+            Some(plaintext) => MountAuth::new(String::from_utf8_lossy(&plaintext).as_ref()),
+            None => {
+                let authorizations = MountAuth::new(auth_str.as_str())?;
+
+                // files written before encryption has been enabled are encrypted right away
+                if self.encrypt {
+                    self.write_auth_file(&authorizations).await?;
+                    println!("🔒 Encrypted {}", self.file_path.display());
+                }
+
+                Ok(authorizations)
+            }
         }
     }
 
     pub(crate) async fn write_auth_file(
-        &mut self,
+        &self,
         authorizations: &MountAuth,
     ) -> Result<(), ServiceError> {
-        let mut file = match File::create(self.file_path.as_path()) {
-            Ok(file) => file,
-            Err(err) => return Err(ServiceError::IOError(err)),
-        };
-
-        if let Err(err) =
-            file.write((serde_json::to_string_pretty(authorizations).unwrap() + "\n").as_bytes())
-        {
-            return Err(ServiceError::IOError(err));
-        }
+        let content = serde_json::to_string_pretty(authorizations).unwrap() + "\n";
 
-        if let Err(err) = file.flush() {
-            return Err(ServiceError::IOError(err));
-        }
+        let content = match (&self.service_key, self.encrypt) {
+            (Some(service_key), true) => seal(service_key, content.as_bytes())?,
+            _ => content,
+        };
 
-        Ok(())
+        Ok(write_private_file(
+            self.file_path.as_path(),
+            content.as_bytes(),
+        )?)
     }
 }
 
@@ -301,7 +624,7 @@ impl MountAuthDBus {
     )
 )]
 impl MountAuthDBus {
-    pub async fn authorize(&mut self, username: &str, hash: String) -> u32 {
+    pub async fn authorize(&mut self, username: &str, hash: String) -> ServiceOperationReport {
         println!("⚙️ Requested add authorization to mount {hash} for user {username}");
 
         {
@@ -310,7 +633,11 @@ impl MountAuthDBus {
                 Ok(auth_str) => auth_str,
                 Err(err) => {
                     eprintln!("❌ Error opening mount authorizations file: {err}");
-                    return ServiceOperationResult::IOError.into();
+                    return ServiceOperationReport::new(
+                        ServiceOperationResult::IOError,
+                        format!("cannot read the mount authorizations: {err}"),
+                        lck.file_path().display(),
+                    );
                 }
             };
 
@@ -318,13 +645,79 @@ impl MountAuthDBus {
 
             if let Err(err) = lck.write_auth_file(&authorizations).await {
                 eprintln!("❌ Error writing the mount authorizations file: {err}");
-                return ServiceOperationResult::IOError.into();
+                return ServiceOperationReport::new(
+                    ServiceOperationResult::IOError,
+                    format!("cannot write the mount authorizations: {err}"),
+                    lck.file_path().display(),
+                );
             }
         }
 
         println!("✅ New mount authorized to user {username}");
 
-        ServiceOperationResult::Ok.into()
+        ServiceOperationReport::ok()
+    }
+
+    /// Authorize the mounts only on the block devices currently holding them,
+    /// so that they cannot be swapped for different ones later on
+    pub async fn authorize_devices(
+        &mut self,
+        username: &str,
+        hash: String,
+        devices: Vec<String>,
+    ) -> ServiceOperationReport {
+        println!(
+            "⚙️ Requested add authorization to mount {hash} on its devices for user {username}"
+        );
+
+        let mut identities = HashMap::new();
+        for device in devices.iter() {
+            // devices that are not block devices (tmpfs, network shares...) have no identity to check
+            let Some(identity) = DeviceIdentity::of_device(Path::new(DISK_BY_DIR), device) else {
+                continue;
+            };
+
+            if identity.is_empty() {
+                eprintln!("❌ Device {device} has neither an UUID nor a partition label");
+                return ServiceOperationReport::new(
+                    ServiceOperationResult::MountError,
+                    "the device has neither an UUID nor a partition label",
+                    device,
+                );
+            }
+
+            identities.insert(device.clone(), identity);
+        }
+
+        {
+            let mut lck = self.auth_mount_op.write().await;
+            let mut authorizations = match lck.read_auth_file().await {
+                Ok(auth_str) => auth_str,
+                Err(err) => {
+                    eprintln!("❌ Error opening mount authorizations file: {err}");
+                    return ServiceOperationReport::new(
+                        ServiceOperationResult::IOError,
+                        format!("cannot read the mount authorizations: {err}"),
+                        lck.file_path().display(),
+                    );
+                }
+            };
+
+            authorizations.add_scoped_authorization(username, hash, identities);
+
+            if let Err(err) = lck.write_auth_file(&authorizations).await {
+                eprintln!("❌ Error writing the mount authorizations file: {err}");
+                return ServiceOperationReport::new(
+                    ServiceOperationResult::IOError,
+                    format!("cannot write the mount authorizations: {err}"),
+                    lck.file_path().display(),
+                );
+            }
+        }
+
+        println!("✅ New mount authorized to user {username} on its current devices");
+
+        ServiceOperationReport::ok()
     }
 
     pub async fn check(&self, username: &str, hash: String) -> bool {
@@ -344,3 +737,59 @@ impl MountAuthDBus {
         authorizations.authorized(username, hash)
     }
 }
+
+/// Unmount the given mounts in the reverse order they were mounted, giving each one up to
+/// the given time to be unmounted cleanly (flushing its data) before detaching it lazily.
+/// Returns the mounts that could not be unmounted cleanly.
+///
+/// The unmounts run on threads of their own rather than on the blocking pool of the runtime,
+/// that would otherwise wait for an unmount stuck in the kernel before letting the service exit.
+pub async fn unmount_all(mounts: Vec<UnmountDrop<Mount>>, timeout: Duration) -> Vec<PathBuf> {
+    let mut detached = vec![];
+
+    for mount in mounts.into_iter().rev() {
+        let target = mount.target_path().to_path_buf();
+
+        // a mount that fails to unmount is detached as soon as it is dropped
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        let unmount = std::thread::Builder::new()
+            .name(String::from("login_ng-umount"))
+            .spawn(move || {
+                let _ = sender.send(mount.unmount(UnmountFlags::empty()));
+            });
+
+        if let Err(err) = unmount {
+            eprintln!("❌ Error unmounting {}: {err}", target.display());
+            detached.push(target);
+            continue;
+        }
+
+        match tokio::time::timeout(timeout, receiver).await {
+            Ok(Ok(Ok(()))) => println!("🟢 Unmounted {}", target.display()),
+            Ok(Ok(Err(err))) => {
+                eprintln!(
+                    "🟠 Couldn't cleanly unmount {}: {err} - detached",
+                    target.display()
+                );
+                detached.push(target);
+            }
+            Ok(Err(err)) => {
+                eprintln!(
+                    "❌ Error awaiting the unmount of {}: {err}",
+                    target.display()
+                );
+                detached.push(target);
+            }
+            Err(_) => {
+                eprintln!("🟠 Timed out unmounting {}: detaching it", target.display());
+
+                if let Err(err) = sys_mount::unmount(&target, UnmountFlags::DETACH) {
+                    eprintln!("❌ Error detaching {}: {err}", target.display());
+                }
+                detached.push(target);
+            }
+        }
+    }
+
+    detached
+}
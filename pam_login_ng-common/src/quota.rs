@@ -0,0 +1,148 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::process::Command;
+
+use thiserror::Error;
+
+use login_ng::{
+    mount::{MountParams, QuotaKind},
+    users::uid_t,
+};
+
+#[derive(Debug, Error)]
+pub enum QuotaError {
+    #[error("I/O error: {0}")]
+    IOError(#[from] std::io::Error),
+
+    #[error("{0} filesystems do not support quotas")]
+    Unsupported(String),
+
+    #[error("{0} exited with {1}")]
+    CommandFailed(&'static str, std::process::ExitStatus),
+}
+
+// from linux/fs.h
+const FS_IOC_FSGETXATTR: libc::c_ulong = 0x801C581F;
+const FS_XFLAG_PROJINHERIT: u32 = 0x00000200;
+
+#[repr(C)]
+#[derive(Default)]
+struct FsXAttr {
+    xflags: u32,
+    extsize: u32,
+    nextents: u32,
+    projid: u32,
+    cowextsize: u32,
+    pad: [u8; 8],
+}
+
+/// Whether the directory already belongs to the project and passes it on to what is created in it:
+/// the (recursive) assignment is then only needed the first time the filesystem is mounted.
+fn project_assigned(path: &Path, project: uid_t) -> bool {
+    let Ok(dir) = std::fs::File::open(path) else {
+        return false;
+    };
+
+    let mut attr = FsXAttr::default();
+    if unsafe { libc::ioctl(dir.as_raw_fd(), FS_IOC_FSGETXATTR, &mut attr) } != 0 {
+        return false;
+    }
+
+    attr.projid == project && attr.xflags & FS_XFLAG_PROJINHERIT != 0
+}
+
+fn run(program: &'static str, args: &[&str]) -> Result<(), QuotaError> {
+    let status = Command::new(program).args(args).status()?;
+
+    match status.success() {
+        true => Ok(()),
+        false => Err(QuotaError::CommandFailed(program, status)),
+    }
+}
+
+/// Cap the space available on a filesystem just mounted for the given user.
+/// Project quotas use the uid as the project id and require the filesystem to be
+/// mounted with the prjquota option: the files are assigned to the project only the first time,
+/// later ones inherit it. btrfs quotas are enabled on first use.
+pub fn apply_quota(mountpoint: &Path, params: &MountParams, uid: uid_t) -> Result<(), QuotaError> {
+    let Some(quota) = params.quota() else {
+        return Ok(());
+    };
+
+    let path = mountpoint.to_string_lossy().to_string();
+    let project = uid.to_string();
+    let limit = quota.to_string();
+
+    match params.quota_kind() {
+        Some(QuotaKind::Project) if params.fstype() == "xfs" => {
+            if !project_assigned(mountpoint, uid) {
+                run(
+                    "xfs_quota",
+                    &[
+                        "-x",
+                        "-c",
+                        format!("project -s -p {path} {project}").as_str(),
+                        path.as_str(),
+                    ],
+                )?;
+            }
+
+            run(
+                "xfs_quota",
+                &[
+                    "-x",
+                    "-c",
+                    format!("limit -p bhard={limit} {project}").as_str(),
+                    path.as_str(),
+                ],
+            )
+        }
+        Some(QuotaKind::Project) => {
+            if !project_assigned(mountpoint, uid) {
+                run(
+                    "chattr",
+                    &["-R", "+P", "-p", project.as_str(), path.as_str()],
+                )?;
+            }
+
+            // setquota counts blocks of 1KiB
+            let blocks = quota.div_ceil(1024).to_string();
+            run(
+                "setquota",
+                &[
+                    "-P",
+                    project.as_str(),
+                    "0",
+                    blocks.as_str(),
+                    "0",
+                    "0",
+                    path.as_str(),
+                ],
+            )
+        }
+        Some(QuotaKind::Qgroup) => {
+            run("btrfs", &["quota", "enable", path.as_str()])?;
+            run("btrfs", &["qgroup", "limit", limit.as_str(), path.as_str()])
+        }
+        None => Err(QuotaError::Unsupported(params.fstype().clone())),
+    }
+}
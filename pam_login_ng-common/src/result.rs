@@ -19,6 +19,9 @@
 
 use std::fmt;
 
+use serde::{Deserialize, Serialize};
+use zbus::zvariant::Type;
+
 #[derive(Clone, Copy, PartialEq, Debug)]
 #[repr(C)]
 pub enum ServiceOperationResult {
@@ -35,6 +38,18 @@ pub enum ServiceOperationResult {
     UnauthorizedMount = 10,
     SerializationError = 11,
     IOError = 12,
+    HomeActivationError = 13,
+    PermissionDenied = 14,
+    RecoveryError = 15,
+    ApprovalUnavailable = 16,
+    ApprovalPending = 17,
+    ApprovalExpired = 18,
+    GuestUnavailable = 19,
+    GuestAccountError = 20,
+    SessionLimitReached = 21,
+    SwitchUnavailable = 22,
+    SwitchError = 23,
+    AutologinError = 24,
     Unknown,
 }
 
@@ -54,6 +69,18 @@ impl fmt::Display for ServiceOperationResult {
             ServiceOperationResult::UnauthorizedMount => "Unauthorized mount attempted",
             ServiceOperationResult::SerializationError => "(De)Serialization error",
             ServiceOperationResult::IOError => "I/O Error",
+            ServiceOperationResult::HomeActivationError => "systemd-homed activation error",
+            ServiceOperationResult::PermissionDenied => "Permission denied",
+            ServiceOperationResult::RecoveryError => "Recovery token error",
+            ServiceOperationResult::ApprovalUnavailable => "Phone-approved login is not enabled",
+            ServiceOperationResult::ApprovalPending => "Waiting for the login to be approved",
+            ServiceOperationResult::ApprovalExpired => "Login challenge expired or refused",
+            ServiceOperationResult::GuestUnavailable => "Guest sessions are not enabled",
+            ServiceOperationResult::GuestAccountError => "Guest account error",
+            ServiceOperationResult::SessionLimitReached => "Too many concurrent sessions",
+            ServiceOperationResult::SwitchUnavailable => "Fast user switching is not enabled",
+            ServiceOperationResult::SwitchError => "User switch error",
+            ServiceOperationResult::AutologinError => "Next boot autologin error",
             ServiceOperationResult::Unknown => "Unknown Error",
         };
         write!(f, "{}", result_str)
@@ -82,7 +109,84 @@ impl From<u32> for ServiceOperationResult {
             10 => ServiceOperationResult::UnauthorizedMount,
             11 => ServiceOperationResult::SerializationError,
             12 => ServiceOperationResult::IOError,
+            13 => ServiceOperationResult::HomeActivationError,
+            14 => ServiceOperationResult::PermissionDenied,
+            15 => ServiceOperationResult::RecoveryError,
+            16 => ServiceOperationResult::ApprovalUnavailable,
+            17 => ServiceOperationResult::ApprovalPending,
+            18 => ServiceOperationResult::ApprovalExpired,
+            19 => ServiceOperationResult::GuestUnavailable,
+            20 => ServiceOperationResult::GuestAccountError,
+            21 => ServiceOperationResult::SessionLimitReached,
+            22 => ServiceOperationResult::SwitchUnavailable,
+            23 => ServiceOperationResult::SwitchError,
+            24 => ServiceOperationResult::AutologinError,
             _ => ServiceOperationResult::Unknown,
         }
     }
 }
+
+/// The outcome of an operation requested over D-Bus: the error code along with
+/// a human-readable description and the item (user, device, file...) that caused the failure.
+#[derive(Serialize, Deserialize, Type, Clone, PartialEq, Debug)]
+pub struct ServiceOperationReport {
+    code: u32,
+    message: String,
+    item: String,
+}
+
+impl ServiceOperationReport {
+    pub fn new<M, I>(result: ServiceOperationResult, message: M, item: I) -> Self
+    where
+        M: ToString,
+        I: ToString,
+    {
+        Self {
+            code: result.into(),
+            message: message.to_string(),
+            item: item.to_string(),
+        }
+    }
+
+    pub fn ok() -> Self {
+        Self::from(ServiceOperationResult::Ok)
+    }
+
+    pub fn result(&self) -> ServiceOperationResult {
+        ServiceOperationResult::from(self.code)
+    }
+
+    pub fn code(&self) -> u32 {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        self.message.as_str()
+    }
+
+    pub fn item(&self) -> &str {
+        self.item.as_str()
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.result() == ServiceOperationResult::Ok
+    }
+}
+
+impl From<ServiceOperationResult> for ServiceOperationReport {
+    fn from(value: ServiceOperationResult) -> Self {
+        Self::new(value, "", "")
+    }
+}
+
+impl fmt::Display for ServiceOperationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let result = self.result();
+
+        match (self.message.is_empty(), self.item.is_empty()) {
+            (true, _) => write!(f, "{result}"),
+            (false, true) => write!(f, "{result}: {}", self.message),
+            (false, false) => write!(f, "{result}: {} ({})", self.message, self.item),
+        }
+    }
+}
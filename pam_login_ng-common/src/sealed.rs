@@ -0,0 +1,142 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::path::Path;
+
+use configparser::ini::Ini;
+use rsa::{Error as RSAError, Pkcs1v15Encrypt, RsaPrivateKey, RsaPublicKey};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use login_ng::{
+    aes_gcm::{
+        aead::{Aead, AeadCore, KeyInit, OsRng},
+        Aes256Gcm, Key, Nonce,
+    },
+    config::read_config_file,
+};
+
+use crate::hooks::SERVICE_CONFIG_FILE;
+
+#[derive(Debug, Error)]
+pub enum SealError {
+    #[error("RSA error: {0}")]
+    RSAError(#[from] RSAError),
+
+    #[error("AES error")]
+    AESError,
+
+    #[error("Malformed sealed file")]
+    Malformed,
+
+    #[error("JSON error: {0}")]
+    JsonError(#[from] serde_json::Error),
+}
+
+/// Encryption at rest of the files the service keeps in the configuration directory,
+/// read from the [Storage] section of service.conf
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SealConfig {
+    encrypt: bool,
+}
+
+impl SealConfig {
+    pub fn new(encrypt: bool) -> Self {
+        Self { encrypt }
+    }
+
+    /// Parse the given service.conf content: files are stored in plain text by default
+    pub fn parse(content: &str) -> Self {
+        let mut config = Ini::new();
+
+        let encrypt = match config.read(String::from(content)) {
+            Ok(_) => config
+                .getbool("storage", "encrypt")
+                .ok()
+                .flatten()
+                .unwrap_or_default(),
+            Err(_) => false,
+        };
+
+        Self::new(encrypt)
+    }
+
+    /// Load the configuration from service.conf in the given directory
+    pub fn load(config_dir: &Path) -> Self {
+        match read_config_file(&config_dir.join(SERVICE_CONFIG_FILE)) {
+            Ok(content) => Self::parse(content.as_str()),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn encrypt(&self) -> bool {
+        self.encrypt
+    }
+}
+
+/// A file encrypted with a random key, that is in turn encrypted to the service key
+#[derive(Serialize, Deserialize)]
+struct SealedFile {
+    sealed_key: Vec<u8>,
+    nonce: Vec<u8>,
+    data: Vec<u8>,
+}
+
+/// Encrypt the content of a file so that only the holder of the service key can read it
+pub fn seal(key: &RsaPrivateKey, plaintext: &[u8]) -> Result<String, SealError> {
+    let file_key = Aes256Gcm::generate_key(&mut OsRng);
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+    let data = Aes256Gcm::new(&file_key)
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| SealError::AESError)?;
+
+    let mut rng = rand::thread_rng();
+    let sealed_key =
+        RsaPublicKey::from(key).encrypt(&mut rng, Pkcs1v15Encrypt, file_key.as_slice())?;
+
+    let sealed = SealedFile {
+        sealed_key,
+        nonce: nonce.to_vec(),
+        data,
+    };
+
+    Ok(serde_json::to_string(&sealed)? + "\n")
+}
+
+/// Decrypt the content of a sealed file: None if the content has not been sealed
+pub fn unseal(key: &RsaPrivateKey, content: &str) -> Result<Option<Vec<u8>>, SealError> {
+    let Ok(sealed) = serde_json::from_str::<SealedFile>(content) else {
+        return Ok(None);
+    };
+
+    let file_key = key.decrypt(Pkcs1v15Encrypt, sealed.sealed_key.as_slice())?;
+    if file_key.len() != 32 || sealed.nonce.len() != 12 {
+        return Err(SealError::Malformed);
+    }
+
+    let plaintext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(file_key.as_slice()))
+        .decrypt(
+            Nonce::from_slice(sealed.nonce.as_slice()),
+            sealed.data.as_slice(),
+        )
+        .map_err(|_| SealError::AESError)?;
+
+    Ok(Some(plaintext))
+}
@@ -35,6 +35,9 @@ pub enum SessionPreludeError {
     #[error("Plaintext too long")]
     PlaintextTooLong,
 
+    #[error("Plaintext contains a NUL byte")]
+    NulInPlaintext,
+
     #[error("Invalid OTP")]
     InvalidOTP,
 
@@ -48,16 +51,13 @@ pub struct SessionPrelude {
     one_time_token: Vec<u8>,
 }
 
-fn string_to_vec_u8(input: String) -> Vec<u8> {
-    // Convert the String to Vec<u8>
-    let vec = input.into_bytes();
-
+fn pad_plaintext(input: &[u8]) -> Vec<u8> {
     // Create a new Vec<u8> of length 255, initialized with 0u8
     let mut result = vec![0u8; 255];
 
-    // Copy the contents of the original Vec<u8> into the new vector
-    let len = vec.len().min(255); // Ensure we don't exceed the length of 255
-    result[..len].copy_from_slice(&vec[..len]);
+    // Copy the contents of the input into the new vector
+    let len = input.len().min(255); // Ensure we don't exceed the length of 255
+    result[..len].copy_from_slice(&input[..len]);
 
     result
 }
@@ -109,7 +109,9 @@ impl SessionPrelude {
         self.one_time_token.clone()
     }
 
-    pub fn encrypt(&self, plaintext: String) -> Result<Vec<u8>, SessionPreludeError> {
+    /// Encrypt a secret for the service: any byte is accepted except NUL,
+    /// as the secret is padded with zeroes and could not cross PAM anyway.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, SessionPreludeError> {
         let key = Aes256Gcm::generate_key(&mut OsRng);
         let serialized_key = <[u8; 32]>::try_from(key.as_slice()).unwrap();
         let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
@@ -120,6 +122,10 @@ impl SessionPrelude {
             return Err(SessionPreludeError::PlaintextTooLong);
         }
 
+        if plaintext.contains(&0u8) {
+            return Err(SessionPreludeError::NulInPlaintext);
+        }
+
         if self.one_time_token.len() != 255 {
             return Err(SessionPreludeError::InvalidOTP);
         }
@@ -128,7 +134,7 @@ impl SessionPrelude {
             return Err(SessionPreludeError::PubKeyImportError);
         };
 
-        let plain_vec = string_to_vec_u8(plaintext);
+        let plain_vec = pad_plaintext(plaintext);
         if plain_vec.len() != 255 {
             return Err(SessionPreludeError::InternalError);
         }
@@ -222,13 +228,12 @@ impl SessionPrelude {
             return Err(SessionPreludeError::InvalidCiphertext);
         }
 
-        let plaintext: Vec<u8> = plaintext_long
+        // only the padding is made of zeroes: the plaintext itself cannot contain any
+        let len = plaintext_long
             .iter()
-            .filter_map(|ch| match ch {
-                0u8 => None,
-                ch => Some(*ch),
-            })
-            .collect();
+            .rposition(|ch| *ch != 0u8)
+            .map_or(0, |last| last + 1);
+        let plaintext = plaintext_long[..len].to_vec();
 
         Ok((otp, plaintext))
     }
@@ -21,24 +21,36 @@ use tokio::{
     sync::{Mutex, RwLock},
     task::spawn,
 };
-use zbus::interface;
+use zbus::{fdo::DBusProxy, interface, message::Header, object_server::SignalEmitter, Connection};
 
 use sys_mount::{Mount, UnmountDrop};
 
 use login_ng::{
+    config::{read_private_file, write_private_file},
+    faillock::{reset_tally, FaillockConfig},
+    guest::GuestConfig,
+    nextboot::{current_boot_id, NextBootAutologin, NextBootAutologinError},
+    recovery::RecoveryTokens,
+    secure::constant_time_eq,
     storage::load_user_mountpoints,
-    users::{get_user_by_name, gid_t, os::unix::UserExt, uid_t},
+    users::{get_user_by_name, get_user_by_uid, gid_t, os::unix::UserExt, uid_t},
+    vt::{activate_vt, first_free_vt},
 };
 
 use std::{
     collections::{hash_map::DefaultHasher, HashMap},
     ffi::OsString,
     ops::DerefMut,
-    sync::Arc,
+    process::Stdio,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Instant,
 };
 use std::{
     hash::{Hash, Hasher},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 use rsa::{
@@ -47,16 +59,177 @@ use rsa::{
 };
 
 use crate::{
+    approval::{ApprovalConfig, ApprovalStatus, PendingApprovals},
     disk::read_file_or_create_default,
-    mount::{mount_all, MountAuthOperations},
+    guest::{ensure_guest_account, wipe_guest_state},
+    homed::{activate_home, deactivate_home, home_state},
+    hooks::{HookEvent, Hooks, LoginEvent},
+    info::{ServiceInfo, PROTOCOL_VERSION},
+    inhibit::InhibitorLock,
+    limits::SessionLimits,
+    metrics::{Metrics, MetricsSnapshot},
+    mount::{
+        mount_all, mount_guest, unmount_all, DeviceIdentity, MountAuthOperations, DISK_BY_DIR,
+    },
     result::*,
     security::*,
+    state::ServiceState,
+    switch::SwitchConfig,
     ServiceError,
 };
 
+/// Directory (only accessible by root) holding the private key of the service: it is kept apart from
+/// the configuration directory, so that a backup or a leak of the latter does not include the key
+/// that the files sealed in it are encrypted to
+pub const SERVICE_KEY_DIR: &str = "/var/lib/login_ng/keys";
+
+/// File name of the private key of the service
+pub const SERVICE_KEY_FILE: &str = "private_key_pkcs1.pem";
+
+/// Move the private key of the service from the configuration directory, where it used to be kept
+pub async fn migrate_service_key(legacy_path: &Path, file_path: &Path) -> Result<(), ServiceError> {
+    if !legacy_path.exists() || file_path.exists() {
+        return Ok(());
+    }
+
+    let key = read_private_file(legacy_path)?;
+    write_private_file(file_path, key.as_bytes())?;
+    std::fs::remove_file(legacy_path)?;
+
+    println!(
+        "🔑 Moved the service key from {} to {}",
+        legacy_path.display(),
+        file_path.display()
+    );
+
+    Ok(())
+}
+
+/// Read the private key of the service, generating it on first use
+pub async fn load_service_key(file_path: PathBuf) -> Result<RsaPrivateKey, ServiceError> {
+    let default_key_gen_fn = || {
+        let mut rng = crate::rand::thread_rng();
+        let priv_key =
+            crate::rsa::RsaPrivateKey::new(&mut rng, 4096).expect("failed to generate a key");
+
+        Ok(priv_key.to_pkcs1_pem(LineEnding::CRLF).unwrap().to_string())
+    };
+
+    let key_as_str = read_file_or_create_default(file_path, default_key_gen_fn).await?;
+
+    RsaPrivateKey::from_pkcs1_pem(key_as_str.as_str()).map_err(ServiceError::PKCS1Error)
+}
+
+enum SessionAuditEvent {
+    Start,
+    End,
+}
+
+/// Reports the session event to the kernel audit subsystem: a no-op if the audit feature is disabled
+#[cfg(feature = "audit")]
+fn audit_session(event: SessionAuditEvent, username: &str, success: bool) {
+    use login_ng::audit::{audit_log, audit_message, AuditRecordType};
+
+    let (record_type, op) = match event {
+        SessionAuditEvent::Start => (AuditRecordType::UserStart, "login_ng:session_open"),
+        SessionAuditEvent::End => (AuditRecordType::UserEnd, "login_ng:session_close"),
+    };
+
+    if let Err(err) = audit_log(record_type, &audit_message(op, username, "", success)) {
+        eprintln!("❌ Error writing the audit record: {err}");
+    }
+}
+
+/// Reports the session event to the kernel audit subsystem: a no-op if the audit feature is disabled
+#[cfg(not(feature = "audit"))]
+fn audit_session(_event: SessionAuditEvent, _username: &str, _success: bool) {}
+
+/// Returns the uid of the process that sent the message, as the bus reports it in the
+/// credentials of the sending connection, if the bus is able to tell it
+async fn caller_uid(header: &Header<'_>, connection: &Connection) -> Option<u32> {
+    let sender = header.sender()?;
+
+    DBusProxy::new(connection)
+        .await
+        .ok()?
+        .get_connection_credentials(sender.clone().into())
+        .await
+        .ok()?
+        .unix_user_id()
+}
+
+/// Returns true if the message comes from root or from the given user
+pub(crate) async fn caller_is_root_or_user(
+    header: &Header<'_>,
+    connection: &Connection,
+    username: &str,
+) -> bool {
+    let caller = caller_uid(header, connection).await;
+    let owner = get_user_by_name(username).map(|user| user.uid());
+
+    caller == Some(0) || (caller.is_some() && caller == owner)
+}
+
+/// Returns true if the message comes from a caller allowed by the limits to act on the sessions of the given user
+async fn caller_may_act_for(
+    header: &Header<'_>,
+    connection: &Connection,
+    limits: &SessionLimits,
+    username: &str,
+) -> bool {
+    let owner = get_user_by_name(username).map(|user| user.uid());
+
+    match caller_uid(header, connection).await {
+        Some(caller) => limits.may_act_for(caller, owner),
+        None => false,
+    }
+}
+
+/// Block shutdown and sleep while the given operation is in flight, so that a power button
+/// pressed halfway doesn't leave half-mounted homes: the returned lock releases them once dropped
+async fn inhibit_shutdown(why: &str) -> InhibitorLock {
+    match InhibitorLock::take(why).await {
+        Ok(lock) => lock,
+        Err(err) => {
+            eprintln!("🟠 Couldn't take the inhibitor lock: {err}");
+            InhibitorLock::default()
+        }
+    }
+}
+
+/// Maximum number of unused one time tokens the D-Bus clients of a single uid can hold:
+/// counted by uid because a client can open as many connections (and unique names) as it likes
+const MAX_TOKENS_PER_UID: usize = 4;
+
+/// D-Bus client a one time token has been handed out to
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TokenClient {
+    name: String,
+    uid: u32,
+}
+
+/// Identifies the client that sent the message by its unique bus name and uid
+async fn token_client(header: &Header<'_>, connection: &Connection) -> Option<TokenClient> {
+    let name = header.sender()?.to_string();
+    let uid = caller_uid(header, connection).await?;
+
+    Some(TokenClient { name, uid })
+}
+
+/// One time token handed out by initiate_session, to be used by the same client before it expires
+struct OneTimeToken {
+    token: Vec<u8>,
+    client: TokenClient,
+    created: Instant,
+}
+
 struct UserSession {
-    _mounts: Vec<UnmountDrop<Mount>>,
+    mounts: Vec<UnmountDrop<Mount>>,
     count: usize,
+    homed: bool,
+    guest: bool,
+    /// VTs the sessions run on, as reported by the PAM module
+    vts: Vec<u32>,
 }
 
 enum RsaPrivateKeyFetchOpStatus {
@@ -66,8 +239,17 @@ enum RsaPrivateKeyFetchOpStatus {
 
 pub struct Sessions {
     mounts_auth: Arc<RwLock<MountAuthOperations>>,
+    approval_config: Option<ApprovalConfig>,
+    approvals: Arc<RwLock<PendingApprovals>>,
+    hooks: Arc<Hooks>,
+    guest: GuestConfig,
+    limits: SessionLimits,
+    switch: SwitchConfig,
+    /// VT of the greeter started by the last switch while it is running, 0 when there is none
+    switch_greeter_vt: Arc<AtomicU32>,
+    metrics: Arc<Metrics>,
     priv_key: Mutex<RsaPrivateKeyFetchOpStatus>,
-    one_time_tokens: HashMap<u64, Vec<u8>>,
+    one_time_tokens: HashMap<u64, OneTimeToken>,
     sessions: HashMap<OsString, UserSession>,
 }
 
@@ -75,36 +257,52 @@ impl Sessions {
     pub fn new(
         private_key_file_path: PathBuf,
         mounts_auth: Arc<RwLock<MountAuthOperations>>,
+        approval_config: Option<ApprovalConfig>,
+        approvals: Arc<RwLock<PendingApprovals>>,
+        hooks: Arc<Hooks>,
+        guest: GuestConfig,
+        limits: SessionLimits,
+        switch: SwitchConfig,
+        metrics: Arc<Metrics>,
     ) -> Self {
         let file_path = private_key_file_path;
 
         let filepath = file_path.clone();
 
-        let priv_key = Mutex::new(RsaPrivateKeyFetchOpStatus::InProgress(spawn(async {
-            let default_key_gen_fn = || {
-                let mut rng = crate::rand::thread_rng();
-                let priv_key = crate::rsa::RsaPrivateKey::new(&mut rng, 4096)
-                    .expect("failed to generate a key");
-
-                Ok(priv_key.to_pkcs1_pem(LineEnding::CRLF).unwrap().to_string())
-            };
-
-            let key_as_str = read_file_or_create_default(filepath, default_key_gen_fn).await?;
-
-            RsaPrivateKey::from_pkcs1_pem(key_as_str.as_str()).map_err(ServiceError::PKCS1Error)
-        })));
+        let priv_key = Mutex::new(RsaPrivateKeyFetchOpStatus::InProgress(spawn(
+            load_service_key(filepath),
+        )));
 
         let one_time_tokens = HashMap::new();
         let sessions = HashMap::new();
 
         Self {
             mounts_auth,
+            approval_config,
+            approvals,
+            hooks,
+            guest,
+            limits,
+            switch,
+            switch_greeter_vt: Arc::new(AtomicU32::new(0)),
+            metrics,
             priv_key,
             one_time_tokens,
             sessions,
         }
     }
 
+    /// Forget the one time tokens that have not been used in time: returns how many
+    pub fn purge_expired_tokens(&mut self) -> usize {
+        let token_ttl = self.limits.token_ttl();
+        let count = self.one_time_tokens.len();
+
+        self.one_time_tokens
+            .retain(|_, token| token.created.elapsed() <= token_ttl);
+
+        count - self.one_time_tokens.len()
+    }
+
     async fn fetch_priv_key(&mut self) -> Result<Arc<RsaPrivateKey>, ServiceError> {
         let mut lck = self.priv_key.lock().await;
         match lck.deref_mut() {
@@ -122,70 +320,46 @@ impl Sessions {
             },
         }
     }
-}
 
-#[interface(
-    name = "org.neroreflex.login_ng_session1",
-    proxy(
-        default_service = "org.neroreflex.login_ng_session",
-        default_path = "/org/zbus/login_ng_session"
-    )
-)]
-impl Sessions {
-    async fn initiate_session(&mut self) -> String {
-        println!("🔓 Requested initialization of a new session");
-
-        let priv_key = match self.fetch_priv_key().await {
-            Ok(priv_key) => priv_key,
-            Err(err) => {
-                println!("❌ Error fetching the private RSA key: {err}");
-                return String::new();
-            }
-        };
-
-        let pub_pkcs1_pem =
-            match RsaPublicKey::from(priv_key.as_ref()).to_pkcs1_pem(LineEnding::CRLF) {
-                Ok(key) => key,
-                Err(err) => {
-                    println!("❌ Error serializing the RSA key: {err}");
-                    return String::new();
-                }
-            };
-
-        let session = SessionPrelude::new(pub_pkcs1_pem);
-
-        let otp = session.one_time_token();
-
-        let mut hasher = DefaultHasher::new();
-        otp.hash(&mut hasher);
-        let key = hasher.finish();
-
-        let serialized = match serde_json::to_string(&session) {
-            Ok(serialized) => serialized,
-            Err(err) => {
-                println!("❌ Error serializing the session one time token: {err}");
-                return String::new();
-            }
-        };
+    /// Refuse to open one more session for a user that already has as many as allowed
+    fn check_session_limit(&self, username: &str) -> Result<(), ServiceOperationReport> {
+        let open_sessions = self
+            .sessions
+            .get(&OsString::from(username))
+            .map_or(0, |session| session.count);
 
-        self.one_time_tokens.insert(key, otp);
+        if self.limits.allows(open_sessions) {
+            return Ok(());
+        }
 
-        println!("✅ Created one time token {key}");
+        eprintln!("🚫 User {username} already has {open_sessions} sessions open");
 
-        serialized
+        Err(ServiceOperationReport::new(
+            ServiceOperationResult::SessionLimitReached,
+            format!(
+                "the maximum of {} concurrent sessions has been reached",
+                self.limits.max_per_user().unwrap_or_default()
+            ),
+            username,
+        ))
     }
 
-    async fn open_user_session(
+    async fn open_session(
         &mut self,
+        client: Option<TokenClient>,
         username: &str,
         password: Vec<u8>,
-    ) -> (u32, uid_t, gid_t) {
+    ) -> Result<(uid_t, gid_t), ServiceOperationReport> {
         println!("👤 Requested session for user '{username}' to be opened");
 
         let source = login_ng::storage::StorageSource::Username(String::from(username));
 
         let Some(user) = get_user_by_name(username) else {
-            return (ServiceOperationResult::CannotIdentifyUser.into(), 0, 0);
+            return Err(ServiceOperationReport::new(
+                ServiceOperationResult::CannotIdentifyUser,
+                "no such user",
+                username,
+            ));
         };
 
         match self.sessions.get_mut(&user.name().to_os_string()) {
@@ -199,7 +373,11 @@ impl Sessions {
                     Ok(priv_key) => priv_key,
                     Err(err) => {
                         println!("❌ Error fetching the private RSA key: {err}");
-                        return (ServiceOperationResult::PubKeyError.into(), 0, 0);
+                        return Err(ServiceOperationReport::new(
+                            ServiceOperationResult::PubKeyError,
+                            err,
+                            "",
+                        ));
                     }
                 };
 
@@ -207,7 +385,11 @@ impl Sessions {
                     Ok(result) => result,
                     Err(err) => {
                         eprintln!("❌ Error in decrypting data: {err}");
-                        return (ServiceOperationResult::DataDecryptionFailed.into(), 0, 0);
+                        return Err(ServiceOperationReport::new(
+                            ServiceOperationResult::DataDecryptionFailed,
+                            err,
+                            "",
+                        ));
                     }
                 };
 
@@ -216,14 +398,43 @@ impl Sessions {
                 otp.hash(&mut hasher);
                 match self.one_time_tokens.remove(&hasher.finish()) {
                     Some(stored) => {
-                        if stored != otp {
+                        if stored.created.elapsed() > self.limits.token_ttl() {
+                            eprintln!("⌛ The provided temporary OTP key has expired");
+                            return Err(ServiceOperationReport::new(
+                                ServiceOperationResult::EncryptionError,
+                                "expired one time token",
+                                "",
+                            ));
+                        }
+
+                        // a token can only be consumed by the client it was handed out to
+                        if client.as_ref() != Some(&stored.client) {
+                            eprintln!(
+                                "🚫 The provided temporary OTP key belongs to another client"
+                            );
+                            return Err(ServiceOperationReport::new(
+                                ServiceOperationResult::EncryptionError,
+                                "the one time token was issued to another client",
+                                "",
+                            ));
+                        }
+
+                        if !constant_time_eq(stored.token.as_slice(), otp.as_slice()) {
                             eprintln!("🚫 The provided temporary OTP key couldn't be verified");
-                            return (ServiceOperationResult::EncryptionError.into(), 0, 0);
+                            return Err(ServiceOperationReport::new(
+                                ServiceOperationResult::EncryptionError,
+                                "the one time token could not be verified",
+                                "",
+                            ));
                         }
                     }
                     None => {
                         println!("❌ Error in finding the provided temporary OTP key");
-                        return (ServiceOperationResult::EncryptionError.into(), 0, 0);
+                        return Err(ServiceOperationReport::new(
+                            ServiceOperationResult::EncryptionError,
+                            "unknown or already used one time token",
+                            "",
+                        ));
                     }
                 }
 
@@ -231,11 +442,11 @@ impl Sessions {
                     Ok(user_cfg) => user_cfg,
                     Err(err) => {
                         eprintln!("❌ Error loading user mount data: {err}");
-                        return (
-                            ServiceOperationResult::CannotLoadUserMountError.into(),
-                            0,
-                            0,
-                        );
+                        return Err(ServiceOperationReport::new(
+                            ServiceOperationResult::CannotLoadUserMountError,
+                            err,
+                            username,
+                        ));
                     }
                 };
 
@@ -245,39 +456,109 @@ impl Sessions {
                 // security and integrity of the whole system.
                 if let Some(mounts) = user_mounts.clone() {
                     let hash_to_check = mounts.hash();
-                    match self.mounts_auth.read().await.read_auth_file().await {
+                    let mounts_auth_op = self.mounts_auth.read().await;
+                    match mounts_auth_op.read_auth_file().await {
                         Ok(mounts_auth) => {
                             if !mounts_auth.authorized(username, hash_to_check.clone()) {
                                 eprintln!(
                                     "🚫 User {username} attempted an unauthorized mount {hash_to_check}."
                                 );
-                                return (ServiceOperationResult::UnauthorizedMount.into(), 0, 0);
+                                return Err(ServiceOperationReport::new(
+                                    ServiceOperationResult::UnauthorizedMount,
+                                    "the mounts have not been authorized by the administrator",
+                                    hash_to_check,
+                                ));
+                            }
+
+                            // the configuration alone doesn't prevent a different disk from being plugged in
+                            if !mounts_auth.authorized_on(
+                                username,
+                                hash_to_check.clone(),
+                                mounts.devices().as_slice(),
+                                |device| DeviceIdentity::of_device(Path::new(DISK_BY_DIR), device),
+                            ) {
+                                eprintln!(
+                                    "🚫 User {username} attempted mount {hash_to_check} on devices it is not authorized on."
+                                );
+                                return Err(ServiceOperationReport::new(
+                                    ServiceOperationResult::UnauthorizedMount,
+                                    "the devices are not the ones authorized by the administrator",
+                                    hash_to_check,
+                                ));
                             }
                         }
                         Err(err) => {
                             eprintln!("❌ Error reading mount authorizations file: {err}");
-                            return (ServiceOperationResult::UnauthorizedMount.into(), 0, 0);
+                            return Err(ServiceOperationReport::new(
+                                ServiceOperationResult::IOError,
+                                err,
+                                mounts_auth_op.file_path().display(),
+                            ));
                         }
                     };
                 };
 
-                let mounted_devices = mount_all(
+                // Unlock the home directory if the user is managed by systemd-homed:
+                // this has to happen before anything gets mounted inside of it.
+                let homed = match Connection::system().await {
+                    Ok(connection) => match home_state(&connection, username).await {
+                        Some(_) => match activate_home(&connection, username, password.as_slice())
+                            .await
+                        {
+                            Ok(activated) => {
+                                if activated {
+                                    println!("🏠 Activated systemd-homed home for user {username}");
+                                }
+
+                                activated
+                            }
+                            Err(err) => {
+                                eprintln!(
+                                    "❌ Error activating systemd-homed home for user {username}: {err}"
+                                );
+                                return Err(ServiceOperationReport::new(
+                                    ServiceOperationResult::HomeActivationError,
+                                    err,
+                                    username,
+                                ));
+                            }
+                        },
+                        None => false,
+                    },
+                    Err(err) => {
+                        println!(
+                            "🟠 Couldn't connect to the system bus to query systemd-homed: {err}"
+                        );
+                        false
+                    }
+                };
+
+                let mounted_devices = match mount_all(
                     user_mounts,
                     password,
                     user.uid(),
                     user.primary_group_id(),
                     user.name().to_string_lossy().to_string(),
                     user.home_dir().as_os_str().to_string_lossy().to_string(),
-                );
+                ) {
+                    Ok(mounted_devices) => mounted_devices,
+                    Err(report) => {
+                        eprintln!("❌ Error mounting one or more devices for user {username}");
 
-                if mounted_devices.is_empty() {
-                    eprintln!("❌ Error mounting one or more devices for user {username}");
-                    return (ServiceOperationResult::MountError.into(), 0, 0);
-                }
+                        if homed {
+                            Self::deactivate_homed(username).await;
+                        }
+
+                        return Err(report);
+                    }
+                };
 
                 let user_session = UserSession {
-                    _mounts: mounted_devices,
+                    mounts: mounted_devices,
                     count: 1,
+                    homed,
+                    guest: false,
+                    vts: vec![],
                 };
 
                 self.sessions
@@ -287,18 +568,18 @@ impl Sessions {
             }
         }
 
-        (
-            ServiceOperationResult::Ok.into(),
-            user.uid(),
-            user.primary_group_id(),
-        )
+        Ok((user.uid(), user.primary_group_id()))
     }
 
-    async fn close_user_session(&mut self, user: &str) -> u32 {
+    async fn close_session(&mut self, user: &str) -> ServiceOperationReport {
         println!("👤 Requested session for user '{user}' to be closed");
 
         let Some(user) = get_user_by_name(user) else {
-            return ServiceOperationResult::CannotIdentifyUser.into();
+            return ServiceOperationReport::new(
+                ServiceOperationResult::CannotIdentifyUser,
+                "no such user",
+                user,
+            );
         };
 
         let username = user.name().to_string_lossy();
@@ -311,20 +592,992 @@ impl Sessions {
                     // either remove the user session from the collection and destroy the session or
                     // report to the caller that the requested session is already closed
                     match self.sessions.remove(user.name()) {
-                        Some(user_session) => drop(user_session),
-                        None => return ServiceOperationResult::SessionAlreadyClosed.into(),
+                        Some(user_session) => {
+                            let homed = user_session.homed;
+                            let guest = user_session.guest;
+
+                            drop(user_session);
+
+                            // lock the systemd-homed home only after everything inside it has been unmounted
+                            if homed {
+                                Self::deactivate_homed(&username).await;
+                            }
+
+                            // the guest home is gone with its tmpfs: clean up everything else
+                            if guest {
+                                wipe_guest_state(&username, user.uid()).await;
+                            }
+                        }
+                        None => {
+                            return ServiceOperationReport::new(
+                                ServiceOperationResult::SessionAlreadyClosed,
+                                "no session is open",
+                                username,
+                            )
+                        }
                     };
                 }
 
                 println!("✅ Successfully closed session for user '{username}'");
 
-                ServiceOperationResult::Ok.into()
+                ServiceOperationReport::ok()
             }
             None => {
                 eprintln!("❌ Error closing session for user {username}: already closed");
 
-                ServiceOperationResult::SessionAlreadyClosed.into()
+                ServiceOperationReport::new(
+                    ServiceOperationResult::SessionAlreadyClosed,
+                    "no session is open",
+                    username,
+                )
             }
         }
     }
+
+    async fn open_guest(
+        &mut self,
+        username: &str,
+    ) -> Result<(uid_t, gid_t), ServiceOperationReport> {
+        println!("👤 Requested guest session for user '{username}' to be opened");
+
+        if !self.guest.is_guest(username) {
+            return Err(ServiceOperationReport::new(
+                ServiceOperationResult::GuestUnavailable,
+                "not a guest account",
+                username,
+            ));
+        }
+
+        let user = match ensure_guest_account(&self.guest, username).await {
+            Ok(user) => user,
+            Err(err) => {
+                eprintln!("❌ Error preparing the guest account {username}: {err}");
+                return Err(ServiceOperationReport::new(
+                    ServiceOperationResult::GuestAccountError,
+                    err,
+                    username,
+                ));
+            }
+        };
+
+        match self.sessions.get_mut(&user.name().to_os_string()) {
+            Some(session) => {
+                session.count += 1;
+
+                println!("✅ Incremented count of sessions for guest {username}");
+            }
+            None => {
+                let mounted_devices = mount_guest(
+                    user.uid(),
+                    user.primary_group_id(),
+                    user.name().to_string_lossy().to_string(),
+                    user.home_dir().as_os_str().to_string_lossy().to_string(),
+                    self.guest.tmpfs_size(),
+                )?;
+
+                let user_session = UserSession {
+                    mounts: mounted_devices,
+                    count: 1,
+                    homed: false,
+                    guest: true,
+                    vts: vec![],
+                };
+
+                self.sessions
+                    .insert(user.name().to_os_string(), user_session);
+
+                println!("✅ Successfully opened guest session for user {username}");
+            }
+        }
+
+        Ok((user.uid(), user.primary_group_id()))
+    }
+
+    /// Close every open session at service shutdown: mounts are unmounted in order, each given
+    /// the configured time to be flushed cleanly, so that a reboot doesn't leave dirty filesystems.
+    /// Returns the state to be saved for the next start of the service.
+    pub async fn close_all_sessions(&mut self) -> ServiceState {
+        let unmount_timeout = self.limits.unmount_timeout();
+        let mut closed = vec![];
+        let mut detached_mounts = vec![];
+
+        for (username, user_session) in std::mem::take(&mut self.sessions).into_iter() {
+            let username = username.to_string_lossy().to_string();
+
+            println!("👤 Closing the session of user '{username}' for shutdown");
+
+            let detached = unmount_all(user_session.mounts, unmount_timeout).await;
+
+            if user_session.homed {
+                Self::deactivate_homed(&username).await;
+            }
+
+            if user_session.guest {
+                if let Some(user) = get_user_by_name(&username) {
+                    wipe_guest_state(&username, user.uid()).await;
+                }
+            }
+
+            for _ in 0..user_session.count {
+                self.metrics.record_session_closed();
+            }
+
+            audit_session(SessionAuditEvent::End, &username, detached.is_empty());
+
+            self.hooks.notify(LoginEvent::new(
+                HookEvent::SessionClose,
+                &username,
+                detached.is_empty(),
+                "closed for service shutdown",
+            ));
+
+            closed.push(username);
+            detached_mounts.extend(detached);
+        }
+
+        // make sure everything written by the sessions reaches the disks, without
+        // waiting forever on a filesystem that cannot be flushed
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        let sync = std::thread::Builder::new()
+            .name(String::from("login_ng-sync"))
+            .spawn(move || {
+                unsafe { libc::sync() };
+                let _ = sender.send(());
+            });
+        if sync.is_err()
+            || tokio::time::timeout(unmount_timeout, receiver)
+                .await
+                .is_err()
+        {
+            eprintln!("🟠 Couldn't flush the filesystems in time");
+        }
+
+        ServiceState::shut_down(closed, detached_mounts)
+    }
+
+    async fn deactivate_homed(username: &str) {
+        let connection = match Connection::system().await {
+            Ok(connection) => connection,
+            Err(err) => {
+                eprintln!("❌ Couldn't connect to the system bus to deactivate the home of {username}: {err}");
+                return;
+            }
+        };
+
+        match deactivate_home(&connection, username).await {
+            Ok(_) => println!("🏠 Deactivated systemd-homed home for user {username}"),
+            Err(err) => {
+                eprintln!("❌ Error deactivating systemd-homed home for user {username}: {err}")
+            }
+        }
+    }
+}
+
+#[interface(
+    name = "org.neroreflex.login_ng_session1",
+    proxy(
+        default_service = "org.neroreflex.login_ng_session",
+        default_path = "/org/zbus/login_ng_session"
+    )
+)]
+impl Sessions {
+    async fn initiate_session(
+        &mut self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+    ) -> String {
+        println!("🔓 Requested initialization of a new session");
+
+        self.purge_expired_tokens();
+
+        let Some(client) = token_client(&header, connection).await else {
+            println!("❌ Error identifying the client requesting the session");
+            self.metrics.record_handshake_error();
+            return String::new();
+        };
+
+        let pending = self
+            .one_time_tokens
+            .values()
+            .filter(|token| token.client.uid == client.uid)
+            .count();
+        if pending >= MAX_TOKENS_PER_UID {
+            println!(
+                "🚫 Client {}: uid {} already holds {pending} unused one time tokens",
+                client.name, client.uid
+            );
+            self.metrics.record_handshake_error();
+            return String::new();
+        }
+
+        let priv_key = match self.fetch_priv_key().await {
+            Ok(priv_key) => priv_key,
+            Err(err) => {
+                println!("❌ Error fetching the private RSA key: {err}");
+                self.metrics.record_handshake_error();
+                return String::new();
+            }
+        };
+
+        let pub_pkcs1_pem =
+            match RsaPublicKey::from(priv_key.as_ref()).to_pkcs1_pem(LineEnding::CRLF) {
+                Ok(key) => key,
+                Err(err) => {
+                    println!("❌ Error serializing the RSA key: {err}");
+                    self.metrics.record_handshake_error();
+                    return String::new();
+                }
+            };
+
+        let session = SessionPrelude::new(pub_pkcs1_pem);
+
+        let otp = session.one_time_token();
+
+        let mut hasher = DefaultHasher::new();
+        otp.hash(&mut hasher);
+        let key = hasher.finish();
+
+        let serialized = match serde_json::to_string(&session) {
+            Ok(serialized) => serialized,
+            Err(err) => {
+                println!("❌ Error serializing the session one time token: {err}");
+                self.metrics.record_handshake_error();
+                return String::new();
+            }
+        };
+
+        self.one_time_tokens.insert(
+            key,
+            OneTimeToken {
+                token: otp,
+                client,
+                created: Instant::now(),
+            },
+        );
+
+        println!("✅ Created one time token {key}");
+
+        serialized
+    }
+
+    async fn open_user_session(
+        &mut self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+        username: &str,
+        password: Vec<u8>,
+    ) -> (ServiceOperationReport, uid_t, gid_t) {
+        let client = token_client(&header, connection).await;
+
+        let _inhibitor = inhibit_shutdown("Opening a user session").await;
+
+        let result = if !caller_may_act_for(&header, connection, &self.limits, username).await {
+            println!(
+                "🚫 Session for user '{username}' requested by a caller not allowed to open it"
+            );
+            Err(ServiceOperationReport::new(
+                ServiceOperationResult::PermissionDenied,
+                "only root, the PAM module and the user itself can open a session",
+                username,
+            ))
+        } else {
+            match self.check_session_limit(username) {
+                Ok(_) => self.open_session(client, username, password).await,
+                Err(report) => Err(report),
+            }
+        };
+
+        match &result {
+            Ok(_) => self.metrics.record_session_opened(),
+            Err(report) => self.metrics.record_session_failure(report.result()),
+        }
+
+        audit_session(SessionAuditEvent::Start, username, result.is_ok());
+
+        self.hooks.notify(LoginEvent::new(
+            HookEvent::SessionOpen,
+            username,
+            result.is_ok(),
+            match &result {
+                Ok(_) => "",
+                Err(report) => report.message(),
+            },
+        ));
+
+        match result {
+            Ok((uid, gid)) => (ServiceOperationReport::ok(), uid, gid),
+            Err(report) => (report, 0, 0),
+        }
+    }
+
+    async fn close_user_session(
+        &mut self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+        user: &str,
+    ) -> ServiceOperationReport {
+        let _inhibitor = inhibit_shutdown("Closing a user session").await;
+
+        let result = if !caller_may_act_for(&header, connection, &self.limits, user).await {
+            println!(
+                "🚫 Closing the session of user '{user}' requested by a caller not allowed to"
+            );
+            ServiceOperationReport::new(
+                ServiceOperationResult::PermissionDenied,
+                "only root, the PAM module and the user itself can close a session",
+                user,
+            )
+        } else {
+            self.close_session(user).await
+        };
+
+        // the last session of the user has been closed
+        if result.is_ok() && !self.sessions.contains_key(&OsString::from(user)) {
+            if let Err(err) = Self::session_closed(&emitter, user).await {
+                eprintln!("❌ Error emitting SessionClosed for user {user}: {err}");
+            }
+        }
+
+        if result.is_ok() {
+            self.metrics.record_session_closed();
+        }
+
+        audit_session(SessionAuditEvent::End, user, result.is_ok());
+
+        self.hooks.notify(LoginEvent::new(
+            HookEvent::SessionClose,
+            user,
+            result.is_ok(),
+            result.message(),
+        ));
+
+        result
+    }
+
+    /// Emitted once every session of the user has been closed and its mounts released
+    #[zbus(signal)]
+    pub async fn session_closed(emitter: &SignalEmitter<'_>, username: &str) -> zbus::Result<()>;
+
+    async fn prepare_guest_account(
+        &mut self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+        username: &str,
+    ) -> ServiceOperationReport {
+        // guest accounts are created on behalf of the PAM module running in root greeters
+        if caller_uid(&header, connection).await != Some(0) {
+            println!("🚫 Guest account {username} requested by a non-root caller");
+            return ServiceOperationReport::new(
+                ServiceOperationResult::PermissionDenied,
+                "only root can prepare guest accounts",
+                username,
+            );
+        }
+
+        if !self.guest.is_guest(username) {
+            return ServiceOperationReport::new(
+                ServiceOperationResult::GuestUnavailable,
+                "not a guest account",
+                username,
+            );
+        }
+
+        match ensure_guest_account(&self.guest, username).await {
+            Ok(_) => ServiceOperationReport::ok(),
+            Err(err) => {
+                eprintln!("❌ Error preparing the guest account {username}: {err}");
+                ServiceOperationReport::new(
+                    ServiceOperationResult::GuestAccountError,
+                    err,
+                    username,
+                )
+            }
+        }
+    }
+
+    async fn open_guest_session(
+        &mut self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+        username: &str,
+    ) -> (ServiceOperationReport, uid_t, gid_t) {
+        // there is no password to check: only root can open guest sessions
+        if caller_uid(&header, connection).await != Some(0) {
+            println!("🚫 Guest session for {username} requested by a non-root caller");
+            return (
+                ServiceOperationReport::new(
+                    ServiceOperationResult::PermissionDenied,
+                    "only root can open guest sessions",
+                    username,
+                ),
+                0,
+                0,
+            );
+        }
+
+        let result = match self.check_session_limit(username) {
+            Ok(_) => self.open_guest(username).await,
+            Err(report) => Err(report),
+        };
+
+        match &result {
+            Ok(_) => self.metrics.record_session_opened(),
+            Err(report) => self.metrics.record_session_failure(report.result()),
+        }
+
+        audit_session(SessionAuditEvent::Start, username, result.is_ok());
+
+        self.hooks.notify(LoginEvent::new(
+            HookEvent::SessionOpen,
+            username,
+            result.is_ok(),
+            match &result {
+                Ok(_) => "guest",
+                Err(report) => report.message(),
+            },
+        ));
+
+        match result {
+            Ok((uid, gid)) => (ServiceOperationReport::ok(), uid, gid),
+            Err(report) => (report, 0, 0),
+        }
+    }
+
+    async fn report_authentication(
+        &mut self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+        username: &str,
+        success: bool,
+    ) -> ServiceOperationReport {
+        // authentication results are reported by the PAM module on behalf of root greeters
+        if caller_uid(&header, connection).await != Some(0) {
+            println!("🚫 Authentication result for {username} refused from a non-root caller");
+            return ServiceOperationReport::new(
+                ServiceOperationResult::PermissionDenied,
+                "only root can report authentication results",
+                username,
+            );
+        }
+
+        self.metrics.record_authentication(success);
+
+        println!(
+            "{} Authentication of user {username} {}",
+            if success { "🔓" } else { "🔒" },
+            if success { "succeeded" } else { "failed" }
+        );
+
+        self.hooks.notify(LoginEvent::new(
+            match success {
+                true => HookEvent::Success,
+                false => HookEvent::Failure,
+            },
+            username,
+            success,
+            "",
+        ));
+
+        ServiceOperationReport::ok()
+    }
+
+    /// Liveness check: replies with the protocol version spoken by the service
+    async fn ping(&self) -> u32 {
+        PROTOCOL_VERSION
+    }
+
+    /// Protocol version, login_ng version and features of the service, for clients
+    /// to check they can talk to it (see [`crate::info::verify_service`])
+    async fn get_info(&self) -> ServiceInfo {
+        ServiceInfo::current()
+    }
+
+    /// Counters of authentications, sessions and failures since the service started
+    async fn stats(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// Returns true if the given user has at least one session open through the service:
+    /// only root and the user itself are allowed to ask, everyone else is always answered false
+    async fn is_session_open(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+        username: &str,
+    ) -> bool {
+        caller_is_root_or_user(&header, connection, username).await
+            && self.sessions.contains_key(&OsString::from(username))
+    }
+
+    /// Number of open sessions of the given user and the mountpoints they hold:
+    /// only root and the user itself are allowed to ask
+    async fn session_summary(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+        username: &str,
+    ) -> (ServiceOperationReport, u32, Vec<String>) {
+        if !caller_is_root_or_user(&header, connection, username).await {
+            return (
+                ServiceOperationReport::new(
+                    ServiceOperationResult::PermissionDenied,
+                    "only root and the user itself can inspect the sessions",
+                    username,
+                ),
+                0,
+                vec![],
+            );
+        }
+
+        match self.sessions.get(&OsString::from(username)) {
+            Some(session) => (
+                ServiceOperationReport::ok(),
+                session.count as u32,
+                session
+                    .mounts
+                    .iter()
+                    .map(|mount| mount.target_path().to_string_lossy().to_string())
+                    .collect(),
+            ),
+            None => (ServiceOperationReport::ok(), 0, vec![]),
+        }
+    }
+
+    /// Record the VT a session of the user runs on, for fast user switching:
+    /// only root and the user itself can tell
+    async fn register_session_vt(
+        &mut self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+        username: &str,
+        vt: u32,
+    ) -> ServiceOperationReport {
+        if !caller_may_act_for(&header, connection, &self.limits, username).await {
+            return ServiceOperationReport::new(
+                ServiceOperationResult::PermissionDenied,
+                "only root, the PAM module and the user itself can register the VT of a session",
+                username,
+            );
+        }
+
+        match self.sessions.get_mut(&OsString::from(username)) {
+            Some(session) => {
+                if vt > 0 && !session.vts.contains(&vt) {
+                    session.vts.push(vt);
+                }
+
+                ServiceOperationReport::ok()
+            }
+            None => ServiceOperationReport::new(
+                ServiceOperationResult::SessionAlreadyClosed,
+                "no session is open",
+                username,
+            ),
+        }
+    }
+
+    /// Forget the VT of a session of the user that is being closed
+    async fn unregister_session_vt(
+        &mut self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+        username: &str,
+        vt: u32,
+    ) -> ServiceOperationReport {
+        if !caller_may_act_for(&header, connection, &self.limits, username).await {
+            return ServiceOperationReport::new(
+                ServiceOperationResult::PermissionDenied,
+                "only root, the PAM module and the user itself can unregister the VT of a session",
+                username,
+            );
+        }
+
+        if let Some(session) = self.sessions.get_mut(&OsString::from(username)) {
+            session.vts.retain(|registered| *registered != vt);
+        }
+
+        ServiceOperationReport::ok()
+    }
+
+    /// Fast user switching: activate the VT of an open session of the given user or, when the
+    /// user has none (or no user is given), start a greeter on a free VT. Returns the VT switched to.
+    /// Only root and users with an open session are allowed to switch.
+    async fn switch_user(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+        username: &str,
+    ) -> (ServiceOperationReport, u32) {
+        if !self.switch.enabled() {
+            return (
+                ServiceOperationReport::new(
+                    ServiceOperationResult::SwitchUnavailable,
+                    "fast user switching is disabled",
+                    username,
+                ),
+                0,
+            );
+        }
+
+        let allowed = match caller_uid(&header, connection).await {
+            Some(0) => true,
+            Some(uid) => {
+                get_user_by_uid(uid).is_some_and(|caller| self.sessions.contains_key(caller.name()))
+            }
+            None => false,
+        };
+
+        if !allowed {
+            return (
+                ServiceOperationReport::new(
+                    ServiceOperationResult::PermissionDenied,
+                    "only root and users with an open session can switch user",
+                    username,
+                ),
+                0,
+            );
+        }
+
+        let registered_vt = self
+            .sessions
+            .get(&OsString::from(username))
+            .and_then(|session| session.vts.last().copied());
+
+        if let Some(vt) = registered_vt {
+            return match activate_vt(vt) {
+                Ok(_) => {
+                    println!("🔀 Switched to VT {vt} of user {username}");
+                    (ServiceOperationReport::ok(), vt)
+                }
+                Err(err) => {
+                    eprintln!("❌ Error switching to VT {vt} of user {username}: {err}");
+                    (
+                        ServiceOperationReport::new(
+                            ServiceOperationResult::SwitchError,
+                            err,
+                            username,
+                        ),
+                        0,
+                    )
+                }
+            };
+        }
+
+        let vt = match first_free_vt() {
+            Ok(Some(vt)) => vt,
+            Ok(None) => {
+                eprintln!("❌ No free VT to start a greeter on");
+                return (
+                    ServiceOperationReport::new(
+                        ServiceOperationResult::SwitchError,
+                        "no free VT available",
+                        username,
+                    ),
+                    0,
+                );
+            }
+            Err(err) => {
+                eprintln!("❌ Error looking for a free VT: {err}");
+                return (
+                    ServiceOperationReport::new(ServiceOperationResult::SwitchError, err, username),
+                    0,
+                );
+            }
+        };
+
+        // a single switch greeter at a time: repeated requests must not fill every free VT
+        if let Err(running_vt) =
+            self.switch_greeter_vt
+                .compare_exchange(0, vt, Ordering::SeqCst, Ordering::SeqCst)
+        {
+            println!("🚫 Switch refused: a greeter is still running on VT {running_vt}");
+            return (
+                ServiceOperationReport::new(
+                    ServiceOperationResult::SwitchError,
+                    format!("a greeter is already running on VT {running_vt}"),
+                    username,
+                ),
+                0,
+            );
+        }
+
+        // the greeter takes the VT as its terminal and activates it on its own
+        let argv = self.switch.greeter_argv(vt);
+        match tokio::process::Command::new(argv[0].as_str())
+            .args(&argv[1..])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(mut child) => {
+                println!("🔀 Started a greeter on VT {vt}");
+
+                // reap the greeter when it exits, allowing the next switch to start another one
+                let switch_greeter_vt = self.switch_greeter_vt.clone();
+                spawn(async move {
+                    if let Err(err) = child.wait().await {
+                        eprintln!("❌ Error waiting for the greeter on VT {vt}: {err}");
+                    }
+
+                    switch_greeter_vt.store(0, Ordering::SeqCst);
+                });
+
+                (ServiceOperationReport::ok(), vt)
+            }
+            Err(err) => {
+                self.switch_greeter_vt.store(0, Ordering::SeqCst);
+
+                eprintln!("❌ Error starting a greeter on VT {vt}: {err}");
+                (
+                    ServiceOperationReport::new(ServiceOperationResult::SwitchError, err, &argv[0]),
+                    0,
+                )
+            }
+        }
+    }
+
+    async fn issue_recovery_token(
+        &mut self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+        username: &str,
+    ) -> (ServiceOperationReport, String) {
+        println!("🔧 Requested a recovery token for user {username}");
+
+        // only root is allowed to hand out recovery tokens
+        let caller_uid = caller_uid(&header, connection).await;
+
+        if caller_uid != Some(0) {
+            println!("🚫 Recovery token for {username} refused to uid {caller_uid:?}");
+            return (
+                ServiceOperationReport::new(
+                    ServiceOperationResult::PermissionDenied,
+                    "only root can issue recovery tokens",
+                    username,
+                ),
+                String::new(),
+            );
+        }
+
+        if get_user_by_name(username).is_none() {
+            println!("❌ Cannot issue a recovery token for unknown user {username}");
+            return (
+                ServiceOperationReport::new(
+                    ServiceOperationResult::CannotIdentifyUser,
+                    "no such user",
+                    username,
+                ),
+                String::new(),
+            );
+        }
+
+        match RecoveryTokens::default().issue(username) {
+            Ok(token) => {
+                println!("✅ Issued a recovery token for user {username}");
+
+                // the token must be usable by a locked-out user: lift the lockout
+                if let Err(err) = reset_tally(&FaillockConfig::load(), username) {
+                    println!("❌ Error resetting the failed logins of user {username}: {err}");
+                }
+                (ServiceOperationReport::ok(), token)
+            }
+            Err(err) => {
+                println!("❌ Error issuing a recovery token for user {username}: {err}");
+                (
+                    ServiceOperationReport::new(
+                        ServiceOperationResult::RecoveryError,
+                        err,
+                        username,
+                    ),
+                    String::new(),
+                )
+            }
+        }
+    }
+
+    /// Arm a single-use autologin of the given user for the next boot: allowed to root only
+    async fn arm_next_boot_autologin(
+        &mut self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+        username: &str,
+    ) -> ServiceOperationReport {
+        println!("🔧 Requested the next boot autologin of user {username}");
+
+        if get_user_by_name(username).is_none() {
+            println!("❌ Cannot arm the next boot autologin of unknown user {username}");
+            return ServiceOperationReport::new(
+                ServiceOperationResult::CannotIdentifyUser,
+                "no such user",
+                username,
+            );
+        }
+
+        // a passwordless login is for the administrator to grant, not for users to give themselves
+        if caller_uid(&header, connection).await != Some(0) {
+            println!("🚫 Next boot autologin of {username} refused to a non-root caller");
+            return ServiceOperationReport::new(
+                ServiceOperationResult::PermissionDenied,
+                "only root can arm the next boot autologin",
+                username,
+            );
+        }
+
+        let armed = current_boot_id()
+            .map_err(NextBootAutologinError::from)
+            .and_then(|boot_id| NextBootAutologin::default().arm(username, &boot_id));
+
+        match armed {
+            Ok(()) => {
+                println!("✅ Armed the next boot autologin of user {username}");
+                ServiceOperationReport::ok()
+            }
+            Err(err) => {
+                println!("❌ Error arming the next boot autologin of user {username}: {err}");
+                ServiceOperationReport::new(ServiceOperationResult::AutologinError, err, username)
+            }
+        }
+    }
+
+    /// Remove the next boot autologin not consumed yet: allowed to root and to the user it is armed for
+    async fn disarm_next_boot_autologin(
+        &mut self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+    ) -> ServiceOperationReport {
+        let autologin = NextBootAutologin::default();
+
+        let username = match autologin.armed() {
+            Ok(Some((username, _))) => username,
+            Ok(None) => return ServiceOperationReport::ok(),
+            Err(err) => {
+                println!("❌ Error reading the next boot autologin: {err}");
+                return ServiceOperationReport::new(
+                    ServiceOperationResult::AutologinError,
+                    err,
+                    autologin.dir().display(),
+                );
+            }
+        };
+
+        if !caller_is_root_or_user(&header, connection, username.as_str()).await {
+            println!("🚫 Next boot autologin of {username} kept: requested by a different user");
+            return ServiceOperationReport::new(
+                ServiceOperationResult::PermissionDenied,
+                "only root and the user it is armed for can disarm the next boot autologin",
+                username,
+            );
+        }
+
+        match autologin.disarm() {
+            Ok(()) => {
+                println!("✅ Disarmed the next boot autologin of user {username}");
+                ServiceOperationReport::ok()
+            }
+            Err(err) => {
+                println!("❌ Error disarming the next boot autologin of user {username}: {err}");
+                ServiceOperationReport::new(ServiceOperationResult::AutologinError, err, username)
+            }
+        }
+    }
+
+    async fn request_approval(
+        &mut self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+        username: &str,
+    ) -> (ServiceOperationReport, String, String) {
+        println!("🔓 Requested a phone-approved login for user {username}");
+
+        // the approved secret is handed back to the caller: only greeters running as root may ask
+        if caller_uid(&header, connection).await != Some(0) {
+            println!("🚫 Phone-approved login for {username} refused to a non-root caller");
+            return (
+                ServiceOperationReport::new(
+                    ServiceOperationResult::PermissionDenied,
+                    "only root can request a phone-approved login",
+                    username,
+                ),
+                String::new(),
+                String::new(),
+            );
+        }
+
+        let Some(approval_config) = &self.approval_config else {
+            return (
+                ServiceOperationReport::new(
+                    ServiceOperationResult::ApprovalUnavailable,
+                    "the approval endpoint is disabled in approval.conf",
+                    "",
+                ),
+                String::new(),
+                String::new(),
+            );
+        };
+
+        let challenge = self.approvals.write().await.create(username);
+        let payload = approval_config.qr_payload(&challenge, username);
+
+        (ServiceOperationReport::ok(), challenge, payload)
+    }
+
+    async fn approval_status(
+        &mut self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+        challenge: &str,
+        username: &str,
+    ) -> (ServiceOperationReport, Vec<u8>) {
+        if caller_uid(&header, connection).await != Some(0) {
+            return (
+                ServiceOperationReport::new(
+                    ServiceOperationResult::PermissionDenied,
+                    "only root can wait for a phone-approved login",
+                    username,
+                ),
+                Vec::new(),
+            );
+        }
+
+        match self.approvals.write().await.status(challenge, username) {
+            ApprovalStatus::Pending => (
+                ServiceOperationReport::from(ServiceOperationResult::ApprovalPending),
+                Vec::new(),
+            ),
+            // the key of the companion device stays here: the greeter gets what it unlocked
+            ApprovalStatus::Approved(main_password) => {
+                println!("✅ Phone-approved login for user {username} released");
+                (ServiceOperationReport::ok(), main_password.to_vec())
+            }
+            ApprovalStatus::Expired => (
+                ServiceOperationReport::new(
+                    ServiceOperationResult::ApprovalExpired,
+                    "the challenge has expired or has been refused",
+                    challenge,
+                ),
+                Vec::new(),
+            ),
+        }
+    }
+
+    async fn cancel_approval(
+        &mut self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+        challenge: &str,
+    ) -> ServiceOperationReport {
+        if caller_uid(&header, connection).await != Some(0) {
+            return ServiceOperationReport::new(
+                ServiceOperationResult::PermissionDenied,
+                "only root can cancel a phone-approved login",
+                challenge,
+            );
+        }
+
+        self.approvals.write().await.cancel(challenge);
+
+        ServiceOperationReport::ok()
+    }
 }
@@ -0,0 +1,120 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use login_ng::config::{read_private_file, write_private_file, ConfigError};
+
+/// Directory (only accessible by root) holding the state the service keeps across restarts
+pub const SERVICE_STATE_DIR: &str = "/var/lib/login_ng/service";
+
+/// File, in the state directory, recording whether and how the service last stopped
+pub const SERVICE_STATE_FILE: &str = "state.json";
+
+#[derive(Debug, Error)]
+pub enum ServiceStateError {
+    #[error("Configuration error: {0}")]
+    ConfigError(#[from] ConfigError),
+
+    #[error("JSON error: {0}")]
+    JsonError(#[from] serde_json::Error),
+}
+
+/// What the service remembers of its last run: written as running at startup and replaced
+/// once every session has been closed at shutdown, so that the next start can tell whether
+/// the service has been stopped cleanly and which filesystems might need to be checked.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ServiceState {
+    /// Unix time the state has been written at
+    updated: u64,
+
+    /// False as long as the service runs
+    clean_shutdown: bool,
+
+    /// The users whose sessions have been closed at shutdown
+    closed_sessions: Vec<String>,
+
+    /// The mounts that could not be cleanly unmounted at shutdown and have been detached
+    detached_mounts: Vec<PathBuf>,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl ServiceState {
+    /// The state of a service that has just started
+    pub fn running() -> Self {
+        Self {
+            updated: unix_now(),
+            ..Default::default()
+        }
+    }
+
+    /// The state of a service that has closed every session and is about to exit
+    pub fn shut_down(closed_sessions: Vec<String>, detached_mounts: Vec<PathBuf>) -> Self {
+        Self {
+            updated: unix_now(),
+            clean_shutdown: true,
+            closed_sessions,
+            detached_mounts,
+        }
+    }
+
+    /// Load the state saved by the previous run: None if there was none
+    pub fn load(path: &Path) -> Result<Option<Self>, ServiceStateError> {
+        if !std::fs::exists(path).map_err(ConfigError::from)? {
+            return Ok(None);
+        }
+
+        Ok(Some(serde_json::from_str(&read_private_file(path)?)?))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ServiceStateError> {
+        Ok(write_private_file(
+            path,
+            serde_json::to_string(self)?.as_bytes(),
+        )?)
+    }
+
+    pub fn updated(&self) -> u64 {
+        self.updated
+    }
+
+    pub fn clean_shutdown(&self) -> bool {
+        self.clean_shutdown
+    }
+
+    pub fn closed_sessions(&self) -> &Vec<String> {
+        &self.closed_sessions
+    }
+
+    pub fn detached_mounts(&self) -> &Vec<PathBuf> {
+        &self.detached_mounts
+    }
+}
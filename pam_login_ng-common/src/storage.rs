@@ -0,0 +1,151 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use zbus::{fdo, interface, message::Header, Connection};
+
+use login_ng::{
+    backend::StorageBackend,
+    storage::{is_login_ng_attribute, StorageError},
+    users::get_user_by_name,
+};
+
+use crate::session::caller_is_root_or_user;
+
+/// Largest value accepted for a single attribute
+pub const MAX_ATTRIBUTE_SIZE: usize = 64 * 1024;
+
+/// Most attributes a user can have stored by the service
+pub const MAX_ATTRIBUTES: usize = 256;
+
+/// The storage of last resort for users that cannot write their data anywhere else:
+/// every user can only read and write its own attributes, root can access everyone's
+#[derive(Default)]
+pub struct StorageDBus;
+
+impl StorageDBus {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// The backend of the given user, if the caller is allowed to access it
+    async fn backend(
+        header: &Header<'_>,
+        connection: &Connection,
+        username: &str,
+    ) -> fdo::Result<StorageBackend> {
+        if get_user_by_name(username).is_none() {
+            return Err(fdo::Error::InvalidArgs(format!("no such user: {username}")));
+        }
+
+        if !caller_is_root_or_user(header, connection, username).await {
+            println!("🚫 Storage of {username} refused to a different user");
+            return Err(fdo::Error::AccessDenied(String::from(
+                "only root and the user itself can access the storage of a user",
+            )));
+        }
+
+        // running as root the service backend reads and writes the directory directly
+        Ok(StorageBackend::Service(String::from(username)))
+    }
+}
+
+fn storage_error(err: StorageError) -> fdo::Error {
+    match err {
+        StorageError::InvalidAttributeName(name) => {
+            fdo::Error::InvalidArgs(format!("invalid attribute name: {name}"))
+        }
+        err => fdo::Error::Failed(err.to_string()),
+    }
+}
+
+#[interface(name = "org.neroreflex.login_ng_storage1")]
+impl StorageDBus {
+    /// Read an attribute: the boolean tells whether it is set at all
+    async fn get_attribute(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+        username: &str,
+        name: &str,
+    ) -> fdo::Result<(bool, Vec<u8>)> {
+        let backend = Self::backend(&header, connection, username).await?;
+
+        match backend.get(name).map_err(storage_error)? {
+            Some(value) => Ok((true, value)),
+            None => Ok((false, vec![])),
+        }
+    }
+
+    /// Write an attribute: only those login_ng stores are accepted, and the storage
+    /// of every user is capped in the size of values and in the number of attributes
+    async fn set_attribute(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+        username: &str,
+        name: &str,
+        value: Vec<u8>,
+    ) -> fdo::Result<()> {
+        let backend = Self::backend(&header, connection, username).await?;
+
+        if !is_login_ng_attribute(name) {
+            return Err(fdo::Error::InvalidArgs(format!(
+                "not a login_ng attribute: {name}"
+            )));
+        }
+
+        if value.len() > MAX_ATTRIBUTE_SIZE {
+            return Err(fdo::Error::InvalidArgs(format!(
+                "attribute {name} exceeds {MAX_ATTRIBUTE_SIZE} bytes"
+            )));
+        }
+
+        let attributes = backend.list().map_err(storage_error)?;
+        if attributes.len() >= MAX_ATTRIBUTES && !attributes.iter().any(|attr| attr == name) {
+            return Err(fdo::Error::InvalidArgs(format!(
+                "{username} already has {MAX_ATTRIBUTES} attributes"
+            )));
+        }
+
+        backend.set(name, value.as_slice()).map_err(storage_error)
+    }
+
+    async fn remove_attribute(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+        username: &str,
+        name: &str,
+    ) -> fdo::Result<()> {
+        let backend = Self::backend(&header, connection, username).await?;
+
+        backend.remove(name).map_err(storage_error)
+    }
+
+    async fn list_attributes(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+        username: &str,
+    ) -> fdo::Result<Vec<String>> {
+        let backend = Self::backend(&header, connection, username).await?;
+
+        backend.list().map_err(storage_error)
+    }
+}
@@ -0,0 +1,95 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::path::Path;
+
+use configparser::ini::Ini;
+use login_ng::config::read_config_file;
+
+use crate::hooks::SERVICE_CONFIG_FILE;
+
+/// Greeter started on a free VT when switching to a user without an open session
+pub const DEFAULT_SWITCH_GREETER: &str = "/usr/bin/login_ng-cli";
+
+/// Fast user switching, read from the [Switch] section of service.conf
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwitchConfig {
+    enabled: bool,
+    greeter: Vec<String>,
+}
+
+impl Default for SwitchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            greeter: vec![String::from(DEFAULT_SWITCH_GREETER)],
+        }
+    }
+}
+
+impl SwitchConfig {
+    /// Parse the given service.conf content: the greeter is a command line
+    /// starting with an absolute path, the VT to run on is appended as --vt N
+    pub fn parse(content: &str) -> Self {
+        let mut config = Ini::new();
+
+        if config.read(String::from(content)).is_err() {
+            return Self::default();
+        }
+
+        let enabled = config
+            .getbool("switch", "enabled")
+            .ok()
+            .flatten()
+            .unwrap_or(false);
+
+        let greeter = config
+            .get("switch", "greeter")
+            .map(|greeter| {
+                greeter
+                    .split_whitespace()
+                    .map(String::from)
+                    .collect::<Vec<_>>()
+            })
+            .filter(|argv| argv.first().is_some_and(|cmd| cmd.starts_with('/')))
+            .unwrap_or(vec![String::from(DEFAULT_SWITCH_GREETER)]);
+
+        Self { enabled, greeter }
+    }
+
+    /// Load the configuration from service.conf in the given directory
+    pub fn load(config_dir: &Path) -> Self {
+        match read_config_file(&config_dir.join(SERVICE_CONFIG_FILE)) {
+            Ok(content) => Self::parse(content.as_str()),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Command line of the greeter to be started on the given VT
+    pub fn greeter_argv(&self, vt: u32) -> Vec<String> {
+        let mut argv = self.greeter.clone();
+        argv.push(String::from("--vt"));
+        argv.push(format!("{vt}"));
+        argv
+    }
+}
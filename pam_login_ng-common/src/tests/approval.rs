@@ -0,0 +1,180 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{os::unix::fs::PermissionsExt, path::Path};
+
+use login_ng::secure::LockedBytes;
+
+use crate::approval::*;
+
+#[test]
+fn approval_form_parsing() {
+    let (challenge, key) = parse_approval_form("challenge=abc123&key=my%20key+2%21\r\n").unwrap();
+
+    assert_eq!(challenge, "abc123");
+    assert_eq!(key, "my key 2!");
+
+    assert!(matches!(
+        parse_approval_form("challenge=abc123"),
+        Err(ApprovalError::MalformedRequest)
+    ));
+
+    assert!(matches!(
+        parse_approval_form("challenge=abc&key=%zz"),
+        Err(ApprovalError::MalformedRequest)
+    ));
+}
+
+#[test]
+fn approval_challenge_lifecycle() {
+    let mut approvals = PendingApprovals::new();
+
+    let challenge = approvals.create("approval_user");
+    assert_eq!(challenge.len(), 64);
+
+    assert!(matches!(
+        approvals.status(&challenge, "approval_user"),
+        ApprovalStatus::Pending
+    ));
+
+    // a challenge is bound to the user it was created for
+    assert!(matches!(
+        approvals.status(&challenge, "other_user"),
+        ApprovalStatus::Expired
+    ));
+
+    assert!(matches!(
+        approvals.begin_approval("unknown"),
+        Err(ApprovalError::UnknownChallenge)
+    ));
+
+    assert_eq!(
+        approvals.begin_approval(&challenge).unwrap(),
+        "approval_user"
+    );
+
+    // no other attempt is accepted while a key is being checked
+    assert!(matches!(
+        approvals.begin_approval(&challenge),
+        Err(ApprovalError::AlreadyApproved)
+    ));
+    assert!(matches!(
+        approvals.status(&challenge, "approval_user"),
+        ApprovalStatus::Pending
+    ));
+
+    // an approved challenge hands out the unlocked main password once
+    let approved = approvals.create("approval_user");
+    approvals.begin_approval(&approved).unwrap();
+    approvals
+        .complete_approval(&approved, Some(LockedBytes::from_slice(b"main")))
+        .unwrap();
+    assert!(matches!(
+        approvals.begin_approval(&approved),
+        Err(ApprovalError::AlreadyApproved)
+    ));
+    match approvals.status(&approved, "approval_user") {
+        ApprovalStatus::Approved(main_password) => assert_eq!(&*main_password, b"main"),
+        _ => panic!("the challenge has been approved"),
+    }
+    assert!(matches!(
+        approvals.status(&approved, "approval_user"),
+        ApprovalStatus::Expired
+    ));
+
+    // a wrong key invalidates the challenge
+    assert!(matches!(
+        approvals.complete_approval(&challenge, None),
+        Err(ApprovalError::InvalidKey)
+    ));
+
+    assert!(matches!(
+        approvals.status(&challenge, "approval_user"),
+        ApprovalStatus::Expired
+    ));
+}
+
+#[test]
+fn approval_config() {
+    let dir_name = "test_approval";
+    std::fs::create_dir_all(dir_name).unwrap();
+
+    assert_eq!(ApprovalConfig::load(Path::new(dir_name)), None);
+
+    // the endpoint stays disabled without a long enough pre-shared token
+    std::fs::write(
+        Path::new(dir_name).join("approval.conf"),
+        "[Approval]\nenabled = true\ntoken = short\n",
+    )
+    .unwrap();
+
+    assert_eq!(ApprovalConfig::load(Path::new(dir_name)), None);
+
+    std::fs::write(
+        Path::new(dir_name).join("approval.conf"),
+        "[Approval]\nenabled = true\nlisten = 0.0.0.0:9000\nurl = http://192.168.1.2:9000/approve\ntoken = 0123456789abcdef\n",
+    )
+    .unwrap();
+
+    // the token must not be readable by anyone else
+    std::fs::set_permissions(dir_name, std::fs::Permissions::from_mode(0o755)).unwrap();
+    std::fs::set_permissions(
+        Path::new(dir_name).join("approval.conf"),
+        std::fs::Permissions::from_mode(0o644),
+    )
+    .unwrap();
+    assert_eq!(ApprovalConfig::load(Path::new(dir_name)), None);
+
+    std::fs::set_permissions(
+        Path::new(dir_name).join("approval.conf"),
+        std::fs::Permissions::from_mode(0o600),
+    )
+    .unwrap();
+
+    let config = ApprovalConfig::load(Path::new(dir_name)).unwrap();
+    assert_eq!(config.listen().port(), 9000);
+    assert_eq!(config.token(), "0123456789abcdef");
+    assert_eq!(
+        config.qr_payload("abc", "user"),
+        "http://192.168.1.2:9000/approve?challenge=abc&user=user"
+    );
+    assert_eq!(
+        config.qr_payload("abc", "first.last&admin=1 x"),
+        "http://192.168.1.2:9000/approve?challenge=abc&user=first.last%26admin%3D1%20x"
+    );
+
+    std::fs::remove_dir_all(dir_name).unwrap();
+}
+
+#[test]
+fn approval_request_authorization() {
+    let head =
+        "POST /approve HTTP/1.1\r\nHost: localhost\r\nauthorization: Bearer 0123456789abcdef\r\n";
+
+    assert!(request_authorized(head, "0123456789abcdef"));
+    assert!(!request_authorized(head, "0123456789abcdeg"));
+    assert!(!request_authorized(
+        "POST /approve HTTP/1.1\r\nAuthorization: Basic 0123456789abcdef\r\n",
+        "0123456789abcdef"
+    ));
+    assert!(!request_authorized(
+        "POST /approve HTTP/1.1\r\nHost: localhost\r\n",
+        "0123456789abcdef"
+    ));
+}
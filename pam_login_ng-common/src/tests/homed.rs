@@ -0,0 +1,44 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use crate::homed::{homed_secret, HomedError};
+
+#[test]
+fn homed_secret_plain_password() {
+    let secret = homed_secret(b"my_password").unwrap();
+
+    let parsed: serde_json::Value = serde_json::from_str(secret.as_str()).unwrap();
+    assert_eq!(parsed, serde_json::json!({ "password": ["my_password"] }));
+}
+
+#[test]
+fn homed_secret_escapes_password() {
+    let secret = homed_secret(b"a\"b\\c").unwrap();
+
+    let parsed: serde_json::Value = serde_json::from_str(secret.as_str()).unwrap();
+    assert_eq!(parsed["password"][0], "a\"b\\c");
+}
+
+#[test]
+fn homed_secret_invalid_utf8() {
+    assert!(matches!(
+        homed_secret(&[0xff, 0xfe]),
+        Err(HomedError::InvalidSecret)
+    ));
+}
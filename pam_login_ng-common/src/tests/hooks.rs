@@ -0,0 +1,120 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::hooks::*;
+
+#[test]
+fn hooks_url_parsing() {
+    assert_eq!(
+        parse_http_url("http://127.0.0.1:8080/login").unwrap(),
+        (String::from("127.0.0.1"), 8080, String::from("/login"))
+    );
+
+    assert_eq!(
+        parse_http_url("http://example.com").unwrap(),
+        (String::from("example.com"), 80, String::from("/"))
+    );
+
+    assert!(matches!(
+        parse_http_url("https://example.com/login"),
+        Err(HookError::UnsupportedUrl(_))
+    ));
+
+    assert!(matches!(
+        parse_http_url("http://example.com:port/"),
+        Err(HookError::UnsupportedUrl(_))
+    ));
+}
+
+#[test]
+fn hooks_event_serialization() {
+    assert_eq!(HookEvent::SessionClose.dir_name(), "session-close.d");
+
+    let event = LoginEvent::new(HookEvent::Failure, "hooks_user", false, "wrong password");
+
+    let json = serde_json::to_string(&event).unwrap();
+    assert!(json.contains("\"event\":\"failure\""));
+
+    let deserialized: LoginEvent = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized, event);
+    assert_eq!(deserialized.username(), "hooks_user");
+    assert!(!deserialized.success());
+}
+
+#[test]
+fn hooks_scripts_discovery() {
+    let dir_name = "test_hooks_scripts";
+    let success_dir = Path::new(dir_name).join(HookEvent::Success.dir_name());
+    std::fs::create_dir_all(&success_dir).unwrap();
+
+    for (name, mode) in [
+        ("20-second", 0o755),
+        ("10-first", 0o700),
+        ("30-disabled", 0o644),
+        ("40-writable", 0o777),
+    ] {
+        let script = success_dir.join(name);
+        std::fs::write(&script, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    std::os::unix::fs::symlink("/bin/true", success_dir.join("50-symlink")).unwrap();
+
+    let hooks = Hooks::new(PathBuf::from(dir_name), None);
+
+    assert_eq!(
+        hooks.scripts(HookEvent::Success),
+        vec![success_dir.join("10-first"), success_dir.join("20-second")]
+    );
+    assert!(hooks.scripts(HookEvent::Failure).is_empty());
+
+    std::fs::remove_dir_all(dir_name).unwrap();
+}
+
+#[test]
+fn hooks_config() {
+    let dir_name = "test_hooks_config";
+    std::fs::create_dir_all(dir_name).unwrap();
+
+    assert_eq!(Hooks::load(Path::new(dir_name)).webhook(), &None);
+
+    std::fs::write(
+        Path::new(dir_name).join(SERVICE_CONFIG_FILE),
+        "[Hooks]\nwebhook = http://192.168.1.2:9000/events\n",
+    )
+    .unwrap();
+
+    // configuration files anyone but root can modify are refused, whatever the umask
+    for (path, mode) in [
+        (Path::new(dir_name).to_path_buf(), 0o755),
+        (Path::new(dir_name).join(SERVICE_CONFIG_FILE), 0o644),
+    ] {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    assert_eq!(
+        Hooks::load(Path::new(dir_name)).webhook(),
+        &Some(String::from("http://192.168.1.2:9000/events"))
+    );
+
+    std::fs::remove_dir_all(dir_name).unwrap();
+}
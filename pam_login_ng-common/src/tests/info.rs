@@ -0,0 +1,34 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use crate::info::{ServiceInfo, PROTOCOL_VERSION};
+
+#[test]
+fn service_info_compatibility() {
+    let info = ServiceInfo::current();
+    assert_eq!(info.protocol_version(), PROTOCOL_VERSION);
+    assert_eq!(info.library_version(), login_ng::LIBRARY_VERSION);
+    assert_eq!(info.has_feature("logind"), cfg!(feature = "logind"));
+    assert_eq!(info.has_feature("audit"), cfg!(feature = "audit"));
+    assert!(info.is_compatible());
+
+    // services predating the handshake do not report any version
+    assert!(!ServiceInfo::default().is_compatible());
+    assert!(!ServiceInfo::new(PROTOCOL_VERSION + 1, String::from("0.0.0"), vec![]).is_compatible());
+}
@@ -0,0 +1,159 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::{os::unix::fs::PermissionsExt, path::PathBuf};
+
+use crate::limits::*;
+
+#[test]
+fn limits_parsing() {
+    let limits = SessionLimits::parse("[Sessions]\nmax_per_user = 2\n");
+    assert_eq!(limits.max_per_user(), Some(2));
+    assert!(limits.allows(0));
+    assert!(limits.allows(1));
+    assert!(!limits.allows(2));
+
+    // zero, invalid or missing values do not limit anything
+    for content in [
+        "[Sessions]\nmax_per_user = 0\n",
+        "[Sessions]\nmax_per_user = many\n",
+        "[Hooks]\nwebhook = http://127.0.0.1/\n",
+        "",
+    ] {
+        let limits = SessionLimits::parse(content);
+        assert_eq!(limits.max_per_user(), None);
+        assert!(limits.allows(usize::MAX - 1));
+    }
+}
+
+#[test]
+fn token_ttl_parsing() {
+    let limits = SessionLimits::parse("[Sessions]\ntoken_ttl = 15\n");
+    assert_eq!(limits.token_ttl(), std::time::Duration::from_secs(15));
+    assert_eq!(limits.max_per_user(), None);
+
+    // zero, invalid or missing values keep the default
+    for content in [
+        "[Sessions]\ntoken_ttl = 0\n",
+        "[Sessions]\ntoken_ttl = soon\n",
+        "[Sessions]\nmax_per_user = 2\n",
+        "",
+    ] {
+        assert_eq!(SessionLimits::parse(content).token_ttl(), DEFAULT_TOKEN_TTL);
+    }
+}
+
+#[test]
+fn unmount_timeout_parsing() {
+    let limits = SessionLimits::parse("[Sessions]\nunmount_timeout = 30\n");
+    assert_eq!(limits.unmount_timeout(), std::time::Duration::from_secs(30));
+    assert_eq!(limits.token_ttl(), DEFAULT_TOKEN_TTL);
+
+    // zero, invalid or missing values keep the default
+    for content in [
+        "[Sessions]\nunmount_timeout = 0\n",
+        "[Sessions]\nunmount_timeout = never\n",
+        "[Sessions]\ntoken_ttl = 15\n",
+        "",
+    ] {
+        assert_eq!(
+            SessionLimits::parse(content).unmount_timeout(),
+            DEFAULT_UNMOUNT_TIMEOUT
+        );
+    }
+}
+
+#[test]
+fn pam_uid_parsing() {
+    let limits = SessionLimits::parse("[Sessions]\npam_uid = 975\n");
+    assert_eq!(limits.pam_uid(), Some(975));
+
+    for content in [
+        "[Sessions]\npam_uid = greeter\n",
+        "[Sessions]\npam_uid = 4294967296\n",
+        "[Sessions]\nmax_per_user = 2\n",
+        "",
+    ] {
+        assert_eq!(SessionLimits::parse(content).pam_uid(), None);
+    }
+}
+
+#[test]
+fn session_callers() {
+    let limits = SessionLimits::default();
+
+    // root can act for anyone, other users only for themselves
+    assert!(limits.may_act_for(0, Some(1000)));
+    assert!(limits.may_act_for(0, None));
+    assert!(limits.may_act_for(1000, Some(1000)));
+    assert!(!limits.may_act_for(1001, Some(1000)));
+    assert!(!limits.may_act_for(1000, None));
+
+    // the PAM module can act for anyone
+    let limits = limits.with_pam_uid(Some(975));
+    assert!(limits.may_act_for(975, Some(1000)));
+    assert!(limits.may_act_for(975, None));
+    assert!(!limits.may_act_for(1001, Some(1000)));
+}
+
+#[test]
+fn limits_loading() {
+    let dir = PathBuf::from("./limits_loading_test");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(SessionLimits::load(&dir), SessionLimits::default());
+
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join(crate::hooks::SERVICE_CONFIG_FILE),
+        "[Hooks]\nwebhook = http://127.0.0.1:8080/\n\n[Sessions]\nmax_per_user = 1\n",
+    )
+    .unwrap();
+
+    // configuration files anyone but root can modify are refused, whatever the umask
+    for (path, mode) in [
+        (dir.clone(), 0o755),
+        (dir.join(crate::hooks::SERVICE_CONFIG_FILE), 0o644),
+    ] {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    assert_eq!(SessionLimits::load(&dir), SessionLimits::new(Some(1)));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn network_timeout_parsing() {
+    let limits = SessionLimits::parse("[Sessions]\nnetwork_timeout = 5\n");
+    assert_eq!(limits.network_timeout(), std::time::Duration::from_secs(5));
+    assert_eq!(limits.unmount_timeout(), DEFAULT_UNMOUNT_TIMEOUT);
+
+    // zero, invalid or missing values keep the default
+    for content in [
+        "[Sessions]\nnetwork_timeout = 0\n",
+        "[Sessions]\nnetwork_timeout = never\n",
+        "",
+    ] {
+        assert_eq!(
+            SessionLimits::parse(content).network_timeout(),
+            login_ng::network::DEFAULT_NETWORK_ONLINE_TIMEOUT
+        );
+    }
+}
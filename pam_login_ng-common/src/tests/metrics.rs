@@ -0,0 +1,79 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::path::PathBuf;
+
+use crate::{metrics::*, result::ServiceOperationResult};
+
+#[test]
+fn metrics_counters() {
+    let metrics = Metrics::new();
+
+    metrics.record_authentication(true);
+    metrics.record_authentication(false);
+    metrics.record_authentication(false);
+
+    metrics.record_session_opened();
+    metrics.record_session_opened();
+    metrics.record_session_closed();
+
+    metrics.record_session_failure(ServiceOperationResult::MountError);
+    metrics.record_session_failure(ServiceOperationResult::DataDecryptionFailed);
+    metrics.record_session_failure(ServiceOperationResult::CannotIdentifyUser);
+
+    let snapshot = metrics.snapshot();
+    assert_eq!(snapshot.auth_attempts(), 3);
+    assert_eq!(snapshot.auth_failures(), 2);
+    assert_eq!(snapshot.open_sessions(), 1);
+    assert_eq!(snapshot.mount_failures(), 1);
+    assert_eq!(snapshot.handshake_errors(), 1);
+
+    // unmatched closes must not wrap the gauge around
+    metrics.record_session_closed();
+    metrics.record_session_closed();
+    assert_eq!(metrics.snapshot().open_sessions(), 0);
+}
+
+#[test]
+fn metrics_prometheus_format() {
+    let metrics = Metrics::new();
+    metrics.record_authentication(false);
+
+    let text = metrics.snapshot().to_prometheus();
+
+    assert!(text.contains("# TYPE login_ng_auth_failures_total counter\n"));
+    assert!(text.contains("\nlogin_ng_auth_failures_total 1\n"));
+    assert!(text.contains("# TYPE login_ng_open_sessions gauge\n"));
+    assert!(text.contains("\nlogin_ng_open_sessions 0\n"));
+    assert!(text.ends_with('\n'));
+}
+
+#[test]
+fn metrics_config_parsing() {
+    assert_eq!(MetricsConfig::parse(""), MetricsConfig::default());
+
+    let config = MetricsConfig::parse(
+        "[Metrics]\ntextfile = /var/lib/node_exporter/login_ng.prom\nsocket =\n",
+    );
+    assert_eq!(
+        config.textfile(),
+        &Some(PathBuf::from("/var/lib/node_exporter/login_ng.prom"))
+    );
+    assert_eq!(config.socket(), &None);
+}
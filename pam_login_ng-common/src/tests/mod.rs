@@ -17,5 +17,15 @@
     51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 */
 
+pub mod approval;
+pub mod homed;
+pub mod hooks;
+pub mod info;
+pub mod limits;
+pub mod metrics;
 pub mod mount;
+pub mod result;
+pub mod sealed;
 pub mod security;
+pub mod state;
+pub mod switch;
@@ -17,7 +17,10 @@
     51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 */
 
-use crate::mount::{MountAuthDBus, MountAuthOperations};
+use crate::mount::{resolve_source, DeviceIdentity, MountAuth, MountAuthDBus, MountAuthOperations};
+use crate::result::ServiceOperationReport;
+use std::collections::HashMap;
+use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -64,7 +67,7 @@ async fn test_authorize() {
         mounts_auth
             .authorize("username", format!("{:X}", NUM))
             .await,
-        0u32
+        ServiceOperationReport::ok()
     );
     assert!(mounts_auth.check("username", format!("{:X}", NUM)).await);
 
@@ -91,13 +94,13 @@ async fn test_authorize_different_users() {
     assert!(!(mounts_auth.check("test", format!("{:X}", NUM2)).await));
     assert_eq!(
         mounts_auth.authorize("test", format!("{:X}", NUM2)).await,
-        0u32
+        ServiceOperationReport::ok()
     );
     assert_eq!(
         mounts_auth
             .authorize("username", format!("{:X}", NUM1))
             .await,
-        0u32
+        ServiceOperationReport::ok()
     );
     assert!(mounts_auth.check("username", format!("{:X}", NUM1)).await);
     assert!(mounts_auth.check("test", format!("{:X}", NUM2)).await);
@@ -128,6 +131,9 @@ async fn test_authorization_file() {
 
     std::fs::write(filepath.clone(), content).unwrap();
 
+    // the authorizations are refused if anyone but root can access them
+    std::fs::set_permissions(filepath.clone(), std::fs::Permissions::from_mode(0o600)).unwrap();
+
     let mounts_auth_op = Arc::new(RwLock::new(MountAuthOperations::new(filepath.clone())));
 
     let mounts_auth = MountAuthDBus::new(mounts_auth_op.clone());
@@ -147,3 +153,138 @@ async fn test_authorization_file() {
 
     std::fs::remove_file(filepath.clone()).unwrap();
 }
+
+#[test]
+fn device_identity_lookup() {
+    let dir = Path::new("./device_identity_lookup_test");
+    let _ = std::fs::remove_dir_all(dir);
+
+    std::fs::create_dir_all(dir.join("by-uuid")).unwrap();
+    std::fs::create_dir_all(dir.join("by-partlabel")).unwrap();
+    std::fs::write(dir.join("sdb1"), b"").unwrap();
+    std::fs::write(dir.join("sdc1"), b"").unwrap();
+
+    let sdb1 = std::fs::canonicalize(dir.join("sdb1")).unwrap();
+    let sdc1 = std::fs::canonicalize(dir.join("sdc1")).unwrap();
+
+    std::os::unix::fs::symlink(&sdb1, dir.join("by-uuid").join("0a1b-2c3d")).unwrap();
+    std::os::unix::fs::symlink(&sdb1, dir.join("by-partlabel").join("home")).unwrap();
+    std::os::unix::fs::symlink(&sdc1, dir.join("by-uuid").join("4e5f-6a7b")).unwrap();
+
+    assert_eq!(
+        DeviceIdentity::lookup(dir, &sdb1),
+        DeviceIdentity::new(Some("0a1b-2c3d".to_string()), Some("home".to_string()))
+    );
+    assert_eq!(
+        DeviceIdentity::lookup(dir, &sdc1),
+        DeviceIdentity::new(Some("4e5f-6a7b".to_string()), None)
+    );
+    assert!(DeviceIdentity::lookup(dir, &dir.join("missing")).is_empty());
+
+    // regular files are not block devices
+    assert_eq!(DeviceIdentity::of_device(dir, sdb1.to_str().unwrap()), None);
+    assert_eq!(DeviceIdentity::of_device(dir, "UUID=0a1b-2c3d"), None);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn device_identity_matching() {
+    let expected = DeviceIdentity::new(Some("0a1b-2c3d".to_string()), None);
+
+    assert!(expected.matches(&DeviceIdentity::new(
+        Some("0a1b-2c3d".to_string()),
+        Some("home".to_string())
+    )));
+    assert!(!expected.matches(&DeviceIdentity::new(Some("4e5f-6a7b".to_string()), None)));
+    assert!(!expected.matches(&DeviceIdentity::new(None, Some("home".to_string()))));
+
+    // an empty identity would match any device
+    assert!(!DeviceIdentity::default().matches(&DeviceIdentity::default()));
+}
+
+#[test]
+fn device_scoped_authorizations() {
+    let identify = |device: &str| match device {
+        "/dev/sdb1" => Some(DeviceIdentity::new(Some("0a1b-2c3d".to_string()), None)),
+        "/dev/sdc1" => Some(DeviceIdentity::new(Some("4e5f-6a7b".to_string()), None)),
+        _ => None,
+    };
+
+    let mut auth = MountAuth::default();
+    auth.add_scoped_authorization(
+        "username",
+        String::from("4E421"),
+        HashMap::from([(
+            "/dev/sdb1".to_string(),
+            DeviceIdentity::new(Some("0a1b-2c3d".to_string()), None),
+        )]),
+    );
+    auth.add_authorization("other", String::from("4E421"));
+
+    let devices = vec!["/dev/sdb1".to_string(), "tmpfs".to_string()];
+    assert!(auth.authorized("username", String::from("4E421")));
+    assert!(auth.authorized_on("username", String::from("4E421"), &devices, identify));
+
+    // a different disk behind the same configuration is refused
+    let swapped = |device: &str| identify(device).map(|_| DeviceIdentity::default());
+    assert!(!auth.authorized_on("username", String::from("4E421"), &devices, swapped));
+
+    // as are block devices that were not there at authorization time
+    let devices = vec!["/dev/sdb1".to_string(), "/dev/sdc1".to_string()];
+    assert!(!auth.authorized_on("username", String::from("4E421"), &devices, identify));
+
+    // authorizations without devices are valid on any device
+    assert!(auth.authorized_on("other", String::from("4E421"), &devices, identify));
+    assert!(!auth.authorized_on("username", String::from("4E422"), &devices, identify));
+}
+
+#[test]
+fn legacy_authorizations() {
+    let auth = MountAuth::new(r#"{ "authorizations": { "username": ["4E421"] } }"#).unwrap();
+
+    assert!(auth.authorized("username", String::from("4E421")));
+    assert!(auth.authorized_on(
+        "username",
+        String::from("4E421"),
+        &["/dev/sdb1".to_string()],
+        |_| Some(DeviceIdentity::default())
+    ));
+}
+
+#[test]
+fn mount_source_resolution() {
+    let dir = Path::new("./mount_source_resolution_test");
+    let _ = std::fs::remove_dir_all(dir);
+
+    for links in ["by-uuid", "by-label", "by-partuuid", "by-partlabel"] {
+        std::fs::create_dir_all(dir.join(links)).unwrap();
+    }
+    std::fs::write(dir.join("sdb1"), b"").unwrap();
+
+    let sdb1 = std::fs::canonicalize(dir.join("sdb1")).unwrap();
+    let sdb1_str = sdb1.to_string_lossy().to_string();
+
+    std::os::unix::fs::symlink(&sdb1, dir.join("by-uuid").join("0a1b-2c3d")).unwrap();
+    std::os::unix::fs::symlink(&sdb1, dir.join("by-label").join("My\\x20Data")).unwrap();
+    std::os::unix::fs::symlink(&sdb1, dir.join("by-partuuid").join("c0ffee-01")).unwrap();
+    std::os::unix::fs::symlink(&sdb1, dir.join("by-partlabel").join("home")).unwrap();
+
+    assert_eq!(resolve_source(dir, "UUID=0a1b-2c3d").unwrap(), sdb1_str);
+    assert_eq!(resolve_source(dir, "LABEL=My Data").unwrap(), sdb1_str);
+    assert_eq!(resolve_source(dir, "LABEL=\"My Data\"").unwrap(), sdb1_str);
+    assert_eq!(resolve_source(dir, "PARTUUID=c0ffee-01").unwrap(), sdb1_str);
+    assert_eq!(resolve_source(dir, "PARTLABEL=home").unwrap(), sdb1_str);
+
+    assert_eq!(
+        resolve_source(dir, "UUID=4e5f-6a7b").unwrap_err().kind(),
+        std::io::ErrorKind::NotFound
+    );
+
+    // other sources are mounted as they are
+    for source in ["/dev/sda2", "tmpfs", "server:/export/home", "TAG=value"] {
+        assert_eq!(resolve_source(dir, source).unwrap(), source);
+    }
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,47 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use crate::result::{ServiceOperationReport, ServiceOperationResult};
+
+#[test]
+fn report_carries_details() {
+    let report = ServiceOperationReport::new(
+        ServiceOperationResult::IOError,
+        "cannot read the mount authorizations",
+        "/etc/login_ng/authorized_mounts.json",
+    );
+
+    assert!(!report.is_ok());
+    assert_eq!(report.result(), ServiceOperationResult::IOError);
+    assert_eq!(report.code(), 12);
+    assert_eq!(report.item(), "/etc/login_ng/authorized_mounts.json");
+    assert_eq!(
+        report.to_string(),
+        "I/O Error: cannot read the mount authorizations (/etc/login_ng/authorized_mounts.json)"
+    );
+}
+
+#[test]
+fn report_from_result() {
+    assert!(ServiceOperationReport::ok().is_ok());
+
+    let report = ServiceOperationReport::from(ServiceOperationResult::MountError);
+    assert_eq!(report.message(), "");
+    assert_eq!(report.to_string(), "Mount Error");
+}
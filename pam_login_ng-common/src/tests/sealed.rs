@@ -0,0 +1,106 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::Arc;
+
+use rsa::{pkcs1::DecodeRsaPrivateKey, RsaPrivateKey};
+use tokio::sync::RwLock;
+
+use crate::mount::{MountAuthDBus, MountAuthOperations};
+use crate::result::ServiceOperationReport;
+use crate::sealed::*;
+use crate::tests::security::RSA_PRIVATE_KEY;
+
+#[test]
+fn seal_config_parsing() {
+    assert!(SealConfig::parse("[Storage]\nencrypt = true\n").encrypt());
+    assert!(!SealConfig::parse("[Storage]\nencrypt = false\n").encrypt());
+    assert!(!SealConfig::parse("[Sessions]\nmax_per_user = 1\n").encrypt());
+    assert!(!SealConfig::parse("").encrypt());
+}
+
+#[test]
+fn seal_roundtrip() {
+    let key = RsaPrivateKey::from_pkcs1_pem(RSA_PRIVATE_KEY).unwrap();
+
+    let plaintext = b"{\"authorizations\":{\"username\":[\"3ED66D06576D7F05\"]}}";
+    let sealed = seal(&key, plaintext).unwrap();
+    assert!(!sealed.contains("username"));
+    assert_eq!(unseal(&key, sealed.as_str()).unwrap().unwrap(), plaintext);
+
+    // plain files are told apart from sealed ones
+    assert_eq!(
+        unseal(&key, std::str::from_utf8(plaintext).unwrap()).unwrap(),
+        None
+    );
+
+    // tampering is detected
+    let tampered = sealed.replacen("\"data\":[", "\"data\":[1,", 1);
+    assert!(unseal(&key, tampered.as_str()).is_err());
+}
+
+#[tokio::test]
+async fn sealed_authorizations() {
+    const AUTHORIZATION_TESTFILE: &str = "test_sealed_authorizations.json";
+    let filepath = Path::new("./").join(AUTHORIZATION_TESTFILE);
+    let _ = std::fs::remove_file(filepath.clone());
+
+    let key = Arc::new(RsaPrivateKey::from_pkcs1_pem(RSA_PRIVATE_KEY).unwrap());
+
+    // authorizations stored before encryption has been enabled are still valid
+    std::fs::write(
+        filepath.clone(),
+        "{\"authorizations\":{\"username\":[\"3ED66D06576D7F05\"]}}",
+    )
+    .unwrap();
+    std::fs::set_permissions(filepath.clone(), std::fs::Permissions::from_mode(0o600)).unwrap();
+
+    let mounts_auth_op = Arc::new(RwLock::new(
+        MountAuthOperations::new(filepath.clone()).with_service_key(key.clone(), true),
+    ));
+    let mut mounts_auth = MountAuthDBus::new(mounts_auth_op.clone());
+
+    assert!(
+        mounts_auth
+            .check("username", String::from("3ED66D06576D7F05"))
+            .await
+    );
+    assert!(!std::fs::read_to_string(filepath.clone())
+        .unwrap()
+        .contains("username"));
+
+    assert_eq!(
+        mounts_auth.authorize("test", String::from("4E42142")).await,
+        ServiceOperationReport::ok()
+    );
+    assert!(!std::fs::read_to_string(filepath.clone())
+        .unwrap()
+        .contains("test"));
+
+    // once encryption gets disabled the service key still reads them
+    let mounts_auth_op = Arc::new(RwLock::new(
+        MountAuthOperations::new(filepath.clone()).with_service_key(key, false),
+    ));
+    let mounts_auth = MountAuthDBus::new(mounts_auth_op.clone());
+    assert!(mounts_auth.check("test", String::from("4E42142")).await);
+
+    std::fs::remove_file(filepath.clone()).unwrap();
+}
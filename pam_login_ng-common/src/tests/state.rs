@@ -0,0 +1,55 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::state::*;
+
+#[test]
+fn service_state_roundtrip() {
+    let dir = Path::new("./test_service_state");
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700)).unwrap();
+
+    let path = dir.join(SERVICE_STATE_FILE);
+
+    // nothing is known about a service that never ran
+    assert_eq!(ServiceState::load(path.as_path()).unwrap(), None);
+
+    // a service that is still running did not stop cleanly
+    let running = ServiceState::running();
+    running.save(path.as_path()).unwrap();
+    let loaded = ServiceState::load(path.as_path()).unwrap().unwrap();
+    assert!(!loaded.clean_shutdown());
+    assert_eq!(loaded, running);
+
+    let stopped = ServiceState::shut_down(
+        vec![String::from("user")],
+        vec![PathBuf::from("/home/user")],
+    );
+    stopped.save(path.as_path()).unwrap();
+    let loaded = ServiceState::load(path.as_path()).unwrap().unwrap();
+    assert!(loaded.clean_shutdown());
+    assert_eq!(loaded.closed_sessions(), &vec![String::from("user")]);
+    assert_eq!(loaded.detached_mounts(), &vec![PathBuf::from("/home/user")]);
+
+    let _ = std::fs::remove_dir_all(dir);
+}
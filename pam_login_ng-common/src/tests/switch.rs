@@ -0,0 +1,46 @@
+/*
+    login-ng A greeter written in rust that also supports autologin with systemd-homed
+    Copyright (C) 2024-2025  Denis Benato
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License along
+    with this program; if not, write to the Free Software Foundation, Inc.,
+    51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+*/
+
+use crate::switch::*;
+
+#[test]
+fn switch_config_parsing() {
+    let config = SwitchConfig::parse("");
+    assert!(!config.enabled());
+    assert_eq!(
+        config.greeter_argv(3),
+        vec![DEFAULT_SWITCH_GREETER, "--vt", "3"]
+    );
+
+    let config = SwitchConfig::parse(
+        "[Switch]\nenabled = true\ngreeter = /usr/bin/login_ng-cli --banner false\n",
+    );
+    assert!(config.enabled());
+    assert_eq!(
+        config.greeter_argv(7),
+        vec!["/usr/bin/login_ng-cli", "--banner", "false", "--vt", "7"]
+    );
+
+    // the greeter runs as root: it must be given by absolute path
+    let config = SwitchConfig::parse("[Switch]\nenabled = true\ngreeter = login_ng-cli\n");
+    assert_eq!(
+        config.greeter_argv(2),
+        vec![DEFAULT_SWITCH_GREETER, "--vt", "2"]
+    );
+}
@@ -27,19 +27,39 @@ use pam::{
     pam_try,
 };
 use pam_login_ng_common::{
+    homed::{change_home_password, home_state},
+    info::verify_service,
+    inhibit::InhibitorLock,
+    limits::SessionLimits,
     login_ng::{
-        storage::{load_user_auth_data, StorageSource},
+        credentials::{provisioned_secret, PROVISIONED_SECRET_PAM_ARG},
+        error::UserOperationError,
+        guest::GuestConfig,
+        keycache::{forget_cached_unlock, KeyCacheConfig},
+        network::{wait_network_online, NetworkWaitError},
+        nextboot::{
+            current_boot_id, NextBootAutologin, NextBootAutologinError, NEXT_BOOT_AUTOLOGIN_PAM_ARG,
+        },
+        recovery::RecoveryTokens,
+        secure::LockedBytes,
+        storage::{load_user_auth_data, load_user_mountpoints, StorageSource},
         user::UserAuthData,
         users::{gid_t, uid_t},
+        vt::vt_of_tty,
     },
-    result::ServiceOperationResult,
+    result::{ServiceOperationReport, ServiceOperationResult},
     security::SessionPrelude,
     serde_json,
     session::SessionsProxy,
-    zbus::{Connection, Result as ZResult},
+    zbus::{Connection, Error as ZError, Result as ZResult},
 };
 
-use std::{borrow::Cow, ffi::CStr, path::PathBuf, sync::Once};
+use std::{
+    borrow::Cow,
+    ffi::CStr,
+    path::{Path, PathBuf},
+    sync::Once,
+};
 use tokio::runtime::Runtime;
 
 static INIT: Once = Once::new();
@@ -48,7 +68,65 @@ static mut RUNTIME: Option<Runtime> = None;
 struct PamQuickEmbedded;
 pam::pam_hooks!(PamQuickEmbedded);
 
+/// Guest sessions configuration, shared with pam_login_ng-service
+fn guest_config() -> GuestConfig {
+    GuestConfig::load(Path::new(
+        match std::fs::exists("/usr/lib/login_ng/").unwrap_or(false) {
+            true => "/usr/lib/login_ng/",
+            false => "/etc/login_ng/",
+        },
+    ))
+}
+
+/// Module argument of the PAM services (greeters) allowed to start guest sessions
+const GUEST_PAM_ARG: &str = "guest";
+
+/// Cache of recent unlocks in the kernel keyring (keycache.conf), disabled by default
+fn key_cache_config() -> KeyCacheConfig {
+    KeyCacheConfig::load(Path::new(
+        match std::fs::exists("/usr/lib/login_ng/").unwrap_or(false) {
+            true => "/usr/lib/login_ng/",
+            false => "/etc/login_ng/",
+        },
+    ))
+}
+
+/// Session limits of pam_login_ng-service (service.conf), for the waits done on its behalf
+fn session_limits() -> SessionLimits {
+    SessionLimits::load(Path::new(
+        match std::fs::exists("/usr/lib/login_ng/").unwrap_or(false) {
+            true => "/usr/lib/login_ng/",
+            false => "/etc/login_ng/",
+        },
+    ))
+}
+
+/// Run a request to pam_login_ng-service on the shared runtime: None if it cannot be created
+fn block_on<F: std::future::Future>(future: F) -> Option<F::Output> {
+    if std::env::var("DBUS_SESSION_BUS_ADDRESS").is_err() {
+        std::env::set_var(
+            "DBUS_SESSION_BUS_ADDRESS",
+            "unix:path=/run/dbus/system_bus_socket",
+        );
+    }
+
+    INIT.call_once(|| {
+        // Initialize the Tokio runtime
+        unsafe {
+            RUNTIME = Some(Runtime::new().unwrap());
+        }
+    });
+
+    unsafe { RUNTIME.as_ref().map(|runtime| runtime.block_on(future)) }
+}
+
 impl PamQuickEmbedded {
+    pub(crate) fn show_error(pamh: &PamHandle, message: &str) {
+        if let Ok(Some(conv)) = pamh.get_item::<Conv>() {
+            let _ = conv.send(PAM_ERROR_MSG, message);
+        }
+    }
+
     pub(crate) fn load_user_auth_data_from_username(
         username: &String,
     ) -> Result<UserAuthData, PamResultCode> {
@@ -69,44 +147,454 @@ impl PamQuickEmbedded {
         }
     }
 
+    /// Connect to pam_login_ng-service, refusing to talk to a service speaking another protocol
+    pub(crate) async fn sessions_proxy(connection: &Connection) -> ZResult<SessionsProxy<'_>> {
+        let proxy = SessionsProxy::new(connection).await?;
+
+        verify_service(&proxy)
+            .await
+            .map_err(|err| ZError::Failure(err.to_string()))?;
+
+        Ok(proxy)
+    }
+
     pub(crate) async fn open_session_for_user(
         user: &String,
-        plain_main_password: String,
-    ) -> ZResult<(ServiceOperationResult, uid_t, gid_t)> {
+        plain_main_password: &[u8],
+    ) -> ZResult<(ServiceOperationReport, uid_t, gid_t)> {
         let connection = Connection::session().await?;
 
-        let proxy = SessionsProxy::new(&connection).await?;
+        let proxy = Self::sessions_proxy(&connection).await?;
 
         let pk = proxy.initiate_session().await?;
 
         // return an unknown error if the service was unable to serialize the RSA public key
         if pk.is_empty() {
-            return Ok((ServiceOperationResult::EmptyPubKey, 0, 0));
+            return Ok((ServiceOperationResult::EmptyPubKey.into(), 0, 0));
         }
 
         let Ok(session_prelude) = serde_json::from_str::<SessionPrelude>(pk.as_str()) else {
-            return Ok((ServiceOperationResult::SerializationError, 0, 0));
+            return Ok((ServiceOperationResult::SerializationError.into(), 0, 0));
         };
 
         let Ok(encrypted_password) = session_prelude.encrypt(plain_main_password) else {
-            return Ok((ServiceOperationResult::EncryptionError, 0, 0));
+            return Ok((ServiceOperationResult::EncryptionError.into(), 0, 0));
         };
 
         let reply = proxy
             .open_user_session(user.as_str(), encrypted_password)
             .await?;
 
-        Ok((ServiceOperationResult::from(reply.0), reply.1, reply.2))
+        Ok(reply)
+    }
+
+    /// Wait for the network to come online if the mounts of the user need it
+    pub(crate) async fn wait_network_for_mounts(user: &str) -> Result<(), NetworkWaitError> {
+        let requires_network = load_user_mountpoints(&StorageSource::Username(String::from(user)))
+            .is_ok_and(|mounts| mounts.is_some_and(|m| m.requires_network()));
+
+        match requires_network {
+            true => wait_network_online(session_limits().network_timeout()).await,
+            false => Ok(()),
+        }
     }
 
-    pub(crate) async fn close_session_for_user(user: &String) -> ZResult<u32> {
+    pub(crate) async fn prepare_guest_account(user: &String) -> ZResult<ServiceOperationReport> {
         let connection = Connection::session().await?;
 
-        let proxy = SessionsProxy::new(&connection).await?;
+        let proxy = Self::sessions_proxy(&connection).await?;
+        let reply = proxy.prepare_guest_account(user.as_str()).await?;
+
+        Ok(reply)
+    }
+
+    pub(crate) async fn open_guest_session_for_user(
+        user: &String,
+    ) -> ZResult<(ServiceOperationReport, uid_t, gid_t)> {
+        let connection = Connection::session().await?;
+
+        let proxy = Self::sessions_proxy(&connection).await?;
+        let reply = proxy.open_guest_session(user.as_str()).await?;
+
+        Ok(reply)
+    }
+
+    pub(crate) async fn report_authentication(
+        user: &String,
+        success: bool,
+    ) -> ZResult<ServiceOperationReport> {
+        let connection = Connection::session().await?;
+
+        let proxy = Self::sessions_proxy(&connection).await?;
+        let reply = proxy.report_authentication(user.as_str(), success).await?;
+
+        Ok(reply)
+    }
+
+    pub(crate) async fn close_session_for_user(user: &String) -> ZResult<ServiceOperationReport> {
+        let connection = Connection::session().await?;
+
+        let proxy = Self::sessions_proxy(&connection).await?;
         let reply = proxy.close_user_session(user.as_str()).await?;
 
         Ok(reply)
     }
+
+    pub(crate) async fn register_session_vt(
+        user: &String,
+        vt: u32,
+    ) -> ZResult<ServiceOperationReport> {
+        let connection = Connection::session().await?;
+
+        let proxy = Self::sessions_proxy(&connection).await?;
+        let reply = proxy.register_session_vt(user.as_str(), vt).await?;
+
+        Ok(reply)
+    }
+
+    pub(crate) async fn unregister_session_vt(
+        user: &String,
+        vt: u32,
+    ) -> ZResult<ServiceOperationReport> {
+        let connection = Connection::session().await?;
+
+        let proxy = Self::sessions_proxy(&connection).await?;
+        let reply = proxy.unregister_session_vt(user.as_str(), vt).await?;
+
+        Ok(reply)
+    }
+
+    /// The VT the session runs on, if PAM_TTY is a virtual terminal
+    pub(crate) fn session_vt(pamh: &PamHandle) -> Option<u32> {
+        match pamh.get_item::<pam::items::Tty>() {
+            Ok(Some(tty)) => vt_of_tty(&tty.to_string_lossy()),
+            _ => None,
+        }
+    }
+
+    pub(crate) async fn change_homed_password(
+        user: &String,
+        old_password: &[u8],
+        new_password: &[u8],
+    ) -> Result<bool, pam_login_ng_common::homed::HomedError> {
+        let connection = Connection::system().await?;
+
+        // users that are not managed by systemd-homed are not a concern of this module
+        if home_state(&connection, user.as_str()).await.is_none() {
+            return Ok(false);
+        }
+
+        change_home_password(&connection, user.as_str(), old_password, new_password).await?;
+
+        Ok(true)
+    }
+
+    /// Obtains the main password from the user-provided secret, skipping the
+    /// password hashes if the same secret unlocked it shortly before
+    pub(crate) fn unlock(
+        pamh: &mut PamHandle,
+        username: &str,
+        user_cfg: &UserAuthData,
+        secret: &[u8],
+    ) -> Result<LockedBytes, UserOperationError> {
+        let key_cache = key_cache_config();
+
+        if let Some(main_password) = key_cache.lookup(username, secret, user_cfg) {
+            return Ok(main_password);
+        }
+
+        let main_password = user_cfg.main_by_secret(Some(secret))?;
+
+        if let Err(err) = key_cache.store(username, secret, user_cfg, &main_password) {
+            pamh.log(
+                pam::module::LogLevel::Warning,
+                format!("login_ng: sm_authenticate: cannot cache the unlock: {err}"),
+            );
+        }
+
+        Ok(main_password)
+    }
+
+    /// Block shutdown and sleep while talking to the service, so that a power button pressed
+    /// halfway doesn't interrupt the handshake: never held while waiting for the user to answer
+    fn inhibit_shutdown(pamh: &mut PamHandle, why: &str) -> Option<InhibitorLock> {
+        match block_on(InhibitorLock::take(why)) {
+            Some(Ok(lock)) => Some(lock),
+            Some(Err(err)) => {
+                pamh.log(
+                    pam::module::LogLevel::Debug,
+                    format!("login_ng: sm_authenticate: cannot take the inhibitor lock: {err}"),
+                );
+
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Whether opening a session of the user needs its main password: to unlock its mounts
+    /// or its systemd-homed home, so that it cannot be opened by the next boot autologin
+    fn session_needs_main_password(username: &str) -> bool {
+        let has_mounts = !matches!(
+            load_user_mountpoints(&StorageSource::Username(String::from(username))),
+            Ok(None)
+        );
+
+        has_mounts
+            || block_on(async {
+                match Connection::system().await {
+                    Ok(connection) => home_state(&connection, username).await.is_some(),
+                    Err(_) => false,
+                }
+            })
+            .unwrap_or(true)
+    }
+
+    /// Key of the PAM data marking the user accepted by sm_authenticate as the guest account
+    fn guest_data_key(username: &str) -> String {
+        format!("{username}-login_ng-guest")
+    }
+
+    /// Whether sm_authenticate has accepted the user as the guest account in this PAM transaction
+    fn is_guest_session(pamh: &PamHandle, username: &str) -> bool {
+        // the data under this key is only ever set as a bool
+        unsafe { pamh.get_data::<bool>(Self::guest_data_key(username).as_str()) }
+            .is_ok_and(|guest| *guest)
+    }
+
+    pub(crate) fn authenticate(
+        pamh: &mut PamHandle,
+        next_boot_autologin: bool,
+        guest_sessions: bool,
+        provisioned_secrets: bool,
+    ) -> PamResultCode {
+        let username = match pamh.get_user(None) {
+            Ok(res) => res,
+            Err(err) => {
+                // If the error is PAM_SUCCESS, we should not return an error
+                if err != PamResultCode::PAM_SUCCESS {
+                    return err;
+                }
+
+                // Attempt to get the user item
+                match pamh.get_item::<pam::items::User>() {
+                    Ok(Some(username)) => username.to_string_lossy(),
+                    Ok(None) => return PamResultCode::PAM_AUTH_ERR,
+                    Err(err) => return err,
+                }
+            }
+        };
+
+        // the guest account has no password: the service creates it on first use,
+        // and refuses an account with the same name that it has not created itself
+        if guest_sessions && guest_config().is_guest(&username) {
+            let _inhibitor = Self::inhibit_shutdown(pamh, "Preparing the guest account");
+            let report = block_on(PamQuickEmbedded::prepare_guest_account(
+                &username.to_string(),
+            ));
+
+            return match report {
+                Some(Ok(report)) if report.is_ok() => {
+                    if let Err(err) =
+                        pamh.set_data(Self::guest_data_key(&username).as_str(), Box::new(true))
+                    {
+                        pamh.log(
+                            pam::module::LogLevel::Error,
+                            format!("login_ng: sm_authenticate: set_data error {err}"),
+                        );
+
+                        return err;
+                    }
+
+                    PamResultCode::PAM_SUCCESS
+                }
+                Some(Ok(report)) => {
+                    pamh.log(
+                        pam::module::LogLevel::Error,
+                        format!(
+                            "login_ng: sm_authenticate: cannot prepare the guest account: {report}"
+                        ),
+                    );
+
+                    PamResultCode::PAM_AUTH_ERR
+                }
+                Some(Err(err)) => {
+                    pamh.log(
+                        pam::module::LogLevel::Error,
+                        format!(
+                            "login_ng: sm_authenticate: pam_login_ng-service dbus error: {err}"
+                        ),
+                    );
+
+                    PamResultCode::PAM_AUTHINFO_UNAVAIL
+                }
+                None => PamResultCode::PAM_SERVICE_ERR,
+            };
+        }
+
+        // try to load the user and return PAM_USER_UNKNOWN if it cannot be loaded
+        let user_cfg =
+            match PamQuickEmbedded::load_user_auth_data_from_username(&username.to_string()) {
+                Ok(user_cfg) => user_cfg,
+                Err(pam_err_code) => return pam_err_code,
+            };
+
+        let cred_data = format!("{}-login_ng", username);
+
+        // NOTE: if main_by_auth returns a main password the authentication was successful:
+        // there is no need to check if the returned main password is the same as the stored one.
+        // This will also used below for the user-provided string.
+        if let Ok(main_password) = user_cfg.main_by_secret(Some(&[])) {
+            if let Err(err) = pamh.set_data(cred_data.as_str(), Box::new(main_password)) {
+                pamh.log(
+                    pam::module::LogLevel::Error,
+                    format!("login_ng: sm_authenticate: set_data error {err}"),
+                );
+
+                return err;
+            }
+
+            return PamResultCode::PAM_SUCCESS;
+        }
+
+        // a secret provisioned for this machine (systemd-creds or the kernel keyring) allows unattended logins,
+        // only honored by the services enabling it (autologin) and never by sshd, su or sudo
+        if let Some((source, secret)) = provisioned_secrets
+            .then(|| provisioned_secret(&username, true))
+            .flatten()
+        {
+            if let Ok(main_password) = user_cfg.main_by_secret(Some(&secret)) {
+                pamh.log(
+                    pam::module::LogLevel::Info,
+                    format!("login_ng: sm_authenticate: user {username} unlocked by a {source:?} credential"),
+                );
+
+                if let Err(err) = pamh.set_data(cred_data.as_str(), Box::new(main_password)) {
+                    pamh.log(
+                        pam::module::LogLevel::Error,
+                        format!("login_ng: sm_authenticate: set_data error {err}"),
+                    );
+
+                    return err;
+                }
+
+                return PamResultCode::PAM_SUCCESS;
+            }
+        }
+
+        // a single-use autologin armed during a previous boot, only honored by the services enabling it
+        if next_boot_autologin {
+            let claimed = current_boot_id()
+                .map_err(NextBootAutologinError::from)
+                .and_then(|boot_id| {
+                    NextBootAutologin::default().claim(
+                        &username,
+                        &boot_id,
+                        Self::session_needs_main_password(&username),
+                    )
+                });
+
+            match claimed {
+                Ok(Some(main_password)) => {
+                    pamh.log(
+                        pam::module::LogLevel::Warning,
+                        format!("login_ng: sm_authenticate: user {username} logged in by the next boot autologin"),
+                    );
+
+                    // sm_open_session hands it to the service, that has nothing to unlock with it
+                    if let Err(err) = pamh.set_data(cred_data.as_str(), Box::new(main_password)) {
+                        pamh.log(
+                            pam::module::LogLevel::Error,
+                            format!("login_ng: sm_authenticate: set_data error {err}"),
+                        );
+
+                        return err;
+                    }
+
+                    return PamResultCode::PAM_SUCCESS;
+                }
+                Ok(None) => {}
+                Err(err) => pamh.log(
+                    pam::module::LogLevel::Error,
+                    format!("login_ng: sm_authenticate: next boot autologin error: {err}"),
+                ),
+            }
+        }
+
+        // if the empty password was not valid then continue and ask for a password
+        let conv = match pamh.get_item::<Conv>() {
+            Ok(Some(conv)) => conv,
+            Ok(None) => {
+                pamh.log(
+                    pam::module::LogLevel::Critical,
+                    "No conv available".to_string(),
+                );
+
+                return PamResultCode::PAM_SERVICE_ERR;
+            }
+            Err(err) => {
+                pamh.log(
+                    pam::module::LogLevel::Error,
+                    format!("Couldn't get pam_conv: pam error {err}"),
+                );
+
+                return err;
+            }
+        };
+
+        // the secret is whatever bytes the conversation returned: it need not be text
+        match pam_try!(conv.send(PAM_PROMPT_ECHO_OFF, "Password: "))
+            .map(|cstr| LockedBytes::from_slice(cstr.to_bytes()))
+        {
+            Some(password) => match Self::unlock(pamh, &username, &user_cfg, &password) {
+                Ok(main_password) => {
+                    if let Err(err) = pamh.set_data(cred_data.as_str(), Box::new(main_password)) {
+                        pamh.log(
+                            pam::module::LogLevel::Error,
+                            format!("login_ng: sm_authenticate: set_data error {err}"),
+                        );
+
+                        return err;
+                    }
+                    PamResultCode::PAM_SUCCESS
+                }
+                Err(err) => {
+                    // a locked-out user might have been given a recovery token by the administrator
+                    let recovery = match std::str::from_utf8(&password) {
+                        Ok(token) => RecoveryTokens::default().redeem(&username, token),
+                        // recovery tokens are always text
+                        Err(_) => Ok(false),
+                    };
+
+                    match recovery {
+                        Ok(true) => {
+                            pamh.log(
+                                pam::module::LogLevel::Warning,
+                                format!("login_ng: sm_authenticate: user {username} used a recovery token"),
+                            );
+
+                            return PamResultCode::PAM_SUCCESS;
+                        }
+                        Ok(false) => {}
+                        Err(recovery_err) => pamh.log(
+                            pam::module::LogLevel::Error,
+                            format!(
+                                "login_ng: sm_authenticate: recovery token error: {recovery_err}"
+                            ),
+                        ),
+                    }
+
+                    pamh.log(
+                        pam::module::LogLevel::Error,
+                        format!("login_ng: sm_authenticate: authentication error: {err}"),
+                    );
+
+                    PamResultCode::PAM_AUTH_ERR
+                }
+            },
+            None => PamResultCode::PAM_CRED_INSUFFICIENT,
+        }
+    }
 }
 
 impl PamHooks for PamQuickEmbedded {
@@ -156,13 +644,39 @@ impl PamHooks for PamQuickEmbedded {
             }
         };
 
+        // the unlock cached for lockscreens and retries must not outlive the session
+        if let Err(err) = forget_cached_unlock(&username) {
+            pamh.log(
+                pam::module::LogLevel::Debug,
+                format!("login_ng: close_session: cannot forget the cached unlock: {err}"),
+            );
+        }
+
+        let session_vt = PamQuickEmbedded::session_vt(pamh);
+
         unsafe {
             match &RUNTIME {
                 Some(runtime) => runtime.block_on(async {
+                    if let Some(vt) = session_vt {
+                        if let Err(err) = PamQuickEmbedded::unregister_session_vt(&username.to_string(), vt).await {
+                            pamh.log(
+                                pam::module::LogLevel::Warning,
+                                format!("login_ng: close_session: could not unregister VT {vt}: {err}"),
+                            );
+                        }
+                    }
+
                     match PamQuickEmbedded::close_session_for_user(&String::from(username)).await {
-                        Ok(result) => match ServiceOperationResult::from(result) {
+                        Ok(report) => match report.result() {
                             ServiceOperationResult::Ok => PamResultCode::PAM_SUCCESS,
-                            _ => PamResultCode::PAM_SERVICE_ERR,
+                            _ => {
+                                pamh.log(
+                                    pam::module::LogLevel::Error,
+                                    format!("login_ng: close_session: pam_login_ng-service errored: {report}"),
+                                );
+
+                                PamResultCode::PAM_SERVICE_ERR
+                            }
                         },
                         Err(_) => PamResultCode::PAM_SERVICE_ERR,
                     }
@@ -233,32 +747,54 @@ impl PamHooks for PamQuickEmbedded {
             format!("login_ng: open_session: loaded data for user {username}"),
         );
 
+        // fast user switching needs to know where the session runs
+        let session_vt = PamQuickEmbedded::session_vt(pamh);
+        let session_user = username.to_string();
+        let guest = PamQuickEmbedded::is_guest_session(pamh, &username);
+
         unsafe {
             match &RUNTIME {
                 Some(runtime) => runtime.block_on(async {
-                    let cred_data = format!("{}-login_ng", username);
-                    let main_password = match pamh.get_data::<String>(cred_data.as_str()) {
-                        Ok(main_password) => main_password.clone(),
-                        Err(err) => {
-                            pamh.log(
-                                pam::module::LogLevel::Error,
-                                format!(
-                                    "login_ng: open_session: get_data error: {err}"
-                                ),
-                            );
+                    let reply = match guest {
+                        // guest sessions have no password: the service mounts an empty home
+                        true => PamQuickEmbedded::open_guest_session_for_user(&String::from(username)).await,
+                        false => {
+                            let cred_data = format!("{}-login_ng", username);
+                            let main_password = match pamh.get_data::<LockedBytes>(cred_data.as_str()) {
+                                Ok(main_password) => LockedBytes::from_slice(main_password),
+                                Err(err) => {
+                                    pamh.log(
+                                        pam::module::LogLevel::Error,
+                                        format!(
+                                            "login_ng: open_session: get_data error: {err}"
+                                        ),
+                                    );
 
-                            return err
-                        },
+                                    return err
+                                },
+                            };
+
+                            // NFS homes and such would fail to mount on fast boots: waited for here,
+                            // as the service cannot serve anybody else while it is opening a session
+                            if let Err(err) = PamQuickEmbedded::wait_network_for_mounts(&username).await {
+                                pamh.log(
+                                    pam::module::LogLevel::Warning,
+                                    format!("login_ng: open_session: network not online for mounts of user {username}: {err}"),
+                                );
+                            }
+
+                            PamQuickEmbedded::open_session_for_user(
+                                &String::from(username),
+                                &main_password,
+                            )
+                            .await
+                        }
                     };
 
-                    match PamQuickEmbedded::open_session_for_user(
-                        &String::from(username),
-                        main_password,
-                    )
-                    .await
+                    match reply
                     {
                         Ok(result) => {
-                            match result.0 {
+                            match result.0.result() {
                                 ServiceOperationResult::Ok => {
                                     pamh.log(
                                         pam::module::LogLevel::Info,
@@ -268,6 +804,15 @@ impl PamHooks for PamQuickEmbedded {
                                     let uid = result.1;
                                     let _gid = result.2;
 
+                                    if let Some(vt) = session_vt {
+                                        if let Err(err) = PamQuickEmbedded::register_session_vt(&session_user, vt).await {
+                                            pamh.log(
+                                                pam::module::LogLevel::Warning,
+                                                format!("login_ng: open_session: could not register VT {vt}: {err}"),
+                                            );
+                                        }
+                                    }
+
                                     let xdg_user_path = PathBuf::from(pam_login_ng_common::XDG_RUNTIME_DIR_PATH).join(format!("{uid}"));
                                     match pamh.env_set(Cow::from("XDG_RUNTIME_DIR"), xdg_user_path.to_string_lossy()) {
                                         Ok(_) => pamh.log(
@@ -282,15 +827,24 @@ impl PamHooks for PamQuickEmbedded {
 
                                     PamResultCode::PAM_SUCCESS
                                 },
-                                err => {
+                                _ => {
+                                    let report = result.0;
+
                                     pamh.log(
                                         pam::module::LogLevel::Error,
                                         format!(
-                                            "login_ng: open_session: pam_login_ng-service errored: {err}"
+                                            "login_ng: open_session: pam_login_ng-service errored: {report}"
                                         ),
                                     );
 
-                                    PamResultCode::PAM_SERVICE_ERR
+                                    // let the user know why the session could not be opened
+                                    PamQuickEmbedded::show_error(pamh, format!("login_ng: {report}").as_str());
+
+                                    match report.result() {
+                                        // the user is allowed in, just not in yet another session
+                                        ServiceOperationResult::SessionLimitReached => PamResultCode::PAM_PERM_DENIED,
+                                        _ => PamResultCode::PAM_SERVICE_ERR,
+                                    }
                                 },
                             }
                         }
@@ -329,6 +883,11 @@ impl PamHooks for PamQuickEmbedded {
             }
         };
 
+        // guest sessions have no login_ng credentials
+        if PamQuickEmbedded::is_guest_session(pamh, &username) {
+            return PamResultCode::PAM_SUCCESS;
+        }
+
         // try to load the user and return PAM_USER_UNKNOWN if it cannot be loaded
         let user_cfg =
             match PamQuickEmbedded::load_user_auth_data_from_username(&username.to_string()) {
@@ -339,13 +898,19 @@ impl PamHooks for PamQuickEmbedded {
         PamResultCode::PAM_SUCCESS
     }
 
-    /*
-        fn acct_mgmt(_pamh: &mut PamHandle, _args: Vec<&CStr>, _flags: PamFlag) -> PamResultCode {
-            println!("account management");
-            PamResultCode::PAM_SUCCESS
+    fn sm_chauthtok(pamh: &mut PamHandle, _args: Vec<&CStr>, flags: PamFlag) -> PamResultCode {
+        // systemd-homed re-keys the home directory in one step: nothing to check beforehand
+        if flags & PAM_UPDATE_AUTHTOK == 0 {
+            return PamResultCode::PAM_IGNORE;
         }
-    */
-    fn sm_authenticate(pamh: &mut PamHandle, _args: Vec<&CStr>, _flags: PamFlag) -> PamResultCode {
+
+        INIT.call_once(|| {
+            // Initialize the Tokio runtime
+            unsafe {
+                RUNTIME = Some(Runtime::new().unwrap());
+            }
+        });
+
         let username = match pamh.get_user(None) {
             Ok(res) => res,
             Err(err) => {
@@ -357,84 +922,148 @@ impl PamHooks for PamQuickEmbedded {
                 // Attempt to get the user item
                 match pamh.get_item::<pam::items::User>() {
                     Ok(Some(username)) => username.to_string_lossy(),
-                    Ok(None) => return PamResultCode::PAM_AUTH_ERR,
+                    Ok(None) => return PamResultCode::PAM_USER_UNKNOWN,
                     Err(err) => return err,
                 }
             }
         };
 
-        // try to load the user and return PAM_USER_UNKNOWN if it cannot be loaded
-        let user_cfg =
-            match PamQuickEmbedded::load_user_auth_data_from_username(&username.to_string()) {
-                Ok(user_cfg) => user_cfg,
-                Err(pam_err_code) => return pam_err_code,
-            };
+        let new_password = match pamh.get_item::<pam::items::AuthTok>() {
+            Ok(Some(new_password)) => new_password.to_bytes().to_vec(),
+            Ok(None) => return PamResultCode::PAM_AUTHTOK_ERR,
+            Err(err) => return err,
+        };
 
-        let cred_data = format!("{}-login_ng", username);
+        // a cached unlock would keep accepting the old password
+        let _ = forget_cached_unlock(&username);
 
-        // NOTE: if main_by_auth returns a main password the authentication was successful:
-        // there is no need to check if the returned main password is the same as the stored one.
-        // This will also used below for the user-provided string.
-        if let Ok(main_password) = user_cfg.main_by_auth(&Some(String::new())) {
+        // the new password replaces the one the recovery token stood for: the re-enrollment
+        // is completed by whoever commits it, not before the new password has been stored
+        let recovery = RecoveryTokens::default();
+        let reenrolling = recovery.requires_reenrollment(&username);
+        if reenrolling {
+            let cred_data = format!("{}-login_ng", username);
+            let main_password = LockedBytes::from_slice(new_password.as_slice());
             if let Err(err) = pamh.set_data(cred_data.as_str(), Box::new(main_password)) {
                 pamh.log(
                     pam::module::LogLevel::Error,
-                    format!("login_ng: sm_authenticate: set_data error {err}"),
+                    format!("login_ng: chauthtok: set_data error {err}"),
                 );
-
-                return err;
             }
-
-            return PamResultCode::PAM_SUCCESS;
         }
 
-        // if the empty password was not valid then continue and ask for a password
-        let conv = match pamh.get_item::<Conv>() {
-            Ok(Some(conv)) => conv,
-            Ok(None) => {
-                pamh.log(
-                    pam::module::LogLevel::Critical,
-                    "No conv available".to_string(),
-                );
+        let old_password = match pamh.get_item::<pam::items::OldAuthTok>() {
+            Ok(Some(old_password)) => old_password.to_bytes().to_vec(),
+            Ok(None) => return PamResultCode::PAM_AUTHTOK_RECOVERY_ERR,
+            Err(err) => return err,
+        };
 
-                return PamResultCode::PAM_SERVICE_ERR;
+        unsafe {
+            match &RUNTIME {
+                Some(runtime) => runtime.block_on(async {
+                    match PamQuickEmbedded::change_homed_password(
+                        &String::from(username),
+                        old_password.as_slice(),
+                        new_password.as_slice(),
+                    )
+                    .await
+                    {
+                        Ok(true) => {
+                            if reenrolling {
+                                if let Err(err) = recovery.complete_reenrollment(&username) {
+                                    pamh.log(
+                                        pam::module::LogLevel::Error,
+                                        format!("login_ng: chauthtok: cannot complete the re-enrollment: {err}"),
+                                    );
+                                }
+                            }
+
+                            PamResultCode::PAM_SUCCESS
+                        }
+                        Ok(false) => PamResultCode::PAM_IGNORE,
+                        Err(err) => {
+                            pamh.log(
+                                pam::module::LogLevel::Error,
+                                format!("login_ng: chauthtok: systemd-homed error: {err}"),
+                            );
+
+                            PamResultCode::PAM_AUTHTOK_ERR
+                        }
+                    }
+                }),
+                None => PamResultCode::PAM_SERVICE_ERR,
             }
+        }
+    }
+
+    fn acct_mgmt(pamh: &mut PamHandle, _args: Vec<&CStr>, _flags: PamFlag) -> PamResultCode {
+        let username = match pamh.get_user(None) {
+            Ok(res) => res,
             Err(err) => {
+                // If the error is PAM_SUCCESS, we should not return an error
+                if err != PamResultCode::PAM_SUCCESS {
+                    return err;
+                }
+
+                // Attempt to get the user item
+                match pamh.get_item::<pam::items::User>() {
+                    Ok(Some(username)) => username.to_string_lossy(),
+                    Ok(None) => return PamResultCode::PAM_USER_UNKNOWN,
+                    Err(err) => return err,
+                }
+            }
+        };
+
+        // a user that logged in with a recovery token must choose a new password
+        match RecoveryTokens::default().requires_reenrollment(&username) {
+            true => {
                 pamh.log(
-                    pam::module::LogLevel::Error,
-                    format!("Couldn't get pam_conv: pam error {err}"),
+                    pam::module::LogLevel::Info,
+                    format!("login_ng: acct_mgmt: user {username} must re-enroll after recovery"),
                 );
 
-                return err;
+                PamResultCode::PAM_NEW_AUTHTOK_REQD
             }
-        };
+            false => PamResultCode::PAM_IGNORE,
+        }
+    }
 
-        match pam_try!(conv.send(PAM_PROMPT_ECHO_OFF, "Password: "))
-            .map(|cstr| cstr.to_str().map(|s| s.to_string()))
-        {
-            Some(Ok(password)) => match user_cfg.main_by_auth(&Some(password)) {
-                Ok(main_password) => {
-                    if let Err(err) = pamh.set_data(cred_data.as_str(), Box::new(main_password)) {
-                        pamh.log(
-                            pam::module::LogLevel::Error,
-                            format!("login_ng: sm_authenticate: set_data error {err}"),
-                        );
+    fn sm_authenticate(pamh: &mut PamHandle, args: Vec<&CStr>, _flags: PamFlag) -> PamResultCode {
+        let next_boot_autologin = args
+            .iter()
+            .any(|arg| arg.to_bytes() == NEXT_BOOT_AUTOLOGIN_PAM_ARG.as_bytes());
+        let guest_sessions = args
+            .iter()
+            .any(|arg| arg.to_bytes() == GUEST_PAM_ARG.as_bytes());
+        let provisioned_secrets = args
+            .iter()
+            .any(|arg| arg.to_bytes() == PROVISIONED_SECRET_PAM_ARG.as_bytes());
 
-                        return err;
-                    }
-                    PamResultCode::PAM_SUCCESS
-                }
-                Err(err) => {
+        let result = PamQuickEmbedded::authenticate(
+            pamh,
+            next_boot_autologin,
+            guest_sessions,
+            provisioned_secrets,
+        );
+
+        // let the service notify login hooks about the outcome for users managed by login_ng
+        if result == PamResultCode::PAM_SUCCESS || result == PamResultCode::PAM_AUTH_ERR {
+            if let Ok(Some(username)) = pamh.get_item::<pam::items::User>() {
+                let username = username.to_string_lossy().to_string();
+                let success = result == PamResultCode::PAM_SUCCESS;
+
+                let _inhibitor = PamQuickEmbedded::inhibit_shutdown(pamh, "Authenticating a user");
+                if let Some(Err(err)) =
+                    block_on(PamQuickEmbedded::report_authentication(&username, success))
+                {
                     pamh.log(
-                        pam::module::LogLevel::Error,
-                        format!("login_ng: sm_authenticate: authentication error: {err}"),
+                        pam::module::LogLevel::Debug,
+                        format!("login_ng: sm_authenticate: cannot report the result: {err}"),
                     );
-
-                    PamResultCode::PAM_AUTH_ERR
                 }
-            },
-            Some(Err(_err)) => PamResultCode::PAM_CRED_INSUFFICIENT,
-            None => PamResultCode::PAM_CRED_INSUFFICIENT,
+            }
         }
+
+        result
     }
 }
@@ -21,9 +21,10 @@ extern crate tokio;
 
 use std::path::PathBuf;
 
+use pam_login_ng_common::info::verify_service;
 use pam_login_ng_common::login_ng::storage::{load_user_mountpoints, StorageSource};
 use pam_login_ng_common::mount::MountAuthDBusProxy;
-use pam_login_ng_common::result::ServiceOperationResult;
+use pam_login_ng_common::session::SessionsProxy;
 use pam_login_ng_common::zbus::Connection;
 
 use pam_login_ng_common::ServiceError;
@@ -61,6 +62,10 @@ struct AuthorizeCommand {
     #[argh(option, short = 'd')]
     /// force the use of a specific home directory
     directory: Option<PathBuf>,
+
+    #[argh(switch)]
+    /// only authorize the mounts on the block devices currently holding them
+    devices: bool,
 }
 
 #[tokio::main]
@@ -80,6 +85,15 @@ async fn main() -> Result<(), ServiceError> {
 
     let connection = Connection::session().await?;
 
+    // a service from a different install could misread the requests
+    let service_info = match verify_service(&SessionsProxy::new(&connection).await?).await {
+        Ok(service_info) => service_info,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(-1)
+        }
+    };
+
     let proxy = MountAuthDBusProxy::new(&connection).await?;
 
     match args.command {
@@ -90,6 +104,12 @@ async fn main() -> Result<(), ServiceError> {
             println!("This is free software, and you are welcome to redistribute it");
             println!("under certain conditions.");
             println!("\n");
+            println!(
+                "pam_login_ng-service: login-ng version {}, protocol version {}, features: {}",
+                service_info.library_version(),
+                service_info.protocol_version(),
+                service_info.features().join(", ")
+            );
         }
         Command::Authorize(auth_data) => {
             let storage_source = match auth_data.directory {
@@ -110,14 +130,25 @@ async fn main() -> Result<(), ServiceError> {
                 std::process::exit(-1)
             };
 
-            let reply = proxy
-                .authorize(auth_data.username.as_str(), loaded_mounts.hash())
-                .await?;
-
-            let result = ServiceOperationResult::from(reply);
+            let reply = match auth_data.devices {
+                true => {
+                    proxy
+                        .authorize_devices(
+                            auth_data.username.as_str(),
+                            loaded_mounts.hash(),
+                            loaded_mounts.devices(),
+                        )
+                        .await?
+                }
+                false => {
+                    proxy
+                        .authorize(auth_data.username.as_str(), loaded_mounts.hash())
+                        .await?
+                }
+            };
 
-            if result != ServiceOperationResult::Ok {
-                eprintln!("Error in authorizing the user mouunt: {result}");
+            if !reply.is_ok() {
+                eprintln!("Error in authorizing the user mount: {reply}");
                 std::process::exit(-1)
             }
         }
@@ -20,14 +20,31 @@
 extern crate tokio;
 
 use pam_login_ng_common::{
+    approval::{serve_approvals, ApprovalConfig, PendingApprovals},
     disk::create_directory,
-    login_ng::users,
+    hooks::Hooks,
+    limits::SessionLimits,
+    login_ng::{
+        guest::GuestConfig,
+        secure::{disable_core_dumps, lock_all_memory},
+        users,
+    },
+    metrics::{
+        export_metrics_textfile, flush_metrics_textfile, serve_metrics, Metrics, MetricsConfig,
+    },
     mount::{MountAuthDBus, MountAuthOperations},
-    session::Sessions,
+    sealed::SealConfig,
+    session::{load_service_key, migrate_service_key, Sessions, SERVICE_KEY_DIR, SERVICE_KEY_FILE},
+    state::{ServiceState, SERVICE_STATE_DIR, SERVICE_STATE_FILE},
+    storage::StorageDBus,
+    switch::SwitchConfig,
     zbus::connection,
     ServiceError,
 };
 
+#[cfg(feature = "logind")]
+use pam_login_ng_common::logind::{logind_available, LOGIND_PROVIDER};
+
 use tokio::signal::unix::{signal, SignalKind};
 use tokio::sync::RwLock;
 
@@ -41,7 +58,15 @@ async fn main() -> Result<(), ServiceError> {
         return Err(ServiceError::MissingPrivilegesError);
     }
 
-    let private_key_file_name_str = "private_key_pkcs1.pem";
+    // keep the private key and users' passwords out of swap and core files
+    if let Err(err) = disable_core_dumps() {
+        eprintln!("❌ Error disabling core dumps: {err}");
+    }
+
+    if let Err(err) = lock_all_memory() {
+        eprintln!("🟠 Couldn't lock the process memory, secrets may be swapped out: {err}");
+    }
+
     let authorization_file_name_str = "authorized_mounts.json";
     let dir_path_str = match std::fs::exists("/usr/lib/login_ng/").unwrap_or(false) {
         true => "/usr/lib/login_ng/",
@@ -61,9 +86,145 @@ async fn main() -> Result<(), ServiceError> {
         }
     }
 
-    let mounts_auth = Arc::new(RwLock::new(MountAuthOperations::new(
-        Path::new(dir_path_str).join(authorization_file_name_str),
-    )));
+    // a previous run that did not stop cleanly may have left filesystems behind
+    create_directory(PathBuf::from(SERVICE_STATE_DIR)).await?;
+    let service_state_path = Path::new(SERVICE_STATE_DIR).join(SERVICE_STATE_FILE);
+    match ServiceState::load(service_state_path.as_path()) {
+        Ok(Some(previous)) => {
+            if !previous.clean_shutdown() {
+                eprintln!("⚠️ The previous run of the service did not stop cleanly");
+            }
+
+            for mountpoint in previous.detached_mounts().iter() {
+                eprintln!(
+                    "⚠️ {} was detached while still busy at the last shutdown: the filesystem may need checking",
+                    mountpoint.display()
+                );
+            }
+        }
+        Ok(None) => {}
+        Err(err) => eprintln!("❌ Error reading the previous service state: {err}"),
+    }
+
+    if let Err(err) = ServiceState::running().save(service_state_path.as_path()) {
+        eprintln!("❌ Error writing the service state: {err}");
+    }
+
+    // the key is kept out of the configuration directory, whose files it may be sealing
+    create_directory(PathBuf::from(SERVICE_KEY_DIR)).await?;
+    let service_key_path = Path::new(SERVICE_KEY_DIR).join(SERVICE_KEY_FILE);
+    migrate_service_key(
+        Path::new(dir_path_str).join(SERVICE_KEY_FILE).as_path(),
+        service_key_path.as_path(),
+    )
+    .await?;
+
+    // also needed to read files encrypted before encryption has been disabled
+    let service_key = Arc::new(load_service_key(service_key_path.clone()).await?);
+
+    let seal_config = SealConfig::load(Path::new(dir_path_str));
+    if seal_config.encrypt() {
+        println!("🔒 Files in {dir_path_str} will be encrypted with the service key");
+    }
+
+    let mounts_auth = Arc::new(RwLock::new(
+        MountAuthOperations::new(Path::new(dir_path_str).join(authorization_file_name_str))
+            .with_service_key(service_key, seal_config.encrypt()),
+    ));
+
+    let approval_config = ApprovalConfig::load(Path::new(dir_path_str));
+
+    let hooks = Arc::new(Hooks::load(Path::new(dir_path_str)));
+    if let Some(webhook) = hooks.webhook() {
+        println!("🔔 Login events will be notified to {webhook}");
+    }
+    let approvals = Arc::new(RwLock::new(PendingApprovals::new()));
+
+    let guest = GuestConfig::load(Path::new(dir_path_str));
+    if guest.enabled() {
+        println!("👤 Guest sessions enabled for user {}", guest.user());
+    }
+
+    let limits = SessionLimits::load(Path::new(dir_path_str));
+    if let Some(max_per_user) = limits.max_per_user() {
+        println!("👥 Users can have at most {max_per_user} concurrent sessions");
+    }
+
+    let switch = SwitchConfig::load(Path::new(dir_path_str));
+    if switch.enabled() {
+        println!("🔀 Fast user switching enabled");
+    }
+
+    let approval_server = match &approval_config {
+        Some(config) => match tokio::net::TcpListener::bind(config.listen()).await {
+            Ok(listener) => {
+                println!(
+                    "🟢 Phone-approved login endpoint listening on {}",
+                    config.listen()
+                );
+
+                let approvals = approvals.clone();
+                let token = String::from(config.token());
+                Some(tokio::spawn(async move {
+                    if let Err(err) = serve_approvals(listener, approvals, token).await {
+                        eprintln!("❌ Phone-approved login endpoint stopped: {err}");
+                    }
+                }))
+            }
+            Err(err) => {
+                eprintln!(
+                    "❌ Couldn't listen on {} for login approvals: {err}",
+                    config.listen()
+                );
+                None
+            }
+        },
+        None => None,
+    };
+
+    let metrics = Arc::new(Metrics::new());
+    let metrics_config = MetricsConfig::load(Path::new(dir_path_str));
+
+    let metrics_textfile = metrics_config.textfile().clone().map(|path| {
+        println!("📊 Writing metrics to {}", path.display());
+
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            if let Err(err) = export_metrics_textfile(path, metrics).await {
+                eprintln!("❌ Metrics textfile exporter stopped: {err}");
+            }
+        })
+    });
+
+    let metrics_server = match metrics_config.socket() {
+        Some(socket) => {
+            // a stale socket from a previous run would make bind fail
+            let _ = std::fs::remove_file(socket);
+
+            match tokio::net::UnixListener::bind(socket) {
+                Ok(listener) => {
+                    println!("📊 Serving metrics on {}", socket.display());
+
+                    let metrics = metrics.clone();
+                    Some(tokio::spawn(async move {
+                        if let Err(err) = serve_metrics(listener, metrics).await {
+                            eprintln!("❌ Metrics socket stopped: {err}");
+                        }
+                    }))
+                }
+                Err(err) => {
+                    eprintln!(
+                        "❌ Couldn't listen on {} for metrics: {err}",
+                        socket.display()
+                    );
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+
+    let token_ttl = limits.token_ttl();
 
     println!("🔧 Building the dbus object...");
 
@@ -87,15 +248,61 @@ async fn main() -> Result<(), ServiceError> {
         .serve_at(
             "/org/zbus/login_ng_session",
             Sessions::new(
-                Path::new(dir_path_str).join(private_key_file_name_str),
+                service_key_path,
                 mounts_auth,
+                approval_config,
+                approvals,
+                hooks,
+                guest,
+                limits,
+                switch,
+                metrics,
             ),
         )
         .map_err(ServiceError::ZbusError)?
+        .serve_at("/org/zbus/login_ng_storage", StorageDBus::new())
+        .map_err(ServiceError::ZbusError)?
         .build()
         .await
         .map_err(ServiceError::ZbusError)?;
 
+    // tokens handed out but never used would otherwise pile up
+    let token_purge = {
+        let dbus_session_conn = dbus_session_conn.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(token_ttl);
+            loop {
+                interval.tick().await;
+
+                match dbus_session_conn
+                    .object_server()
+                    .interface::<_, Sessions>("/org/zbus/login_ng_session")
+                    .await
+                {
+                    Ok(sessions) => {
+                        let purged = sessions.get_mut().await.purge_expired_tokens();
+                        if purged > 0 {
+                            println!("⌛ Purged {purged} expired one time tokens");
+                        }
+                    }
+                    Err(err) => eprintln!("❌ Error purging the expired one time tokens: {err}"),
+                }
+            }
+        })
+    };
+
+    #[cfg(feature = "logind")]
+    match pam_login_ng_common::zbus::Connection::system().await {
+        Ok(system_conn) => match logind_available(&system_conn).await {
+            true => println!("🟢 {LOGIND_PROVIDER} is available"),
+            false => println!("🟠 {LOGIND_PROVIDER} is not available: logind integration disabled"),
+        },
+        Err(err) => {
+            println!("🟠 Couldn't connect to the system bus to reach {LOGIND_PROVIDER}: {err}")
+        }
+    }
+
     println!("🔄 Application running");
 
     // Create a signal listener for SIGTERM
@@ -105,6 +312,56 @@ async fn main() -> Result<(), ServiceError> {
     // Wait for a SIGTERM signal
     sigterm.recv().await;
 
+    token_purge.abort();
+
+    if let Some(approval_server) = approval_server {
+        approval_server.abort();
+    }
+
+    if let Some(metrics_server) = metrics_server {
+        metrics_server.abort();
+    }
+
+    if let Some(metrics_textfile) = metrics_textfile {
+        metrics_textfile.abort();
+    }
+
+    // sessions must be closed before going away: a reboot would otherwise leave dirty filesystems
+    match dbus_session_conn
+        .object_server()
+        .interface::<_, Sessions>("/org/zbus/login_ng_session")
+        .await
+    {
+        Ok(sessions) => {
+            let state = sessions.get_mut().await.close_all_sessions().await;
+
+            for username in state.closed_sessions().iter() {
+                if let Err(err) =
+                    Sessions::session_closed(sessions.signal_emitter(), username.as_str()).await
+                {
+                    eprintln!("❌ Error emitting SessionClosed for user {username}: {err}");
+                }
+            }
+
+            println!(
+                "🔒 Closed {} sessions for shutdown",
+                state.closed_sessions().len()
+            );
+
+            if let Err(err) = state.save(service_state_path.as_path()) {
+                eprintln!("❌ Error writing the service state: {err}");
+            }
+        }
+        Err(err) => eprintln!("❌ Error closing the open sessions: {err}"),
+    }
+
+    // the last values would otherwise be lost
+    if let Some(textfile) = metrics_config.textfile() {
+        if let Err(err) = flush_metrics_textfile(textfile, &metrics).await {
+            eprintln!("❌ Error writing the metrics textfile: {err}");
+        }
+    }
+
     drop(dbus_session_conn);
     drop(dbus_mounts_auth_con);
 
@@ -0,0 +1,46 @@
+# login-ng messages: copy this file to <language>.ftl (for example it.ftl or pt_BR.ftl)
+# and translate the values, leaving the { $placeables } untouched.
+
+username-prompt = username: 
+login-prompt = login: 
+locked-retry = retry in { $seconds } seconds
+locked-contact-admin = contact the system administrator
+password-change-offer = { $expired }. Change it now? [y/N] 
+password-expires-today = Your password expires today
+password-expires-tomorrow = Your password expires in 1 day
+password-expires-in = Your password expires in { $days } days
+password-expired = Your password has expired
+account-expires-today = Your account expires today
+account-expires-tomorrow = Your account expires in 1 day
+account-expires-in = Your account expires in { $days } days
+recovery-reenroll = A recovery token has been used: a new password must be chosen
+exec-context-reset-failed = Unable to reset the exec context: { $error }
+home-creation-failed = Unable to create the home directory: { $error }
+session-environment-failed = Unable to apply the session environment: { $error }
+history-update-failed = Unable to update the login history: { $error }
+qr-scan = Scan the code with your companion device to log in as { $username }.
+qr-failed = Phone-approved login failed: { $error }
+qr-username-required = Phone-approved login requires the username to be specified
+login-attempt-failed = Login attempt { $attempt }/{ $max } failed.
+login-attempt-errored = Login attempt { $attempt }/{ $max } errored: { $error }
+login-auth-failed = Wrong username or password.
+login-account-expired = The account has expired: contact the system administrator.
+login-permission-denied = The account is not allowed to log in here.
+login-max-tries = Too many failed attempts: the account has been locked.
+login-credentials-unavailable = The credentials of the account are not available.
+greeter-failed = Could not start the unprivileged greeter: { $error }
+ask-password-failed = Could not answer the password request: { $error }
+kiosk-not-configured = Kiosk mode requires the user and cmd keys in the Kiosk section of kiosk.conf
+kiosk-login-failed = Kiosk login as { $username } failed.
+kiosk-login-errored = Kiosk login as { $username } errored: { $error }
+lock-unknown-user = Could not identify the current user.
+lock-not-configured = { $username } has no login-ng authentication data: the session cannot be unlocked.
+lock-locked = Session of { $username } locked.
+lock-unlock-prompt = Password or PIN: 
+lock-wrong-secret = Wrong password or PIN.
+lock-empty-secret = { $username } can log in without a password: the session cannot be locked.
+lock-prompt-error = Cannot read the password: { $error }
+guest-hint = Log in as { $username } for a guest session: nothing is kept after logout.
+test-auth-ok = { $username } (uid { $uid }) authenticated: the account is allowed to log in.
+test-auth-password-expired = The password of { $username } has expired and must be changed at the next login.
+test-auth-failed = Authentication test failed: { $error }
@@ -0,0 +1,43 @@
+username-prompt = nome utente: 
+login-prompt = accesso: 
+locked-retry = riprova tra { $seconds } secondi
+locked-contact-admin = contatta l'amministratore di sistema
+password-change-offer = { $expired }. Cambiarla ora? [y/N] 
+password-expires-today = La tua password scade oggi
+password-expires-tomorrow = La tua password scade tra 1 giorno
+password-expires-in = La tua password scade tra { $days } giorni
+password-expired = La tua password è scaduta
+account-expires-today = Il tuo account scade oggi
+account-expires-tomorrow = Il tuo account scade tra 1 giorno
+account-expires-in = Il tuo account scade tra { $days } giorni
+recovery-reenroll = È stato usato un token di recupero: è necessario scegliere una nuova password
+exec-context-reset-failed = Impossibile ripristinare il contesto di esecuzione: { $error }
+home-creation-failed = Impossibile creare la directory home: { $error }
+session-environment-failed = Impossibile applicare l'ambiente della sessione: { $error }
+history-update-failed = Impossibile aggiornare la cronologia degli accessi: { $error }
+qr-scan = Scansiona il codice con il dispositivo associato per accedere come { $username }.
+qr-failed = Accesso approvato dal telefono non riuscito: { $error }
+qr-username-required = L'accesso approvato dal telefono richiede di specificare il nome utente
+login-attempt-failed = Tentativo di accesso { $attempt }/{ $max } fallito.
+login-attempt-errored = Tentativo di accesso { $attempt }/{ $max } terminato con errore: { $error }
+login-auth-failed = Nome utente o password errati.
+login-account-expired = L'account è scaduto: contatta l'amministratore di sistema.
+login-permission-denied = L'account non è autorizzato ad accedere qui.
+login-max-tries = Troppi tentativi falliti: l'account è stato bloccato.
+login-credentials-unavailable = Le credenziali dell'account non sono disponibili.
+greeter-failed = Impossibile avviare il greeter non privilegiato: { $error }
+ask-password-failed = Impossibile rispondere alla richiesta di password: { $error }
+kiosk-not-configured = La modalità kiosk richiede le chiavi user e cmd nella sezione Kiosk di kiosk.conf
+kiosk-login-failed = Accesso kiosk come { $username } fallito.
+kiosk-login-errored = Accesso kiosk come { $username } terminato con errore: { $error }
+lock-unknown-user = Impossibile identificare l'utente corrente.
+lock-not-configured = { $username } non ha dati di autenticazione login-ng: la sessione non può essere sbloccata.
+lock-locked = Sessione di { $username } bloccata.
+lock-unlock-prompt = Password o PIN: 
+lock-wrong-secret = Password o PIN errato.
+lock-empty-secret = { $username } può accedere senza password: la sessione non può essere bloccata.
+lock-prompt-error = Impossibile leggere la password: { $error }
+guest-hint = Accedi come { $username } per una sessione ospite: nulla viene conservato dopo la disconnessione.
+test-auth-ok = { $username } (uid { $uid }) autenticato: l'account può accedere.
+test-auth-password-expired = La password di { $username } è scaduta e dovrà essere cambiata al prossimo accesso.
+test-auth-failed = Test di autenticazione fallito: { $error }